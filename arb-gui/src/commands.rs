@@ -289,6 +289,39 @@ impl CommandDef {
                     });
                 }
             }
+            for dom in &domains {
+                let ssh_dom = match dom.downcast_ref::<mux::ssh::RemoteSshDomain>() {
+                    Some(ssh_dom) => ssh_dom,
+                    None => continue,
+                };
+                let name = dom.domain_name();
+                for forward in ssh_dom.forwards() {
+                    let spec = forward.spec.to_string();
+                    let (brief, icon) = if forward.active {
+                        (format!("Stop forwarding {spec}"), "md_lan_disconnect")
+                    } else {
+                        (format!("Start forwarding {spec}"), "md_lan_connect")
+                    };
+                    let doc = match (&forward.error, forward.port) {
+                        (Some(err), _) => format!("Failed to start: {err}"),
+                        (None, Some(port)) if port != forward.spec.port => {
+                            format!("Listening on port {port}")
+                        }
+                        _ => String::new(),
+                    };
+                    result.push(ExpandedCommand {
+                        brief: format!("{brief} (Domain {name})").into(),
+                        doc: doc.into(),
+                        keys: vec![],
+                        action: KeyAssignment::ToggleSshForward {
+                            domain: name.to_string(),
+                            forward: spec,
+                        },
+                        menubar: &["Shell", "Port Forwarding"],
+                        icon: Some(icon.into()),
+                    });
+                }
+            }
 
             let active_workspace = mux.active_workspace();
             for workspace in mux.iter_workspaces() {
@@ -848,6 +881,88 @@ pub fn derive_command_from_key_assignment(action: &KeyAssignment) -> Option<Comm
             menubar: &[],
             icon: None,
         },
+        AddAsciicastMarker(_) => CommandDef {
+            brief: "Add a marker to the asciicast recording".into(),
+            doc: "Inserts a marker at the current position of the \
+                  in-progress recording of the current pane, or of \
+                  the `arb record` session running in it"
+                .into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &[],
+            icon: None,
+        },
+        TogglePaneRecording => CommandDef {
+            brief: "Start or stop recording the current pane".into(),
+            doc: "Starts recording the output of the current pane to an \
+                  asciicast file, or stops an in-progress recording"
+                .into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &["Shell"],
+            icon: None,
+        },
+        CopyCommandBlockOutput(destination) => CommandDef {
+            brief: "Copy the output of the last command".into(),
+            doc: format!(
+                "Copies the output of the command block at the top of the \
+                 viewport, or of the most recent command, to {destination:?}"
+            )
+            .into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &["Edit"],
+            icon: Some("md_content_copy"),
+        },
+        ToggleCommandBlockFold => CommandDef {
+            brief: "Fold or unfold the output of the last command".into(),
+            doc: "Hides or reveals the output of the command block at the \
+                  top of the viewport, or of the most recent command"
+                .into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &["View"],
+            icon: None,
+        },
+        ShowCommandHistory => CommandDef {
+            brief: "Search command history".into(),
+            doc: "Fuzzy searches the commands that have been run in any \
+                  pane, and pastes or re-runs the chosen one.  Commands \
+                  are only recorded when `command_history` is enabled"
+                .into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &["Shell"],
+            icon: Some("md_history"),
+        },
+        ShowSftpBrowser => CommandDef {
+            brief: "Browse remote files".into(),
+            doc: "Browses the remote filesystem of the current ssh domain pane \
+                  over sftp, to download files or upload them by dropping \
+                  them onto the pane"
+                .into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &["Shell"],
+            icon: Some("md_folder_network"),
+        },
+        ScrollToFailedCommand(n) => {
+            let (direction, amount) = if *n < 0 { ("up", -n) } else { ("down", *n) };
+            let ordinal = english_ordinal(amount);
+            CommandDef {
+                brief: format!("Scroll {direction} {amount} failed command(s)").into(),
+                doc: format!(
+                    "Scrolls the viewport {direction} to the \
+                             {ordinal} command that exited with a \
+                             non-zero status in that direction"
+                )
+                .into(),
+                keys: vec![],
+                args: &[ArgType::ActivePane],
+                menubar: &["View"],
+                icon: Some("oct_terminal"),
+            }
+        }
         PromptInputLine(_) => CommandDef {
             brief: "Prompt the user for a line of text".into(),
             doc: "Activates the prompt overlay and wait for input".into(),
@@ -1987,6 +2102,15 @@ pub fn derive_command_from_key_assignment(action: &KeyAssignment) -> Option<Comm
             menubar: &["Shell", "Attach"],
             icon: Some("md_pipe"),
         },
+        ToggleSshForward { domain, forward } => CommandDef {
+            brief: format!("Toggle forwarding `{forward}` (Domain {domain})").into(),
+            doc: format!("Starts or stops the port forward `{forward}` of domain `{domain}`")
+                .into(),
+            keys: vec![],
+            args: &[],
+            menubar: &[],
+            icon: Some("md_lan_pending"),
+        },
         CopyMode(copy_mode) => CommandDef {
             brief: format!("{copy_mode:?}").into(),
             doc: "".into(),
@@ -2066,6 +2190,8 @@ fn compute_default_actions() -> Vec<KeyAssignment> {
         CloseCurrentPane { confirm: true },
         DetachDomain(SpawnTabDomain::CurrentPaneDomain),
         ResetTerminal,
+        ShowCommandHistory,
+        ShowSftpBrowser,
         // ----------------- Edit
         #[cfg(not(target_os = "macos"))]
         PasteFrom(ClipboardPasteSource::PrimarySelection),
@@ -2075,6 +2201,7 @@ fn compute_default_actions() -> Vec<KeyAssignment> {
         PasteFrom(ClipboardPasteSource::Clipboard),
         ClearScrollback(ScrollbackEraseMode::ScrollbackOnly),
         ClearScrollback(ScrollbackEraseMode::ScrollbackAndViewport),
+        CopyCommandBlockOutput(ClipboardCopyDestination::Clipboard),
         QuickSelect,
         CharSelect(CharSelectArguments::default()),
         ActivateCopyMode,
@@ -2089,6 +2216,9 @@ fn compute_default_actions() -> Vec<KeyAssignment> {
         ScrollByPage(NotNan::new(1.0).unwrap()),
         ScrollToTop,
         ScrollToBottom,
+        ScrollToFailedCommand(-1),
+        ScrollToFailedCommand(1),
+        ToggleCommandBlockFold,
         // ----------------- Window
         ToggleFullScreen,
         ToggleAlwaysOnTop,
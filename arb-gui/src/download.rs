@@ -1,7 +1,7 @@
 use anyhow::Context;
 use std::fs::{File, OpenOptions};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Simple heuristics to try to avoid obvious trickery with
 /// the name provided by the remote system
@@ -25,7 +25,7 @@ fn neuter_name(name: &str) -> Option<&str> {
 /// in the user's download folder that doesn't conflict with any other
 /// files in that folder.
 /// Returns the selected name and the opened File on success.
-fn resolve_file_name(name: Option<&str>) -> anyhow::Result<(PathBuf, File)> {
+pub fn resolve_file_name(name: Option<&str>) -> anyhow::Result<(PathBuf, File)> {
     let name = name.and_then(neuter_name).unwrap_or("downloaded-via-arb");
 
     let download_dir = dirs_next::download_dir()
@@ -59,6 +59,13 @@ pub fn save_to_downloads(orig_name: Option<String>, data: &[u8]) -> anyhow::Resu
     file.write_all(data)
         .with_context(|| format!("writing {} of data to {}", data.len(), name.display()))?;
 
+    notify_downloaded(&name);
+
+    Ok(())
+}
+
+/// Lets the user know that `name` has been written to the download folder
+pub fn notify_downloaded(name: &Path) {
     let url = format!("file://{}", name.display());
     wezterm_toast_notification::persistent_toast_notification_with_click_to_open_url(
         "Download completed",
@@ -67,6 +74,4 @@ pub fn save_to_downloads(orig_name: Option<String>, data: &[u8]) -> anyhow::Resu
     );
 
     log::info!("Downloaded {}", name.display());
-
-    Ok(())
 }
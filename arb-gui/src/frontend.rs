@@ -12,6 +12,7 @@ use mux::{Mux, MuxNotification};
 use promise::{Future, Promise};
 use std::cell::RefCell;
 use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
 use std::path::Path;
 use std::process::{Command, Stdio};
 use std::rc::Rc;
@@ -178,6 +179,25 @@ impl GuiFrontEnd {
                 MuxNotification::WindowInvalidated(_) => {}
                 MuxNotification::PaneOutput(_) => {}
                 MuxNotification::PaneAdded(_) => {}
+                MuxNotification::QueryClipboard { pane_id, selection } => {
+                    // Handled via TermWindowNotif, which knows which window
+                    // contains the pane.  A pane that isn't in any window
+                    // gets an empty reply, rather than leaving the
+                    // application waiting for one that never comes.
+                    promise::spawn::spawn_into_main_thread(async move {
+                        let mux = Mux::get();
+                        if mux.resolve_pane_id(pane_id).is_some() {
+                            return;
+                        }
+                        if let Some(pane) = mux.get_pane(pane_id) {
+                            let response = selection.osc52_response("");
+                            if let Err(err) = pane.writer().write_all(response.as_bytes()) {
+                                log::error!("replying to OSC 52 query in pane {pane_id}: {err:#}");
+                            }
+                        }
+                    })
+                    .detach();
+                }
                 MuxNotification::Alert {
                     pane_id,
                     alert:
@@ -216,6 +236,12 @@ impl GuiFrontEnd {
                         }
                     }
                 }
+                MuxNotification::Alert {
+                    pane_id,
+                    alert: Alert::CommandFinished(block),
+                } => {
+                    crate::history::record_command(pane_id, block);
+                }
                 MuxNotification::Alert {
                     pane_id: _,
                     alert: Alert::Bell | Alert::Progress(_),
@@ -0,0 +1,283 @@
+//! An index of the command lines that have been run in any pane of
+//! any window, including those in remote domains, as reported by shell
+//! integration via OSC 133.  Entries are ranked by frecency.
+//!
+//! The history is only kept when `command_history` is enabled.  Each
+//! command that is run is appended to the history file as a line of
+//! JSON; the file is occasionally compacted to a single line per
+//! distinct command.  A lock file serializes access to the history
+//! file between the arb processes that share it.
+use chrono::{DateTime, Utc};
+use frecency::Frecency;
+use mux::pane::{CachePolicy, PaneId};
+use mux::Mux;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use wezterm_term::CommandBlock;
+
+/// The number of distinct command lines that are retained;
+/// the lowest ranked are discarded first
+const MAX_ENTRIES: usize = 10_000;
+
+/// The history file is compacted once it grows beyond this size
+const COMPACT_THRESHOLD: u64 = 4 * 1024 * 1024;
+
+/// A command line, with the details of the last time that it was run
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryEntry {
+    pub command: String,
+    pub cwd: Option<String>,
+    pub domain: String,
+    pub pane_id: PaneId,
+    pub exit_status: Option<i32>,
+    pub timestamp: DateTime<Utc>,
+    pub duration_ms: Option<u64>,
+    pub frecency: Frecency,
+}
+
+fn history_file_name() -> PathBuf {
+    config::DATA_DIR.join("command-history.jsonl")
+}
+
+/// Opens `path` for writing, creating it such that only
+/// the user can read it
+fn open_private(path: &Path, options: &mut OpenOptions) -> std::io::Result<File> {
+    options.create(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// Takes the lock that guards the history file at `path`.
+/// The lock is held until the returned file is dropped.
+fn lock_history(path: &Path) -> anyhow::Result<File> {
+    let lock = open_private(
+        &path.with_extension("lock"),
+        OpenOptions::new().read(true).write(true),
+    )?;
+    lock.lock()?;
+    Ok(lock)
+}
+
+/// Reads the history file, merging the entries for the same
+/// command line.  Returns the entries highest ranked first.
+fn read_history(path: &Path) -> anyhow::Result<Vec<HistoryEntry>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut history: Vec<HistoryEntry> = vec![];
+    let mut by_command: HashMap<String, usize> = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let entry: HistoryEntry = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            Err(err) => {
+                // Most likely a line that was cut short by a crash
+                log::warn!("Skipping invalid command history entry: {err:#}");
+                continue;
+            }
+        };
+        match by_command.get(&entry.command) {
+            Some(&idx) => {
+                // A compacted file has a single entry per command,
+                // so this is a subsequent run of the command
+                let mut frecency = history[idx].frecency.clone();
+                frecency.register_access_at_time(entry.timestamp);
+                history[idx] = HistoryEntry { frecency, ..entry };
+            }
+            None => {
+                by_command.insert(entry.command.clone(), history.len());
+                history.push(entry);
+            }
+        }
+    }
+
+    history.sort_by(|a, b| b.frecency.score().total_cmp(&a.frecency.score()));
+    history.truncate(MAX_ENTRIES);
+    Ok(history)
+}
+
+/// Rewrites the history file with a single entry per command line
+fn compact_history(path: &Path) -> anyhow::Result<()> {
+    let history = read_history(path)?;
+
+    let temp = path.with_extension("tmp");
+    let mut file = std::io::BufWriter::new(open_private(
+        &temp,
+        OpenOptions::new().write(true).truncate(true),
+    )?);
+    for entry in &history {
+        serde_json::to_writer(&mut file, entry)?;
+        file.write_all(b"\n")?;
+    }
+    file.into_inner()?.sync_all()?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+fn append_entry(path: &Path, entry: &HistoryEntry) -> anyhow::Result<()> {
+    let _lock = lock_history(path)?;
+
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    let mut file = open_private(path, OpenOptions::new().append(true))?;
+    file.write_all(line.as_bytes())?;
+
+    if file.metadata()?.len() > COMPACT_THRESHOLD {
+        compact_history(path)?;
+    }
+    Ok(())
+}
+
+/// Loads the history, highest ranked first
+pub fn load_history() -> anyhow::Result<Vec<HistoryEntry>> {
+    let path = history_file_name();
+    let _lock = lock_history(&path)?;
+    read_history(&path)
+}
+
+/// Records a command that finished in a pane
+pub fn record_command(pane_id: PaneId, block: CommandBlock) {
+    if !config::configuration().command_history {
+        return;
+    }
+    let command = match block.command {
+        // Like HISTCONTROL=ignorespace, a leading space
+        // keeps a command out of the history
+        Some(command) if !command.starts_with(' ') => command,
+        _ => return,
+    };
+
+    let mux = Mux::get();
+    let pane = mux.get_pane(pane_id);
+    let cwd = pane
+        .as_ref()
+        .and_then(|pane| pane.get_current_working_dir(CachePolicy::AllowStale))
+        .map(|url| match url.to_file_path() {
+            Ok(path) => path.display().to_string(),
+            Err(_) => url.path().to_string(),
+        });
+    let domain = pane
+        .and_then(|pane| mux.get_domain(pane.domain_id()))
+        .map(|domain| domain.domain_name().to_string())
+        .unwrap_or_default();
+
+    let timestamp = Utc::now();
+    let mut frecency = Frecency::new_at_time(timestamp);
+    frecency.register_access_at_time(timestamp);
+    let entry = HistoryEntry {
+        command,
+        cwd,
+        domain,
+        pane_id,
+        exit_status: block.exit_status,
+        timestamp,
+        duration_ms: block.duration.map(|d| d.as_millis() as u64),
+        frecency,
+    };
+
+    // Waiting for the lock, or compacting the file, can take
+    // a moment, so keep it off the gui thread
+    std::thread::spawn(move || {
+        if let Err(err) = append_entry(&history_file_name(), &entry) {
+            log::error!("Failed to save command history: {err:#}");
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+
+    fn entry(command: &str, timestamp: DateTime<Utc>) -> HistoryEntry {
+        let mut frecency = Frecency::new_at_time(timestamp);
+        frecency.register_access_at_time(timestamp);
+        HistoryEntry {
+            command: command.to_string(),
+            cwd: None,
+            domain: "local".to_string(),
+            pane_id: 0,
+            exit_status: Some(0),
+            timestamp,
+            duration_ms: None,
+            frecency,
+        }
+    }
+
+    fn commands(history: &[HistoryEntry]) -> Vec<&str> {
+        history.iter().map(|e| e.command.as_str()).collect()
+    }
+
+    #[test]
+    fn should_record_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        assert!(read_history(&path).unwrap().is_empty());
+
+        let now = Utc::now();
+        append_entry(&path, &entry("ls", now - Duration::days(1))).unwrap();
+        append_entry(&path, &entry("make", now - Duration::hours(1))).unwrap();
+        append_entry(&path, &entry("ls", now)).unwrap();
+
+        let history = read_history(&path).unwrap();
+        assert_eq!(commands(&history), vec!["ls", "make"]);
+        assert_eq!(history[0].frecency.num_accesses(), 2);
+        assert_eq!(history[0].timestamp, now);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn should_skip_invalid_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        append_entry(&path, &entry("ls", Utc::now())).unwrap();
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"command\": \"trunc")
+            .unwrap();
+
+        assert_eq!(commands(&read_history(&path).unwrap()), vec!["ls"]);
+    }
+
+    #[test]
+    fn should_compact_and_truncate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let now = Utc::now();
+        for i in 0..MAX_ENTRIES + 5 {
+            let age = Duration::minutes((MAX_ENTRIES + 5 - i) as i64);
+            append_entry(&path, &entry(&format!("cmd {i}"), now - age)).unwrap();
+        }
+        append_entry(&path, &entry("cmd 10", now)).unwrap();
+
+        compact_history(&path).unwrap();
+        let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+        assert_eq!(lines, MAX_ENTRIES);
+
+        let history = read_history(&path).unwrap();
+        assert_eq!(history.len(), MAX_ENTRIES);
+        assert_eq!(history[0].command, "cmd 10");
+        assert_eq!(history[0].frecency.num_accesses(), 2);
+        // The oldest entries are the lowest ranked, and are dropped
+        assert!(!history.iter().any(|e| e.command == "cmd 0"));
+        assert!(history.iter().any(|e| e.command == "cmd 11"));
+    }
+}
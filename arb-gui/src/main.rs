@@ -54,6 +54,7 @@ mod customglyph;
 mod download;
 mod frontend;
 mod glyphcache;
+mod history;
 mod inputmap;
 mod overlay;
 mod quad;
@@ -362,6 +363,17 @@ async fn async_run_terminal_gui(
         trigger_and_log_gui_startup(spawn_command).await;
     }
 
+    if let Some(name) = &opts.restore {
+        let window_ids = mux::session::restore_session(name)
+            .await
+            .with_context(|| format!("restoring session {name:?}"))?;
+        if !window_ids.is_empty() {
+            trigger_and_log_gui_attached(MuxDomain(mux.default_domain().domain_id())).await;
+            return Ok(());
+        }
+        log::warn!("session {name:?} has no windows; spawning the default program instead");
+    }
+
     let is_connecting = opts.attach;
 
     if let Some(domain) = &domain {
@@ -693,7 +705,7 @@ fn run_terminal_gui(opts: StartCommand, default_domain_name: Option<String>) ->
     let mut publish = Publish::resolve(
         &mux,
         &config,
-        opts.always_new_process || opts.position.is_some(),
+        opts.always_new_process || opts.position.is_some() || opts.restore.is_some(),
     );
     log::trace!("{:?}", publish);
     if publish.try_spawn(
@@ -721,7 +733,31 @@ fn run_terminal_gui(opts: StartCommand, default_domain_name: Option<String>) ->
     .detach();
 
     maybe_show_configuration_error_window();
-    gui.run_forever()
+    let res = gui.run_forever();
+    save_session_on_exit();
+    res
+}
+
+/// Saves the layout of the windows that are still open as the "last"
+/// session, so that it can be brought back with `arb start --restore last`.
+fn save_session_on_exit() {
+    if !config::configuration().save_session_on_exit {
+        return;
+    }
+    let mux = match Mux::try_get() {
+        Some(mux) => mux,
+        None => return,
+    };
+    let snapshot = mux::session::SessionSnapshot::capture(&mux);
+    // Closing the last window leaves nothing worth restoring;
+    // keep the previous session rather than replacing it
+    if snapshot.is_empty() {
+        return;
+    }
+    match snapshot.save(mux::session::LAST_SESSION) {
+        Ok(path) => log::trace!("saved session to {}", path.display()),
+        Err(err) => log::error!("saving session on exit: {:#}", err),
+    }
 }
 
 fn fatal_toast_notification(title: &str, message: &str) {
@@ -0,0 +1,293 @@
+use crate::history::{load_history, HistoryEntry};
+use crate::overlay::selector::{matcher_pattern, matcher_score};
+use chrono::Utc;
+use mux::pane::PaneId;
+use mux::termwiztermtab::TermWizTerminal;
+use mux::Mux;
+use rayon::prelude::*;
+use std::io::Write;
+use termwiz::cell::{AttributeChange, CellAttributes, Intensity};
+use termwiz::color::{AnsiColor, ColorAttribute};
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+use termwiz_funcs::truncate_right;
+use wezterm_term::unicode_column_width;
+
+const ROW_OVERHEAD: usize = 3;
+
+struct HistoryState {
+    active_idx: usize,
+    max_items: usize,
+    top_row: usize,
+    filter_term: String,
+    entries: Vec<HistoryEntry>,
+    filtered_entries: Vec<usize>,
+    pane_id: PaneId,
+}
+
+/// Formats how long ago `entry` was run, eg: `5m ago`
+fn age(entry: &HistoryEntry) -> String {
+    let secs = (Utc::now() - entry.timestamp).num_seconds().max(0);
+    match secs {
+        0..=59 => format!("{secs}s ago"),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+impl HistoryState {
+    fn update_filter(&mut self) {
+        self.active_idx = 0;
+        self.top_row = 0;
+
+        if self.filter_term.is_empty() {
+            self.filtered_entries = (0..self.entries.len()).collect();
+            return;
+        }
+
+        let pattern = matcher_pattern(&self.filter_term);
+
+        let mut scores: Vec<(usize, u32)> = self
+            .entries
+            .par_iter()
+            .enumerate()
+            .filter_map(|(row_idx, entry)| {
+                let score = matcher_score(&pattern, &entry.command)?;
+                Some((row_idx, score))
+            })
+            .collect();
+
+        // Stable, so that equal scores retain their frecency order
+        scores.sort_by(|a, b| a.1.cmp(&b.1).reverse());
+        self.filtered_entries = scores.into_iter().map(|(idx, _)| idx).collect();
+    }
+
+    fn render(&mut self, term: &mut TermWizTerminal) -> termwiz::Result<()> {
+        let size = term.get_screen_size()?;
+        let max_width = size.cols.saturating_sub(2);
+        self.max_items = size.rows.saturating_sub(ROW_OVERHEAD);
+
+        let mut changes = vec![
+            Change::ClearScreen(ColorAttribute::Default),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            Change::Text(truncate_right(
+                &format!("History: {}", self.filter_term),
+                max_width,
+            )),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(1),
+            },
+            AttributeChange::Intensity(Intensity::Half).into(),
+            Change::Text(truncate_right(
+                "Enter to paste, CTRL-Enter to run, Escape to cancel",
+                max_width,
+            )),
+            Change::AllAttributes(CellAttributes::default()),
+            Change::Text("\r\n".to_string()),
+        ];
+
+        for (row_num, (entry_idx, &idx)) in self
+            .filtered_entries
+            .iter()
+            .enumerate()
+            .skip(self.top_row)
+            .enumerate()
+        {
+            if row_num > self.max_items {
+                break;
+            }
+            let entry = &self.entries[idx];
+
+            if entry_idx == self.active_idx {
+                changes.push(AttributeChange::Reverse(true).into());
+            }
+
+            let (marker, color) = match entry.exit_status {
+                Some(0) => ("✓", AnsiColor::Green),
+                Some(_) => ("✗", AnsiColor::Maroon),
+                None => (" ", AnsiColor::Silver),
+            };
+            changes.push(AttributeChange::Foreground(color.into()).into());
+            changes.push(Change::Text(format!(" {marker} ")));
+            changes.push(AttributeChange::Foreground(ColorAttribute::Default).into());
+
+            let details = format!(
+                "  {} · {} · {}",
+                entry.cwd.as_deref().unwrap_or(""),
+                if entry.domain.is_empty() {
+                    "local"
+                } else {
+                    &entry.domain
+                },
+                age(entry)
+            );
+            let details = truncate_right(&details, max_width / 2);
+            let command = truncate_right(
+                &entry.command.replace('\n', " ⏎ "),
+                max_width.saturating_sub(3 + unicode_column_width(&details, None)),
+            );
+            changes.push(Change::Text(command));
+            changes.push(AttributeChange::Intensity(Intensity::Half).into());
+            changes.push(Change::Text(details));
+            changes.push(Change::AllAttributes(CellAttributes::default()));
+            changes.push(Change::Text("\r\n".to_string()));
+        }
+
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(9 + self.filter_term.len()),
+            y: Position::Absolute(0),
+        });
+
+        term.render(&changes)
+    }
+
+    /// Sends the active entry to the pane, followed by a carriage
+    /// return if `run` is true.  Returns false if there is no entry.
+    fn launch(&self, run: bool) -> bool {
+        let command = match self.filtered_entries.get(self.active_idx) {
+            Some(&idx) => self.entries[idx].command.clone(),
+            None => return false,
+        };
+        let pane_id = self.pane_id;
+
+        promise::spawn::spawn_into_main_thread(async move {
+            let pane = match Mux::get().get_pane(pane_id) {
+                Some(pane) => pane,
+                None => return,
+            };
+            if let Err(err) = pane.send_paste(&command) {
+                log::error!("pasting history entry into pane {pane_id}: {err:#}");
+                return;
+            }
+            if run {
+                if let Err(err) = pane.writer().write_all(b"\r") {
+                    log::error!("running history entry in pane {pane_id}: {err:#}");
+                }
+            }
+        })
+        .detach();
+        true
+    }
+
+    fn move_up(&mut self) {
+        self.active_idx = self.active_idx.saturating_sub(1);
+        if self.active_idx < self.top_row {
+            self.top_row = self.active_idx;
+        }
+    }
+
+    fn move_down(&mut self) {
+        self.active_idx = (self.active_idx + 1).min(self.filtered_entries.len().saturating_sub(1));
+        if self.active_idx > self.top_row + self.max_items {
+            self.top_row = self.active_idx.saturating_sub(self.max_items);
+        }
+    }
+
+    fn run_loop(&mut self, term: &mut TermWizTerminal) -> anyhow::Result<()> {
+        while let Ok(Some(event)) = term.poll_input(None) {
+            match event {
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('P' | 'K'),
+                    modifiers: Modifiers::CTRL,
+                })
+                | InputEvent::Key(KeyEvent {
+                    key: KeyCode::UpArrow,
+                    ..
+                }) => {
+                    self.move_up();
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('N' | 'J'),
+                    modifiers: Modifiers::CTRL,
+                })
+                | InputEvent::Key(KeyEvent {
+                    key: KeyCode::DownArrow,
+                    ..
+                }) => {
+                    self.move_down();
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('G' | 'C'),
+                    modifiers: Modifiers::CTRL,
+                })
+                | InputEvent::Key(KeyEvent {
+                    key: KeyCode::Escape,
+                    ..
+                }) => {
+                    break;
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Backspace,
+                    ..
+                }) => {
+                    self.filter_term.pop();
+                    self.update_filter();
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Enter,
+                    modifiers,
+                }) => {
+                    if self.launch(modifiers.contains(Modifiers::CTRL)) {
+                        break;
+                    }
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char(c),
+                    modifiers: Modifiers::NONE | Modifiers::SHIFT,
+                }) => {
+                    self.filter_term.push(c);
+                    self.update_filter();
+                }
+                InputEvent::Mouse(MouseEvent { mouse_buttons, .. })
+                    if mouse_buttons.contains(MouseButtons::VERT_WHEEL) =>
+                {
+                    if mouse_buttons.contains(MouseButtons::WHEEL_POSITIVE) {
+                        self.move_up();
+                    } else {
+                        self.move_down();
+                    }
+                }
+                InputEvent::Mouse(MouseEvent {
+                    y, mouse_buttons, ..
+                }) => {
+                    let row = y as usize;
+                    if row >= 2 && row - 2 + self.top_row < self.filtered_entries.len() {
+                        self.active_idx = self.top_row + row - 2;
+                        if mouse_buttons == MouseButtons::LEFT && self.launch(false) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            self.render(term)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn history(mut term: TermWizTerminal, pane_id: PaneId) -> anyhow::Result<()> {
+    let entries = load_history().unwrap_or_else(|_| vec![]);
+    let mut state = HistoryState {
+        active_idx: 0,
+        max_items: 0,
+        top_row: 0,
+        filter_term: String::new(),
+        entries,
+        filtered_entries: vec![],
+        pane_id,
+    };
+
+    term.set_raw_mode()?;
+    term.render(&[Change::Title("Command History".to_string())])?;
+    state.update_filter();
+    state.render(&mut term)?;
+    state.run_loop(&mut term)
+}
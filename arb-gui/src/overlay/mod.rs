@@ -10,10 +10,12 @@ pub mod confirm;
 pub mod confirm_close_pane;
 pub mod copy;
 pub mod debug;
+pub mod history;
 pub mod launcher;
 pub mod prompt;
 pub mod quickselect;
 pub mod selector;
+pub mod sftp;
 
 pub use confirm_close_pane::{
     confirm_close_pane, confirm_close_tab, confirm_close_window, confirm_quit_program,
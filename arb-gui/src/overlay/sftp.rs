@@ -0,0 +1,868 @@
+use crate::download::{notify_downloaded, resolve_file_name};
+use crate::overlay::selector::{matcher_pattern, matcher_score};
+use anyhow::Context;
+use mux::pane::PaneId;
+use mux::termwiztermtab::TermWizTerminal;
+use parking_lot::Mutex;
+use smol::channel::{bounded, unbounded, Receiver, Sender, TryRecvError};
+use smol::io::{AsyncReadExt, AsyncWriteExt};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+use termwiz::cell::{AttributeChange, CellAttributes, Intensity};
+use termwiz::color::{AnsiColor, ColorAttribute};
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+use termwiz_funcs::truncate_right;
+use wezterm_ssh::{Metadata, Sftp, Utf8PathBuf};
+use wezterm_term::unicode_column_width;
+
+const ROW_OVERHEAD: usize = 3;
+
+/// How often to look for files that were dropped onto the pane
+/// while waiting for input
+const DROP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How often to look for the cancel key while an sftp
+/// operation is in progress
+const OP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+lazy_static::lazy_static! {
+    /// The open browsers, keyed by the id of the pane that they cover
+    static ref DROP_TARGETS: Mutex<HashMap<PaneId, Sender<Vec<PathBuf>>>> = Mutex::new(HashMap::new());
+}
+
+/// Hands files that were dropped onto `pane_id` to the sftp browser
+/// that is open over it, which uploads them into the directory that
+/// it is showing.  Returns false if the pane has no browser open.
+pub fn accept_dropped_files(pane_id: PaneId, paths: &[PathBuf]) -> bool {
+    match DROP_TARGETS.lock().get(&pane_id) {
+        Some(tx) => tx.try_send(paths.to_vec()).is_ok(),
+        None => false,
+    }
+}
+
+/// Unregisters the browser from DROP_TARGETS when it closes
+struct DropTarget(PaneId);
+
+impl Drop for DropTarget {
+    fn drop(&mut self) {
+        DROP_TARGETS.lock().remove(&self.0);
+    }
+}
+
+struct Entry {
+    name: String,
+    path: Utf8PathBuf,
+    meta: Metadata,
+}
+
+enum Status {
+    Info(String),
+    Error(String),
+    /// An operation is in progress
+    Busy(String),
+}
+
+/// An sftp operation that runs on a thread of its own, so that the
+/// browser can respond to the cancel key while it is in progress.
+/// Dropping it cancels the operation.
+struct BackgroundOp<T> {
+    result: Receiver<anyhow::Result<T>>,
+    _cancel: Sender<()>,
+}
+
+impl<T: Send + 'static> BackgroundOp<T> {
+    fn spawn<F>(op: F) -> Self
+    where
+        F: Future<Output = anyhow::Result<T>> + Send + 'static,
+    {
+        let (result_tx, result) = bounded(1);
+        let (cancel, cancelled) = bounded::<()>(1);
+        std::thread::spawn(move || {
+            smol::block_on(async move {
+                let op = async { Some(op.await) };
+                // Resolves once the BackgroundOp is dropped,
+                // which drops the operation in turn
+                let cancelled = async {
+                    cancelled.recv().await.ok();
+                    None
+                };
+                if let Some(result) = smol::future::or(op, cancelled).await {
+                    result_tx.try_send(result).ok();
+                }
+            })
+        });
+        Self {
+            result,
+            _cancel: cancel,
+        }
+    }
+
+    /// Returns the result of the operation, if it has completed
+    fn try_result(&self) -> Option<anyhow::Result<T>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Closed) => Some(Err(anyhow::anyhow!("the sftp operation failed"))),
+        }
+    }
+}
+
+/// Returns true for the keys that close the browser,
+/// or cancel the operation that is in progress
+fn is_cancel_key(event: &InputEvent) -> bool {
+    matches!(
+        event,
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('G' | 'C'),
+            modifiers: Modifiers::CTRL,
+        }) | InputEvent::Key(KeyEvent {
+            key: KeyCode::Escape,
+            ..
+        })
+    )
+}
+
+struct SftpState {
+    sftp: Sftp,
+    domain_name: String,
+    cwd: Utf8PathBuf,
+    entries: Vec<Entry>,
+    filtered_entries: Vec<usize>,
+    filter_term: String,
+    active_idx: usize,
+    max_items: usize,
+    top_row: usize,
+    status: Option<Status>,
+    drops: Receiver<Vec<PathBuf>>,
+}
+
+/// Formats a file size, eg: `1.5M`
+fn human_size(size: u64) -> String {
+    const UNITS: &[&str] = &["K", "M", "G", "T"];
+    if size < 1024 {
+        return format!("{size}B");
+    }
+    let mut value = size as f64 / 1024.;
+    for unit in UNITS {
+        if value < 1024. {
+            return format!("{value:.1}{unit}");
+        }
+        value /= 1024.;
+    }
+    format!("{:.1}P", value)
+}
+
+/// Orders directories first, then by name
+fn sort_entries(entries: &mut [Entry]) {
+    entries.sort_by(|a, b| {
+        b.meta
+            .is_dir()
+            .cmp(&a.meta.is_dir())
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+}
+
+/// Lists `dir`, with an entry for its parent directory first
+async fn list_dir(sftp: Sftp, dir: Utf8PathBuf) -> anyhow::Result<Vec<Entry>> {
+    let listing = sftp
+        .read_dir(dir.clone())
+        .await
+        .with_context(|| format!("listing {dir}"))?;
+
+    let mut entries: Vec<Entry> = listing
+        .into_iter()
+        .map(|(path, meta)| Entry {
+            name: path.file_name().unwrap_or(path.as_str()).to_string(),
+            path,
+            meta,
+        })
+        .collect();
+    sort_entries(&mut entries);
+
+    if let Some(parent) = dir.parent() {
+        let meta = sftp
+            .metadata(parent.to_path_buf())
+            .await
+            .with_context(|| format!("reading metadata of {parent}"))?;
+        entries.insert(
+            0,
+            Entry {
+                name: "..".to_string(),
+                path: parent.to_path_buf(),
+                meta,
+            },
+        );
+    }
+    Ok(entries)
+}
+
+/// Copies the remote file at `path` into the local `file`
+async fn download_file(
+    sftp: Sftp,
+    path: Utf8PathBuf,
+    mut file: std::fs::File,
+    local: PathBuf,
+) -> anyhow::Result<()> {
+    let mut remote = sftp
+        .open(path.clone())
+        .await
+        .with_context(|| format!("opening {path}"))?;
+    let mut buf = vec![0u8; COPY_CHUNK_SIZE];
+    loop {
+        let n = remote
+            .read(&mut buf)
+            .await
+            .with_context(|| format!("reading {path}"))?;
+        if n == 0 {
+            return Ok(());
+        }
+        file.write_all(&buf[..n])
+            .with_context(|| format!("writing {}", local.display()))?;
+    }
+}
+
+/// Returns a path in `dir` for a file named `name` that doesn't
+/// conflict with an existing file, using the same numbering
+/// scheme as downloads
+async fn unused_remote_path(
+    sftp: &Sftp,
+    dir: &Utf8PathBuf,
+    name: &str,
+) -> anyhow::Result<Utf8PathBuf> {
+    for n in 0..20 {
+        let candidate = if n == 0 {
+            dir.join(name)
+        } else {
+            dir.join(format!("{name}.{n}"))
+        };
+        if sftp.symlink_metadata(candidate.clone()).await.is_err() {
+            return Ok(candidate);
+        }
+    }
+    anyhow::bail!(
+        "Unable to find non-conflicting name for {} in {}",
+        name,
+        dir
+    );
+}
+
+/// Removes a remote file that is being written, unless the
+/// upload completes; this covers both failed and cancelled uploads
+struct PartialUpload {
+    sftp: Sftp,
+    path: Option<Utf8PathBuf>,
+}
+
+impl Drop for PartialUpload {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            let sftp = self.sftp.clone();
+            std::thread::spawn(move || smol::block_on(sftp.remove_file(path)).ok());
+        }
+    }
+}
+
+/// Copies the local file at `local` into `dir`
+async fn upload_file(sftp: Sftp, dir: Utf8PathBuf, local: PathBuf) -> anyhow::Result<Utf8PathBuf> {
+    if local.is_dir() {
+        anyhow::bail!(
+            "{} is a directory; only files can be uploaded",
+            local.display()
+        );
+    }
+    let name = local
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow::anyhow!("{} has no usable file name", local.display()))?;
+    let remote_path = unused_remote_path(&sftp, &dir, name).await?;
+
+    let mut file =
+        std::fs::File::open(&local).with_context(|| format!("opening {}", local.display()))?;
+    let mut remote = sftp
+        .create(remote_path.clone())
+        .await
+        .with_context(|| format!("creating {remote_path}"))?;
+    let mut partial = PartialUpload {
+        sftp: sftp.clone(),
+        path: Some(remote_path.clone()),
+    };
+    let mut buf = vec![0u8; COPY_CHUNK_SIZE];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .with_context(|| format!("reading {}", local.display()))?;
+        if n == 0 {
+            break;
+        }
+        remote
+            .write_all(&buf[..n])
+            .await
+            .with_context(|| format!("writing {remote_path}"))?;
+    }
+    remote
+        .close()
+        .await
+        .with_context(|| format!("writing {remote_path}"))?;
+    partial.path.take();
+    Ok(remote_path)
+}
+
+impl SftpState {
+    /// Runs `op` in the background, showing `what` as the status until
+    /// it completes, while watching for the cancel key.  Returns None if
+    /// the operation was cancelled, or the terminal went away.
+    fn run<T, F>(
+        &mut self,
+        term: &mut TermWizTerminal,
+        what: String,
+        op: F,
+    ) -> Option<anyhow::Result<T>>
+    where
+        T: Send + 'static,
+        F: Future<Output = anyhow::Result<T>> + Send + 'static,
+    {
+        let op = BackgroundOp::spawn(op);
+        self.status = Some(Status::Busy(what));
+        self.render(term).ok();
+        loop {
+            if let Some(result) = op.try_result() {
+                self.status = None;
+                return Some(result);
+            }
+            match term.poll_input(Some(OP_POLL_INTERVAL)) {
+                Ok(Some(event)) if is_cancel_key(&event) => {
+                    self.status = Some(Status::Error("Cancelled".to_string()));
+                    return None;
+                }
+                Ok(_) => {}
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Lists `dir` and makes it the current directory.
+    /// Returns None if that was cancelled.
+    fn read_dir(
+        &mut self,
+        term: &mut TermWizTerminal,
+        dir: Utf8PathBuf,
+    ) -> Option<anyhow::Result<()>> {
+        let op = list_dir(self.sftp.clone(), dir.clone());
+        let entries = match self.run(term, format!("Listing {dir}"), op)? {
+            Ok(entries) => entries,
+            Err(err) => return Some(Err(err)),
+        };
+        self.entries = entries;
+        self.cwd = dir;
+        self.filter_term.clear();
+        self.update_filter();
+        Some(Ok(()))
+    }
+
+    /// Locates the remote home directory.
+    /// Returns None if that was cancelled.
+    fn home_dir(&mut self, term: &mut TermWizTerminal) -> Option<anyhow::Result<Utf8PathBuf>> {
+        let sftp = self.sftp.clone();
+        self.run(
+            term,
+            "Locating the remote home directory".to_string(),
+            async move {
+                sftp.canonicalize(".")
+                    .await
+                    .context("locating the remote home directory")
+            },
+        )
+    }
+
+    fn update_filter(&mut self) {
+        self.active_idx = 0;
+        self.top_row = 0;
+
+        if self.filter_term.is_empty() {
+            self.filtered_entries = (0..self.entries.len()).collect();
+            return;
+        }
+
+        let pattern = matcher_pattern(&self.filter_term);
+        let mut scores: Vec<(usize, u32)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, entry)| Some((idx, matcher_score(&pattern, &entry.name)?)))
+            .collect();
+        // Stable, so that equal scores retain the directories-first order
+        scores.sort_by(|a, b| a.1.cmp(&b.1).reverse());
+        self.filtered_entries = scores.into_iter().map(|(idx, _)| idx).collect();
+    }
+
+    fn prompt(&self) -> String {
+        format!("{}:{}> {}", self.domain_name, self.cwd, self.filter_term)
+    }
+
+    fn render(&mut self, term: &mut TermWizTerminal) -> termwiz::Result<()> {
+        let size = term.get_screen_size()?;
+        let max_width = size.cols.saturating_sub(2);
+        self.max_items = size.rows.saturating_sub(ROW_OVERHEAD);
+
+        let prompt = self.prompt();
+        let mut changes = vec![
+            Change::ClearScreen(ColorAttribute::Default),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            Change::Text(truncate_right(&prompt, max_width)),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(1),
+            },
+        ];
+        match &self.status {
+            Some(Status::Info(text)) => {
+                changes.push(AttributeChange::Foreground(AnsiColor::Green.into()).into());
+                changes.push(Change::Text(truncate_right(text, max_width)));
+            }
+            Some(Status::Error(text)) => {
+                changes.push(AttributeChange::Foreground(AnsiColor::Maroon.into()).into());
+                changes.push(Change::Text(truncate_right(text, max_width)));
+            }
+            Some(Status::Busy(text)) => {
+                changes.push(AttributeChange::Foreground(AnsiColor::Olive.into()).into());
+                changes.push(Change::Text(truncate_right(
+                    &format!("{text}... Escape to cancel"),
+                    max_width,
+                )));
+            }
+            None => {
+                changes.push(AttributeChange::Intensity(Intensity::Half).into());
+                changes.push(Change::Text(truncate_right(
+                    "Enter to open or download, Backspace for the parent directory, \
+                     drop files here to upload, Escape to close",
+                    max_width,
+                )));
+            }
+        }
+        changes.push(Change::AllAttributes(CellAttributes::default()));
+        changes.push(Change::Text("\r\n".to_string()));
+
+        for (row_num, (entry_idx, &idx)) in self
+            .filtered_entries
+            .iter()
+            .enumerate()
+            .skip(self.top_row)
+            .enumerate()
+        {
+            if row_num > self.max_items {
+                break;
+            }
+            let entry = &self.entries[idx];
+
+            if entry_idx == self.active_idx {
+                changes.push(AttributeChange::Reverse(true).into());
+            }
+
+            let details = if entry.meta.is_file() {
+                entry.meta.size.map(human_size).unwrap_or_default()
+            } else {
+                String::new()
+            };
+            let name = if entry.meta.is_dir() {
+                changes.push(AttributeChange::Foreground(AnsiColor::Navy.into()).into());
+                format!(" {}/", entry.name)
+            } else if entry.meta.is_symlink() {
+                format!(" {}@", entry.name)
+            } else {
+                format!(" {}", entry.name)
+            };
+            let name = truncate_right(
+                &name,
+                max_width.saturating_sub(2 + unicode_column_width(&details, None)),
+            );
+            let padding = max_width
+                .saturating_sub(unicode_column_width(&name, None))
+                .saturating_sub(unicode_column_width(&details, None));
+            changes.push(Change::Text(name));
+            changes.push(AttributeChange::Foreground(ColorAttribute::Default).into());
+            changes.push(Change::Text(" ".repeat(padding)));
+            changes.push(AttributeChange::Intensity(Intensity::Half).into());
+            changes.push(Change::Text(details));
+            changes.push(Change::AllAttributes(CellAttributes::default()));
+            changes.push(Change::Text("\r\n".to_string()));
+        }
+
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(unicode_column_width(&prompt, None).min(max_width)),
+            y: Position::Absolute(0),
+        });
+
+        term.render(&changes)
+    }
+
+    /// Enters the active entry if it is a directory, or downloads it
+    fn activate(&mut self, term: &mut TermWizTerminal) {
+        let entry = match self.filtered_entries.get(self.active_idx) {
+            Some(&idx) => &self.entries[idx],
+            None => return,
+        };
+        let (name, path, meta) = (entry.name.clone(), entry.path.clone(), entry.meta);
+
+        let is_dir = if meta.is_symlink() {
+            // Follow the link to find out what it points to
+            let sftp = self.sftp.clone();
+            let target = path.clone();
+            let op = async move {
+                sftp.metadata(target.clone())
+                    .await
+                    .map(|meta| meta.is_dir())
+                    .with_context(|| format!("reading metadata of {target}"))
+            };
+            match self.run(term, format!("Following {path}"), op) {
+                Some(Ok(is_dir)) => is_dir,
+                Some(Err(err)) => {
+                    self.status = Some(Status::Error(format!("{err:#}")));
+                    return;
+                }
+                None => return,
+            }
+        } else {
+            meta.is_dir()
+        };
+
+        if is_dir {
+            if let Some(Err(err)) = self.read_dir(term, path) {
+                self.status = Some(Status::Error(format!("{err:#}")));
+            }
+        } else {
+            self.download(term, &name, &path);
+        }
+    }
+
+    fn parent(&mut self, term: &mut TermWizTerminal) {
+        if let Some(parent) = self.cwd.parent().map(|p| p.to_path_buf()) {
+            if let Some(Err(err)) = self.read_dir(term, parent) {
+                self.status = Some(Status::Error(format!("{err:#}")));
+            }
+        }
+    }
+
+    /// Copies the remote file at `path` into the download folder
+    fn download(&mut self, term: &mut TermWizTerminal, name: &str, path: &Utf8PathBuf) {
+        let (local, file) = match resolve_file_name(Some(name)) {
+            Ok(resolved) => resolved,
+            Err(err) => {
+                self.status = Some(Status::Error(format!("{err:#}")));
+                return;
+            }
+        };
+
+        let op = download_file(self.sftp.clone(), path.clone(), file, local.clone());
+        match self.run(term, format!("Downloading {path}"), op) {
+            Some(Ok(())) => {
+                notify_downloaded(&local);
+                self.status = Some(Status::Info(format!("Downloaded {}", local.display())));
+            }
+            Some(Err(err)) => {
+                // Don't leave a truncated file behind
+                std::fs::remove_file(&local).ok();
+                self.status = Some(Status::Error(format!("{err:#}")));
+            }
+            None => {
+                std::fs::remove_file(&local).ok();
+            }
+        }
+    }
+
+    fn upload_dropped_files(&mut self, term: &mut TermWizTerminal, paths: Vec<PathBuf>) {
+        let mut uploaded = vec![];
+        let mut errors = vec![];
+        for path in paths {
+            let op = upload_file(self.sftp.clone(), self.cwd.clone(), path.clone());
+            match self.run(term, format!("Uploading {}", path.display()), op) {
+                Some(Ok(remote_path)) => uploaded.push(remote_path),
+                Some(Err(err)) => errors.push(format!("{err:#}")),
+                None => {
+                    errors.push("Cancelled".to_string());
+                    break;
+                }
+            }
+        }
+
+        if let Some(Err(err)) = self.read_dir(term, self.cwd.clone()) {
+            errors.push(format!("{err:#}"));
+        }
+
+        self.status = Some(if !errors.is_empty() {
+            Status::Error(errors.join("; "))
+        } else if let [remote_path] = uploaded.as_slice() {
+            Status::Info(format!("Uploaded {remote_path}"))
+        } else {
+            Status::Info(format!("Uploaded {} files to {}", uploaded.len(), self.cwd))
+        });
+    }
+
+    fn move_up(&mut self) {
+        self.active_idx = self.active_idx.saturating_sub(1);
+        if self.active_idx < self.top_row {
+            self.top_row = self.active_idx;
+        }
+    }
+
+    fn move_down(&mut self) {
+        self.active_idx = (self.active_idx + 1).min(self.filtered_entries.len().saturating_sub(1));
+        if self.active_idx > self.top_row + self.max_items {
+            self.top_row = self.active_idx.saturating_sub(self.max_items);
+        }
+    }
+
+    fn run_loop(&mut self, term: &mut TermWizTerminal) -> anyhow::Result<()> {
+        loop {
+            let event = match term.poll_input(Some(DROP_POLL_INTERVAL)) {
+                Ok(event) => event,
+                Err(_) => break,
+            };
+            let mut dirty = event.is_some();
+
+            match event {
+                Some(InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('P' | 'K'),
+                    modifiers: Modifiers::CTRL,
+                }))
+                | Some(InputEvent::Key(KeyEvent {
+                    key: KeyCode::UpArrow,
+                    ..
+                })) => {
+                    self.move_up();
+                }
+                Some(InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('N' | 'J'),
+                    modifiers: Modifiers::CTRL,
+                }))
+                | Some(InputEvent::Key(KeyEvent {
+                    key: KeyCode::DownArrow,
+                    ..
+                })) => {
+                    self.move_down();
+                }
+                Some(ref event) if is_cancel_key(event) => {
+                    break;
+                }
+                Some(InputEvent::Key(KeyEvent {
+                    key: KeyCode::Backspace,
+                    ..
+                })) => {
+                    if self.filter_term.pop().is_some() {
+                        self.update_filter();
+                    } else {
+                        self.parent(term);
+                    }
+                }
+                Some(InputEvent::Key(KeyEvent {
+                    key: KeyCode::Enter,
+                    ..
+                })) => {
+                    self.activate(term);
+                }
+                Some(InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char(c),
+                    modifiers: Modifiers::NONE | Modifiers::SHIFT,
+                })) => {
+                    self.filter_term.push(c);
+                    self.update_filter();
+                }
+                Some(InputEvent::Mouse(MouseEvent { mouse_buttons, .. }))
+                    if mouse_buttons.contains(MouseButtons::VERT_WHEEL) =>
+                {
+                    if mouse_buttons.contains(MouseButtons::WHEEL_POSITIVE) {
+                        self.move_up();
+                    } else {
+                        self.move_down();
+                    }
+                }
+                Some(InputEvent::Mouse(MouseEvent {
+                    y, mouse_buttons, ..
+                })) => {
+                    let row = y as usize;
+                    if row >= 2 && row - 2 + self.top_row < self.filtered_entries.len() {
+                        self.active_idx = self.top_row + row - 2;
+                        if mouse_buttons == MouseButtons::LEFT {
+                            self.activate(term);
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            while let Ok(paths) = self.drops.try_recv() {
+                self.upload_dropped_files(term, paths);
+                dirty = true;
+            }
+
+            if dirty {
+                self.render(term)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Browses the remote filesystem of an ssh domain, starting in `cwd`,
+/// or the remote home directory if that is not known
+pub fn sftp_browser(
+    mut term: TermWizTerminal,
+    pane_id: PaneId,
+    sftp: Sftp,
+    domain_name: String,
+    cwd: Option<String>,
+) -> anyhow::Result<()> {
+    let (tx, drops) = unbounded();
+    DROP_TARGETS.lock().insert(pane_id, tx);
+    let _drop_target = DropTarget(pane_id);
+
+    let mut state = SftpState {
+        sftp,
+        domain_name,
+        cwd: Utf8PathBuf::new(),
+        entries: vec![],
+        filtered_entries: vec![],
+        filter_term: String::new(),
+        active_idx: 0,
+        max_items: 0,
+        top_row: 0,
+        status: None,
+        drops,
+    };
+
+    term.set_raw_mode()?;
+    term.render(&[Change::Title(format!("SFTP: {}", state.domain_name))])?;
+
+    // Cancelling any of these closes the browser
+    let start = match cwd {
+        Some(cwd) => Utf8PathBuf::from(cwd),
+        None => match state.home_dir(&mut term) {
+            Some(home) => home?,
+            None => return Ok(()),
+        },
+    };
+    match state.read_dir(&mut term, start) {
+        Some(Ok(())) => {}
+        Some(Err(err)) => {
+            // The shell may have reported a directory that sftp
+            // can't see; fall back to the home directory
+            let home = match state.home_dir(&mut term) {
+                Some(home) => home?,
+                None => return Ok(()),
+            };
+            match state.read_dir(&mut term, home) {
+                Some(result) => result?,
+                None => return Ok(()),
+            }
+            state.status = Some(Status::Error(format!("{err:#}")));
+        }
+        None => return Ok(()),
+    }
+
+    state.render(&mut term)?;
+    state.run_loop(&mut term)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wezterm_ssh::FileType;
+
+    fn entry(name: &str, ty: FileType) -> Entry {
+        Entry {
+            name: name.to_string(),
+            path: Utf8PathBuf::from(format!("/tmp/{name}")),
+            meta: Metadata {
+                ty,
+                permissions: None,
+                size: None,
+                uid: None,
+                gid: None,
+                accessed: None,
+                modified: None,
+            },
+        }
+    }
+
+    fn wait_for<T: Send + 'static>(op: &BackgroundOp<T>) -> anyhow::Result<T> {
+        for _ in 0..500 {
+            if let Some(result) = op.try_result() {
+                return result;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("the operation didn't complete");
+    }
+
+    #[test]
+    fn should_run_op_in_background() {
+        let op = BackgroundOp::spawn(async { Ok(42) });
+        assert_eq!(wait_for(&op).unwrap(), 42);
+
+        let op: BackgroundOp<()> = BackgroundOp::spawn(async { anyhow::bail!("no such file") });
+        assert_eq!(wait_for(&op).unwrap_err().to_string(), "no such file");
+    }
+
+    #[test]
+    fn should_cancel_op_when_dropped() {
+        struct Dropped(Sender<()>);
+        impl Drop for Dropped {
+            fn drop(&mut self) {
+                self.0.try_send(()).ok();
+            }
+        }
+
+        let (started_tx, started) = bounded(1);
+        let (dropped_tx, dropped) = bounded(1);
+        let op: BackgroundOp<()> = BackgroundOp::spawn(async move {
+            let _dropped = Dropped(dropped_tx);
+            started_tx.send(()).await.ok();
+            smol::future::pending().await
+        });
+        smol::block_on(started.recv()).unwrap();
+        assert!(op.try_result().is_none());
+
+        drop(op);
+        smol::block_on(dropped.recv()).unwrap();
+    }
+
+    #[test]
+    fn should_recognize_cancel_keys() {
+        let key = |key, modifiers| InputEvent::Key(KeyEvent { key, modifiers });
+        assert!(is_cancel_key(&key(KeyCode::Escape, Modifiers::NONE)));
+        assert!(is_cancel_key(&key(KeyCode::Char('C'), Modifiers::CTRL)));
+        assert!(is_cancel_key(&key(KeyCode::Char('G'), Modifiers::CTRL)));
+        assert!(!is_cancel_key(&key(KeyCode::Char('c'), Modifiers::NONE)));
+        assert!(!is_cancel_key(&key(KeyCode::Enter, Modifiers::NONE)));
+    }
+
+    #[test]
+    fn should_sort_directories_first() {
+        let mut entries = vec![
+            entry("b.txt", FileType::File),
+            entry("src", FileType::Dir),
+            entry("A.txt", FileType::File),
+            entry("Docs", FileType::Dir),
+        ];
+        sort_entries(&mut entries);
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["Docs", "src", "A.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn should_format_sizes() {
+        assert_eq!(human_size(512), "512B");
+        assert_eq!(human_size(1536), "1.5K");
+        assert_eq!(human_size(5 * 1024 * 1024), "5.0M");
+    }
+}
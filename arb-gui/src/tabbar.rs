@@ -7,6 +7,7 @@ use termwiz::color::{AnsiColor, ColorSpec};
 use termwiz::escape::csi::Sgr;
 use termwiz::escape::parser::Parser;
 use termwiz::escape::{Action, ControlCode, CSI};
+use termwiz::nerdfonts::NERD_FONTS;
 use termwiz::surface::SEQ_ZERO;
 use termwiz_funcs::{format_as_escapes, FormatColor, FormatItem};
 use wezterm_term::{Line, Progress};
@@ -170,6 +171,18 @@ fn compute_tab_title(
                     title = format!("{}{classic_spacing}", title);
                 }
 
+                if pane.is_reconnecting {
+                    if let Some(glyph) = NERD_FONTS.get("md_lan_pending") {
+                        let graphic = format!("{glyph} ");
+                        len += unicode_column_width(&graphic, None);
+                        items.push(FormatItem::Foreground(FormatColor::AnsiColor(
+                            AnsiColor::Yellow,
+                        )));
+                        items.push(FormatItem::Text(graphic));
+                        items.push(FormatItem::Foreground(FormatColor::Default));
+                    }
+                }
+
                 match pane.progress {
                     Progress::None => {}
                     Progress::Percentage(pct) | Progress::Error(pct) => {
@@ -1,11 +1,27 @@
+use crate::overlay::{confirm, start_overlay_pane};
 use crate::termwindow::TermWindowNotif;
 use crate::TermWindow;
 use config::keyassignment::{ClipboardCopyDestination, ClipboardPasteSource};
-use mux::pane::Pane;
+use config::ClipboardReadPolicy;
+use mux::pane::{Pane, PaneId};
 use mux::Mux;
+use std::io::Write;
 use std::sync::Arc;
+use wezterm_term::ClipboardSelection;
 use window::{Clipboard, WindowOps};
 
+/// Sends the OSC 52 reply for `selection` to the application in the pane
+fn reply_to_clipboard_query(pane_id: PaneId, selection: ClipboardSelection, text: &str) {
+    let pane = match Mux::get().get_pane(pane_id) {
+        Some(pane) => pane,
+        None => return,
+    };
+    let response = selection.osc52_response(text);
+    if let Err(err) = pane.writer().write_all(response.as_bytes()) {
+        log::error!("replying to OSC 52 query in pane {pane_id}: {err:#}");
+    }
+}
+
 impl TermWindow {
     pub fn copy_to_clipboard(&self, clipboard: ClipboardCopyDestination, text: String) {
         let clipboard = match clipboard {
@@ -57,4 +73,88 @@ impl TermWindow {
         .detach();
         self.maybe_scroll_to_bottom_for_input(pane);
     }
+
+    /// Responds to an OSC 52 query made by the application in `pane_id`,
+    /// according to the clipboard_read_policy of its domain.
+    /// A refused query is answered with an empty clipboard, so that
+    /// the application doesn't wait for a reply that never comes.
+    pub fn query_clipboard(&mut self, pane_id: PaneId, selection: ClipboardSelection) {
+        let mux = Mux::get();
+        let pane = match mux.get_pane(pane_id) {
+            Some(pane) => pane,
+            None => return,
+        };
+        let domain_name = mux
+            .get_domain(pane.domain_id())
+            .map(|domain| domain.domain_name().to_string())
+            .unwrap_or_default();
+
+        match self.config.clipboard_read_policy_for_domain(&domain_name) {
+            ClipboardReadPolicy::Allow => self.send_clipboard_contents(pane_id, selection),
+            ClipboardReadPolicy::Deny => {
+                log::info!(
+                    "denied OSC 52 clipboard query in pane {pane_id} \
+                     as clipboard_read_policy=Deny for domain {domain_name}"
+                );
+                reply_to_clipboard_query(pane_id, selection, "");
+            }
+            ClipboardReadPolicy::Ask => {
+                if self.pane_state(pane_id).overlay.is_some() {
+                    // Either a previous query is still awaiting an
+                    // answer, or the user is busy with another overlay
+                    reply_to_clipboard_query(pane_id, selection, "");
+                    return;
+                }
+
+                let window = self.window.clone().unwrap();
+                let message = format!(
+                    "🔒 The program running in pane {pane_id} ({domain_name}) \
+                     wants to read the clipboard. Allow it?"
+                );
+                let (overlay, future) =
+                    start_overlay_pane(self, &pane, move |pane_id, mut term| {
+                        let allow = confirm::run_confirmation(&message, &mut term)?;
+                        window.notify(TermWindowNotif::Apply(Box::new(move |myself| {
+                            if allow {
+                                myself.send_clipboard_contents(pane_id, selection);
+                            } else {
+                                reply_to_clipboard_query(pane_id, selection, "");
+                            }
+                        })));
+                        TermWindow::schedule_cancel_overlay_for_pane(window, pane_id);
+                        Ok(())
+                    });
+                self.assign_overlay_for_pane(pane_id, overlay);
+                promise::spawn::spawn(future).detach();
+            }
+        }
+    }
+
+    fn send_clipboard_contents(&self, pane_id: PaneId, selection: ClipboardSelection) {
+        let clipboard = match selection {
+            ClipboardSelection::Clipboard => Clipboard::Clipboard,
+            ClipboardSelection::PrimarySelection => Clipboard::PrimarySelection,
+        };
+        let max_bytes = self.config.clipboard_read_max_bytes;
+        let future = self.window.as_ref().unwrap().get_clipboard(clipboard);
+        promise::spawn::spawn(async move {
+            let text = match future.await {
+                Ok(text) if text.len() <= max_bytes => text,
+                Ok(text) => {
+                    log::warn!(
+                        "refused OSC 52 clipboard query in pane {pane_id}: the clipboard \
+                         holds {} bytes, more than clipboard_read_max_bytes={max_bytes}",
+                        text.len()
+                    );
+                    String::new()
+                }
+                Err(err) => {
+                    log::error!("reading clipboard for OSC 52 query: {err:#}");
+                    String::new()
+                }
+            };
+            reply_to_clipboard_query(pane_id, selection, &text);
+        })
+        .detach();
+    }
 }
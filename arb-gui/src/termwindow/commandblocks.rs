@@ -0,0 +1,238 @@
+//! Command blocks group a prompt, the command entered at it and that
+//! command's output, as delimited by the OSC 133 markers emitted by
+//! shell integration.  This module implements the actions that operate
+//! on them and the folding of their output.
+//!
+//! Command blocks are tracked by the terminal model, which for panes
+//! in a multiplexer domain lives in the mux server; the client doesn't
+//! receive them, so these actions do nothing in such panes.
+use crate::TermWindow;
+use config::keyassignment::ClipboardCopyDestination;
+use mux::pane::{Pane, PaneId};
+use std::ops::Range;
+use std::sync::Arc;
+use termwiz::surface::SequenceNo;
+use wezterm_term::{CommandBlock, StableRowIndex};
+
+#[derive(Clone, Default)]
+pub struct CommandBlockCache {
+    seqno: SequenceNo,
+    blocks: Vec<CommandBlock>,
+}
+
+/// Splits the sorted `rows` into ranges of consecutive rows
+pub fn consecutive_row_ranges(rows: &[StableRowIndex]) -> Vec<Range<StableRowIndex>> {
+    let mut ranges: Vec<Range<StableRowIndex>> = vec![];
+    for &row in rows {
+        match ranges.last_mut() {
+            Some(range) if range.end == row => range.end = row + 1,
+            _ => ranges.push(row..row + 1),
+        }
+    }
+    ranges
+}
+
+impl TermWindow {
+    /// Returns the command blocks of the pane, oldest first
+    pub fn get_command_blocks(&mut self, pane: &Arc<dyn Pane>) -> &[CommandBlock] {
+        let cache = self.command_blocks.entry(pane.pane_id()).or_default();
+
+        let seqno = pane.get_current_seqno();
+        if cache.seqno != seqno {
+            cache.blocks = pane.get_command_blocks();
+            cache.seqno = seqno;
+        }
+        &cache.blocks
+    }
+
+    /// The block that actions apply to: the one at the top of the
+    /// viewport when scrolled back, otherwise the most recently
+    /// finished one
+    fn target_command_block(&mut self, pane: &Arc<dyn Pane>) -> Option<CommandBlock> {
+        let viewport = self.get_viewport(pane.pane_id());
+        let blocks = self.get_command_blocks(pane);
+        match viewport {
+            Some(top) => blocks
+                .iter()
+                .find(|block| block.is_finished() && block.contains_row(top)),
+            None => blocks.iter().rev().find(|block| block.is_finished()),
+        }
+        .cloned()
+    }
+
+    pub fn copy_command_block_output(
+        &mut self,
+        pane: &Arc<dyn Pane>,
+        dest: ClipboardCopyDestination,
+    ) {
+        let rows = match self
+            .target_command_block(pane)
+            .and_then(|block| block.output_rows())
+        {
+            Some(rows) => rows,
+            None => return,
+        };
+
+        let (_, lines) = pane.get_lines(rows);
+        let mut text = String::new();
+        let mut last_was_wrapped = false;
+        for line in lines {
+            if !text.is_empty() && !last_was_wrapped {
+                text.push('\n');
+            }
+            last_was_wrapped = line.last_cell_was_wrapped();
+            if last_was_wrapped {
+                text.push_str(&line.as_str());
+            } else {
+                text.push_str(line.as_str().trim_end());
+            }
+        }
+        self.copy_to_clipboard(dest, text);
+    }
+
+    pub fn toggle_command_block_fold(&mut self, pane: &Arc<dyn Pane>) {
+        let block = match self.target_command_block(pane) {
+            Some(block) if block.output_rows().is_some() => block,
+            _ => return,
+        };
+        let starts: Vec<StableRowIndex> = self
+            .get_command_blocks(pane)
+            .iter()
+            .map(|block| block.start_row)
+            .collect();
+
+        let folding = {
+            let mut state = self.pane_state(pane.pane_id());
+            let folded = &mut state.folded_command_blocks;
+            // Forget blocks that have since scrolled out of the scrollback
+            folded.retain(|row| starts.contains(row));
+            if folded.remove(&block.start_row) {
+                false
+            } else {
+                folded.insert(block.start_row);
+                true
+            }
+        };
+
+        if folding && self.get_viewport(pane.pane_id()).is_some() {
+            // Keep the folded block in view rather than whatever
+            // followed its output
+            self.set_viewport(pane.pane_id(), Some(block.start_row), pane.get_dimensions());
+        }
+        if let Some(win) = self.window.as_ref() {
+            win.invalidate();
+        }
+    }
+
+    pub fn scroll_to_failed_command(
+        &mut self,
+        amount: isize,
+        pane: &Arc<dyn Pane>,
+    ) -> anyhow::Result<()> {
+        if pane.is_primary_peek() {
+            pane.set_primary_peek(false);
+        }
+        let dims = pane.get_dimensions();
+        let position = self
+            .get_viewport(pane.pane_id())
+            .unwrap_or(dims.physical_top);
+        let row = {
+            let failed: Vec<StableRowIndex> = self
+                .get_command_blocks(pane)
+                .iter()
+                .filter(|block| block.failed())
+                .map(|block| block.start_row)
+                .collect();
+            let idx = match failed.binary_search(&position) {
+                Ok(idx) | Err(idx) => idx,
+            };
+            let idx = ((idx as isize) + amount).max(0) as usize;
+            failed.get(idx).cloned()
+        };
+        if let Some(row) = row {
+            self.set_viewport(pane.pane_id(), Some(row), dims);
+        }
+
+        if let Some(win) = self.window.as_ref() {
+            win.invalidate();
+        }
+        Ok(())
+    }
+
+    /// Returns the output rows of the folded command blocks of the pane
+    fn folded_rows(&mut self, pane: &Arc<dyn Pane>) -> Vec<Range<StableRowIndex>> {
+        let folded = self
+            .pane_state(pane.pane_id())
+            .folded_command_blocks
+            .clone();
+        if folded.is_empty() {
+            return vec![];
+        }
+        self.get_command_blocks(pane)
+            .iter()
+            .filter(|block| folded.contains(&block.start_row))
+            .filter_map(|block| block.output_rows())
+            .collect()
+    }
+
+    /// Returns the stable row shown in each row of the viewport,
+    /// skipping the output of folded command blocks, or None if
+    /// nothing is folded and the rows are contiguous.
+    pub fn displayed_rows(&mut self, pane: &Arc<dyn Pane>) -> Option<Vec<StableRowIndex>> {
+        let hidden = self.folded_rows(pane);
+        if hidden.is_empty() {
+            return None;
+        }
+        let is_hidden = |row: StableRowIndex| hidden.iter().any(|range| range.contains(&row));
+
+        let dims = pane.get_dimensions();
+        let rows = dims.viewport_rows;
+        let bottom = dims.physical_top + rows as StableRowIndex;
+        let mut displayed = Vec::with_capacity(rows);
+        match self.get_viewport(pane.pane_id()) {
+            Some(top) => {
+                let mut row = top;
+                while displayed.len() < rows && row < bottom {
+                    if !is_hidden(row) {
+                        displayed.push(row);
+                    }
+                    row += 1;
+                }
+            }
+            None => {
+                let mut row = bottom - 1;
+                while displayed.len() < rows && row >= dims.scrollback_top {
+                    if !is_hidden(row) {
+                        displayed.push(row);
+                    }
+                    row -= 1;
+                }
+                displayed.reverse();
+            }
+        }
+        Some(displayed)
+    }
+
+    /// Returns the stable row that is shown in `row` of the viewport
+    pub fn stable_row_for_viewport_row(
+        &mut self,
+        pane: &Arc<dyn Pane>,
+        row: StableRowIndex,
+    ) -> StableRowIndex {
+        if let Some(displayed) = self.displayed_rows(pane) {
+            if let Some(stable_row) = usize::try_from(row).ok().and_then(|row| displayed.get(row)) {
+                return *stable_row;
+            }
+        }
+        let dims = pane.get_dimensions();
+        self.get_viewport(pane.pane_id())
+            .unwrap_or(dims.physical_top)
+            + row
+    }
+
+    pub fn is_command_block_folded(&self, pane_id: PaneId, block: &CommandBlock) -> bool {
+        self.pane_state(pane_id)
+            .folded_command_blocks
+            .contains(&block.start_row)
+    }
+}
@@ -54,7 +54,7 @@ use mux_lua::MuxPane;
 use smol::channel::Sender;
 use smol::Timer;
 use std::cell::{RefCell, RefMut};
-use std::collections::{HashMap, LinkedList};
+use std::collections::{HashMap, HashSet, LinkedList};
 use std::ops::Add;
 use std::path::PathBuf;
 use std::rc::Rc;
@@ -74,6 +74,7 @@ pub mod background;
 pub mod box_model;
 pub mod charselect;
 pub mod clipboard;
+pub mod commandblocks;
 pub mod keyevent;
 pub mod modal;
 mod mouseevent;
@@ -316,6 +317,8 @@ pub struct PaneState {
 
     bell_start: Option<Instant>,
     pub mouse_terminal_coords: Option<(ClickPosition, StableRowIndex)>,
+    /// The start rows of the command blocks whose output is folded
+    folded_command_blocks: HashSet<StableRowIndex>,
 }
 
 /// Data used when synchronously formatting pane and window titles
@@ -384,6 +387,9 @@ pub struct PaneInformation {
     pub title: String,
     pub user_vars: HashMap<String, String>,
     pub progress: Progress,
+    /// true if the connection to the server hosting the
+    /// pane was lost and is being reestablished
+    pub is_reconnecting: bool,
 }
 
 impl UserData for PaneInformation {
@@ -400,6 +406,7 @@ impl UserData for PaneInformation {
         fields.add_field_method_get("pixel_width", |_, this| Ok(this.pixel_width));
         fields.add_field_method_get("pixel_height", |_, this| Ok(this.pixel_height));
         fields.add_field_method_get("progress", |lua, this| lua.to_value(&this.progress));
+        fields.add_field_method_get("is_reconnecting", |_, this| Ok(this.is_reconnecting));
         fields.add_field_method_get("title", |_, this| Ok(this.title.clone()));
         fields.add_field_method_get("user_vars", |_, this| Ok(this.user_vars.clone()));
         fields.add_field_method_get("foreground_process_name", |_, this| {
@@ -524,6 +531,7 @@ pub struct TermWindow {
     tab_state: RefCell<HashMap<TabId, TabState>>,
     pane_state: RefCell<HashMap<PaneId, PaneState>>,
     semantic_zones: HashMap<PaneId, SemanticZoneCache>,
+    command_blocks: HashMap<PaneId, commandblocks::CommandBlockCache>,
 
     window_background: Vec<LoadedBackgroundLayer>,
 
@@ -928,6 +936,7 @@ impl TermWindow {
             scheduled_animation: RefCell::new(None),
             allow_images: AllowImage::Yes,
             semantic_zones: HashMap::new(),
+            command_blocks: HashMap::new(),
             ui_items: vec![],
             dragging: None,
             split_drag_state: None,
@@ -1199,6 +1208,13 @@ impl TermWindow {
                 Ok(true)
             }
             WindowEvent::DroppedFile(paths) => {
+                // Files dropped onto a pane showing the sftp browser
+                // are uploaded rather than pasted
+                if let Some(pane) = self.get_active_pane_no_overlay() {
+                    if crate::overlay::sftp::accept_dropped_files(pane.pane_id(), &paths) {
+                        return Ok(true);
+                    }
+                }
                 let pane = match self.get_active_pane_or_overlay() {
                     Some(pane) => pane,
                     None => return Ok(true),
@@ -1418,7 +1434,7 @@ impl TermWindow {
                     window.invalidate();
                 }
                 MuxNotification::Alert {
-                    alert: Alert::ToastNotification { .. },
+                    alert: Alert::ToastNotification { .. } | Alert::CommandFinished(_),
                     ..
                 } => {}
                 MuxNotification::TabAddedToWindow {
@@ -1465,6 +1481,12 @@ impl TermWindow {
                 MuxNotification::AssignClipboard { .. } => {
                     // Handled by frontend
                 }
+                MuxNotification::QueryClipboard { pane_id, selection } => {
+                    if !self.window_contains_pane(pane_id) {
+                        return Ok(());
+                    }
+                    self.query_clipboard(pane_id, selection);
+                }
                 MuxNotification::SaveToDownloads { .. } => {
                     // Handled by frontend
                 }
@@ -1635,6 +1657,7 @@ impl TermWindow {
                     | Alert::SetUserVar { .. }
                     | Alert::Bell,
             }
+            | MuxNotification::QueryClipboard { pane_id, .. }
             | MuxNotification::PaneFocused(pane_id)
             | MuxNotification::PaneRemoved(pane_id)
             | MuxNotification::PaneOutput(pane_id) => {
@@ -1687,7 +1710,7 @@ impl TermWindow {
                 }
             }
             MuxNotification::Alert {
-                alert: Alert::ToastNotification { .. },
+                alert: Alert::ToastNotification { .. } | Alert::CommandFinished(_),
                 ..
             }
             | MuxNotification::AssignClipboard { .. }
@@ -2502,6 +2525,49 @@ impl TermWindow {
         promise::spawn::spawn(future).detach();
     }
 
+    fn show_command_history(&mut self, pane: &Arc<dyn Pane>) {
+        let (overlay, future) = start_overlay_pane(self, pane, move |pane_id, term| {
+            crate::overlay::history::history(term, pane_id)
+        });
+        self.assign_overlay_for_pane(pane.pane_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
+    fn show_sftp_browser(&mut self, pane: &Arc<dyn Pane>) -> anyhow::Result<()> {
+        let mux = Mux::get();
+        let domain = mux
+            .get_domain(pane.domain_id())
+            .ok_or_else(|| anyhow!("pane {} has no domain", pane.pane_id()))?;
+        let ssh_domain = domain
+            .downcast_ref::<mux::ssh::RemoteSshDomain>()
+            .ok_or_else(|| anyhow!("{} is not an ssh domain", domain.domain_name()))?;
+        let sftp = ssh_domain
+            .sftp()
+            .ok_or_else(|| anyhow!("{} is not connected", domain.domain_name()))?;
+        let domain_name = domain.domain_name().to_string();
+        let cwd = pane
+            .get_current_working_dir(CachePolicy::AllowStale)
+            .and_then(|url| mux::session::url_to_path(&url));
+
+        let (overlay, future) = start_overlay_pane(self, pane, move |pane_id, term| {
+            crate::overlay::sftp::sftp_browser(term, pane_id, sftp, domain_name, cwd)
+        });
+        self.assign_overlay_for_pane(pane.pane_id(), overlay);
+        promise::spawn::spawn(future).detach();
+        Ok(())
+    }
+
+    fn toggle_ssh_forward(domain: &str, forward: &str) -> anyhow::Result<()> {
+        let mux = Mux::get();
+        let domain = mux
+            .get_domain_by_name(domain)
+            .ok_or_else(|| anyhow!("{} is not a valid domain name", domain))?;
+        let ssh_domain = domain
+            .downcast_ref::<mux::ssh::RemoteSshDomain>()
+            .ok_or_else(|| anyhow!("{} is not an ssh domain", domain.domain_name()))?;
+        ssh_domain.toggle_forward(&forward.parse()?)
+    }
+
     fn show_prompt_input_line(&mut self, args: &PromptInputLine) {
         let mux = Mux::get();
         let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
@@ -3005,6 +3071,7 @@ impl TermWindow {
             ScrollByLine(n) => self.scroll_by_line(*n, pane)?,
             ScrollByCurrentEventWheelDelta => self.scroll_by_current_event_wheel_delta(pane)?,
             ScrollToPrompt(n) => self.scroll_to_prompt(*n, pane)?,
+            ScrollToFailedCommand(n) => self.scroll_to_failed_command(*n, pane)?,
             ScrollToTop => self.scroll_to_top(pane),
             ScrollToBottom => self.scroll_to_bottom(pane),
             ShowTabNavigator => self.show_tab_navigator(),
@@ -3396,6 +3463,68 @@ impl TermWindow {
             PromptInputLine(args) => self.show_prompt_input_line(args),
             InputSelector(args) => self.show_input_selector(args),
             Confirmation(args) => self.show_confirmation(args),
+            AddAsciicastMarker(label) => {
+                let pane = Arc::clone(pane);
+                let label = label.clone();
+                promise::spawn::spawn(async move {
+                    let result = match pane.add_recording_marker(&label).await {
+                        Ok(true) => Ok(()),
+                        // The pane isn't being recorded by the mux, so ask any
+                        // `arb record` running in it to add the marker instead
+                        Ok(false) => {
+                            let marker = config::keyassignment::asciicast_marker_sequence(&label);
+                            pane.writer()
+                                .write_all(marker.as_bytes())
+                                .map_err(anyhow::Error::from)
+                        }
+                        Err(err) => Err(err),
+                    };
+                    if let Err(err) = result {
+                        wezterm_toast_notification::persistent_toast_notification(
+                            "Arb",
+                            &format!("Unable to add marker: {err:#}"),
+                        );
+                    }
+                })
+                .detach();
+            }
+            TogglePaneRecording => {
+                let pane = Arc::clone(pane);
+                promise::spawn::spawn(async move {
+                    let message = match pane.toggle_recording(Default::default()).await {
+                        Ok((path, true)) => format!("Recording pane to {}", path.display()),
+                        Ok((path, false)) => format!("Saved recording to {}", path.display()),
+                        Err(err) => format!("Unable to record pane: {err:#}"),
+                    };
+                    wezterm_toast_notification::persistent_toast_notification("Arb", &message);
+                })
+                .detach();
+            }
+            CopyCommandBlockOutput(dest) => self.copy_command_block_output(pane, *dest),
+            ToggleCommandBlockFold => self.toggle_command_block_fold(pane),
+            ShowCommandHistory => self.show_command_history(pane),
+            ShowSftpBrowser => {
+                if let Err(err) = self.show_sftp_browser(pane) {
+                    wezterm_toast_notification::persistent_toast_notification(
+                        "Arb",
+                        &format!("Unable to browse remote files: {err:#}"),
+                    );
+                }
+            }
+            ToggleSshForward { domain, forward } => {
+                let domain = domain.to_string();
+                let forward = forward.to_string();
+                // Starting a remote forward waits for the server to respond,
+                // so keep that off the gui thread
+                std::thread::spawn(move || {
+                    if let Err(err) = Self::toggle_ssh_forward(&domain, &forward) {
+                        wezterm_toast_notification::persistent_toast_notification(
+                            "Arb",
+                            &format!("{err:#}"),
+                        );
+                    }
+                });
+            }
         };
         Ok(PerformAssignmentResult::Handled)
     }
@@ -3687,6 +3816,13 @@ impl TermWindow {
             title: pos.pane.get_title(),
             user_vars: pos.pane.copy_user_vars(),
             progress: pos.pane.get_progress(),
+            is_reconnecting: match pos.pane.get_metadata() {
+                Value::Object(obj) => matches!(
+                    obj.get(&Value::String("is_reconnecting".to_string())),
+                    Some(Value::Bool(true))
+                ),
+                _ => false,
+            },
         }
     }
 
@@ -941,11 +941,7 @@ impl super::TermWindow {
             event
         );
 
-        let dims = pane.get_dimensions();
-        let stable_row = self
-            .get_viewport(pane.pane_id())
-            .unwrap_or(dims.physical_top)
-            + row as StableRowIndex;
+        let stable_row = self.stable_row_for_viewport_row(&pane, row as StableRowIndex);
 
         self.pane_state(pane.pane_id())
             .mouse_terminal_coords
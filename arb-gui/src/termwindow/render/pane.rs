@@ -14,10 +14,11 @@ use mux::pane::{PaneId, WithPaneLines};
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
 use mux::tab::PositionedPane;
 use ordered_float::NotNan;
+use std::ops::Range;
 use std::time::Instant;
 use wezterm_dynamic::Value;
 use wezterm_term::color::{ColorAttribute, ColorPalette};
-use wezterm_term::{Line, StableRowIndex};
+use wezterm_term::{CommandBlock, Line, StableRowIndex};
 use window::color::LinearRgba;
 
 impl crate::TermWindow {
@@ -339,13 +340,30 @@ impl crate::TermWindow {
             palette.cursor_fg == global_cursor_fg && palette.cursor_bg == global_cursor_bg;
 
         {
-            let stable_range = match current_viewport {
-                Some(top) => top..top + dims.viewport_rows as StableRowIndex,
-                None => dims.physical_top..dims.physical_top + dims.viewport_rows as StableRowIndex,
+            // When command blocks are folded, the viewport shows a subset
+            // of the rows in the stable range
+            let displayed_rows = self.displayed_rows(&pos.pane);
+            let stable_range = match (&displayed_rows, current_viewport) {
+                (Some(rows), _) if !rows.is_empty() => rows[0]..rows[rows.len() - 1] + 1,
+                (_, Some(top)) => top..top + dims.viewport_rows as StableRowIndex,
+                (_, None) => {
+                    dims.physical_top..dims.physical_top + dims.viewport_rows as StableRowIndex
+                }
             };
 
-            pos.pane
-                .apply_hyperlinks(stable_range.clone(), &self.config.hyperlink_rules);
+            // Only fetch the rows that are displayed, rather than every
+            // row of the folded blocks that lie between them
+            let row_ranges = match &displayed_rows {
+                Some(rows) if !rows.is_empty() => {
+                    crate::termwindow::commandblocks::consecutive_row_ranges(rows)
+                }
+                _ => vec![stable_range.clone()],
+            };
+
+            for range in &row_ranges {
+                pos.pane
+                    .apply_hyperlinks(range.clone(), &self.config.hyperlink_rules);
+            }
 
             struct LineRender<'a, 'b> {
                 term_window: &'a mut crate::TermWindow,
@@ -357,6 +375,7 @@ impl crate::TermWindow {
                 content_pixel_width: f32,
                 pos: &'a PositionedPane,
                 pane_id: PaneId,
+                displayed_rows: Option<&'a [StableRowIndex]>,
                 cursor: &'a StableCursorPosition,
                 palette: &'a ColorPalette,
                 default_bg: LinearRgba,
@@ -395,6 +414,7 @@ impl crate::TermWindow {
                 content_pixel_width,
                 pos,
                 pane_id,
+                displayed_rows: displayed_rows.as_deref(),
                 cursor: &cursor,
                 palette: &palette,
                 cursor_border_color,
@@ -415,11 +435,10 @@ impl crate::TermWindow {
             impl<'a, 'b> LineRender<'a, 'b> {
                 fn render_line(
                     &mut self,
-                    stable_top: StableRowIndex,
+                    stable_row: StableRowIndex,
                     line_idx: usize,
                     line: &&mut Line,
                 ) -> anyhow::Result<()> {
-                    let stable_row = stable_top + line_idx as StableRowIndex;
                     let selrange = self
                         .selrange
                         .map_or(0..0, |sel| sel.cols_for_row(stable_row, self.rectangular));
@@ -600,8 +619,17 @@ impl crate::TermWindow {
 
             impl<'a, 'b> WithPaneLines for LineRender<'a, 'b> {
                 fn with_lines_mut(&mut self, stable_top: StableRowIndex, lines: &mut [&mut Line]) {
-                    for (line_idx, line) in lines.iter().enumerate() {
-                        if let Err(err) = self.render_line(stable_top, line_idx, line) {
+                    for (idx, line) in lines.iter().enumerate() {
+                        let stable_row = stable_top + idx as StableRowIndex;
+                        let line_idx = match self.displayed_rows {
+                            Some(rows) => match rows.binary_search(&stable_row) {
+                                Ok(line_idx) => line_idx,
+                                // Part of a folded command block
+                                Err(_) => continue,
+                            },
+                            None => idx,
+                        };
+                        if let Err(err) = self.render_line(stable_row, line_idx, line) {
                             self.error.replace(err);
                             return;
                         }
@@ -609,9 +637,24 @@ impl crate::TermWindow {
                 }
             }
 
-            pos.pane.with_lines_mut(stable_range.clone(), &mut render);
-            if let Some(error) = render.error.take() {
-                return Err(error).context("error while calling with_lines_mut");
+            for range in row_ranges {
+                pos.pane.with_lines_mut(range, &mut render);
+                if let Some(error) = render.error.take() {
+                    return Err(error).context("error while calling with_lines_mut");
+                }
+            }
+
+            if config.show_command_block_markers {
+                self.paint_command_block_markers(
+                    pos,
+                    layers,
+                    stable_range,
+                    displayed_rows.as_deref(),
+                    top_pixel_y,
+                    left_pixel_x,
+                    &palette,
+                )
+                .context("paint_command_block_markers")?;
             }
         }
 
@@ -626,6 +669,90 @@ impl crate::TermWindow {
         Ok(())
     }
 
+    /// Draws a marker to the left of each finished command block, colored
+    /// by its exit status, and a rule beneath blocks whose output is folded
+    #[allow(clippy::too_many_arguments)]
+    fn paint_command_block_markers(
+        &mut self,
+        pos: &PositionedPane,
+        layers: &mut TripleLayerQuadAllocator,
+        stable_range: Range<StableRowIndex>,
+        displayed_rows: Option<&[StableRowIndex]>,
+        top_pixel_y: f32,
+        left_pixel_x: f32,
+        palette: &ColorPalette,
+    ) -> anyhow::Result<()> {
+        let blocks: Vec<CommandBlock> = self
+            .get_command_blocks(&pos.pane)
+            .iter()
+            .filter(|block| {
+                block.start_row < stable_range.end
+                    && matches!(block.end_row, Some(end) if end >= stable_range.start)
+                    && block.exit_status.is_some()
+            })
+            .cloned()
+            .collect();
+        if blocks.is_empty() {
+            return Ok(());
+        }
+
+        let display_idx = |row: StableRowIndex| match displayed_rows {
+            Some(rows) => rows.binary_search(&row).ok(),
+            None if stable_range.contains(&row) => Some((row - stable_range.start) as usize),
+            None => None,
+        };
+
+        let cell_width = self.render_metrics.cell_size.width as f32;
+        let cell_height = self.render_metrics.cell_size.height as f32;
+        let marker_width = (cell_width / 4.).max(2.);
+        let marker_x = (left_pixel_x - marker_width).max(0.);
+
+        for block in blocks {
+            let color = if block.failed() {
+                palette.colors.0[1]
+            } else {
+                palette.colors.0[2]
+            }
+            .to_linear();
+
+            let end = block.end_row.unwrap_or(block.start_row);
+            let rows = block.start_row.max(stable_range.start)..=end.min(stable_range.end - 1);
+            let mut shown = rows.filter_map(&display_idx);
+            let first = match shown.next() {
+                Some(first) => first,
+                None => continue,
+            };
+            let last = shown.last().unwrap_or(first);
+
+            self.filled_rectangle(
+                layers,
+                2,
+                euclid::rect(
+                    marker_x,
+                    top_pixel_y + (first + pos.top) as f32 * cell_height,
+                    marker_width,
+                    (last + 1 - first) as f32 * cell_height,
+                ),
+                color,
+            )?;
+
+            if self.is_command_block_folded(pos.pane.pane_id(), &block) {
+                self.filled_rectangle(
+                    layers,
+                    2,
+                    euclid::rect(
+                        left_pixel_x,
+                        top_pixel_y + (last + 1 + pos.top) as f32 * cell_height - 1.,
+                        pos.width as f32 * cell_width,
+                        1.,
+                    ),
+                    color,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn build_pane(&mut self, pos: &PositionedPane) -> anyhow::Result<ComputedElement> {
         // First compute the bounds for the pane background
 
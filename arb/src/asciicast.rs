@@ -1,14 +1,10 @@
 use anyhow::Context;
-use chrono::serde::ts_seconds_option;
-use chrono::{DateTime, Utc};
 use clap::Parser;
 use config::ConfigHandle;
 use filedescriptor::FileDescriptor;
 use portable_pty::{native_pty_system, PtySize};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
-use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::PathBuf;
 use std::sync::mpsc::channel;
 use std::time::{Duration, Instant};
@@ -16,116 +12,22 @@ use termwiz::escape::parser::Parser as TWParser;
 use termwiz::escape::Action;
 #[cfg(unix)]
 use unix::UnixTty as Tty;
-use wezterm_term::color::ColorPalette;
 #[cfg(windows)]
 use win::WinTty as Tty;
 
-/// See <https://github.com/asciinema/asciinema/blob/develop/doc/asciicast-v2.md>
-/// for file format specification
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
-pub struct Header {
-    /// Must be 2 or higher
-    pub version: u32,
-    /// Initial terminal width (number of columns)
-    pub width: u32,
-    /// Initial terminal height (number of columns)
-    pub height: u32,
-    /// Unix timestamp of starting time of session
-    #[serde(
-        default,
-        with = "ts_seconds_option",
-        skip_serializing_if = "Option::is_none"
-    )]
-    pub timestamp: Option<DateTime<Utc>>,
-    /// Duration of the whole recording in seconds
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub duration: Option<f32>,
-    /// Used to reduce terminal inactivity (delays between frames)
-    /// to a maximum of this amount.
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub idle_time_limit: Option<f32>,
-    /// Command that was recorded
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub command: Option<String>,
-    /// Title of the asciicast
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub title: Option<String>,
-    /// Map of captured environment variables
-    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
-    pub env: HashMap<String, String>,
-    /// Color theme of the recorded terminal
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub theme: Option<Theme>,
-}
-
-impl Header {
-    fn new(config: &ConfigHandle, size: PtySize, prog: &[&OsStr]) -> Self {
-        let mut env = HashMap::new();
-        env.insert("TERM".to_string(), config.term.to_string());
-        env.insert("ARB_VERSION".to_string(), config::arb_version().to_string());
-        env.insert(
-            "ARB_TARGET_TRIPLE".to_string(),
-            config::arb_target_triple().to_string(),
-        );
-        if let Ok(shell) = std::env::var("SHELL") {
-            env.insert("SHELL".to_string(), shell);
-        }
-        if let Ok(lang) = std::env::var("LANG") {
-            env.insert("LANG".to_string(), lang);
-        }
+use mux::asciicast::extract_markers;
+pub use mux::asciicast::{CastReader, CastVersion, CastWriter, EventKind, Header};
 
-        let palette: ColorPalette = config.resolved_palette.clone().into();
-        let ansi_colors: Vec<String> = palette.colors.0[0..16]
+/// Formats the command line being recorded for the asciicast header
+fn command_line(prog: &[&OsStr]) -> Option<String> {
+    if prog.is_empty() {
+        None
+    } else {
+        let args: Vec<String> = prog
             .iter()
-            .map(|c| c.to_rgb_string())
+            .map(|s| s.to_string_lossy().to_string())
             .collect();
-
-        let theme = Theme {
-            fg: palette.foreground.to_rgb_string(),
-            bg: palette.background.to_rgb_string(),
-            palette: ansi_colors.join(":"),
-        };
-
-        let command = if prog.is_empty() {
-            None
-        } else {
-            let args: Vec<String> = prog
-                .iter()
-                .map(|s| s.to_string_lossy().to_string())
-                .collect();
-            Some(shell_words::join(&args))
-        };
-
-        Header {
-            version: 2,
-            height: size.rows.into(),
-            width: size.cols.into(),
-            timestamp: Some(Utc::now()),
-            env,
-            command,
-            theme: Some(theme),
-            ..Default::default()
-        }
-    }
-}
-
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
-pub struct Theme {
-    /// Normal text color
-    pub fg: String,
-    /// Normal background color
-    pub bg: String,
-    /// List of 8 or 16 colors separated by a colon character
-    pub palette: String,
-}
-
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
-pub struct Event(pub f32, pub String, pub String);
-
-impl Event {
-    fn log_output<W: Write>(mut w: W, elapsed: f32, output: &str) -> std::io::Result<()> {
-        let event = Event(elapsed, "o".to_string(), output.to_string());
-        writeln!(w, "{}", serde_json::to_string(&event)?)
+        Some(shell_words::join(&args))
     }
 }
 
@@ -322,6 +224,11 @@ mod unix {
     }
 }
 
+/// The most messages that `arb record` handles between checks of the
+/// size of the terminal, so that resizes are noticed during a flood
+/// of output
+const MAX_BATCH: usize = 64;
+
 #[derive(Debug)]
 enum Message {
     /// Input from the user
@@ -344,6 +251,11 @@ pub struct RecordCommand {
     #[arg(short)]
     outfile: Option<std::path::PathBuf>,
 
+    /// Which version of the asciicast format to write.
+    /// Version 3 additionally records the exit status of prog.
+    #[arg(long, default_value_t)]
+    format: CastVersion,
+
     /// Start prog instead of the default_prog defined by your
     /// wezterm configuration
     #[arg(value_parser)]
@@ -357,7 +269,12 @@ impl RecordCommand {
         let mut tty = Tty::new()?;
         let size = tty.get_size()?;
 
-        let header = Header::new(&config, size, &prog);
+        let header = Header::new(
+            &config,
+            size.cols.into(),
+            size.rows.into(),
+            command_line(&prog),
+        );
 
         let (cast_file, cast_file_name) = match self.outfile.as_ref() {
             Some(outfile) => (
@@ -377,8 +294,7 @@ impl RecordCommand {
                     .keep()?
             }
         };
-        let mut cast_file = BufWriter::new(cast_file);
-        writeln!(cast_file, "{}", serde_json::to_string(&header)?)?;
+        let mut cast_file = CastWriter::new(BufWriter::new(cast_file), &header, self.format)?;
 
         let pty_system = native_pty_system();
         let pair = pty_system.openpty(size)?;
@@ -443,46 +359,53 @@ impl RecordCommand {
 
         let mut child_status = None;
         let first_output = Instant::now();
-        let mut buffer = vec![];
         let mut writer = pair.master.take_writer()?;
 
-        for msg in rx {
-            match msg {
-                Message::Stdin(data) => {
-                    writer.write_all(&data)?;
+        let mut size = size;
+
+        'recording: while let Ok(msg) = rx.recv() {
+            // Follow size changes of the controlling terminal so that
+            // the child and the recording both see them.  The size is
+            // checked once for each batch of messages that are ready,
+            // rather than for every message.
+            if let Ok(new_size) = tty.get_size() {
+                if new_size != size {
+                    size = new_size;
+                    pair.master.resize(size)?;
+                    cast_file.resize(
+                        first_output.elapsed().as_secs_f32(),
+                        size.cols.into(),
+                        size.rows.into(),
+                    )?;
                 }
-                Message::Stdout(mut data) => {
-                    let elapsed = first_output.elapsed().as_secs_f32();
-                    tty.write_all(&data)?;
-
-                    // The end of the data may be an incomplete utf8 sequence
-                    // that straddles the buffer boundary.  JSON requires strings
-                    // to be utf-8 so we need to send the currently-valid portions
-                    // through to the .cast file and buffer up the remainder
-                    buffer.append(&mut data);
-                    match std::str::from_utf8(&buffer) {
-                        Ok(valid) => {
-                            Event::log_output(&mut cast_file, elapsed, valid)?;
-                            buffer.clear();
-                        }
-                        Err(error) => {
-                            let valid_len = error.valid_up_to();
-                            Event::log_output(&mut cast_file, elapsed, unsafe {
-                                std::str::from_utf8_unchecked(&buffer[0..valid_len])
-                            })?;
-
-                            buffer.drain(0..valid_len);
+            }
 
-                            if let Some(invalid_sequence_length) = error.error_len() {
-                                // Invalid sequence: skip it
-                                buffer.drain(0..invalid_sequence_length);
+            for msg in std::iter::once(msg).chain(rx.try_iter().take(MAX_BATCH - 1)) {
+                match msg {
+                    Message::Stdin(data) => {
+                        // The AddAsciicastMarker key assignment sends its
+                        // request through the input stream; turn those into
+                        // marker events rather than passing them to the child
+                        let (data, markers) = extract_markers(&data);
+                        if !markers.is_empty() {
+                            let elapsed = first_output.elapsed().as_secs_f32();
+                            for label in markers {
+                                cast_file.marker(elapsed, &label)?;
                             }
                         }
+                        writer.write_all(&data)?;
+                    }
+                    Message::Stdout(data) => {
+                        let elapsed = first_output.elapsed().as_secs_f32();
+                        tty.write_all(&data)?;
+                        cast_file.output_bytes(elapsed, &data)?;
+                    }
+                    Message::Terminated(status) => {
+                        let elapsed = first_output.elapsed().as_secs_f32();
+                        cast_file.exit(elapsed, status.exit_code())?;
+                        child_status.replace(status);
+                        break 'recording;
                     }
-                }
-                Message::Terminated(status) => {
-                    child_status.replace(status);
-                    break;
                 }
             }
         }
@@ -510,30 +433,57 @@ pub struct PlayCommand {
     #[arg(long, conflicts_with = "explain")]
     cat: bool,
 
+    /// Instead of replaying, render the recording into an animation.
+    /// The format is chosen by the extension: .gif, .png (animated PNG)
+    /// or .svg (animated SVG)
+    #[arg(long, value_name = "PATH", conflicts_with_all=&["explain", "explain_only", "cat"])]
+    export: Option<PathBuf>,
+
+    /// When exporting, the maximum number of frames per second
+    #[arg(long, default_value = "30", requires = "export")]
+    fps: f32,
+
+    /// When exporting, shorten pauses longer than this many seconds
+    #[arg(long, requires = "export")]
+    idle_time_limit: Option<f32>,
+
+    /// When exporting, use this font size in points instead of
+    /// the configured font_size
+    #[arg(long, requires = "export")]
+    font_size: Option<f64>,
+
     cast_file: PathBuf,
 }
 
 impl PlayCommand {
-    pub fn run(&self) -> anyhow::Result<()> {
-        let mut cast_file = BufReader::new(
+    pub fn run(&self, opts: &crate::Opt) -> anyhow::Result<()> {
+        if let Some(output) = &self.export {
+            return crate::cast_export::export(
+                &crate::init_config(opts)?,
+                &self.cast_file,
+                output,
+                &crate::cast_export::ExportOptions {
+                    fps: self.fps,
+                    idle_time_limit: self.idle_time_limit,
+                    font_size: self.font_size,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let cast_file = CastReader::new(BufReader::new(
             std::fs::File::open(&self.cast_file)
                 .with_context(|| format!("reading cast file {}", self.cast_file.display()))?,
-        );
-        let mut header_line = String::new();
-        cast_file
-            .read_line(&mut header_line)
-            .context("reading Header line")?;
-
-        let header: Header = serde_json::from_str(&header_line).context("parsing Header")?;
+        ))?;
+        let header = cast_file.header().clone();
 
         if self.cat {
-            for line in cast_file.lines() {
-                let line = line?;
-                let event: Event = serde_json::from_str(&line)?;
-                if event.1 != "o" {
+            for event in cast_file {
+                let event = event?;
+                if event.kind != EventKind::Output {
                     continue;
                 }
-                std::io::stdout().write_all(event.2.as_bytes())?;
+                std::io::stdout().write_all(event.data.as_bytes())?;
             }
 
             return Ok(());
@@ -542,28 +492,31 @@ impl PlayCommand {
         let (tx, rx) = channel();
         let mut sent_parser = TWParser::new();
         let mut sent_actions = vec![];
+        let mut markers = vec![];
 
         if self.explain_only {
-            for line in cast_file.lines() {
-                let line = line?;
-                let event: Event = serde_json::from_str(&line)?;
-                if event.1 != "o" {
-                    continue;
+            for event in cast_file {
+                let event = event?;
+                match event.kind {
+                    EventKind::Output => {
+                        sent_parser.parse(event.data.as_bytes(), |act| sent_actions.push(act))
+                    }
+                    EventKind::Marker => markers.push(event),
+                    _ => {}
                 }
-                sent_parser.parse(event.2.as_bytes(), |act| sent_actions.push(act));
             }
             drop(tx);
         } else {
             let mut tty = Tty::new()?;
             let size = tty.get_size()?;
-            if u32::from(size.cols) < header.width || u32::from(size.rows) < header.height {
+            if u32::from(size.cols) < header.width() || u32::from(size.rows) < header.height() {
                 anyhow::bail!(
                     "{} was recorded with width={} and height={}
                      but the current screen dimensions {}x{} are
                      too small to display it",
                     self.cast_file.display(),
-                    header.width,
-                    header.height,
+                    header.width(),
+                    header.height(),
                     size.cols,
                     size.rows
                 );
@@ -589,18 +542,22 @@ impl PlayCommand {
 
             let start = Instant::now();
 
-            for line in cast_file.lines() {
-                let line = line?;
-                let event: Event = serde_json::from_str(&line)?;
-                if event.1 != "o" {
-                    continue;
+            for event in cast_file {
+                let event = event?;
+                match event.kind {
+                    EventKind::Output => {}
+                    EventKind::Marker => {
+                        markers.push(event);
+                        continue;
+                    }
+                    _ => continue,
                 }
-                let target = start + Duration::from_secs_f32(event.0);
+                let target = start + Duration::from_secs_f32(event.time);
                 let duration = target.saturating_duration_since(Instant::now());
                 std::thread::sleep(duration);
 
-                tty.write_all(event.2.as_bytes())?;
-                sent_parser.parse(event.2.as_bytes(), |act| sent_actions.push(act));
+                tty.write_all(event.data.as_bytes())?;
+                sent_parser.parse(event.data.as_bytes(), |act| sent_actions.push(act));
             }
 
             std::thread::sleep(Duration::from_millis(100));
@@ -611,6 +568,13 @@ impl PlayCommand {
         if self.explain || self.explain_only {
             println!("> SENT");
             print_summarized(sent_actions);
+
+            if !markers.is_empty() {
+                println!("# MARKERS");
+                for marker in markers {
+                    println!("\t{:.3}s {:?}", marker.time, marker.data);
+                }
+            }
         }
 
         if !self.explain_only {
@@ -0,0 +1,165 @@
+//! Saving emulation results and comparing a run against them, so that
+//! regressions can be caught in CI.
+
+use super::emulation::Measurement;
+use anyhow::Context;
+use std::path::Path;
+
+/// Regressions smaller than this percentage are considered noise
+pub(crate) const DEFAULT_THRESHOLD_PCT: f64 = 10.0;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub(crate) struct Comparison {
+    pub name: String,
+    pub unit: String,
+    pub baseline: f64,
+    pub current: f64,
+    /// Signed change relative to the baseline value
+    pub change_pct: f64,
+    pub regressed: bool,
+}
+
+/// Loads the emulation measurements from a file written by
+/// `arb bench --save-baseline` (or `arb bench --json`).
+pub(crate) fn load_baseline(path: &Path) -> anyhow::Result<Vec<Measurement>> {
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("reading baseline {}", path.display()))?;
+    let report: serde_json::Value = serde_json::from_str(&data)
+        .with_context(|| format!("parsing baseline {}", path.display()))?;
+    let measurements = report
+        .get("emulation")
+        .and_then(|e| e.get("measurements"))
+        .cloned()
+        .with_context(|| {
+            format!(
+                "{} has no emulation measurements; create it with `arb bench --save-baseline`",
+                path.display()
+            )
+        })?;
+    serde_json::from_value(measurements)
+        .with_context(|| format!("parsing measurements in {}", path.display()))
+}
+
+/// Compares each current measurement with the baseline measurement of
+/// the same name.  Measurements that are missing from the baseline, or
+/// that were recorded in a different unit, are skipped.
+pub(crate) fn compare(
+    baseline: &[Measurement],
+    current: &[Measurement],
+    threshold_pct: f64,
+) -> Vec<Comparison> {
+    current
+        .iter()
+        .filter_map(|cur| {
+            let base = baseline
+                .iter()
+                .find(|b| b.name == cur.name && b.unit == cur.unit)?;
+            if base.value == 0.0 {
+                return None;
+            }
+            let change_pct = (cur.value - base.value) / base.value * 100.0;
+            let regressed = if cur.higher_is_better {
+                change_pct < -threshold_pct
+            } else {
+                change_pct > threshold_pct
+            };
+            Some(Comparison {
+                name: cur.name.clone(),
+                unit: cur.unit.clone(),
+                baseline: base.value,
+                current: cur.value,
+                change_pct,
+                regressed,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn m(name: &str, value: f64, unit: &str, higher_is_better: bool) -> Measurement {
+        Measurement {
+            name: name.to_string(),
+            value,
+            unit: unit.to_string(),
+            higher_is_better,
+        }
+    }
+
+    #[test]
+    fn should_flag_throughput_drop_as_regression() {
+        let baseline = vec![m("parser_throughput", 100.0, "MB/s", true)];
+        let within = compare(
+            &baseline,
+            &[m("parser_throughput", 95.0, "MB/s", true)],
+            10.0,
+        );
+        assert!(!within[0].regressed);
+        let beyond = compare(
+            &baseline,
+            &[m("parser_throughput", 80.0, "MB/s", true)],
+            10.0,
+        );
+        assert!(beyond[0].regressed);
+        assert!((beyond[0].change_pct - -20.0).abs() < 0.001);
+        let faster = compare(
+            &baseline,
+            &[m("parser_throughput", 150.0, "MB/s", true)],
+            10.0,
+        );
+        assert!(!faster[0].regressed);
+    }
+
+    #[test]
+    fn should_flag_latency_increase_as_regression() {
+        let baseline = vec![m("echo_latency_p99", 50.0, "\u{b5}s", false)];
+        let slower = compare(
+            &baseline,
+            &[m("echo_latency_p99", 60.0, "\u{b5}s", false)],
+            10.0,
+        );
+        assert!(slower[0].regressed);
+        let quicker = compare(
+            &baseline,
+            &[m("echo_latency_p99", 20.0, "\u{b5}s", false)],
+            10.0,
+        );
+        assert!(!quicker[0].regressed);
+    }
+
+    #[test]
+    fn should_skip_unmatched_measurements() {
+        let baseline = vec![
+            m("render_model", 10.0, "ms/frame", false),
+            m("scrollback_append", 0.0, "ns/line", false),
+        ];
+        let current = vec![
+            m("render_model", 10.0, "\u{b5}s/frame", false),
+            m("scrollback_append", 5.0, "ns/line", false),
+            m("parser_throughput", 5.0, "MB/s", true),
+        ];
+        assert!(compare(&baseline, &current, 10.0).is_empty());
+    }
+
+    #[test]
+    fn should_load_saved_baseline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("baseline.json");
+        std::fs::write(
+            &path,
+            r#"{"emulation": {"stream": "synthetic", "bytes": 10,
+                "measurements": [{"name": "parser_throughput", "value": 12.5,
+                                  "unit": "MB/s", "higher_is_better": true}]}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            load_baseline(&path).unwrap(),
+            vec![m("parser_throughput", 12.5, "MB/s", true)]
+        );
+
+        std::fs::write(&path, r#"{"shell": "zsh"}"#).unwrap();
+        assert!(load_baseline(&path).is_err());
+    }
+}
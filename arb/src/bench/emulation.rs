@@ -0,0 +1,456 @@
+//! Headless terminal emulation benchmarks.
+//!
+//! These feed a byte stream through the same layers that a pane does:
+//! the escape sequence parser, the terminal model (including scrollback)
+//! and the per-frame extraction of the lines that the renderer shapes.
+//! Nothing here needs a display or a pty, so they run anywhere.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use termwiz::escape::parser::Parser;
+use wezterm_term::color::ColorPalette;
+use wezterm_term::{Terminal, TerminalConfiguration, TerminalSize};
+
+/// Size of the chunks fed to the terminal; matches a typical pty read.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub(crate) struct EmulationOptions {
+    /// The stream to feed through the parser and terminal model
+    pub stream: Vec<u8>,
+    /// How many times each throughput measurement is repeated;
+    /// the median is reported
+    pub rounds: usize,
+    /// Number of lines appended to the scrollback
+    pub scrollback_lines: usize,
+    /// Number of frames of render model to generate
+    pub frames: usize,
+    /// Number of keystroke echoes to time
+    pub echo_samples: usize,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+/// A single emulation measurement.
+// This is written to the `--save-baseline` file and read back by
+// `--baseline`, so the field names are a stable format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Measurement {
+    pub name: String,
+    pub value: f64,
+    pub unit: String,
+    pub higher_is_better: bool,
+}
+
+impl Measurement {
+    fn new(name: &str, value: f64, unit: &str, higher_is_better: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            value,
+            unit: unit.to_string(),
+            higher_is_better,
+        }
+    }
+}
+
+/// Human readable name of a measurement, for the ANSI and Markdown reports
+pub(crate) fn label(name: &str) -> &str {
+    match name {
+        "parser_throughput" => "Parser throughput",
+        "terminal_throughput" => "Terminal throughput",
+        "scrollback_append" => "Scrollback append",
+        "render_model" => "Render model",
+        "echo_latency_p50" => "Echo latency (p50)",
+        "echo_latency_p99" => "Echo latency (p99)",
+        other => other,
+    }
+}
+
+/// Minimal configuration so that the benchmarks don't depend on
+/// (or pay for loading) the user's arb.lua.
+#[derive(Debug)]
+struct BenchConfig {
+    scrollback: usize,
+}
+
+impl TerminalConfiguration for BenchConfig {
+    fn scrollback_size(&self) -> usize {
+        self.scrollback
+    }
+
+    fn color_palette(&self) -> ColorPalette {
+        ColorPalette::default()
+    }
+}
+
+fn new_terminal(opts: &EmulationOptions, scrollback: usize) -> Terminal {
+    Terminal::new(
+        TerminalSize {
+            rows: opts.rows,
+            cols: opts.cols,
+            ..Default::default()
+        },
+        Arc::new(BenchConfig { scrollback }),
+        "arb",
+        config::arb_version(),
+        Box::new(std::io::sink()),
+    )
+}
+
+/// Produces a deterministic stream that resembles build and log output:
+/// plain text, SGR colors (16, 256 and truecolor), wide and combining
+/// characters, tabs, and progress bars that redraw using `\r` and EL.
+pub(crate) fn synthetic_stream(len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len + 256);
+    let mut i = 0usize;
+    while out.len() < len {
+        let line = match i % 6 {
+            0 => format!(
+                "[{:>5}.{:06}] build: compiling crate_{:04} v0.{}.{} (/src/crate_{:04})\r\n",
+                i / 1000,
+                (i * 7919) % 1_000_000,
+                i % 10000,
+                i % 10,
+                i % 7,
+                i % 10000
+            ),
+            1 => format!(
+                "\x1b[1;32m   Compiling\x1b[0m widget-{} v1.{}.0\r\n\x1b[38;5;{}mwarning\x1b[0m: unused variable `x{}`\r\n",
+                i,
+                i % 40,
+                i % 256,
+                i
+            ),
+            2 => format!(
+                "\x1b[38;2;{};{};{}m\u{2714}\x1b[0m テスト {} \u{fc}ber \u{2192} e\u{301}t\u{e9} \u{1f389}\r\n",
+                i % 256,
+                (i * 3) % 256,
+                (i * 7) % 256,
+                i
+            ),
+            3 => {
+                let filled = i % 40;
+                format!(
+                    "\r\x1b[K[{}>{}] {:>3}%",
+                    "=".repeat(filled),
+                    " ".repeat(40 - filled),
+                    filled * 100 / 40
+                )
+            }
+            4 => format!(
+                "\r\n\x1b[01;34mdir_{}\x1b[0m\t\x1b[01;32mbin_{}\x1b[0m\tfile_{}.rs\tREADME.md\r\n",
+                i, i, i
+            ),
+            _ => format!(
+                "\x1b[7m{:<60}\x1b[27m \x1b[4mhttps://example.com/{}\x1b[24m\r\n",
+                format!("status line {i}"),
+                i
+            ),
+        };
+        out.extend_from_slice(line.as_bytes());
+        i += 1;
+    }
+    out
+}
+
+/// Loads a captured stream from `path`.  asciicast files contribute the
+/// concatenation of their output events; anything else (eg: the output of
+/// `script -q /dev/null`) is used verbatim.
+pub(crate) fn load_stream(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let data = std::fs::read(path)?;
+
+    let reader = match crate::asciicast::CastReader::new(data.as_slice()) {
+        Ok(reader) => reader,
+        Err(_) => return Ok(data),
+    };
+
+    let mut stream = vec![];
+    for event in reader {
+        let event = event?;
+        if event.kind == crate::asciicast::EventKind::Output {
+            stream.extend_from_slice(event.data.as_bytes());
+        }
+    }
+    Ok(stream)
+}
+
+fn percentile(mut samples: Vec<f64>, pct: f64) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    samples.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let idx = ((samples.len() - 1) as f64 * pct / 100.0).round() as usize;
+    samples[idx.min(samples.len() - 1)]
+}
+
+fn mb_per_sec(bytes: usize, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs == 0.0 {
+        return 0.0;
+    }
+    bytes as f64 / (1024.0 * 1024.0) / secs
+}
+
+/// Cost of decoding the stream into actions, without applying them.
+pub(crate) fn bench_parser(opts: &EmulationOptions) -> Measurement {
+    let samples = (0..opts.rounds.max(1))
+        .map(|_| {
+            let mut parser = Parser::new();
+            let mut actions = 0usize;
+            let start = Instant::now();
+            for chunk in opts.stream.chunks(CHUNK_SIZE) {
+                parser.parse(chunk, |_| actions += 1);
+            }
+            let elapsed = start.elapsed();
+            std::hint::black_box(actions);
+            mb_per_sec(opts.stream.len(), elapsed)
+        })
+        .collect();
+    Measurement::new("parser_throughput", percentile(samples, 50.0), "MB/s", true)
+}
+
+/// Cost of parsing and applying the stream to the terminal model.
+pub(crate) fn bench_terminal(opts: &EmulationOptions) -> Measurement {
+    let samples = (0..opts.rounds.max(1))
+        .map(|_| {
+            let mut terminal = new_terminal(opts, 3500);
+            let start = Instant::now();
+            for chunk in opts.stream.chunks(CHUNK_SIZE) {
+                terminal.advance_bytes(chunk);
+            }
+            mb_per_sec(opts.stream.len(), start.elapsed())
+        })
+        .collect();
+    Measurement::new(
+        "terminal_throughput",
+        percentile(samples, 50.0),
+        "MB/s",
+        true,
+    )
+}
+
+/// Per-line cost of scrolling plain lines into a scrollback that is
+/// already full, so that the oldest lines are being evicted.
+pub(crate) fn bench_scrollback(opts: &EmulationOptions) -> Measurement {
+    let lines = opts.scrollback_lines.max(1);
+    let mut fill = Vec::with_capacity(lines * 48);
+    for i in 0..lines {
+        fill.extend_from_slice(
+            format!("scrollback line {i:>8} lorem ipsum dolor sit\r\n").as_bytes(),
+        );
+    }
+
+    let samples = (0..opts.rounds.max(1))
+        .map(|_| {
+            let mut terminal = new_terminal(opts, lines);
+            // Fill the scrollback first so that we measure steady state
+            for chunk in fill.chunks(CHUNK_SIZE) {
+                terminal.advance_bytes(chunk);
+            }
+            let start = Instant::now();
+            for chunk in fill.chunks(CHUNK_SIZE) {
+                terminal.advance_bytes(chunk);
+            }
+            start.elapsed().as_nanos() as f64 / lines as f64
+        })
+        .collect();
+    Measurement::new(
+        "scrollback_append",
+        percentile(samples, 50.0),
+        "ns/line",
+        false,
+    )
+}
+
+/// Extracts the viewport lines and clusters them for shaping; this is
+/// the work done for each pane on every frame that the GUI paints.
+fn render_viewport(terminal: &Terminal) -> usize {
+    let screen = terminal.screen();
+    let top = screen.visible_row_to_stable_row(0);
+    let phys = screen.stable_range(&(top..top + screen.physical_rows as isize));
+    screen
+        .lines_in_phys_range(phys)
+        .iter()
+        .map(|line| line.cluster(None).len())
+        .sum()
+}
+
+pub(crate) fn bench_render_model(opts: &EmulationOptions) -> Measurement {
+    let mut terminal = new_terminal(opts, 3500);
+    for chunk in opts.stream.chunks(CHUNK_SIZE) {
+        terminal.advance_bytes(chunk);
+    }
+
+    let samples = (0..opts.frames.max(1))
+        .map(|_| {
+            let start = Instant::now();
+            std::hint::black_box(render_viewport(&terminal));
+            start.elapsed().as_secs_f64() * 1_000_000.0
+        })
+        .collect();
+    Measurement::new(
+        "render_model",
+        percentile(samples, 50.0),
+        "\u{b5}s/frame",
+        false,
+    )
+}
+
+/// Time from a single echoed keystroke arriving from the pty to the
+/// changed lines being ready to paint.
+pub(crate) fn bench_echo_latency(opts: &EmulationOptions) -> Vec<Measurement> {
+    let mut terminal = new_terminal(opts, 3500);
+    for chunk in opts.stream.chunks(CHUNK_SIZE) {
+        terminal.advance_bytes(chunk);
+    }
+    terminal.advance_bytes(b"\r\n$ ");
+
+    let samples: Vec<f64> = (0..opts.echo_samples.max(1))
+        .map(|i| {
+            let seqno = terminal.current_seqno();
+            let echo = [b'a' + (i % 26) as u8];
+            let start = Instant::now();
+            terminal.advance_bytes(echo);
+            let screen = terminal.screen();
+            let top = screen.visible_row_to_stable_row(0);
+            let dirty =
+                screen.get_changed_stable_rows(top..top + screen.physical_rows as isize, seqno);
+            let clusters: usize = dirty
+                .into_iter()
+                .filter_map(|row| screen.stable_row_to_phys(row))
+                .flat_map(|phys| screen.lines_in_phys_range(phys..phys + 1))
+                .map(|line| line.cluster(None).len())
+                .sum();
+            std::hint::black_box(clusters);
+            let elapsed = start.elapsed().as_secs_f64() * 1_000_000.0;
+            if i % opts.cols.max(2) == opts.cols.max(2) - 1 {
+                // Keep the prompt from wrapping forever
+                terminal.advance_bytes(b"\r\n$ ");
+            }
+            elapsed
+        })
+        .collect();
+
+    vec![
+        Measurement::new(
+            "echo_latency_p50",
+            percentile(samples.clone(), 50.0),
+            "\u{b5}s",
+            false,
+        ),
+        Measurement::new(
+            "echo_latency_p99",
+            percentile(samples, 99.0),
+            "\u{b5}s",
+            false,
+        ),
+    ]
+}
+
+pub(crate) fn run_all(opts: &EmulationOptions) -> Vec<Measurement> {
+    let mut results = vec![
+        bench_parser(opts),
+        bench_terminal(opts),
+        bench_scrollback(opts),
+        bench_render_model(opts),
+    ];
+    results.extend(bench_echo_latency(opts));
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_options() -> EmulationOptions {
+        EmulationOptions {
+            stream: synthetic_stream(16 * 1024),
+            rounds: 1,
+            scrollback_lines: 100,
+            frames: 3,
+            echo_samples: 10,
+            rows: 24,
+            cols: 80,
+        }
+    }
+
+    #[test]
+    fn should_generate_stream_of_requested_size() {
+        let stream = synthetic_stream(4096);
+        assert!(stream.len() >= 4096);
+        assert!(stream.len() < 4096 + 512);
+        assert_eq!(stream, synthetic_stream(4096));
+        assert!(std::str::from_utf8(&stream).is_ok());
+    }
+
+    #[test]
+    fn should_produce_all_measurements() {
+        let results = run_all(&small_options());
+        let names: Vec<&str> = results.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "parser_throughput",
+                "terminal_throughput",
+                "scrollback_append",
+                "render_model",
+                "echo_latency_p50",
+                "echo_latency_p99",
+            ]
+        );
+        for m in &results {
+            assert!(m.value.is_finite() && m.value >= 0.0, "{m:?}");
+            assert_ne!(label(&m.name), "");
+        }
+    }
+
+    #[test]
+    fn should_render_populated_viewport() {
+        let opts = small_options();
+        let mut terminal = new_terminal(&opts, 100);
+        terminal.advance_bytes(&opts.stream);
+        assert!(render_viewport(&terminal) > 0);
+    }
+
+    #[test]
+    fn should_load_asciicast_output_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("demo.cast");
+        std::fs::write(
+            &path,
+            "{\"version\": 2, \"width\": 80, \"height\": 24}\n\
+             [0.1, \"o\", \"hello \"]\n\
+             [0.2, \"i\", \"ignored\"]\n\
+             [0.3, \"o\", \"world\\r\\n\"]\n",
+        )
+        .unwrap();
+        assert_eq!(load_stream(&path).unwrap(), b"hello world\r\n");
+
+        let v3 = dir.path().join("demo-v3.cast");
+        std::fs::write(
+            &v3,
+            "{\"version\": 3, \"term\": {\"cols\": 80, \"rows\": 24}}\n\
+             [0.1, \"o\", \"hello \"]\n\
+             [0.2, \"m\", \"marker\"]\n\
+             [0.3, \"o\", \"world\\r\\n\"]\n",
+        )
+        .unwrap();
+        assert_eq!(load_stream(&v3).unwrap(), b"hello world\r\n");
+
+        let raw = dir.path().join("raw.txt");
+        std::fs::write(&raw, b"\x1b[1mraw\x1b[0m").unwrap();
+        assert_eq!(load_stream(&raw).unwrap(), b"\x1b[1mraw\x1b[0m");
+    }
+
+    #[test]
+    fn should_compute_percentiles() {
+        let samples: Vec<f64> = (1..=100).map(|v| v as f64).collect();
+        assert_eq!(percentile(samples.clone(), 50.0), 51.0);
+        assert_eq!(percentile(samples.clone(), 99.0), 99.0);
+        assert_eq!(percentile(samples, 100.0), 100.0);
+        assert_eq!(percentile(vec![], 99.0), 0.0);
+        assert_eq!(percentile(vec![3.0, 1.0, 2.0], 50.0), 2.0);
+    }
+}
@@ -1,4 +1,23 @@
-use clap::Parser;
+use anyhow::Context;
+use clap::{Parser, ValueHint};
+use std::path::PathBuf;
+
+mod baseline;
+mod emulation;
+
+use baseline::Comparison;
+use emulation::{EmulationOptions, Measurement};
+
+/// Size of the synthetic stream when `--stream-size` is not given
+const DEFAULT_STREAM_MIB: usize = 8;
+
+// ANSI color codes
+const BOLD: &str = "\x1b[1m";
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const GRAY: &str = "\x1b[90m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
 
 #[derive(Debug, Parser, Clone, Default)]
 pub struct BenchCommand {
@@ -9,39 +28,162 @@ pub struct BenchCommand {
     /// Output results as a Markdown table (for sharing on GitHub/Twitter)
     #[arg(long)]
     markdown: bool,
+
+    /// Skip the shell startup benchmark and only run the
+    /// terminal emulation benchmarks
+    #[arg(long)]
+    emulation_only: bool,
+
+    /// Feed this captured stream through the emulation benchmarks
+    /// instead of the built-in synthetic stream.
+    /// Accepts raw pty output (eg: from `script`) or an asciicast
+    /// recording made with `arb record`.
+    #[arg(long, value_hint=ValueHint::FilePath)]
+    input: Option<PathBuf>,
+
+    /// Size of the synthetic stream in MiB; defaults to 8
+    #[arg(long, conflicts_with = "input")]
+    stream_size: Option<usize>,
+
+    /// Compare the emulation results against a file previously
+    /// written by `--save-baseline`, and fail if any of them
+    /// regressed by more than `--threshold` percent
+    #[arg(long, value_hint=ValueHint::FilePath)]
+    baseline: Option<PathBuf>,
+
+    /// Save the results to this file for use with `--baseline`
+    #[arg(long, value_hint=ValueHint::FilePath)]
+    save_baseline: Option<PathBuf>,
+
+    /// Percentage change that counts as a regression when comparing
+    /// against `--baseline`; defaults to 10
+    #[arg(long, requires = "baseline")]
+    threshold: Option<f64>,
 }
 
 impl BenchCommand {
     pub fn run(&self) -> anyhow::Result<()> {
-        imp::run(self)
+        let shell = if self.emulation_only {
+            None
+        } else {
+            imp::run_shell_benchmark()?
+        };
+
+        let (stream_source, stream) = match &self.input {
+            Some(path) => (
+                path.display().to_string(),
+                emulation::load_stream(path)
+                    .with_context(|| format!("loading {}", path.display()))?,
+            ),
+            None => {
+                let mib = self.stream_size.unwrap_or(DEFAULT_STREAM_MIB).max(1);
+                (
+                    "synthetic".to_string(),
+                    emulation::synthetic_stream(mib * 1024 * 1024),
+                )
+            }
+        };
+        let stream_bytes = stream.len();
+
+        let emulation = emulation::run_all(&EmulationOptions {
+            stream,
+            rounds: 5,
+            scrollback_lines: 100_000,
+            frames: 200,
+            echo_samples: 2000,
+            rows: 50,
+            cols: 200,
+        });
+
+        let threshold = self.threshold.unwrap_or(baseline::DEFAULT_THRESHOLD_PCT);
+        let comparison = match &self.baseline {
+            Some(path) => baseline::compare(&baseline::load_baseline(path)?, &emulation, threshold),
+            None => vec![],
+        };
+
+        let report = BenchReport {
+            shell,
+            stream_source,
+            stream_bytes,
+            emulation,
+            comparison,
+        };
+
+        if self.json {
+            print_json(&report);
+        } else if self.markdown {
+            print_markdown(&report);
+        } else {
+            print_ansi(&report);
+        }
+
+        if let Some(path) = &self.save_baseline {
+            std::fs::write(path, serde_json::to_string_pretty(&report_json(&report))?)
+                .with_context(|| format!("writing baseline {}", path.display()))?;
+        }
+
+        let regressions = report.comparison.iter().filter(|c| c.regressed).count();
+        if regressions > 0 {
+            anyhow::bail!(
+                "{regressions} benchmark(s) regressed by more than {threshold}% \
+                 compared with the baseline"
+            );
+        }
+
+        Ok(())
     }
 }
 
+/// Result of detecting a terminal.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) struct TerminalDetection {
+    pub name: String,
+    pub path: String,
+    pub found: bool,
+}
+
+/// Result of a shell benchmark run.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) struct BenchResult {
+    pub shell: String,
+    pub iterations: usize,
+    pub arb_median_ms: f64,
+    pub raw_median_ms: f64,
+    pub terminals: Vec<TerminalDetection>,
+}
+
+/// Everything measured by a single `arb bench` invocation.
+pub(crate) struct BenchReport {
+    /// Shell startup results; only measured on macOS
+    pub shell: Option<BenchResult>,
+    /// Where the emulation stream came from
+    pub stream_source: String,
+    pub stream_bytes: usize,
+    pub emulation: Vec<Measurement>,
+    /// Empty unless `--baseline` was used
+    pub comparison: Vec<Comparison>,
+}
+
 #[cfg(not(target_os = "macos"))]
 mod imp {
-    use anyhow::bail;
-
-    pub fn run(_cmd: &super::BenchCommand) -> anyhow::Result<()> {
-        bail!("`arb bench` is currently supported on macOS only")
+    /// The shell startup benchmark relies on zsh's `--no-rcs` and the
+    /// macOS app locations, so it is not measured elsewhere.
+    pub fn run_shell_benchmark() -> anyhow::Result<Option<super::BenchResult>> {
+        Ok(None)
     }
 }
 
 #[cfg(target_os = "macos")]
 mod imp {
-    use super::BenchCommand;
+    pub(crate) use super::{BenchResult, TerminalDetection};
     use std::path::PathBuf;
     use std::process::Command;
     use std::time::{Duration, Instant};
 
     const ITERATIONS: usize = 10;
 
-    // ANSI color codes
-    const BOLD: &str = "\x1b[1m";
-    const GREEN: &str = "\x1b[32m";
-    const GRAY: &str = "\x1b[90m";
-    const DIM: &str = "\x1b[2m";
-    const RESET: &str = "\x1b[0m";
-
     /// Terminal application info for detection.
     pub(crate) struct TerminalInfo {
         pub name: &'static str,
@@ -71,36 +213,8 @@ mod imp {
         },
     ];
 
-    /// Result of detecting a terminal.
-    #[derive(Debug, Clone)]
-    pub(crate) struct TerminalDetection {
-        pub name: String,
-        pub path: String,
-        pub found: bool,
-    }
-
-    /// Result of a shell benchmark run.
-    #[derive(Debug, Clone)]
-    pub(crate) struct BenchResult {
-        pub shell: String,
-        pub iterations: usize,
-        pub arb_median_ms: f64,
-        pub raw_median_ms: f64,
-        pub terminals: Vec<TerminalDetection>,
-    }
-
-    pub fn run(cmd: &BenchCommand) -> anyhow::Result<()> {
-        let result = run_benchmark()?;
-
-        if cmd.json {
-            print_json(&result);
-        } else if cmd.markdown {
-            print_markdown(&result);
-        } else {
-            print_ansi(&result);
-        }
-
-        Ok(())
+    pub fn run_shell_benchmark() -> anyhow::Result<Option<BenchResult>> {
+        run_benchmark().map(Some)
     }
 
     pub(crate) fn run_benchmark() -> anyhow::Result<BenchResult> {
@@ -142,10 +256,7 @@ mod imp {
     /// If `with_arb_env` is true, runs the shell normally (which picks up arb
     /// shell integration). If false, passes `--no-rcs` to skip all rc files
     /// for a baseline measurement.
-    fn measure_shell_startup(
-        shell: &str,
-        with_arb_env: bool,
-    ) -> anyhow::Result<Vec<Duration>> {
+    fn measure_shell_startup(shell: &str, with_arb_env: bool) -> anyhow::Result<Vec<Duration>> {
         let shell_path = format!("/bin/{shell}");
         let mut timings = Vec::with_capacity(ITERATIONS);
 
@@ -208,14 +319,20 @@ mod imp {
             ms[mid]
         }
     }
+}
+
+fn format_stream_size(bytes: usize) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Format benchmark results as pretty ANSI output.
+fn print_ansi(report: &BenchReport) {
+    let divider = "\u{2500}".repeat(33);
 
-    /// Format benchmark results as pretty ANSI output.
-    fn print_ansi(result: &BenchResult) {
+    if let Some(result) = &report.shell {
         println!();
         println!("{BOLD}Arb Shell Benchmark{RESET}");
-        println!(
-            "{DIM}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}{RESET}"
-        );
+        println!("{DIM}{divider}{RESET}");
         println!("Shell: {}", result.shell);
         println!("Iterations: {}", result.iterations);
         println!();
@@ -234,16 +351,71 @@ mod imp {
             if t.found {
                 println!("  {GREEN}\u{2714}{RESET} {:<10} {}", t.name, t.path);
             } else {
-                println!("  {DIM}\u{2500}{RESET} {:<10} {GRAY}not found{RESET}", t.name);
+                println!(
+                    "  {DIM}\u{2500}{RESET} {:<10} {GRAY}not found{RESET}",
+                    t.name
+                );
             }
         }
+    }
+
+    println!();
+    println!("{BOLD}Arb Terminal Emulation Benchmark{RESET}");
+    println!("{DIM}{divider}{RESET}");
+    println!(
+        "Stream: {} ({})",
+        report.stream_source,
+        format_stream_size(report.stream_bytes)
+    );
+    println!();
+    for m in &report.emulation {
+        println!(
+            "  {:<22} {GREEN}{:>10.2}{RESET} {}",
+            emulation::label(&m.name),
+            m.value,
+            m.unit
+        );
+    }
+
+    if !report.comparison.is_empty() {
         println!();
+        println!("{BOLD}Compared with baseline:{RESET}");
+        for c in &report.comparison {
+            let (color, verdict) = if c.regressed {
+                (RED, "regressed")
+            } else {
+                (GREEN, "ok")
+            };
+            println!(
+                "  {:<22} {:>10.2} \u{2192} {:>10.2} {:<8} {color}{:>+7.1}% {verdict}{RESET}",
+                emulation::label(&c.name),
+                c.baseline,
+                c.current,
+                c.unit,
+                c.change_pct
+            );
+        }
+    }
+
+    println!();
+    if report.shell.is_some() {
         println!("{GRAY}Share your results: arb bench --markdown | pbcopy{RESET}");
         println!();
     }
+}
+
+/// Builds the JSON representation of the results.
+/// This is also the format of the `--save-baseline` file.
+fn report_json(report: &BenchReport) -> serde_json::Value {
+    let mut json = serde_json::json!({
+        "emulation": {
+            "stream": report.stream_source,
+            "bytes": report.stream_bytes,
+            "measurements": report.emulation,
+        },
+    });
 
-    /// Format benchmark results as JSON.
-    fn print_json(result: &BenchResult) {
+    if let Some(result) = &report.shell {
         let terminals: Vec<serde_json::Value> = result
             .terminals
             .iter()
@@ -256,19 +428,31 @@ mod imp {
             })
             .collect();
 
-        let json = serde_json::json!({
-            "shell": result.shell,
-            "iterations": result.iterations,
-            "arb_median_ms": round2(result.arb_median_ms),
-            "raw_median_ms": round2(result.raw_median_ms),
-            "terminals": terminals,
-        });
+        json["shell"] = result.shell.clone().into();
+        json["iterations"] = result.iterations.into();
+        json["arb_median_ms"] = round2(result.arb_median_ms).into();
+        json["raw_median_ms"] = round2(result.raw_median_ms).into();
+        json["terminals"] = terminals.into();
+    }
 
-        println!("{}", serde_json::to_string_pretty(&json).unwrap_or_default());
+    if !report.comparison.is_empty() {
+        json["comparison"] = serde_json::to_value(&report.comparison).unwrap_or_default();
     }
 
-    /// Format benchmark results as a Markdown table.
-    fn print_markdown(result: &BenchResult) {
+    json
+}
+
+/// Format benchmark results as JSON.
+fn print_json(report: &BenchReport) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report_json(report)).unwrap_or_default()
+    );
+}
+
+/// Format benchmark results as a Markdown table.
+fn print_markdown(report: &BenchReport) {
+    if let Some(result) = &report.shell {
         println!("## Arb Shell Benchmark");
         println!();
         println!("| Metric | Value |");
@@ -293,14 +477,50 @@ mod imp {
             let path = if t.found { t.path.as_str() } else { "-" };
             println!("| {} | {} | {} |", t.name, status, path);
         }
+        println!();
     }
 
-    /// Round a float to 2 decimal places.
-    fn round2(v: f64) -> f64 {
-        (v * 100.0).round() / 100.0
+    println!("## Arb Terminal Emulation Benchmark");
+    println!();
+    println!(
+        "Stream: {} ({})",
+        report.stream_source,
+        format_stream_size(report.stream_bytes)
+    );
+    println!();
+    if report.comparison.is_empty() {
+        println!("| Benchmark | Result |");
+        println!("|-----------|--------|");
+        for m in &report.emulation {
+            println!(
+                "| {} | {:.2} {} |",
+                emulation::label(&m.name),
+                m.value,
+                m.unit
+            );
+        }
+    } else {
+        println!("| Benchmark | Baseline | Result | Change |");
+        println!("|-----------|----------|--------|--------|");
+        for c in &report.comparison {
+            println!(
+                "| {} | {:.2} {unit} | {:.2} {unit} | {:+.1}%{} |",
+                emulation::label(&c.name),
+                c.baseline,
+                c.current,
+                c.change_pct,
+                if c.regressed { " (regressed)" } else { "" },
+                unit = c.unit,
+            );
+        }
     }
 }
 
+/// Round a float to 2 decimal places.
+fn round2(v: f64) -> f64 {
+    (v * 100.0).round() / 100.0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -310,6 +530,66 @@ mod tests {
         let cmd = BenchCommand::default();
         assert!(!cmd.json);
         assert!(!cmd.markdown);
+        assert!(!cmd.emulation_only);
+        assert!(cmd.baseline.is_none());
+    }
+
+    fn emulation_report(comparison: Vec<Comparison>) -> BenchReport {
+        BenchReport {
+            shell: None,
+            stream_source: "synthetic".to_string(),
+            stream_bytes: 1024,
+            emulation: vec![Measurement {
+                name: "parser_throughput".to_string(),
+                value: 250.0,
+                unit: "MB/s".to_string(),
+                higher_is_better: true,
+            }],
+            comparison,
+        }
+    }
+
+    #[test]
+    fn should_include_emulation_results_in_json() {
+        let json = report_json(&emulation_report(vec![]));
+        assert_eq!(json["emulation"]["stream"], "synthetic");
+        assert_eq!(json["emulation"]["bytes"], 1024);
+        assert_eq!(
+            json["emulation"]["measurements"][0]["name"],
+            "parser_throughput"
+        );
+        assert_eq!(json["emulation"]["measurements"][0]["value"], 250.0);
+        // Shell results are only present when they were measured
+        assert!(json.get("shell").is_none());
+        assert!(json.get("comparison").is_none());
+    }
+
+    #[test]
+    fn should_round_trip_json_report_as_baseline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("baseline.json");
+        let report = emulation_report(vec![]);
+        std::fs::write(
+            &path,
+            serde_json::to_string_pretty(&report_json(&report)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(baseline::load_baseline(&path).unwrap(), report.emulation);
+    }
+
+    #[test]
+    fn should_include_comparison_in_json() {
+        let report = emulation_report(vec![Comparison {
+            name: "parser_throughput".to_string(),
+            unit: "MB/s".to_string(),
+            baseline: 300.0,
+            current: 250.0,
+            change_pct: -16.7,
+            regressed: true,
+        }]);
+        let json = report_json(&report);
+        assert_eq!(json["comparison"][0]["regressed"], true);
+        assert_eq!(json["comparison"][0]["baseline"], 300.0);
     }
 
     #[cfg(target_os = "macos")]
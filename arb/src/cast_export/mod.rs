@@ -0,0 +1,451 @@
+//! Renders an asciicast into an animation.
+//!
+//! The recording is played through an offscreen `wezterm_term::Terminal`
+//! and the visible screen is captured as a sequence of frames, which
+//! are then either emitted as an animated SVG, or rasterized using the
+//! same font stack as the GUI and encoded as an animated GIF or PNG.
+
+use crate::asciicast::{CastReader, EventKind};
+use std::io::BufRead;
+use std::path::Path;
+use std::sync::Arc;
+use termwiz::surface::CursorVisibility;
+use wezterm_term::color::{ColorPalette, SrgbaTuple};
+use wezterm_term::{CellAttributes, Terminal, TerminalConfiguration, TerminalSize};
+
+mod raster;
+mod svg;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Gif,
+    /// Animated PNG
+    Png,
+    Svg,
+}
+
+impl ExportFormat {
+    /// Determines the format from the extension of the output file
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("gif") => Ok(Self::Gif),
+            Some("png") | Some("apng") => Ok(Self::Png),
+            Some("svg") => Ok(Self::Svg),
+            _ => anyhow::bail!(
+                "cannot determine the export format of {}; \
+                 use a .gif, .png, .apng or .svg extension",
+                path.display()
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// Maximum number of frames per second
+    pub fps: f32,
+    /// Pauses longer than this many seconds are shortened to it.
+    /// Defaults to the idle_time_limit of the recording.
+    pub idle_time_limit: Option<f32>,
+    /// How long to hold the final frame before looping, in seconds
+    pub final_frame_hold: f32,
+    /// Font size in points, overriding the configured font_size
+    pub font_size: Option<f64>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            fps: 30.,
+            idle_time_limit: None,
+            final_frame_hold: 2.,
+            font_size: None,
+        }
+    }
+}
+
+/// A sequence of cells on a line that share the same attributes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    /// The first column occupied by the run
+    pub col: usize,
+    /// The number of columns occupied by the run
+    pub width: usize,
+    pub text: String,
+    /// The column of each char in `text`, relative to `col`.
+    /// Combining characters share the column of their base character.
+    pub char_cols: Vec<usize>,
+    pub attrs: CellAttributes,
+    /// Resolved foreground color, taking reverse video into account
+    pub fg: SrgbaTuple,
+    /// Resolved background color, taking reverse video into account
+    pub bg: SrgbaTuple,
+}
+
+/// The visible contents of the terminal at a point in time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screen {
+    pub cols: usize,
+    pub rows: usize,
+    pub lines: Vec<Vec<Run>>,
+    /// The cursor position as (column, row), if it is visible
+    pub cursor: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// When the frame is first displayed, in seconds
+    pub time: f32,
+    /// How long the frame is displayed for, in seconds
+    pub duration: f32,
+    pub screen: Screen,
+}
+
+/// The frames of a recording along with what is needed to draw them
+#[derive(Debug)]
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    pub palette: ColorPalette,
+    /// The largest size of the terminal during the recording
+    pub cols: usize,
+    pub rows: usize,
+}
+
+#[derive(Debug)]
+struct ExportConfig {
+    palette: ColorPalette,
+}
+
+impl TerminalConfiguration for ExportConfig {
+    fn scrollback_size(&self) -> usize {
+        0
+    }
+
+    fn color_palette(&self) -> ColorPalette {
+        self.palette.clone()
+    }
+}
+
+fn capture_screen(terminal: &Terminal, palette: &ColorPalette) -> Screen {
+    let screen = terminal.screen();
+    let top = screen.visible_row_to_stable_row(0);
+    let phys = screen.stable_range(&(top..top + screen.physical_rows as isize));
+
+    let lines = screen
+        .lines_in_phys_range(phys)
+        .iter()
+        .map(|line| {
+            line.cluster(None)
+                .into_iter()
+                .map(|cluster| {
+                    let mut fg = palette.resolve_fg(cluster.attrs.foreground());
+                    let mut bg = palette.resolve_bg(cluster.attrs.background());
+                    if cluster.attrs.reverse() {
+                        std::mem::swap(&mut fg, &mut bg);
+                    }
+                    if cluster.attrs.invisible() {
+                        fg = bg;
+                    }
+                    let char_cols = cluster
+                        .text
+                        .char_indices()
+                        .map(|(idx, _)| cluster.byte_to_cell_idx(idx) - cluster.first_cell_idx)
+                        .collect();
+                    Run {
+                        col: cluster.first_cell_idx,
+                        width: cluster.width,
+                        char_cols,
+                        text: cluster.text,
+                        attrs: cluster.attrs,
+                        fg,
+                        bg,
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let cursor = terminal.cursor_pos();
+    let cursor = if cursor.visibility == CursorVisibility::Visible && cursor.y >= 0 {
+        Some((cursor.x, cursor.y as usize))
+    } else {
+        None
+    };
+
+    Screen {
+        cols: screen.physical_cols,
+        rows: screen.physical_rows,
+        lines,
+        cursor,
+    }
+}
+
+/// Adds a frame, unless the screen is unchanged from the previous frame
+fn push_frame(frames: &mut Vec<Frame>, time: f32, screen: Screen) {
+    if frames.last().map(|f| f.screen == screen).unwrap_or(false) {
+        return;
+    }
+    frames.push(Frame {
+        time,
+        duration: 0.,
+        screen,
+    });
+}
+
+fn parse_resize(data: &str) -> Option<(usize, usize)> {
+    let (cols, rows) = data.split_once('x')?;
+    Some((cols.trim().parse().ok()?, rows.trim().parse().ok()?))
+}
+
+/// Plays the recording through an offscreen terminal, capturing
+/// a frame whenever the screen changes, but no more often than
+/// `opts.fps` allows
+pub fn capture_frames<R: BufRead>(
+    reader: CastReader<R>,
+    palette: ColorPalette,
+    opts: &ExportOptions,
+) -> anyhow::Result<Animation> {
+    let header = reader.header();
+    let mut cols = header.width() as usize;
+    let mut rows = header.height() as usize;
+    let mut terminal = Terminal::new(
+        TerminalSize {
+            rows,
+            cols,
+            ..Default::default()
+        },
+        Arc::new(ExportConfig {
+            palette: palette.clone(),
+        }),
+        "arb",
+        config::arb_version(),
+        Box::new(std::io::sink()),
+    );
+
+    // As with asciinema players, the limit from the recording
+    // applies unless one was specified
+    let idle_time_limit = opts.idle_time_limit.or(header.idle_time_limit());
+    let min_interval = 1. / opts.fps.max(1.);
+    let mut frames: Vec<Frame> = vec![];
+    // The (idle limited) time at which an uncaptured change was made
+    let mut pending: Option<f32> = None;
+    let mut last_event = 0.;
+    let mut now = 0.;
+
+    for event in reader {
+        let event = event?;
+        let mut gap = (event.time - last_event).max(0.);
+        last_event = event.time;
+        if let Some(limit) = idle_time_limit {
+            gap = gap.min(limit);
+        }
+        now += gap;
+
+        if let Some(start) = pending {
+            if now - start >= min_interval {
+                push_frame(&mut frames, start, capture_screen(&terminal, &palette));
+                pending = None;
+            }
+        }
+
+        match event.kind {
+            EventKind::Output => terminal.advance_bytes(event.data.as_bytes()),
+            EventKind::Resize => match parse_resize(&event.data) {
+                Some((new_cols, new_rows)) => {
+                    terminal.resize(TerminalSize {
+                        rows: new_rows,
+                        cols: new_cols,
+                        ..Default::default()
+                    });
+                    cols = cols.max(new_cols);
+                    rows = rows.max(new_rows);
+                }
+                None => log::warn!("ignoring malformed resize event {:?}", event.data),
+            },
+            _ => continue,
+        }
+        if pending.is_none() {
+            pending = Some(now);
+        }
+    }
+    if let Some(start) = pending {
+        push_frame(&mut frames, start, capture_screen(&terminal, &palette));
+    }
+
+    if frames.is_empty() {
+        anyhow::bail!("the recording has no output");
+    }
+
+    for idx in 0..frames.len() {
+        frames[idx].duration = match frames.get(idx + 1) {
+            Some(next) => next.time - frames[idx].time,
+            None => opts.final_frame_hold.max(min_interval),
+        };
+    }
+
+    Ok(Animation {
+        frames,
+        palette,
+        cols,
+        rows,
+    })
+}
+
+/// Renders `cast_file` into `output`, choosing the format
+/// from the extension of `output`
+pub fn export(
+    config: &config::ConfigHandle,
+    cast_file: &Path,
+    output: &Path,
+    opts: &ExportOptions,
+) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    let format = ExportFormat::from_path(output)?;
+    let reader = CastReader::new(std::io::BufReader::new(
+        std::fs::File::open(cast_file)
+            .with_context(|| format!("reading cast file {}", cast_file.display()))?,
+    ))?;
+
+    let palette: ColorPalette = config.resolved_palette.clone().into();
+    let animation = capture_frames(reader, palette, opts)?;
+
+    let mut file = std::io::BufWriter::new(
+        std::fs::File::create(output).with_context(|| format!("creating {}", output.display()))?,
+    );
+    match format {
+        ExportFormat::Svg => {
+            let font_size = opts.font_size.unwrap_or(config.font_size);
+            svg::write_svg(
+                &mut file,
+                &animation,
+                &svg::SvgOptions::new(config, font_size),
+            )?
+        }
+        ExportFormat::Gif => raster::write_raster(
+            &mut file,
+            &animation,
+            config,
+            opts,
+            raster::RasterFormat::Gif,
+        )?,
+        ExportFormat::Png => raster::write_raster(
+            &mut file,
+            &animation,
+            config,
+            opts,
+            raster::RasterFormat::Png,
+        )?,
+    }
+    std::io::Write::flush(&mut file)?;
+
+    eprintln!(
+        "Wrote {} frames to {}",
+        animation.frames.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animation(cast: &str, opts: &ExportOptions) -> Animation {
+        let reader = CastReader::new(cast.as_bytes()).unwrap();
+        capture_frames(reader, ColorPalette::default(), opts).unwrap()
+    }
+
+    fn row_text(screen: &Screen, row: usize) -> String {
+        screen.lines[row]
+            .iter()
+            .map(|run| run.text.as_str())
+            .collect::<String>()
+            .trim_end()
+            .to_string()
+    }
+
+    #[test]
+    fn should_determine_format_from_extension() {
+        assert_eq!(
+            ExportFormat::from_path(Path::new("demo.GIF")).unwrap(),
+            ExportFormat::Gif
+        );
+        assert_eq!(
+            ExportFormat::from_path(Path::new("demo.apng")).unwrap(),
+            ExportFormat::Png
+        );
+        assert_eq!(
+            ExportFormat::from_path(Path::new("out/demo.svg")).unwrap(),
+            ExportFormat::Svg
+        );
+        assert!(ExportFormat::from_path(Path::new("demo.cast")).is_err());
+    }
+
+    #[test]
+    fn should_coalesce_output_within_a_frame() {
+        let cast = "{\"version\": 2, \"width\": 20, \"height\": 3}\n\
+                    [0.0, \"o\", \"a\"]\n\
+                    [0.01, \"o\", \"b\"]\n\
+                    [1.0, \"m\", \"marker\"]\n\
+                    [1.5, \"o\", \"\\r\\nc\"]\n";
+        let anim = animation(
+            cast,
+            &ExportOptions {
+                fps: 10.,
+                ..Default::default()
+            },
+        );
+        assert_eq!(anim.frames.len(), 2);
+        assert_eq!(row_text(&anim.frames[0].screen, 0), "ab");
+        assert_eq!(anim.frames[0].time, 0.);
+        assert_eq!(anim.frames[0].duration, 1.5);
+        assert_eq!(row_text(&anim.frames[1].screen, 1), "c");
+        assert_eq!(anim.frames[1].duration, 2.);
+        assert_eq!(anim.frames[1].screen.cursor, Some((1, 1)));
+    }
+
+    #[test]
+    fn should_limit_idle_time() {
+        let cast = "{\"version\": 3, \"term\": {\"cols\": 20, \"rows\": 3}}\n\
+                    [0.5, \"o\", \"a\"]\n\
+                    [10.0, \"o\", \"b\"]\n";
+        let anim = animation(
+            cast,
+            &ExportOptions {
+                idle_time_limit: Some(1.),
+                ..Default::default()
+            },
+        );
+        let times: Vec<f32> = anim.frames.iter().map(|f| f.time).collect();
+        assert_eq!(times, vec![0.5, 1.5]);
+    }
+
+    #[test]
+    fn should_follow_resize_events() {
+        let cast = "{\"version\": 2, \"width\": 10, \"height\": 2}\n\
+                    [0.0, \"o\", \"x\"]\n\
+                    [1.0, \"r\", \"30x5\"]\n\
+                    [2.0, \"o\", \"y\"]\n";
+        let anim = animation(cast, &ExportOptions::default());
+        assert_eq!((anim.cols, anim.rows), (30, 5));
+        let last = &anim.frames.last().unwrap().screen;
+        assert_eq!((last.cols, last.rows), (30, 5));
+    }
+
+    #[test]
+    fn should_resolve_reverse_video_colors() {
+        let cast = "{\"version\": 2, \"width\": 10, \"height\": 1}\n\
+                    [0.0, \"o\", \"\\u001b[7mR\"]\n";
+        let anim = animation(cast, &ExportOptions::default());
+        let palette = ColorPalette::default();
+        let run = &anim.frames[0].screen.lines[0][0];
+        assert_eq!(run.text, "R");
+        assert_eq!(run.fg, palette.background);
+        assert_eq!(run.bg, palette.foreground);
+    }
+}
@@ -0,0 +1,391 @@
+//! Rasterizes frames using the same font stack as the GUI, and
+//! encodes them as an animated GIF or PNG.
+
+use super::{Animation, ExportOptions, Run, Screen};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, RgbaImage};
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+use wezterm_bidi::Direction;
+use wezterm_font::{FallbackIdx, FontConfiguration, LoadedFont, LoadedFontId, RasterizedGlyph};
+use wezterm_term::color::SrgbaTuple;
+use wezterm_term::{Intensity, Underline};
+
+type Rgba = [u8; 4];
+
+/// The formats that `write_raster` can produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RasterFormat {
+    Gif,
+    /// Animated PNG
+    Png,
+}
+
+fn rgba(c: SrgbaTuple) -> Rgba {
+    let (r, g, b, _) = c.to_srgb_u8();
+    [r, g, b, 0xff]
+}
+
+fn blend(dest: &mut Rgba, src: Rgba, alpha: u8) {
+    let alpha = alpha as u32;
+    for i in 0..3 {
+        dest[i] = ((src[i] as u32 * alpha + dest[i] as u32 * (255 - alpha)) / 255) as u8;
+    }
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<Rgba>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize, bg: Rgba) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![bg; width * height],
+        }
+    }
+
+    fn fill_rect(&mut self, x: isize, y: isize, width: usize, height: usize, color: Rgba) {
+        for py in y.max(0)..(y + height as isize).min(self.height as isize) {
+            for px in x.max(0)..(x + width as isize).min(self.width as isize) {
+                self.pixels[py as usize * self.width + px as usize] = color;
+            }
+        }
+    }
+
+    /// Draws a glyph with its top left corner at x, y.
+    /// Monochrome glyphs are tinted with `fg`; color glyphs
+    /// (which are premultiplied) are composited as-is.
+    fn draw_glyph(&mut self, glyph: &RasterizedGlyph, x: isize, y: isize, fg: Rgba) {
+        for gy in 0..glyph.height {
+            let py = y + gy as isize;
+            if py < 0 || py >= self.height as isize {
+                continue;
+            }
+            for gx in 0..glyph.width {
+                let px = x + gx as isize;
+                if px < 0 || px >= self.width as isize {
+                    continue;
+                }
+                let offset = (gy * glyph.width + gx) * 4;
+                let src = &glyph.data[offset..offset + 4];
+                let alpha = src[3];
+                if alpha == 0 {
+                    continue;
+                }
+                let dest = &mut self.pixels[py as usize * self.width + px as usize];
+                if glyph.has_color {
+                    for i in 0..3 {
+                        dest[i] = (src[i] as u32 + dest[i] as u32 * (255 - alpha as u32) / 255)
+                            .min(255) as u8;
+                    }
+                } else {
+                    blend(dest, fg, alpha);
+                }
+            }
+        }
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.pixels.into_iter().flatten().collect()
+    }
+}
+
+/// Returns the color of the glyph in column `col` of `run`.
+/// `cursor` is the column of the cursor on the row of the run, along
+/// with the color of text in the cursor cell.
+fn glyph_fg(run: &Run, col: usize, fg: Rgba, cursor: Option<(usize, Rgba)>) -> Rgba {
+    match cursor {
+        Some((cursor_col, cursor_fg)) if cursor_col == run.col + col => cursor_fg,
+        _ => fg,
+    }
+}
+
+struct Rasterizer<'a> {
+    config: &'a config::ConfigHandle,
+    fonts: FontConfiguration,
+    cell_width: usize,
+    cell_height: usize,
+    /// The baseline, relative to the top of the cell
+    baseline: isize,
+    underline_thickness: usize,
+    underline_position: isize,
+    glyphs: HashMap<(LoadedFontId, u32, FallbackIdx), Rc<RasterizedGlyph>>,
+}
+
+impl<'a> Rasterizer<'a> {
+    fn new(config: &'a config::ConfigHandle, opts: &ExportOptions) -> anyhow::Result<Self> {
+        let dpi = config.dpi.unwrap_or(96.) as usize;
+        let fonts = FontConfiguration::new(Some(config.clone()), dpi)?;
+        if let Some(font_size) = opts.font_size {
+            fonts.change_scaling(font_size / config.font_size, dpi);
+        }
+        let metrics = fonts.default_font_metrics()?;
+
+        let cell_height = metrics.cell_height.get().ceil() as usize;
+        Ok(Self {
+            config,
+            fonts,
+            cell_width: metrics.cell_width.get().ceil() as usize,
+            cell_height,
+            baseline: (cell_height as f64 + metrics.descender.get()).round() as isize,
+            underline_thickness: metrics.underline_thickness.get().round().max(1.) as usize,
+            underline_position: metrics.underline_position.get().round() as isize,
+            glyphs: HashMap::new(),
+        })
+    }
+
+    fn glyph(
+        &mut self,
+        font: &LoadedFont,
+        glyph_pos: u32,
+        font_idx: FallbackIdx,
+    ) -> anyhow::Result<Rc<RasterizedGlyph>> {
+        let key = (font.id(), glyph_pos, font_idx);
+        if let Some(glyph) = self.glyphs.get(&key) {
+            return Ok(Rc::clone(glyph));
+        }
+        let glyph = Rc::new(font.rasterize_glyph(glyph_pos, font_idx)?);
+        self.glyphs.insert(key, Rc::clone(&glyph));
+        Ok(glyph)
+    }
+
+    fn draw_run(
+        &mut self,
+        canvas: &mut Canvas,
+        run: &Run,
+        row: usize,
+        bg: Rgba,
+        cursor: Option<(usize, Rgba)>,
+    ) -> anyhow::Result<()> {
+        let top = (row * self.cell_height) as isize;
+        let left = (run.col * self.cell_width) as isize;
+        let width = run.width * self.cell_width;
+
+        let mut fg = rgba(run.fg);
+        if run.attrs.intensity() == Intensity::Half {
+            let mut dim = bg;
+            blend(&mut dim, fg, 0x80);
+            fg = dim;
+        }
+
+        if run.attrs.underline() != Underline::None {
+            let y = top + self.baseline - self.underline_position;
+            canvas.fill_rect(left, y, width, self.underline_thickness, fg);
+        }
+        if run.attrs.strikethrough() {
+            let y = top + self.cell_height as isize / 2;
+            canvas.fill_rect(left, y, width, self.underline_thickness, fg);
+        }
+
+        if run.text.trim().is_empty() {
+            return Ok(());
+        }
+
+        let config = self.config;
+        let style = self.fonts.match_style(config, &run.attrs);
+        let font = self.fonts.resolve_font(style)?;
+        let infos = font.blocking_shape(&run.text, None, Direction::LeftToRight, None, None)?;
+
+        // Map the byte offset of each cluster to the column it starts in
+        let cols: HashMap<usize, usize> = run
+            .text
+            .char_indices()
+            .map(|(idx, _)| idx)
+            .zip(run.char_cols.iter().copied())
+            .collect();
+
+        for info in infos {
+            if info.is_space {
+                continue;
+            }
+            let col = cols.get(&(info.cluster as usize)).copied().unwrap_or(0);
+            let glyph = self.glyph(&font, info.glyph_pos, info.font_idx)?;
+            if glyph.width == 0 || glyph.height == 0 {
+                continue;
+            }
+            let x = left
+                + (col * self.cell_width) as isize
+                + (glyph.bearing_x.get() + info.x_offset.get()).round() as isize;
+            let y = top + self.baseline
+                - (glyph.bearing_y.get() + info.y_offset.get()).round() as isize;
+            canvas.draw_glyph(&glyph, x, y, glyph_fg(run, col, fg, cursor));
+        }
+        Ok(())
+    }
+
+    fn draw_screen(&mut self, screen: &Screen, anim: &Animation) -> anyhow::Result<Canvas> {
+        let default_bg = rgba(anim.palette.background);
+        let mut canvas = Canvas::new(
+            anim.cols * self.cell_width,
+            anim.rows * self.cell_height,
+            default_bg,
+        );
+
+        for (row, line) in screen.lines.iter().enumerate() {
+            for run in line.iter().filter(|run| run.bg != anim.palette.background) {
+                canvas.fill_rect(
+                    (run.col * self.cell_width) as isize,
+                    (row * self.cell_height) as isize,
+                    run.width * self.cell_width,
+                    self.cell_height,
+                    rgba(run.bg),
+                );
+            }
+        }
+
+        if let Some((col, row)) = screen.cursor {
+            canvas.fill_rect(
+                (col * self.cell_width) as isize,
+                (row * self.cell_height) as isize,
+                self.cell_width,
+                self.cell_height,
+                rgba(anim.palette.cursor_bg),
+            );
+        }
+
+        let cursor_fg = rgba(anim.palette.cursor_fg);
+        for (row, line) in screen.lines.iter().enumerate() {
+            let cursor = screen
+                .cursor
+                .filter(|&(_, cursor_row)| cursor_row == row)
+                .map(|(col, _)| (col, cursor_fg));
+            for run in line {
+                self.draw_run(&mut canvas, run, row, rgba(run.bg), cursor)?;
+            }
+        }
+
+        Ok(canvas)
+    }
+}
+
+/// Converts a duration in seconds to milliseconds, as used by
+/// the frame delays of the encoders
+fn duration_ms(seconds: f32) -> u32 {
+    (seconds * 1000.).round().max(1.) as u32
+}
+
+pub fn write_raster<W: Write>(
+    out: &mut W,
+    anim: &Animation,
+    config: &config::ConfigHandle,
+    opts: &ExportOptions,
+    format: RasterFormat,
+) -> anyhow::Result<()> {
+    let mut rasterizer = Rasterizer::new(config, opts)?;
+    let width = (anim.cols * rasterizer.cell_width) as u32;
+    let height = (anim.rows * rasterizer.cell_height) as u32;
+
+    match format {
+        RasterFormat::Gif => {
+            let mut encoder = GifEncoder::new_with_speed(out, 10);
+            encoder.set_repeat(Repeat::Infinite)?;
+            for frame in &anim.frames {
+                let canvas = rasterizer.draw_screen(&frame.screen, anim)?;
+                let image = RgbaImage::from_raw(width, height, canvas.into_bytes())
+                    .ok_or_else(|| anyhow::anyhow!("frame buffer has the wrong size"))?;
+                encoder.encode_frame(image::Frame::from_parts(
+                    image,
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(duration_ms(frame.duration), 1),
+                ))?;
+            }
+        }
+        RasterFormat::Png => {
+            let mut encoder = png::Encoder::new(out, width, height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(anim.frames.len() as u32, 0)?;
+            let mut writer = encoder.write_header()?;
+            for frame in &anim.frames {
+                let canvas = rasterizer.draw_screen(&frame.screen, anim)?;
+                let ms = duration_ms(frame.duration).min(u16::MAX as u32) as u16;
+                writer.set_frame_delay(ms, 1000)?;
+                writer.write_image_data(&canvas.into_bytes())?;
+            }
+            writer.finish()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wezterm_font::units::PixelLength;
+    use wezterm_term::CellAttributes;
+
+    const BLACK: Rgba = [0, 0, 0, 0xff];
+    const WHITE: Rgba = [0xff, 0xff, 0xff, 0xff];
+    const RED: Rgba = [0xff, 0, 0, 0xff];
+
+    fn glyph(data: Vec<u8>, width: usize, height: usize, has_color: bool) -> RasterizedGlyph {
+        RasterizedGlyph {
+            data,
+            height,
+            width,
+            bearing_x: PixelLength::new(0.),
+            bearing_y: PixelLength::new(0.),
+            has_color,
+            is_scaled: false,
+        }
+    }
+
+    #[test]
+    fn should_clip_rects_to_the_canvas() {
+        let mut canvas = Canvas::new(3, 2, BLACK);
+        canvas.fill_rect(-1, 1, 3, 5, WHITE);
+        assert_eq!(
+            canvas.pixels,
+            vec![BLACK, BLACK, BLACK, WHITE, WHITE, BLACK]
+        );
+        assert_eq!(canvas.into_bytes().len(), 3 * 2 * 4);
+    }
+
+    #[test]
+    fn should_tint_monochrome_glyphs() {
+        let mut canvas = Canvas::new(2, 1, BLACK);
+        let coverage = vec![0, 0, 0, 0xff, 0, 0, 0, 0x80];
+        canvas.draw_glyph(&glyph(coverage, 2, 1, false), 0, 0, RED);
+        assert_eq!(canvas.pixels, vec![RED, [0x80, 0, 0, 0xff]]);
+    }
+
+    #[test]
+    fn should_composite_color_glyphs() {
+        let mut canvas = Canvas::new(2, 1, WHITE);
+        // Premultiplied: opaque blue, then half transparent blue
+        let data = vec![0, 0, 0xff, 0xff, 0, 0, 0x80, 0x80];
+        canvas.draw_glyph(&glyph(data, 2, 1, true), 0, 0, RED);
+        assert_eq!(
+            canvas.pixels,
+            vec![[0, 0, 0xff, 0xff], [0x7f, 0x7f, 0xff, 0xff]]
+        );
+    }
+
+    #[test]
+    fn should_draw_cursor_cell_text_in_cursor_fg() {
+        let run = Run {
+            col: 4,
+            width: 3,
+            text: "abc".to_string(),
+            char_cols: vec![0, 1, 2],
+            attrs: CellAttributes::default(),
+            fg: SrgbaTuple::default(),
+            bg: SrgbaTuple::default(),
+        };
+        assert_eq!(glyph_fg(&run, 1, WHITE, Some((5, RED))), RED);
+        assert_eq!(glyph_fg(&run, 0, WHITE, Some((5, RED))), WHITE);
+        assert_eq!(glyph_fg(&run, 1, WHITE, None), WHITE);
+    }
+
+    #[test]
+    fn should_give_every_frame_a_delay() {
+        assert_eq!(duration_ms(1.5), 1500);
+        assert_eq!(duration_ms(0.), 1);
+    }
+}
@@ -0,0 +1,275 @@
+//! Emits an animated SVG.
+//!
+//! All of the frames are stacked vertically inside a viewport that is
+//! the size of a single frame, and a CSS animation steps the stack up
+//! by one frame height at the start time of each frame.
+
+use super::{Animation, Run, Screen};
+use std::fmt::Write as _;
+use std::io::Write;
+use wezterm_term::color::SrgbaTuple;
+use wezterm_term::Intensity;
+
+#[derive(Debug, Clone)]
+pub struct SvgOptions {
+    /// CSS font-family list
+    pub font_family: String,
+    /// Font size in pixels
+    pub font_size: f64,
+    pub cell_width: f64,
+    pub cell_height: f64,
+}
+
+impl SvgOptions {
+    pub fn new(config: &config::ConfigHandle, font_size: f64) -> Self {
+        // SVG user units are CSS pixels, which are 1/96th of an inch
+        let font_size = font_size * 96. / 72.;
+        let mut families: Vec<String> = config
+            .font
+            .font
+            .iter()
+            .map(|attr| format!("'{}'", attr.family.replace('\'', "")))
+            .collect();
+        families.push("monospace".to_string());
+
+        Self {
+            font_family: families.join(", "),
+            font_size,
+            // Typical advance of a monospace font; the viewer picks
+            // the actual font so we can't measure it here
+            cell_width: (font_size * 0.6 * config.cell_width).round(),
+            cell_height: (font_size * 1.2 * config.line_height).round(),
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn color(c: SrgbaTuple) -> String {
+    c.to_rgb_string()
+}
+
+fn render_run(out: &mut String, run: &Run, y: f64, opts: &SvgOptions) {
+    let x: Vec<String> = run
+        .char_cols
+        .iter()
+        .map(|col| format!("{}", (run.col + col) as f64 * opts.cell_width))
+        .collect();
+
+    let mut class = vec![];
+    match run.attrs.intensity() {
+        Intensity::Bold => class.push("b"),
+        Intensity::Half => class.push("h"),
+        Intensity::Normal => {}
+    }
+    if run.attrs.italic() {
+        class.push("i");
+    }
+    let underline = run.attrs.underline() != wezterm_term::Underline::None;
+    match (underline, run.attrs.strikethrough()) {
+        (true, true) => class.push("us"),
+        (true, false) => class.push("u"),
+        (false, true) => class.push("s"),
+        (false, false) => {}
+    }
+
+    let class = if class.is_empty() {
+        String::new()
+    } else {
+        format!(" class=\"{}\"", class.join(" "))
+    };
+
+    let _ = write!(
+        out,
+        "<text x=\"{}\" y=\"{y}\" fill=\"{}\"{class}>{}</text>",
+        x.join(" "),
+        color(run.fg),
+        escape(&run.text)
+    );
+}
+
+fn render_screen(
+    out: &mut String,
+    screen: &Screen,
+    offset: f64,
+    anim: &Animation,
+    opts: &SvgOptions,
+) {
+    let _ = write!(out, "<g transform=\"translate(0 {offset})\">");
+    let default_bg = anim.palette.background;
+
+    for (row, line) in screen.lines.iter().enumerate() {
+        let top = row as f64 * opts.cell_height;
+        for run in line.iter().filter(|run| run.bg != default_bg) {
+            let _ = write!(
+                out,
+                "<rect x=\"{}\" y=\"{top}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                run.col as f64 * opts.cell_width,
+                run.width as f64 * opts.cell_width,
+                opts.cell_height,
+                color(run.bg)
+            );
+        }
+    }
+
+    if let Some((col, row)) = screen.cursor {
+        let _ = write!(
+            out,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            col as f64 * opts.cell_width,
+            row as f64 * opts.cell_height,
+            opts.cell_width,
+            opts.cell_height,
+            color(anim.palette.cursor_bg)
+        );
+    }
+
+    // Place the baseline about where a typical font puts it
+    // within the line, leaving room for descenders
+    let baseline = (opts.cell_height + opts.font_size * 0.7) / 2.;
+    for (row, line) in screen.lines.iter().enumerate() {
+        let y = row as f64 * opts.cell_height + baseline;
+        for run in line.iter().filter(|run| !run.text.trim().is_empty()) {
+            render_run(out, run, y, opts);
+        }
+    }
+    out.push_str("</g>");
+}
+
+/// Produces the SVG document as a string
+pub fn render_svg(anim: &Animation, opts: &SvgOptions) -> String {
+    let width = anim.cols as f64 * opts.cell_width;
+    let height = anim.rows as f64 * opts.cell_height;
+    let total: f32 = anim.frames.iter().map(|f| f.duration).sum();
+
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" \
+         width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">"
+    );
+
+    out.push_str("<style>");
+    let _ = write!(
+        out,
+        "text{{font-family:{};font-size:{}px;white-space:pre}}",
+        escape(&opts.font_family),
+        opts.font_size
+    );
+    out.push_str(
+        ".b{font-weight:bold}.h{opacity:0.5}.i{font-style:italic}\
+         .u{text-decoration:underline}.s{text-decoration:line-through}\
+         .us{text-decoration:underline line-through}",
+    );
+    if anim.frames.len() > 1 {
+        let _ = write!(
+            out,
+            ".frames{{animation:play {total:.3}s step-end infinite}}@keyframes play{{"
+        );
+        for (idx, frame) in anim.frames.iter().enumerate() {
+            let pct = if total > 0. {
+                frame.time / total * 100.
+            } else {
+                0.
+            };
+            let _ = write!(
+                out,
+                "{pct:.3}%{{transform:translateY(-{}px)}}",
+                idx as f64 * height
+            );
+        }
+        out.push('}');
+    }
+    out.push_str("</style>");
+
+    let _ = write!(
+        out,
+        "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>",
+        color(anim.palette.background)
+    );
+    out.push_str("<g class=\"frames\">");
+    for (idx, frame) in anim.frames.iter().enumerate() {
+        render_screen(&mut out, &frame.screen, idx as f64 * height, anim, opts);
+    }
+    out.push_str("</g></svg>\n");
+    out
+}
+
+pub fn write_svg<W: Write>(out: &mut W, anim: &Animation, opts: &SvgOptions) -> anyhow::Result<()> {
+    out.write_all(render_svg(anim, opts).as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{capture_frames, ExportOptions};
+    use super::*;
+    use crate::asciicast::CastReader;
+    use wezterm_term::color::ColorPalette;
+
+    fn opts() -> SvgOptions {
+        SvgOptions {
+            font_family: "'JetBrains Mono', monospace".to_string(),
+            font_size: 16.,
+            cell_width: 10.,
+            cell_height: 20.,
+        }
+    }
+
+    fn render(cast: &str) -> String {
+        let reader = CastReader::new(cast.as_bytes()).unwrap();
+        let anim =
+            capture_frames(reader, ColorPalette::default(), &ExportOptions::default()).unwrap();
+        render_svg(&anim, &opts())
+    }
+
+    #[test]
+    fn should_render_single_frame_without_animation() {
+        let svg = render(
+            "{\"version\": 2, \"width\": 8, \"height\": 2}\n\
+             [0.0, \"o\", \"a<b & \\u001b[1mc\"]\n",
+        );
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.contains("width=\"80\" height=\"40\""));
+        assert!(!svg.contains("@keyframes"));
+        assert!(svg.contains(">a&lt;b </text>"), "{svg}");
+        assert!(svg.contains(">&amp; </text>"), "{svg}");
+        assert!(svg.contains("class=\"b\">c</text>"));
+        assert!(svg.contains("font-family:'JetBrains Mono', monospace"));
+    }
+
+    #[test]
+    fn should_step_through_frames() {
+        let svg = render(
+            "{\"version\": 2, \"width\": 4, \"height\": 1}\n\
+             [0.0, \"o\", \"a\"]\n\
+             [1.0, \"o\", \"b\"]\n",
+        );
+        // Two frames of one second and the final hold of two seconds
+        assert!(svg.contains("animation:play 3.000s step-end infinite"));
+        assert!(svg.contains("0.000%{transform:translateY(-0px)}"));
+        assert!(svg.contains("33.333%{transform:translateY(-20px)}"));
+        assert!(svg.contains("<g transform=\"translate(0 20)\">"));
+    }
+
+    #[test]
+    fn should_position_wide_characters_by_cell() {
+        let svg = render(
+            "{\"version\": 2, \"width\": 8, \"height\": 1}\n\
+             [0.0, \"o\", \"\u{4f60}\u{597d}x\\u001b[?25l\"]\n",
+        );
+        assert!(svg.contains("<text x=\"0 20 40"), "{svg}");
+    }
+}
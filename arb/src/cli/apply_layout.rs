@@ -0,0 +1,70 @@
+use crate::cli::CliOutputFormatKind;
+use clap::{Parser, ValueHint};
+use config::ConfigHandle;
+use mux::layout::Layout;
+use mux::window::WindowId;
+use std::path::PathBuf;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct ApplyLayout {
+    /// The workspace in which to create the tabs.
+    /// Overrides the workspace named by the layout file.
+    /// The default is the `default_workspace` from the config.
+    #[arg(long)]
+    workspace: Option<String>,
+
+    /// Controls the output format.
+    /// "table" and "json" are possible formats.
+    #[arg(long = "format", default_value = "table")]
+    format: CliOutputFormatKind,
+
+    /// The layout file, in the TOML format produced by `dump-layout`.
+    /// Relative working directories in the file are relative to
+    /// the directory that contains it.
+    #[arg(value_parser, value_hint=ValueHint::FilePath)]
+    layout: PathBuf,
+}
+
+// This will be serialized to JSON via the 'ApplyLayout' command.
+// As such it is intended to be a stable output format.
+#[derive(serde::Serialize)]
+struct CliApplyLayoutResult {
+    window_id: WindowId,
+    workspace: String,
+}
+
+impl ApplyLayout {
+    pub async fn run(self, client: Client, config: &ConfigHandle) -> anyhow::Result<()> {
+        let mut layout = Layout::load(&self.layout)?;
+        if self.workspace.is_some() {
+            layout.workspace = self.workspace;
+        }
+        let workspace = layout.workspace.clone().unwrap_or_else(|| {
+            config
+                .default_workspace
+                .as_deref()
+                .unwrap_or(mux::DEFAULT_WORKSPACE)
+                .to_string()
+        });
+
+        let response = client
+            .apply_layout(codec::ApplyLayout {
+                layout,
+                workspace: workspace.clone(),
+                size: config.initial_size(0, None),
+            })
+            .await?;
+
+        match self.format {
+            CliOutputFormatKind::Json => crate::cli::print_json(&CliApplyLayoutResult {
+                window_id: response.window_id,
+                workspace,
+            }),
+            CliOutputFormatKind::Table => {
+                println!("{}", response.window_id);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use clap::Parser;
+use mux::pane::PaneId;
+use mux::tab::TabId;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct DumpLayout {
+    /// Dump all of the tabs in this workspace,
+    /// rather than a single tab
+    #[arg(long, conflicts_with_all=&["tab_id", "pane_id"])]
+    workspace: Option<String>,
+
+    /// Specify the target tab by its id
+    #[arg(long, conflicts_with_all=&["pane_id"])]
+    tab_id: Option<TabId>,
+
+    /// Specify the current pane.
+    /// The default is to use the current pane based on the
+    /// environment variable ARB_PANE (or WEZTERM_PANE).
+    ///
+    /// The pane is used to figure out which tab should be dumped.
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+}
+
+impl DumpLayout {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let tab_id = match (&self.workspace, self.tab_id) {
+            (Some(_), _) => None,
+            (None, Some(tab_id)) => Some(tab_id),
+            (None, None) => Some(
+                crate::cli::resolve_pane_entry(&client, self.pane_id)
+                    .await?
+                    .tab_id,
+            ),
+        };
+
+        let response = client
+            .dump_layout(codec::DumpLayout {
+                tab_id,
+                workspace: self.workspace,
+            })
+            .await?;
+        print!("{}", response.layout.to_toml()?);
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::cli::activate_pane_direction::PaneDirectionParser;
+use crate::cli::CliOutputFormatKind;
 use clap::Parser;
 use config::keyassignment::PaneDirection;
 use mux::pane::PaneId;
@@ -15,6 +16,17 @@ pub struct GetPaneDirection {
     /// The direction to consider.
     #[arg(value_parser=PaneDirectionParser{})]
     direction: PaneDirection,
+
+    /// Controls the output format.
+    /// "table" and "json" are possible formats.
+    #[arg(long = "format", default_value = "table")]
+    format: CliOutputFormatKind,
+}
+
+#[derive(serde::Serialize)]
+struct CliGetPaneDirectionResult {
+    /// None if there is no pane in that direction
+    pane_id: Option<PaneId>,
 }
 
 impl GetPaneDirection {
@@ -26,8 +38,15 @@ impl GetPaneDirection {
                 direction: self.direction,
             })
             .await?;
-        if let Some(pane_id) = response.pane_id {
-            println!("{pane_id}");
+        match self.format {
+            CliOutputFormatKind::Json => crate::cli::print_json(&CliGetPaneDirectionResult {
+                pane_id: response.pane_id,
+            })?,
+            CliOutputFormatKind::Table => {
+                if let Some(pane_id) = response.pane_id {
+                    println!("{pane_id}");
+                }
+            }
         }
         Ok(())
     }
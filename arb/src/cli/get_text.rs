@@ -1,3 +1,4 @@
+use crate::cli::CliOutputFormatKind;
 use clap::Parser;
 use mux::pane::PaneId;
 use termwiz_funcs::lines_to_escapes;
@@ -32,6 +33,21 @@ pub struct GetText {
     /// If omitted, unattributed text will be returned.
     #[arg(long)]
     escapes: bool,
+
+    /// Controls the output format.
+    /// "table" prints the text as-is, while "json" reports each
+    /// line along with its line number.
+    #[arg(long = "format", default_value = "table")]
+    format: CliOutputFormatKind,
+}
+
+// This will be serialized to JSON via the 'GetText' command.
+// As such it is intended to be a stable output format.
+#[derive(serde::Serialize)]
+struct CliGetTextResultLine {
+    /// The line number, using the same scheme as --start-line
+    line: isize,
+    text: String,
 }
 
 impl GetText {
@@ -76,18 +92,35 @@ impl GetText {
             })
             .await?;
 
-        let lines = lines
-            .lines
-            .extract_data()
-            .0
-            .into_iter()
-            .map(|(_idx, line)| line)
-            .collect();
+        let lines: Vec<_> = lines.lines.extract_data().0;
 
-        if self.escapes {
-            println!("{}", lines_to_escapes(lines)?);
-        } else {
-            lines.iter().for_each(|line| println!("{}", line.as_str()));
+        match self.format {
+            CliOutputFormatKind::Json => {
+                let lines = lines
+                    .into_iter()
+                    .map(|(idx, line)| {
+                        Ok(CliGetTextResultLine {
+                            line: idx as isize - info.dimensions.physical_top as isize,
+                            text: if self.escapes {
+                                lines_to_escapes(vec![line])?
+                                    .trim_end_matches(['\r', '\n'])
+                                    .to_string()
+                            } else {
+                                line.as_str().to_string()
+                            },
+                        })
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                crate::cli::print_json(&lines)?;
+            }
+            CliOutputFormatKind::Table => {
+                let lines = lines.into_iter().map(|(_idx, line)| line).collect();
+                if self.escapes {
+                    println!("{}", lines_to_escapes(lines)?);
+                } else {
+                    lines.iter().for_each(|line| println!("{}", line.as_str()));
+                }
+            }
         }
         Ok(())
     }
@@ -0,0 +1,35 @@
+use clap::Parser;
+use mux::pane::PaneId;
+use mux::tab::TabId;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct KillTab {
+    /// Specify the target tab by its id
+    #[arg(long, conflicts_with_all=&["pane_id"])]
+    tab_id: Option<TabId>,
+
+    /// Specify the current pane.
+    /// The default is to use the current pane based on the
+    /// environment variable ARB_PANE (or WEZTERM_PANE).
+    ///
+    /// The pane is used to figure out which tab should be closed.
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+}
+
+impl KillTab {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let tab_id = match self.tab_id {
+            Some(tab_id) => tab_id,
+            None => {
+                crate::cli::resolve_pane_entry(&client, self.pane_id)
+                    .await?
+                    .tab_id
+            }
+        };
+
+        client.kill_tab(codec::KillTab { tab_id }).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+use clap::Parser;
+use mux::pane::PaneId;
+use mux::window::WindowId;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct KillWindow {
+    /// Specify the target window by its id
+    #[arg(long, conflicts_with_all=&["pane_id"])]
+    window_id: Option<WindowId>,
+
+    /// Specify the current pane.
+    /// The default is to use the current pane based on the
+    /// environment variable ARB_PANE (or WEZTERM_PANE).
+    ///
+    /// The pane is used to figure out which window should be closed.
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+}
+
+impl KillWindow {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let window_id = match self.window_id {
+            Some(window_id) => window_id,
+            None => {
+                crate::cli::resolve_pane_entry(&client, self.pane_id)
+                    .await?
+                    .window_id
+            }
+        };
+
+        client.kill_window(codec::KillWindow { window_id }).await?;
+        Ok(())
+    }
+}
@@ -1,30 +1,105 @@
 use crate::cli::CliOutputFormatKind;
 use clap::Parser;
+use regex::Regex;
 use serde::Serializer as _;
 use tabout::{tabulate_output, Alignment, Column};
 use wezterm_client::client::Client;
 use wezterm_term::TerminalSize;
 
-#[derive(Debug, Parser, Clone, Copy)]
+#[derive(Debug, Parser, Clone)]
 pub struct ListCommand {
     /// Controls the output format.
     /// "table" and "json" are possible formats.
     #[arg(long = "format", default_value = "table")]
     format: CliOutputFormatKind,
+
+    /// Only list panes in this workspace
+    #[arg(long)]
+    workspace: Option<String>,
+
+    /// Only list panes that belong to the domain with this name
+    #[arg(long)]
+    domain: Option<String>,
+
+    /// Only list panes whose title matches this regex
+    #[arg(long, value_name = "REGEX")]
+    title: Option<Regex>,
+
+    /// Only list panes whose current working directory
+    /// contains this string
+    #[arg(long)]
+    cwd: Option<String>,
+
+    /// Only list panes whose foreground process has this name,
+    /// eg: `cargo`.  Either the full path or just the file name
+    /// of the executable may be used.
+    #[arg(long)]
+    process: Option<String>,
 }
 
 impl ListCommand {
+    /// Returns true if the pane satisfies all of the filters
+    fn matches(&self, entry: &mux::tab::PaneEntry) -> bool {
+        if let Some(workspace) = &self.workspace {
+            if entry.workspace != *workspace {
+                return false;
+            }
+        }
+        if let Some(domain) = &self.domain {
+            if entry.domain_name.as_ref() != Some(domain) {
+                return false;
+            }
+        }
+        if let Some(title) = &self.title {
+            if !title.is_match(&entry.title) {
+                return false;
+            }
+        }
+        if let Some(cwd) = &self.cwd {
+            let path = entry
+                .working_dir
+                .as_ref()
+                .map(|url| match url.url.to_file_path() {
+                    Ok(path) => path.display().to_string(),
+                    Err(_) => url.url.path().to_string(),
+                });
+            if !matches!(path, Some(path) if path.contains(cwd.as_str())) {
+                return false;
+            }
+        }
+        if let Some(process) = &self.process {
+            let matched = entry
+                .foreground_process_name
+                .as_deref()
+                .is_some_and(|name| {
+                    name == process
+                        || std::path::Path::new(name)
+                            .file_name()
+                            .is_some_and(|base| base == process.as_str())
+                });
+            if !matched {
+                return false;
+            }
+        }
+        true
+    }
+
     pub async fn run(&self, client: Client) -> anyhow::Result<()> {
         let out = std::io::stdout();
 
         let mut output_items = vec![];
-        let panes = client.list_panes().await?;
+        // The domain and foreground process of each pane are costly
+        // for the server to look up, so only ask for them if needed
+        let details = matches!(self.format, CliOutputFormatKind::Json)
+            || self.domain.is_some()
+            || self.process.is_some();
+        let panes = client.list_panes_with(codec::ListPanes { details }).await?;
 
         for (tabroot, tab_title) in panes.tabs.into_iter().zip(panes.tab_titles.iter()) {
             let mut cursor = tabroot.into_tree().cursor();
 
             loop {
-                if let Some(entry) = cursor.leaf_mut() {
+                if let Some(entry) = cursor.leaf_mut().filter(|entry| self.matches(entry)) {
                     let window_title = panes
                         .window_titles
                         .get(&entry.window_id)
@@ -139,6 +214,8 @@ struct CliListResultItem {
     is_active: bool,
     is_zoomed: bool,
     tty_name: Option<String>,
+    domain_name: String,
+    foreground_process_name: Option<String>,
 }
 
 impl CliListResultItem {
@@ -157,6 +234,8 @@ impl CliListResultItem {
             is_active_pane,
             is_zoomed_pane,
             tty_name,
+            domain_name,
+            foreground_process_name,
             size:
                 TerminalSize {
                     rows,
@@ -197,6 +276,8 @@ impl CliListResultItem {
             is_active: is_active_pane,
             is_zoomed: is_zoomed_pane,
             tty_name,
+            domain_name: domain_name.unwrap_or_default(),
+            foreground_process_name,
         }
     }
 }
@@ -0,0 +1,76 @@
+use crate::cli::CliOutputFormatKind;
+use clap::Parser;
+use tabout::{tabulate_output, Alignment, Column};
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone, Copy)]
+pub struct ListWorkspaces {
+    /// Controls the output format.
+    /// "table" and "json" are possible formats.
+    #[arg(long = "format", default_value = "table")]
+    format: CliOutputFormatKind,
+}
+
+// This will be serialized to JSON via the 'ListWorkspaces' command.
+// As such it is intended to be a stable output format.
+#[derive(serde::Serialize)]
+struct CliListWorkspacesResultItem {
+    name: String,
+    window_ids: Vec<mux::window::WindowId>,
+    num_panes: usize,
+    is_active: bool,
+}
+
+impl ListWorkspaces {
+    pub async fn run(&self, client: Client) -> anyhow::Result<()> {
+        let workspaces = client.list_workspaces().await?.workspaces;
+
+        match self.format {
+            CliOutputFormatKind::Json => {
+                let items: Vec<_> = workspaces
+                    .into_iter()
+                    .map(|w| CliListWorkspacesResultItem {
+                        name: w.name,
+                        window_ids: w.window_ids,
+                        num_panes: w.num_panes,
+                        is_active: w.is_active,
+                    })
+                    .collect();
+                crate::cli::print_json(&items)?;
+            }
+            CliOutputFormatKind::Table => {
+                let cols = vec![
+                    Column {
+                        name: "WORKSPACE".to_string(),
+                        alignment: Alignment::Left,
+                    },
+                    Column {
+                        name: "WINDOWS".to_string(),
+                        alignment: Alignment::Right,
+                    },
+                    Column {
+                        name: "PANES".to_string(),
+                        alignment: Alignment::Right,
+                    },
+                    Column {
+                        name: "ACTIVE".to_string(),
+                        alignment: Alignment::Left,
+                    },
+                ];
+                let data = workspaces
+                    .iter()
+                    .map(|w| {
+                        vec![
+                            w.name.to_string(),
+                            w.window_ids.len().to_string(),
+                            w.num_panes.to_string(),
+                            if w.is_active { "*" } else { "" }.to_string(),
+                        ]
+                    })
+                    .collect::<Vec<_>>();
+                tabulate_output(&cols, &data, &mut std::io::stdout().lock())?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -7,20 +7,30 @@ mod activate_pane;
 mod activate_pane_direction;
 mod activate_tab;
 mod adjust_pane_size;
+mod apply_layout;
+mod dump_layout;
 mod get_pane_direction;
 mod get_text;
 mod kill_pane;
+mod kill_tab;
+mod kill_window;
 mod list;
 mod list_clients;
+mod list_workspaces;
 mod move_pane_to_new_tab;
+mod move_tab_to_window;
 mod proxy;
+mod record_pane;
 mod rename_workspace;
 mod send_text;
 mod set_tab_title;
 mod set_window_title;
 mod spawn_command;
 mod split_pane;
+mod switch_workspace;
+mod tail;
 mod tls_creds;
+mod wait_for;
 mod zoom_pane;
 
 #[derive(Debug, Parser, Clone, Copy)]
@@ -163,6 +173,57 @@ Outputs the pane-id for the newly created pane on success"
     /// Zoom, unzoom, or toggle zoom state
     #[command(name = "zoom-pane", rename_all = "kebab")]
     ZoomPane(zoom_pane::ZoomPane),
+
+    /// Start or stop recording the output of a pane to an asciicast file
+    #[command(name = "record-pane", rename_all = "kebab")]
+    RecordPane(record_pane::RecordPane),
+
+    /// Wait until a pane's output matches a regex, the pane goes idle,
+    /// the next command finishes or the pane is closed
+    #[command(name = "wait-for", rename_all = "kebab")]
+    WaitFor(wait_for::WaitFor),
+
+    /// Print the recent output of a pane, optionally following
+    /// its output as it arrives
+    #[command(name = "tail", rename_all = "kebab")]
+    Tail(tail::Tail),
+
+    /// List the workspaces, marking the one that is active in the gui
+    #[command(name = "list-workspaces", rename_all = "kebab")]
+    ListWorkspaces(list_workspaces::ListWorkspaces),
+
+    /// Switch the gui to a workspace, creating it by
+    /// spawning a command if it doesn't already exist
+    #[command(
+        name = "switch-workspace",
+        rename_all = "kebab",
+        trailing_var_arg = true
+    )]
+    SwitchWorkspace(switch_workspace::SwitchWorkspace),
+
+    /// Move a tab into another window, or into a new window.
+    /// Outputs the window-id of the window containing the tab on success
+    #[command(name = "move-tab-to-window", rename_all = "kebab")]
+    MoveTabToWindow(move_tab_to_window::MoveTabToWindow),
+
+    /// Close a tab, killing the programs running in its panes
+    #[command(name = "kill-tab", rename_all = "kebab")]
+    KillTab(kill_tab::KillTab),
+
+    /// Close a window, killing the programs running in its panes
+    #[command(name = "kill-window", rename_all = "kebab")]
+    KillWindow(kill_window::KillWindow),
+
+    /// Create a window with the tabs and split panes described by
+    /// a layout file.
+    /// Outputs the window-id of the new window on success
+    #[command(name = "apply-layout", rename_all = "kebab")]
+    ApplyLayout(apply_layout::ApplyLayout),
+
+    /// Print the layout of a tab, or of a whole workspace, in the
+    /// format accepted by `apply-layout`
+    #[command(name = "dump-layout", rename_all = "kebab")]
+    DumpLayout(dump_layout::DumpLayout),
 }
 
 async fn run_cli_async(opts: &crate::Opt, cli: CliCommand) -> anyhow::Result<()> {
@@ -199,6 +260,16 @@ async fn run_cli_async(opts: &crate::Opt, cli: CliCommand) -> anyhow::Result<()>
         CliSubCommand::SetWindowTitle(cmd) => cmd.run(client).await,
         CliSubCommand::RenameWorkspace(cmd) => cmd.run(client).await,
         CliSubCommand::ZoomPane(cmd) => cmd.run(client).await,
+        CliSubCommand::RecordPane(cmd) => cmd.run(client).await,
+        CliSubCommand::WaitFor(cmd) => cmd.run(client).await,
+        CliSubCommand::Tail(cmd) => cmd.run(client).await,
+        CliSubCommand::ListWorkspaces(cmd) => cmd.run(client).await,
+        CliSubCommand::SwitchWorkspace(cmd) => cmd.run(client, &crate::init_config(opts)?).await,
+        CliSubCommand::MoveTabToWindow(cmd) => cmd.run(client).await,
+        CliSubCommand::KillTab(cmd) => cmd.run(client).await,
+        CliSubCommand::KillWindow(cmd) => cmd.run(client).await,
+        CliSubCommand::ApplyLayout(cmd) => cmd.run(client, &crate::init_config(opts)?).await,
+        CliSubCommand::DumpLayout(cmd) => cmd.run(client).await,
     }
 }
 
@@ -222,3 +293,70 @@ pub fn resolve_relative_cwd(cwd: Option<OsString>) -> anyhow::Result<Option<Stri
         )),
     }
 }
+
+// This will be serialized to JSON via the 'SpawnCommand' and
+// 'SplitPane' commands.
+// As such it is intended to be a stable output format.
+#[derive(serde::Serialize)]
+struct CliSpawnResult {
+    pane_id: mux::pane::PaneId,
+    tab_id: mux::tab::TabId,
+    window_id: mux::window::WindowId,
+    rows: usize,
+    cols: usize,
+}
+
+/// Prints the ids of a newly spawned pane in the requested format
+fn print_spawned(
+    format: CliOutputFormatKind,
+    spawned: &codec::SpawnResponse,
+) -> anyhow::Result<()> {
+    match format {
+        CliOutputFormatKind::Json => print_json(&CliSpawnResult {
+            pane_id: spawned.pane_id,
+            tab_id: spawned.tab_id,
+            window_id: spawned.window_id,
+            rows: spawned.size.rows,
+            cols: spawned.size.cols,
+        }),
+        CliOutputFormatKind::Table => {
+            println!("{}", spawned.pane_id);
+            Ok(())
+        }
+    }
+}
+
+/// Returns the entry for `pane_id`, or for the current pane
+/// based on the environment if None
+async fn resolve_pane_entry(
+    client: &Client,
+    pane_id: Option<mux::pane::PaneId>,
+) -> anyhow::Result<mux::tab::PaneEntry> {
+    let pane_id = client.resolve_pane_id(pane_id).await?;
+    let panes = client.list_panes().await?;
+    for tabroot in panes.tabs {
+        let mut cursor = tabroot.into_tree().cursor();
+        loop {
+            if let Some(entry) = cursor.leaf_mut() {
+                if entry.pane_id == pane_id {
+                    return Ok(entry.clone());
+                }
+            }
+            match cursor.preorder_next() {
+                Ok(c) => cursor = c,
+                Err(_) => break,
+            }
+        }
+    }
+    anyhow::bail!("unable to resolve pane {pane_id}")
+}
+
+/// Prints `value` to stdout as pretty printed JSON, for subcommands
+/// invoked with `--format json`
+pub fn print_json<T: serde::Serialize>(value: &T) -> anyhow::Result<()> {
+    use std::io::Write;
+    let mut out = std::io::stdout().lock();
+    serde_json::to_writer_pretty(&mut out, value)?;
+    writeln!(out)?;
+    Ok(())
+}
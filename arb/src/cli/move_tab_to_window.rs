@@ -0,0 +1,80 @@
+use crate::cli::CliOutputFormatKind;
+use clap::Parser;
+use mux::pane::PaneId;
+use mux::tab::TabId;
+use mux::window::WindowId;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct MoveTabToWindow {
+    /// Specify the tab to move by its id
+    #[arg(long, conflicts_with_all=&["pane_id"])]
+    tab_id: Option<TabId>,
+
+    /// Specify the current pane.
+    /// The default is to use the current pane based on the
+    /// environment variable ARB_PANE (or WEZTERM_PANE).
+    ///
+    /// The pane is used to figure out which tab should be moved.
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+
+    /// The window into which the tab should be moved
+    #[arg(long, conflicts_with_all=&["new_window", "workspace"])]
+    window_id: Option<WindowId>,
+
+    /// Move the tab into a new window
+    #[arg(long)]
+    new_window: bool,
+
+    /// The workspace for the new window.  The default is the
+    /// workspace of the window that currently contains the tab.
+    /// Requires `--new-window`.
+    #[arg(long, requires = "new_window")]
+    workspace: Option<String>,
+
+    /// Controls the output format.
+    /// "table" and "json" are possible formats.
+    #[arg(long = "format", default_value = "table")]
+    format: CliOutputFormatKind,
+}
+
+#[derive(serde::Serialize)]
+struct CliMoveTabToWindowResult {
+    tab_id: TabId,
+    window_id: WindowId,
+}
+
+impl MoveTabToWindow {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        if self.window_id.is_none() && !self.new_window {
+            anyhow::bail!("one of --window-id or --new-window is required");
+        }
+
+        let tab_id = match self.tab_id {
+            Some(tab_id) => tab_id,
+            None => {
+                crate::cli::resolve_pane_entry(&client, self.pane_id)
+                    .await?
+                    .tab_id
+            }
+        };
+
+        let response = client
+            .move_tab_to_window(codec::MoveTabToWindow {
+                tab_id,
+                window_id: self.window_id,
+                workspace: self.workspace,
+            })
+            .await?;
+
+        match self.format {
+            CliOutputFormatKind::Json => crate::cli::print_json(&CliMoveTabToWindowResult {
+                tab_id,
+                window_id: response.window_id,
+            })?,
+            CliOutputFormatKind::Table => println!("{}", response.window_id),
+        }
+        Ok(())
+    }
+}
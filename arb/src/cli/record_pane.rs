@@ -0,0 +1,92 @@
+use crate::cli::CliOutputFormatKind;
+use clap::Parser;
+use codec::RecordPaneAction;
+use mux::asciicast::CastVersion;
+use mux::pane::PaneId;
+use std::path::PathBuf;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct RecordPane {
+    /// Specify the target pane.
+    /// The default is to use the current pane based on the
+    /// environment variable ARB_PANE (or WEZTERM_PANE).
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+
+    /// Stop an in-progress recording of the pane
+    #[arg(long, conflicts_with_all=&["toggle", "outfile"])]
+    stop: bool,
+
+    /// Stop recording if the pane is being recorded,
+    /// otherwise start recording
+    #[arg(long, conflicts_with = "stop")]
+    toggle: bool,
+
+    /// Save the asciicast to the specified file, instead of
+    /// a new file in the recordings directory of the arb data dir
+    #[arg(short, long)]
+    outfile: Option<PathBuf>,
+
+    /// Which version of the asciicast format to write
+    #[arg(long, default_value_t)]
+    cast_version: CastVersion,
+
+    /// Controls the output format.
+    /// "table" and "json" are possible formats.
+    #[arg(long = "format", default_value = "table")]
+    format: CliOutputFormatKind,
+}
+
+#[derive(serde::Serialize)]
+struct CliRecordPaneResult {
+    pane_id: PaneId,
+    /// true if the pane is now being recorded
+    recording: bool,
+    path: Option<PathBuf>,
+}
+
+impl RecordPane {
+    pub async fn run(&self, client: Client) -> anyhow::Result<()> {
+        let pane_id = client.resolve_pane_id(self.pane_id).await?;
+
+        let action = if self.stop {
+            RecordPaneAction::Stop
+        } else if self.toggle {
+            RecordPaneAction::Toggle
+        } else {
+            RecordPaneAction::Start
+        };
+
+        // The recording is written by the gui or mux server, whose
+        // working directory is unrelated to ours
+        let path = match &self.outfile {
+            Some(path) if path.is_relative() => Some(std::env::current_dir()?.join(path)),
+            path => path.clone(),
+        };
+
+        let response = client
+            .record_pane(codec::RecordPane {
+                pane_id,
+                action,
+                path,
+                cast_version: self.cast_version,
+            })
+            .await?;
+
+        if let CliOutputFormatKind::Json = self.format {
+            return crate::cli::print_json(&CliRecordPaneResult {
+                pane_id,
+                recording: response.recording,
+                path: response.path,
+            });
+        }
+
+        match (response.recording, response.path) {
+            (true, Some(path)) => println!("recording pane {pane_id} to {}", path.display()),
+            (false, Some(path)) => println!("finished recording to {}", path.display()),
+            (_, None) => eprintln!("pane {pane_id} is not being recorded"),
+        }
+        Ok(())
+    }
+}
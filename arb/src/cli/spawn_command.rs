@@ -1,4 +1,4 @@
-use crate::cli::resolve_relative_cwd;
+use crate::cli::{resolve_relative_cwd, CliOutputFormatKind};
 use clap::{Parser, ValueHint};
 use config::keyassignment::SpawnTabDomain;
 use config::ConfigHandle;
@@ -43,6 +43,11 @@ pub struct SpawnCommand {
     #[arg(long, requires = "new_window")]
     workspace: Option<String>,
 
+    /// Controls the output format.
+    /// "table" and "json" are possible formats.
+    #[arg(long = "format", default_value = "table")]
+    format: CliOutputFormatKind,
+
     /// Instead of executing your shell, run PROG.
     /// For example: `wezterm cli spawn -- bash -l` will spawn bash
     /// as if it were a login shell.
@@ -117,7 +122,6 @@ impl SpawnCommand {
             .await?;
 
         log::debug!("{:?}", spawned);
-        println!("{}", spawned.pane_id);
-        Ok(())
+        crate::cli::print_spawned(self.format, &spawned)
     }
 }
@@ -1,4 +1,4 @@
-use crate::cli::resolve_relative_cwd;
+use crate::cli::{resolve_relative_cwd, CliOutputFormatKind};
 use clap::{Parser, ValueHint};
 use mux::pane::PaneId;
 use mux::tab::{SplitDirection, SplitRequest, SplitSize};
@@ -60,6 +60,11 @@ pub struct SplitPane {
     #[arg(long, conflicts_with_all=&["cwd", "prog"])]
     move_pane_id: Option<PaneId>,
 
+    /// Controls the output format.
+    /// "table" and "json" are possible formats.
+    #[arg(long = "format", default_value = "table")]
+    format: CliOutputFormatKind,
+
     /// Instead of executing your shell, run PROG.
     /// For example: `wezterm cli split-pane -- bash -l` will spawn bash
     /// as if it were a login shell.
@@ -107,7 +112,6 @@ impl SplitPane {
             .await?;
 
         log::debug!("{:?}", spawned);
-        println!("{}", spawned.pane_id);
-        Ok(())
+        crate::cli::print_spawned(self.format, &spawned)
     }
 }
@@ -0,0 +1,64 @@
+use crate::cli::resolve_relative_cwd;
+use clap::{Parser, ValueHint};
+use config::keyassignment::SpawnTabDomain;
+use config::ConfigHandle;
+use portable_pty::cmdbuilder::CommandBuilder;
+use std::ffi::OsString;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct SwitchWorkspace {
+    /// Specify the domain in which to spawn the initial program
+    /// if the workspace doesn't yet exist
+    #[arg(long)]
+    domain_name: Option<String>,
+
+    /// Specify the current working directory for the initially
+    /// spawned program
+    #[arg(long, value_parser, value_hint=ValueHint::DirPath)]
+    cwd: Option<OsString>,
+
+    /// The name of the workspace to activate
+    workspace: String,
+
+    /// If the workspace doesn't yet exist, it is created by spawning
+    /// a new window running PROG, or your shell if omitted.
+    /// For example: `arb cli switch-workspace api -- cargo watch`
+    #[arg(value_parser, value_hint=ValueHint::CommandWithArguments, num_args=1..)]
+    prog: Vec<OsString>,
+}
+
+impl SwitchWorkspace {
+    pub async fn run(self, client: Client, config: &ConfigHandle) -> anyhow::Result<()> {
+        let workspaces = client.list_workspaces().await?.workspaces;
+
+        if !workspaces.iter().any(|w| w.name == self.workspace) {
+            let spawned = client
+                .spawn_v2(codec::SpawnV2 {
+                    domain: self
+                        .domain_name
+                        .map_or(SpawnTabDomain::DefaultDomain, |name| {
+                            SpawnTabDomain::DomainName(name)
+                        }),
+                    window_id: None,
+                    command: if self.prog.is_empty() {
+                        None
+                    } else {
+                        Some(CommandBuilder::from_argv(self.prog))
+                    },
+                    command_dir: resolve_relative_cwd(self.cwd)?,
+                    size: config.initial_size(0, None),
+                    workspace: self.workspace.clone(),
+                })
+                .await?;
+            log::debug!("{:?}", spawned);
+        }
+
+        client
+            .set_active_workspace(codec::SetActiveWorkspace {
+                workspace: self.workspace,
+            })
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use clap::Parser;
+use mux::pane::PaneId;
+use std::io::Write;
+use termwiz_funcs::lines_to_escapes;
+use wezterm_client::client::Client;
+use wezterm_term::{Line, StableRowIndex};
+
+#[derive(Debug, Parser, Clone)]
+pub struct Tail {
+    /// Specify the target pane.
+    /// The default is to use the current pane based on the
+    /// environment variable ARB_PANE (or WEZTERM_PANE).
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+
+    /// How many lines of existing output to print,
+    /// counting back from the line holding the cursor
+    #[arg(long, short = 'n', default_value_t = 10)]
+    lines: usize,
+
+    /// Keep printing the output of the pane as it arrives,
+    /// until the pane is closed
+    #[arg(long, short = 'f')]
+    follow: bool,
+
+    /// Include escape sequences that color and style the text.
+    /// If omitted, only the text and line breaks are printed.
+    #[arg(long)]
+    escapes: bool,
+}
+
+impl Tail {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let pane_id = client.resolve_pane_id(self.pane_id).await?;
+
+        // When following, the existing lines are taken together with
+        // the subscription, so that the streamed output continues from
+        // exactly where they end
+        let (mut lines, rx) = if self.follow {
+            let (lines, rx) = client
+                .subscribe_pane_output(pane_id, self.escapes, self.lines)
+                .await?;
+            (lines, Some(rx))
+        } else {
+            (self.existing_lines(&client, pane_id).await?, None)
+        };
+
+        let mut stdout = std::io::stdout().lock();
+
+        // The cursor is commonly on a fresh line after the last output
+        let cursor_line_empty = matches!(lines.last(), Some(line) if line.is_whitespace());
+        if cursor_line_empty {
+            lines.pop();
+        }
+        let mut text = if self.escapes {
+            lines_to_escapes(lines)?
+        } else {
+            lines
+                .iter()
+                .map(|line| line.as_str().trim_end().to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        // When following, the streamed output continues from the
+        // cursor position, so only break the line if the cursor
+        // is on a line of its own
+        if (!self.follow || cursor_line_empty) && !text.is_empty() {
+            text.push('\n');
+        }
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()?;
+
+        if let Some(rx) = rx {
+            while let Ok(output) = rx.recv().await {
+                if stdout
+                    .write_all(output.text.as_bytes())
+                    .and_then(|_| stdout.flush())
+                    .is_err()
+                {
+                    // Most likely the reader of our output went away
+                    break;
+                }
+                if output.overflowed {
+                    anyhow::bail!("fell behind the output of pane {pane_id}; stopped following");
+                }
+                if output.finished {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns up to `self.lines` lines, ending with the line
+    /// holding the cursor
+    async fn existing_lines(&self, client: &Client, pane_id: PaneId) -> anyhow::Result<Vec<Line>> {
+        if self.lines == 0 {
+            return Ok(vec![]);
+        }
+        let info = client
+            .get_dimensions(codec::GetPaneRenderableDimensions { pane_id })
+            .await?;
+        let cursor_row = info.cursor_position.y;
+        let start_line =
+            (cursor_row + 1 - self.lines as StableRowIndex).max(info.dimensions.scrollback_top);
+
+        let lines = client
+            .get_lines(codec::GetLines {
+                pane_id,
+                #[allow(clippy::single_range_in_vec_init)]
+                lines: vec![start_line..cursor_row + 1],
+            })
+            .await?;
+        Ok(lines
+            .lines
+            .extract_data()
+            .0
+            .into_iter()
+            .map(|(_idx, line)| line)
+            .collect())
+    }
+}
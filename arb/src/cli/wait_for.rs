@@ -0,0 +1,149 @@
+use crate::cli::CliOutputFormatKind;
+use clap::Parser;
+use codec::{WaitCondition, WaitForPaneResult};
+use mux::pane::PaneId;
+use std::time::Duration;
+use wezterm_client::client::Client;
+use wezterm_term::{ScrollbackOrVisibleRowIndex, StableRowIndex};
+
+#[derive(Debug, Parser, Clone)]
+pub struct WaitFor {
+    /// Specify the target pane.
+    /// The default is to use the current pane based on the
+    /// environment variable ARB_PANE (or WEZTERM_PANE).
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+
+    /// Wait until the output of the pane matches this regex.
+    /// The matching lines are printed to stdout.
+    #[arg(
+        long = "match",
+        value_name = "REGEX",
+        conflicts_with_all = &["idle", "command_end", "pane_exit"]
+    )]
+    pattern: Option<String>,
+
+    /// Where to start looking for --match.
+    /// 0 is the first line of terminal screen.
+    /// Negative numbers proceed backwards into the scrollback.
+    /// The default is the line holding the cursor, so that
+    /// only output produced from here on is considered.
+    #[arg(long, allow_hyphen_values = true, requires = "pattern")]
+    start_line: Option<ScrollbackOrVisibleRowIndex>,
+
+    /// Wait until the pane has produced no output for this
+    /// many milliseconds
+    #[arg(long, value_name = "MS", conflicts_with_all = &["command_end", "pane_exit"])]
+    idle: Option<u64>,
+
+    /// Wait until the next command finishes, as reported by
+    /// shell integration (OSC 133), and exit with its exit status.
+    /// If the shell did not report an exit status, exit with
+    /// status 125.
+    #[arg(long, conflicts_with = "pane_exit")]
+    command_end: bool,
+
+    /// Wait until the pane is closed
+    #[arg(long)]
+    pane_exit: bool,
+
+    /// Give up after this long, eg: `30s` or `500ms`,
+    /// and exit with a non-zero status
+    #[arg(long, value_parser = humantime::parse_duration)]
+    timeout: Option<Duration>,
+
+    /// Controls the output format.
+    /// "table" and "json" are possible formats.
+    #[arg(long = "format", default_value = "table")]
+    format: CliOutputFormatKind,
+}
+
+/// The status to exit with when --command-end was satisfied but the
+/// shell did not report the exit status of the command
+const UNKNOWN_EXIT_STATUS: i32 = 125;
+
+// This will be serialized to JSON via the 'WaitFor' command.
+// As such it is intended to be a stable output format.
+#[derive(serde::Serialize)]
+struct CliWaitForResult {
+    pane_id: PaneId,
+    /// One of "matched", "idle", "command_finished",
+    /// "pane_died" or "timed_out"
+    result: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_status: Option<i32>,
+}
+
+impl WaitFor {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let pane_id = client.resolve_pane_id(self.pane_id).await?;
+
+        let condition = if let Some(pattern) = self.pattern {
+            let start_row = match self.start_line {
+                Some(n) => {
+                    let info = client
+                        .get_dimensions(codec::GetPaneRenderableDimensions { pane_id })
+                        .await?;
+                    let line = info.dimensions.physical_top as isize + n as isize;
+                    Some(line.max(info.dimensions.scrollback_top as isize) as StableRowIndex)
+                }
+                None => None,
+            };
+            WaitCondition::Match { pattern, start_row }
+        } else if let Some(idle) = self.idle {
+            WaitCondition::Idle {
+                idle: Duration::from_millis(idle),
+            }
+        } else if self.command_end {
+            WaitCondition::CommandFinished
+        } else if self.pane_exit {
+            WaitCondition::PaneDead
+        } else {
+            anyhow::bail!("one of --match, --idle, --command-end or --pane-exit is required");
+        };
+
+        let response = client
+            .wait_for_pane(codec::WaitForPane {
+                pane_id,
+                condition,
+                timeout: self.timeout,
+            })
+            .await?;
+
+        if let CliOutputFormatKind::Json = self.format {
+            let (result, text, exit_status) = match &response.result {
+                WaitForPaneResult::Matched { text } => ("matched", Some(text.clone()), None),
+                WaitForPaneResult::Idle => ("idle", None, None),
+                WaitForPaneResult::CommandFinished { exit_status } => {
+                    ("command_finished", None, *exit_status)
+                }
+                WaitForPaneResult::PaneDied => ("pane_died", None, None),
+                WaitForPaneResult::TimedOut => ("timed_out", None, None),
+            };
+            crate::cli::print_json(&CliWaitForResult {
+                pane_id,
+                result,
+                text,
+                exit_status,
+            })?;
+        }
+
+        match response.result {
+            WaitForPaneResult::Matched { text } => {
+                if let CliOutputFormatKind::Table = self.format {
+                    println!("{text}");
+                }
+            }
+            WaitForPaneResult::Idle => {}
+            WaitForPaneResult::CommandFinished { exit_status } => {
+                std::process::exit(exit_status.unwrap_or(UNKNOWN_EXIT_STATUS));
+            }
+            WaitForPaneResult::PaneDied if self.pane_exit => {}
+            WaitForPaneResult::PaneDied => anyhow::bail!("pane {pane_id} was closed"),
+            WaitForPaneResult::TimedOut => anyhow::bail!("timed out waiting for pane {pane_id}"),
+        }
+        Ok(())
+    }
+}
@@ -1,28 +1,43 @@
-use clap::Parser;
-use std::path::PathBuf;
-use std::process::Command;
+use clap::{Parser, ValueEnum};
 
 #[derive(Debug, Parser, Clone, Default)]
-pub struct DoctorCommand {}
+pub struct DoctorCommand {
+    /// Controls the output format.
+    /// "text" and "json" are possible formats.
+    #[arg(long, value_enum, default_value_t)]
+    format: DoctorOutputFormat,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DoctorOutputFormat {
+    /// Human readable, colored report
+    #[default]
+    Text,
+    /// Machine readable JSON report, suitable for provisioning scripts
+    Json,
+}
 
 impl DoctorCommand {
     pub fn run(&self) -> anyhow::Result<()> {
-        imp::run()
+        imp::run(self.format)
     }
 }
 
-#[cfg(not(target_os = "macos"))]
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
 mod imp {
     use anyhow::bail;
 
-    pub fn run() -> anyhow::Result<()> {
-        bail!("`arb doctor` is currently supported on macOS only")
+    pub fn run(_format: super::DoctorOutputFormat) -> anyhow::Result<()> {
+        bail!("`arb doctor` is currently supported on macOS and Linux only")
     }
 }
 
-#[cfg(target_os = "macos")]
-mod imp {
-    use super::*;
+/// Check machinery and checks that are shared between the platform
+/// specific implementations.
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+mod common {
+    use super::DoctorOutputFormat;
+    use std::path::PathBuf;
 
     // ANSI color codes
     const GREEN: &str = "\x1b[32m";
@@ -32,14 +47,18 @@ mod imp {
     const GRAY: &str = "\x1b[90m";
     const RESET: &str = "\x1b[0m";
 
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+    #[serde(rename_all = "lowercase")]
     pub(crate) enum CheckStatus {
         Pass,
         Fail,
         Warn,
     }
 
-    #[derive(Debug, Clone)]
+    // This is serialized to JSON via `arb doctor --format json`.
+    // Provisioning scripts assert on it, so treat the field names
+    // and their types as a stable output format.
+    #[derive(Debug, Clone, serde::Serialize)]
     pub(crate) struct CheckResult {
         pub name: String,
         pub status: CheckStatus,
@@ -62,32 +81,25 @@ mod imp {
         }
     }
 
-    pub fn run() -> anyhow::Result<()> {
+    fn count(results: &[CheckResult], status: CheckStatus) -> usize {
+        results.iter().filter(|r| r.status == status).count()
+    }
+
+    fn print_text(results: &[CheckResult]) {
         println!();
         println!("{BOLD}Arb Doctor{RESET}");
         println!("{GRAY}Diagnosing your Arb setup...{RESET}");
         println!();
 
-        let results = run_all_checks();
-
-        for result in &results {
+        for result in results {
             print_result(result);
         }
 
         println!();
 
-        let pass_count = results
-            .iter()
-            .filter(|r| r.status == CheckStatus::Pass)
-            .count();
-        let fail_count = results
-            .iter()
-            .filter(|r| r.status == CheckStatus::Fail)
-            .count();
-        let warn_count = results
-            .iter()
-            .filter(|r| r.status == CheckStatus::Warn)
-            .count();
+        let pass_count = count(results, CheckStatus::Pass);
+        let fail_count = count(results, CheckStatus::Fail);
+        let warn_count = count(results, CheckStatus::Warn);
 
         if fail_count == 0 && warn_count == 0 {
             println!("{GREEN}{BOLD}All {pass_count} checks passed.{RESET}");
@@ -99,7 +111,85 @@ mod imp {
             );
         }
         println!();
+    }
+
+    pub(crate) fn report_json(results: &[CheckResult]) -> serde_json::Value {
+        serde_json::json!({
+            "version": config::arb_version(),
+            "platform": std::env::consts::OS,
+            "summary": {
+                "pass": count(results, CheckStatus::Pass),
+                "warn": count(results, CheckStatus::Warn),
+                "fail": count(results, CheckStatus::Fail),
+            },
+            "checks": results,
+        })
+    }
+
+    pub(crate) fn print_report(results: &[CheckResult], format: DoctorOutputFormat) {
+        match format {
+            DoctorOutputFormat::Text => print_text(results),
+            DoctorOutputFormat::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(&report_json(results)).unwrap_or_default()
+            ),
+        }
+    }
+
+    /// The exit status of `arb doctor`: non-zero when any check failed,
+    /// so that scripts can tell without parsing the report
+    pub(crate) fn exit_code(results: &[CheckResult]) -> i32 {
+        if count(results, CheckStatus::Fail) > 0 {
+            1
+        } else {
+            0
+        }
+    }
 
+    pub(crate) fn exit_on_failure(results: &[CheckResult]) {
+        let code = exit_code(results);
+        if code != 0 {
+            std::process::exit(code);
+        }
+    }
+
+    pub(crate) fn config_home() -> PathBuf {
+        config::CONFIG_DIRS
+            .first()
+            .cloned()
+            .unwrap_or_else(|| crate::paths::home_dir().join(".config").join("arb"))
+    }
+
+    // -- Version --
+
+    pub(crate) fn check_version() -> CheckResult {
+        let arb_ver = config::arb_version();
+
+        let config_version_path = config_home().join(".arb_config_version");
+        let config_ver = std::fs::read_to_string(&config_version_path)
+            .map(|s| s.trim().to_string())
+            .unwrap_or_else(|_| "not found".to_string());
+
+        CheckResult {
+            name: "Version".into(),
+            status: CheckStatus::Pass,
+            message: format!("arb {}, config version: {}", arb_ver, config_ver),
+            fix: None,
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use super::common::*;
+    use super::DoctorOutputFormat;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    pub fn run(format: DoctorOutputFormat) -> anyhow::Result<()> {
+        let results = run_all_checks();
+        print_report(&results, format);
+        exit_on_failure(&results);
         Ok(())
     }
 
@@ -117,14 +207,7 @@ mod imp {
         results
     }
 
-    fn config_home() -> PathBuf {
-        config::CONFIG_DIRS
-            .first()
-            .cloned()
-            .unwrap_or_else(|| crate::paths::home_dir().join(".config").join("arb"))
-    }
-
-    // -- Check 1: Shell integration --
+    // -- Shell integration --
 
     pub(crate) fn check_shell_integration() -> CheckResult {
         let arb_zsh = config_home().join("zsh").join("arb.zsh");
@@ -185,7 +268,7 @@ mod imp {
         }
     }
 
-    // -- Check 2: Starship --
+    // -- Starship --
 
     pub(crate) fn check_starship() -> CheckResult {
         let starship = config_home().join("zsh").join("bin").join("starship");
@@ -227,7 +310,7 @@ mod imp {
         }
     }
 
-    // -- Check 3: Delta --
+    // -- Delta --
 
     pub(crate) fn check_delta() -> CheckResult {
         let in_path = crate::paths::command_exists("delta");
@@ -258,7 +341,7 @@ mod imp {
         }
     }
 
-    // -- Check 4: User config --
+    // -- User config --
 
     pub(crate) fn check_user_config() -> CheckResult {
         let config_path = config::CONFIG_DIRS
@@ -338,7 +421,7 @@ mod imp {
         }
     }
 
-    // -- Check 5: App bundle --
+    // -- App bundle --
 
     pub(crate) fn check_app_bundle() -> CheckResult {
         let candidates = [
@@ -365,25 +448,7 @@ mod imp {
         }
     }
 
-    // -- Check 6: Version --
-
-    pub(crate) fn check_version() -> CheckResult {
-        let arb_ver = config::arb_version();
-
-        let config_version_path = config_home().join(".arb_config_version");
-        let config_ver = std::fs::read_to_string(&config_version_path)
-            .map(|s| s.trim().to_string())
-            .unwrap_or_else(|_| "not found".to_string());
-
-        CheckResult {
-            name: "Version".into(),
-            status: CheckStatus::Pass,
-            message: format!("arb {}, config version: {}", arb_ver, config_ver),
-            fix: None,
-        }
-    }
-
-    // -- Check 7: Zsh plugins --
+    // -- Zsh plugins --
 
     pub(crate) fn check_zsh_plugins() -> Vec<CheckResult> {
         let plugins_dir = config_home().join("zsh").join("plugins");
@@ -417,7 +482,7 @@ mod imp {
             .collect()
     }
 
-    // -- Check 8: Homebrew installation --
+    // -- Homebrew installation --
 
     pub(crate) fn check_homebrew_cask() -> CheckResult {
         if !crate::paths::command_exists("brew") {
@@ -485,6 +550,461 @@ mod imp {
     }
 }
 
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::common::*;
+    use super::DoctorOutputFormat;
+    use config::{Config, FrontEndSelection, UnixDomain};
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    pub fn run(format: DoctorOutputFormat) -> anyhow::Result<()> {
+        let results = run_all_checks();
+        print_report(&results, format);
+        exit_on_failure(&results);
+        Ok(())
+    }
+
+    pub(crate) fn run_all_checks() -> Vec<CheckResult> {
+        let (config_result, config) = check_config_load();
+
+        let mut results = vec![
+            config_result,
+            check_shell_integration(),
+            check_fonts(&config),
+            check_terminfo(&config.term),
+            check_renderer(&config),
+            check_version(),
+        ];
+        results.extend(check_mux_sockets(&config));
+        results
+    }
+
+    // -- Config load --
+
+    /// Evaluates the configuration the same way the GUI would, so that
+    /// runtime errors are reported in addition to syntax errors.
+    /// Returns the loaded config (or the defaults if loading failed) so
+    /// that the remaining checks can inspect it.
+    pub(crate) fn check_config_load() -> (CheckResult, Config) {
+        let loaded = Config::load();
+        let file_name = loaded.file_name.as_ref().map(|p| p.display().to_string());
+
+        match loaded.config {
+            Err(err) => (
+                CheckResult {
+                    name: "Config".into(),
+                    status: CheckStatus::Fail,
+                    message: format!(
+                        "{} failed to load: {err:#}",
+                        file_name.as_deref().unwrap_or("configuration")
+                    ),
+                    fix: Some(
+                        "Fix the reported error; arb falls back to defaults until then".into(),
+                    ),
+                },
+                Config::default_config(),
+            ),
+            Ok(config) => {
+                let result = match (file_name, loaded.warnings.len()) {
+                    (None, _) => CheckResult {
+                        name: "Config".into(),
+                        status: CheckStatus::Warn,
+                        message: "no arb.lua found; using built-in defaults".into(),
+                        fix: Some(format!(
+                            "Create {} to customize arb",
+                            config_home().join("arb.lua").display()
+                        )),
+                    },
+                    (Some(file_name), 0) => CheckResult {
+                        name: "Config".into(),
+                        status: CheckStatus::Pass,
+                        message: format!("{file_name} loads without errors"),
+                        fix: None,
+                    },
+                    (Some(file_name), n) => CheckResult {
+                        name: "Config".into(),
+                        status: CheckStatus::Warn,
+                        message: format!(
+                            "{file_name} loads with {n} warning(s): {}",
+                            loaded.warnings.join("; ")
+                        ),
+                        fix: Some("Address the warnings listed above".into()),
+                    },
+                };
+                (result, config)
+            }
+        }
+    }
+
+    // -- Shell integration --
+
+    /// Markers that indicate an OSC 7/133 shell integration script is
+    /// being sourced from a shell startup file.
+    const SHELL_INTEGRATION_MARKERS: &[&str] = &["arb/zsh/arb.zsh", "arb.sh", "wezterm.sh"];
+
+    fn shell_rc_path(shell: &str) -> Option<PathBuf> {
+        let home = crate::paths::home_dir();
+        match shell {
+            "zsh" => Some(crate::paths::zshrc_path()),
+            "bash" => Some(home.join(".bashrc")),
+            "fish" => Some(home.join(".config").join("fish").join("config.fish")),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn rc_has_shell_integration(content: &str) -> bool {
+        SHELL_INTEGRATION_MARKERS
+            .iter()
+            .any(|marker| content.contains(marker))
+    }
+
+    pub(crate) fn check_shell_integration() -> CheckResult {
+        let shell = std::env::var("SHELL")
+            .ok()
+            .and_then(|s| {
+                Path::new(&s)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+            })
+            .unwrap_or_else(|| "sh".to_string());
+
+        let Some(rc) = shell_rc_path(&shell) else {
+            return CheckResult {
+                name: "Shell integration".into(),
+                status: CheckStatus::Warn,
+                message: format!("shell integration is not available for {shell}"),
+                fix: Some("Use bash, zsh or fish to get cwd tracking and prompt marks".into()),
+            };
+        };
+
+        match std::fs::read_to_string(&rc) {
+            Ok(content) if rc_has_shell_integration(&content) => CheckResult {
+                name: "Shell integration".into(),
+                status: CheckStatus::Pass,
+                message: format!("sourced from {}", rc.display()),
+                fix: None,
+            },
+            Ok(_) => CheckResult {
+                name: "Shell integration".into(),
+                status: CheckStatus::Warn,
+                message: format!("not sourced from {}", rc.display()),
+                fix: Some(format!(
+                    "Source an OSC 7/133 shell integration script from {}",
+                    rc.display()
+                )),
+            },
+            Err(_) => CheckResult {
+                name: "Shell integration".into(),
+                status: CheckStatus::Warn,
+                message: format!("{} not found", rc.display()),
+                fix: Some(format!(
+                    "Create {} and source a shell integration script from it",
+                    rc.display()
+                )),
+            },
+        }
+    }
+
+    // -- Fonts --
+
+    /// Returns true if the family list printed by `fc-match -f '%{family}'`
+    /// (a comma separated list of names) contains `requested`.
+    pub(crate) fn fc_family_matches(requested: &str, fc_output: &str) -> bool {
+        fc_output
+            .split(',')
+            .any(|family| family.trim().eq_ignore_ascii_case(requested.trim()))
+    }
+
+    fn fc_match_family(family: &str) -> Option<String> {
+        let output = Command::new("fc-match")
+            .args(["-f", "%{family}", family])
+            .output()
+            .ok()?;
+        if output.status.success() {
+            Some(String::from_utf8_lossy(&output.stdout).to_string())
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn check_fonts(config: &Config) -> CheckResult {
+        let bundled = config::FontAttributes::default().family;
+        let families: Vec<&str> = config
+            .font
+            .font
+            .iter()
+            .filter(|attr| !attr.is_fallback)
+            .map(|attr| attr.family.as_str())
+            .collect();
+
+        let mut missing = vec![];
+        for family in &families {
+            if family.eq_ignore_ascii_case(&bundled) {
+                // Always available; arb embeds it.
+                continue;
+            }
+            match fc_match_family(family) {
+                Some(resolved) if fc_family_matches(family, &resolved) => {}
+                Some(resolved) => missing.push(format!("{family} (resolves to {resolved})")),
+                None => {
+                    return CheckResult {
+                        name: "Fonts".into(),
+                        status: CheckStatus::Warn,
+                        message: "fc-match not found; cannot verify font resolution".into(),
+                        fix: Some("Install fontconfig (e.g. `apt install fontconfig`)".into()),
+                    }
+                }
+            }
+        }
+
+        if missing.is_empty() {
+            CheckResult {
+                name: "Fonts".into(),
+                status: CheckStatus::Pass,
+                message: format!("{} resolved via fontconfig", families.join(", ")),
+                fix: None,
+            }
+        } else {
+            CheckResult {
+                name: "Fonts".into(),
+                status: CheckStatus::Warn,
+                message: format!(
+                    "not found by fontconfig: {}; falling back to bundled {bundled}",
+                    missing.join(", ")
+                ),
+                fix: Some(
+                    "Install the font or adjust config.font (check with `fc-list : family`)".into(),
+                ),
+            }
+        }
+    }
+
+    // -- Terminfo --
+
+    /// The directories searched by ncurses, in priority order.
+    fn terminfo_dirs() -> Vec<PathBuf> {
+        let mut dirs = vec![];
+        if let Some(dir) = std::env::var_os("TERMINFO") {
+            dirs.push(PathBuf::from(dir));
+        }
+        dirs.push(crate::paths::home_dir().join(".terminfo"));
+        if let Some(list) = std::env::var_os("TERMINFO_DIRS") {
+            dirs.extend(std::env::split_paths(&list).filter(|p| !p.as_os_str().is_empty()));
+        }
+        for dir in [
+            "/etc/terminfo",
+            "/lib/terminfo",
+            "/usr/share/terminfo",
+            "/usr/lib/terminfo",
+        ] {
+            dirs.push(PathBuf::from(dir));
+        }
+        dirs
+    }
+
+    /// Locates the compiled terminfo entry for `term`, accounting for both
+    /// the `x/xterm` and the hashed `78/xterm` directory layouts.
+    pub(crate) fn find_terminfo(term: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+        let first = term.chars().next()?;
+        for dir in dirs {
+            for sub in [first.to_string(), format!("{:x}", first as u32)] {
+                let candidate = dir.join(sub).join(term);
+                if candidate.is_file() {
+                    return Some(candidate);
+                }
+            }
+        }
+        None
+    }
+
+    pub(crate) fn check_terminfo(term: &str) -> CheckResult {
+        match find_terminfo(term, &terminfo_dirs()) {
+            Some(path) => CheckResult {
+                name: "Terminfo".into(),
+                status: CheckStatus::Pass,
+                message: format!("{term} found at {}", path.display()),
+                fix: None,
+            },
+            None => CheckResult {
+                name: "Terminfo".into(),
+                status: CheckStatus::Fail,
+                message: format!("no terminfo entry for TERM={term}"),
+                fix: Some(format!(
+                    "Install the entry (e.g. `apt install ncurses-term`) or set config.term \
+                     to an installed entry; verify with `infocmp {term}`"
+                )),
+            },
+        }
+    }
+
+    // -- Renderer --
+
+    fn has_gpu_render_node() -> Option<PathBuf> {
+        std::fs::read_dir("/dev/dri")
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .find(|path| {
+                path.file_name()
+                    .map(|n| n.to_string_lossy().starts_with("renderD"))
+                    .unwrap_or(false)
+            })
+    }
+
+    pub(crate) fn check_renderer(config: &Config) -> CheckResult {
+        match config.front_end {
+            FrontEndSelection::Software => {
+                return CheckResult {
+                    name: "Renderer".into(),
+                    status: CheckStatus::Warn,
+                    message: "front_end = \"Software\"; rendering on the CPU".into(),
+                    fix: Some("Remove front_end from arb.lua to use OpenGL".into()),
+                }
+            }
+            FrontEndSelection::WebGpu => {
+                return CheckResult {
+                    name: "Renderer".into(),
+                    status: CheckStatus::Fail,
+                    message: "front_end = \"WebGpu\" is built with the Metal backend only".into(),
+                    fix: Some("Set config.front_end = \"OpenGL\"".into()),
+                }
+            }
+            FrontEndSelection::OpenGL => {}
+        }
+
+        if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+            return CheckResult {
+                name: "Renderer".into(),
+                status: CheckStatus::Warn,
+                message: "neither DISPLAY nor WAYLAND_DISPLAY is set".into(),
+                fix: Some("Run arb from within an X11 or Wayland session".into()),
+            };
+        }
+
+        if std::env::var("LIBGL_ALWAYS_SOFTWARE")
+            .map(|v| v != "0" && !v.is_empty())
+            .unwrap_or(false)
+        {
+            return CheckResult {
+                name: "Renderer".into(),
+                status: CheckStatus::Warn,
+                message: "LIBGL_ALWAYS_SOFTWARE forces Mesa's software rasterizer".into(),
+                fix: Some("Unset LIBGL_ALWAYS_SOFTWARE to use the GPU".into()),
+            };
+        }
+
+        let egl = if config.prefer_egl { "EGL" } else { "GLX" };
+        match has_gpu_render_node() {
+            Some(node) => CheckResult {
+                name: "Renderer".into(),
+                status: CheckStatus::Pass,
+                message: format!("OpenGL via {egl}, GPU render node {}", node.display()),
+                fix: None,
+            },
+            None => CheckResult {
+                name: "Renderer".into(),
+                status: CheckStatus::Warn,
+                message: "no GPU render node in /dev/dri; OpenGL will fall back to a software \
+                          rasterizer (llvmpipe)"
+                    .into(),
+                fix: Some("Install GPU drivers or check that you are in the `render` group".into()),
+            },
+        }
+    }
+
+    // -- Mux sockets --
+
+    #[derive(Debug, PartialEq, Eq)]
+    pub(crate) enum SocketState {
+        /// Nothing at the path; the server is started on demand
+        Absent,
+        /// A server is accepting connections
+        Listening,
+        /// A socket exists but nothing is listening on it
+        Stale,
+        /// Something other than a socket is in the way
+        NotASocket,
+    }
+
+    pub(crate) fn probe_socket(path: &Path) -> SocketState {
+        match std::fs::symlink_metadata(path) {
+            Err(_) => SocketState::Absent,
+            Ok(meta) if !meta.file_type().is_socket() => SocketState::NotASocket,
+            Ok(_) => match std::os::unix::net::UnixStream::connect(path) {
+                Ok(_) => SocketState::Listening,
+                Err(_) => SocketState::Stale,
+            },
+        }
+    }
+
+    fn check_mux_socket(domain: &UnixDomain) -> CheckResult {
+        let name = format!("Mux socket ({})", domain.name);
+        if domain.proxy_command.is_some() {
+            return CheckResult {
+                name,
+                status: CheckStatus::Pass,
+                message: "uses proxy_command (skipped)".into(),
+                fix: None,
+            };
+        }
+
+        let path = domain.socket_path();
+        if !domain.skip_permissions_check {
+            if let Some(dir) = path.parent() {
+                if let Ok(meta) = std::fs::metadata(dir) {
+                    if meta.permissions().mode() & 0o022 != 0 {
+                        return CheckResult {
+                            name,
+                            status: CheckStatus::Fail,
+                            message: format!(
+                                "{} is group or world writable; clients will refuse to connect",
+                                dir.display()
+                            ),
+                            fix: Some(format!("Run: chmod 700 {}", dir.display())),
+                        };
+                    }
+                }
+            }
+        }
+
+        match probe_socket(&path) {
+            SocketState::Absent => CheckResult {
+                name,
+                status: CheckStatus::Pass,
+                message: format!(
+                    "no server running ({} will be created on demand)",
+                    path.display()
+                ),
+                fix: None,
+            },
+            SocketState::Listening => CheckResult {
+                name,
+                status: CheckStatus::Pass,
+                message: format!("server accepting connections on {}", path.display()),
+                fix: None,
+            },
+            SocketState::Stale => CheckResult {
+                name,
+                status: CheckStatus::Warn,
+                message: format!("{} exists but nothing is listening", path.display()),
+                fix: Some(format!("Remove the stale socket: rm {}", path.display())),
+            },
+            SocketState::NotASocket => CheckResult {
+                name,
+                status: CheckStatus::Fail,
+                message: format!("{} exists but is not a socket", path.display()),
+                fix: Some(format!("Move {} out of the way", path.display())),
+            },
+        }
+    }
+
+    pub(crate) fn check_mux_sockets(config: &Config) -> Vec<CheckResult> {
+        config.unix_domains.iter().map(check_mux_socket).collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -496,8 +1016,31 @@ mod tests {
         let _ = format!("{:?}", cmd);
     }
 
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    #[test]
+    fn should_exit_non_zero_when_a_check_fails() {
+        use super::common::*;
+
+        let result = |status| CheckResult {
+            name: "Check".into(),
+            status,
+            message: String::new(),
+            fix: None,
+        };
+        assert_eq!(exit_code(&[]), 0);
+        assert_eq!(
+            exit_code(&[result(CheckStatus::Pass), result(CheckStatus::Warn)]),
+            0
+        );
+        assert_eq!(
+            exit_code(&[result(CheckStatus::Pass), result(CheckStatus::Fail)]),
+            1
+        );
+    }
+
     #[cfg(target_os = "macos")]
     mod macos_tests {
+        use super::super::common::*;
         use super::super::imp::*;
 
         #[test]
@@ -579,4 +1122,127 @@ mod tests {
             assert_ne!(pass, warn);
         }
     }
+
+    #[cfg(target_os = "linux")]
+    mod linux_tests {
+        use super::super::common::*;
+        use super::super::imp::*;
+        use std::path::PathBuf;
+
+        #[test]
+        fn should_match_fontconfig_family_lists() {
+            assert!(fc_family_matches("Fira Code", "Fira Code"));
+            assert!(fc_family_matches("fira code", "Fira Code,Fira Code Retina"));
+            assert!(fc_family_matches(
+                "Fira Code Retina",
+                "Fira Code, Fira Code Retina"
+            ));
+            assert!(!fc_family_matches("Fira Code", "DejaVu Sans"));
+            assert!(!fc_family_matches("Fira", "Fira Code"));
+        }
+
+        #[test]
+        fn should_find_terminfo_in_letter_and_hex_layouts() {
+            let dir = tempfile::tempdir().unwrap();
+            let letter = dir.path().join("letter");
+            let hashed = dir.path().join("hashed");
+            std::fs::create_dir_all(letter.join("x")).unwrap();
+            std::fs::write(letter.join("x").join("xterm-256color"), b"").unwrap();
+            std::fs::create_dir_all(hashed.join("77")).unwrap();
+            std::fs::write(hashed.join("77").join("wezterm"), b"").unwrap();
+
+            let dirs = vec![
+                PathBuf::from("/nonexistent"),
+                letter.clone(),
+                hashed.clone(),
+            ];
+            assert_eq!(
+                find_terminfo("xterm-256color", &dirs),
+                Some(letter.join("x").join("xterm-256color"))
+            );
+            assert_eq!(
+                find_terminfo("wezterm", &dirs),
+                Some(hashed.join("77").join("wezterm"))
+            );
+            assert_eq!(find_terminfo("nonexistent-term", &dirs), None);
+            assert_eq!(find_terminfo("", &dirs), None);
+        }
+
+        #[test]
+        fn should_probe_socket_state() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("sock");
+            assert_eq!(probe_socket(&path), SocketState::Absent);
+
+            let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+            assert_eq!(probe_socket(&path), SocketState::Listening);
+
+            drop(listener);
+            assert_eq!(probe_socket(&path), SocketState::Stale);
+
+            let file = dir.path().join("file");
+            std::fs::write(&file, b"").unwrap();
+            assert_eq!(probe_socket(&file), SocketState::NotASocket);
+        }
+
+        #[test]
+        fn should_detect_shell_integration_markers() {
+            assert!(rc_has_shell_integration(
+                "source ~/.config/arb/zsh/arb.zsh\n"
+            ));
+            assert!(rc_has_shell_integration(". /etc/profile.d/wezterm.sh"));
+            assert!(!rc_has_shell_integration("export PATH=$HOME/bin:$PATH"));
+        }
+
+        #[test]
+        fn should_report_terminfo_failure_for_unknown_term() {
+            let result = check_terminfo("arb-doctor-no-such-term");
+            assert_eq!(result.name, "Terminfo");
+            assert_eq!(result.status, CheckStatus::Fail);
+            assert!(result.fix.is_some());
+        }
+
+        #[test]
+        fn should_flag_software_front_end() {
+            let mut config = config::Config::default_config();
+            config.front_end = config::FrontEndSelection::Software;
+            let result = check_renderer(&config);
+            assert_eq!(result.status, CheckStatus::Warn);
+        }
+
+        #[test]
+        fn should_report_one_mux_check_per_unix_domain() {
+            let config = config::Config::default_config();
+            let results = check_mux_sockets(&config);
+            assert_eq!(results.len(), config.unix_domains.len());
+            assert!(results[0].name.starts_with("Mux socket"));
+        }
+
+        #[test]
+        fn should_serialize_report_as_json() {
+            let results = vec![
+                CheckResult {
+                    name: "Terminfo".into(),
+                    status: CheckStatus::Pass,
+                    message: "found".into(),
+                    fix: None,
+                },
+                CheckResult {
+                    name: "Fonts".into(),
+                    status: CheckStatus::Warn,
+                    message: "missing".into(),
+                    fix: Some("install it".into()),
+                },
+            ];
+            let json = report_json(&results);
+            assert_eq!(json["platform"], "linux");
+            assert_eq!(json["summary"]["pass"], 1);
+            assert_eq!(json["summary"]["warn"], 1);
+            assert_eq!(json["summary"]["fail"], 0);
+            assert_eq!(json["checks"][0]["status"], "pass");
+            assert_eq!(json["checks"][1]["status"], "warn");
+            assert_eq!(json["checks"][1]["fix"], "install it");
+            assert!(json["checks"][0]["fix"].is_null());
+        }
+    }
 }
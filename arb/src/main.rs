@@ -18,11 +18,13 @@ use wezterm_gui_subcommands::*;
 
 mod asciicast;
 mod bench;
+mod cast_export;
 mod cli;
 mod config_cmd;
 mod doctor;
 mod init;
 mod reset;
+mod ssh_copy;
 pub(crate) mod paths;
 pub mod update;
 
@@ -157,9 +159,15 @@ enum SubCommand {
 
     #[command(
         name = "bench",
-        about = "Benchmark shell startup time and detect installed terminals"
+        about = "Benchmark terminal emulation throughput and latency, and shell startup time"
     )]
     Bench(bench::BenchCommand),
+
+    #[command(
+        name = "ssh-copy",
+        about = "Copy files to and from a remote host using the built-in ssh client"
+    )]
+    SshCopy(ssh_copy::SshCopyCommand),
 }
 
 use termwiz::escape::osc::{
@@ -768,7 +776,7 @@ fn run() -> anyhow::Result<()> {
         SubCommand::SetCwd(cmd) => cmd.run(),
         SubCommand::Cli(cli) => cli::run_cli(&opts, cli),
         SubCommand::Record(cmd) => cmd.run(init_config(&opts)?),
-        SubCommand::Replay(cmd) => cmd.run(),
+        SubCommand::Replay(cmd) => cmd.run(&opts),
         SubCommand::ShellCompletion { shell } => {
             use clap::CommandFactory;
             let mut cmd = Opt::command();
@@ -782,6 +790,7 @@ fn run() -> anyhow::Result<()> {
         SubCommand::Reset(cmd) => cmd.run(),
         SubCommand::Doctor(cmd) => cmd.run(),
         SubCommand::Bench(cmd) => cmd.run(),
+        SubCommand::SshCopy(cmd) => cmd.run(init_config(&opts)?),
     }
 }
 
@@ -0,0 +1,886 @@
+use anyhow::{anyhow, bail, Context};
+use clap::builder::ValueParser;
+use clap::Parser;
+use config::{ConfigHandle, SshBackend};
+use indicatif::{ProgressBar, ProgressStyle};
+use mux::ssh::PasswordPromptHost;
+use smol::io::{AsyncReadExt, AsyncWriteExt};
+use std::collections::HashSet;
+use std::fs::{FileTimes, OpenOptions as LocalOpenOptions};
+use std::io::{IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use termwiz::lineedit::{line_editor_terminal, LineEditor};
+use wezterm_gui_subcommands::name_equals_value;
+use wezterm_ssh::{
+    ConfigMap, FilePermissions, FileType, Metadata, OpenFileType, OpenOptions, Session,
+    SessionEvent, Sftp, Utf8Path, Utf8PathBuf, WriteMode,
+};
+
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Parser, Clone)]
+pub struct SshCopyCommand {
+    /// The files to copy.
+    /// Remote files are specified using the form `[username@]host:path`,
+    /// where host may also be the name of one of your `ssh_domains`.
+    /// A relative remote path is relative to the home directory
+    /// of the remote user.
+    #[arg(required = true, num_args = 1..)]
+    sources: Vec<String>,
+
+    /// Where to copy to, using the same form as the sources.
+    /// When copying more than one source, this must be an
+    /// existing directory.
+    dest: String,
+
+    /// Recursively copy directories
+    #[arg(short, long)]
+    recursive: bool,
+
+    /// Preserve the permissions, access and modification
+    /// times of the copied files
+    #[arg(short, long)]
+    preserve: bool,
+
+    /// Continue interrupted transfers.
+    /// A file that already exists at the destination and is smaller
+    /// than the source is appended to, rather than copied again.
+    #[arg(long)]
+    resume: bool,
+
+    /// The port to connect to on the remote host
+    #[arg(short = 'P', long)]
+    port: Option<u16>,
+
+    /// Override specific SSH configuration options.
+    /// For example:
+    ///
+    /// `arb ssh-copy -oIdentityFile=/secret/id_ed25519 notes.txt some-host:`
+    #[arg(
+        long = "ssh-option",
+        short = 'o',
+        name = "name=value",
+        value_parser=ValueParser::new(name_equals_value),
+        number_of_values = 1)]
+    config_override: Vec<(String, String)>,
+
+    /// Enable verbose ssh protocol tracing.
+    /// The trace information is printed to the stderr stream of
+    /// the process.
+    #[arg(short = 'v')]
+    verbose: bool,
+
+    /// Don't show the transfer progress
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Location {
+    Local(PathBuf),
+    Remote { host: String, path: String },
+}
+
+impl Location {
+    /// Follows the conventions of scp: `host:path` is a remote path,
+    /// unless there is a slash before the colon, so that local files
+    /// with a colon in their name can be written as `./name:with:colons`.
+    /// IPv6 addresses can be written as `[addr]:path`.
+    fn parse(arg: &str) -> Self {
+        if let Some(rest) = arg.strip_prefix('[') {
+            if let Some((host, path)) = rest.split_once("]:") {
+                return Self::Remote {
+                    host: host.to_string(),
+                    path: path.to_string(),
+                };
+            }
+        }
+        if let Some((host, path)) = arg.split_once(':') {
+            // `C:\foo` is a local path on Windows
+            let is_drive_letter = cfg!(windows) && host.len() == 1;
+            if !host.is_empty() && !host.contains(['/', '\\']) && !is_drive_letter {
+                return Self::Remote {
+                    host: host.to_string(),
+                    path: path.to_string(),
+                };
+            }
+        }
+        Self::Local(PathBuf::from(arg))
+    }
+}
+
+enum Transfer {
+    Upload { sources: Vec<PathBuf>, dest: String },
+    Download { sources: Vec<String>, dest: PathBuf },
+}
+
+impl SshCopyCommand {
+    pub fn run(self, config: ConfigHandle) -> anyhow::Result<()> {
+        let (host, transfer) = match Location::parse(&self.dest) {
+            Location::Remote { host, path } => {
+                let mut sources = vec![];
+                for source in &self.sources {
+                    match Location::parse(source) {
+                        Location::Local(source) => sources.push(source),
+                        Location::Remote { .. } => {
+                            bail!("copying from one remote location to another is not supported")
+                        }
+                    }
+                }
+                (
+                    host,
+                    Transfer::Upload {
+                        sources,
+                        dest: path,
+                    },
+                )
+            }
+            Location::Local(dest) => {
+                let mut remote_host: Option<String> = None;
+                let mut sources = vec![];
+                for source in &self.sources {
+                    match Location::parse(source) {
+                        Location::Remote { host, path } => {
+                            if remote_host.as_ref().is_some_and(|h| *h != host) {
+                                bail!("all of the remote sources must be on the same host");
+                            }
+                            remote_host = Some(host);
+                            sources.push(path);
+                        }
+                        Location::Local(source) => bail!(
+                            "{} is a local path; when copying to a local \
+                             destination, all of the sources must be remote",
+                            source.display()
+                        ),
+                    }
+                }
+                let host = remote_host.ok_or_else(|| anyhow!("no remote path was specified"))?;
+                (host, Transfer::Download { sources, dest })
+            }
+        };
+
+        let session = connect(self.ssh_config(&config, &host)?)?;
+        let copier = Copier {
+            sftp: session.sftp(),
+            recursive: self.recursive,
+            preserve: self.preserve,
+            resume: self.resume,
+            progress: !self.quiet && std::io::stderr().is_terminal(),
+        };
+
+        smol::block_on(async {
+            match transfer {
+                Transfer::Upload { sources, dest } => copier.upload(&sources, &dest).await,
+                Transfer::Download { sources, dest } => copier.download(&sources, &dest).await,
+            }
+        })
+    }
+
+    fn ssh_config(&self, config: &ConfigHandle, host: &str) -> anyhow::Result<ConfigMap> {
+        let (user, host) = match host.rsplit_once('@') {
+            Some((user, host)) => (Some(user), host),
+            None => (None, host),
+        };
+
+        // Prefer the settings of a matching ssh domain, so that the
+        // transfer authenticates the same way as the domain's panes
+        let mut ssh_config = match config.ssh_domains().iter().find(|dom| dom.name == host) {
+            Some(dom) => mux::ssh::ssh_domain_to_ssh_config(dom)?,
+            None => {
+                let mut ssh_config = wezterm_ssh::Config::new();
+                ssh_config.add_default_config_files();
+                let mut ssh_config = ssh_config.for_host(host);
+                ssh_config.insert(
+                    "wezterm_ssh_backend".to_string(),
+                    match config.ssh_backend {
+                        SshBackend::Ssh2 => "ssh2",
+                        SshBackend::LibSsh => "libssh",
+                    }
+                    .to_string(),
+                );
+                ssh_config
+            }
+        };
+
+        if let Some(user) = user {
+            ssh_config.insert("user".to_string(), user.to_string());
+        }
+        if let Some(port) = self.port {
+            ssh_config.insert("port".to_string(), port.to_string());
+        }
+        for (k, v) in &self.config_override {
+            ssh_config.insert(k.to_lowercase(), v.to_string());
+        }
+        if self.verbose {
+            ssh_config.insert("wezterm_ssh_verbose".to_string(), "true".to_string());
+        }
+        Ok(ssh_config)
+    }
+}
+
+/// Connects to the host and processes the authentication related
+/// events, prompting on the terminal as needed
+fn connect(ssh_config: ConfigMap) -> anyhow::Result<Session> {
+    let (session, events) = Session::connect(ssh_config)?;
+
+    while let Ok(event) = smol::block_on(events.recv()) {
+        match event {
+            SessionEvent::Banner(banner) => {
+                if let Some(banner) = banner {
+                    eprintln!("{banner}");
+                }
+            }
+            SessionEvent::HostVerify(verify) => {
+                eprintln!("{}", verify.message);
+                let ok = match prompt("Enter [y/n]> ", true)?.as_deref() {
+                    Some("y" | "Y" | "yes" | "YES") => true,
+                    _ => false,
+                };
+                smol::block_on(verify.answer(ok)).context("send verify response")?;
+            }
+            SessionEvent::Authenticate(auth) => {
+                if !auth.username.is_empty() {
+                    eprintln!("Authentication for {}", auth.username);
+                }
+                if !auth.instructions.is_empty() {
+                    eprintln!("{}", auth.instructions);
+                }
+                let mut answers = vec![];
+                for p in &auth.prompts {
+                    let mut prompt_lines = p.prompt.split('\n').collect::<Vec<_>>();
+                    let editor_prompt = prompt_lines.pop().unwrap();
+                    for line in &prompt_lines {
+                        eprintln!("{line}");
+                    }
+                    match prompt(editor_prompt, p.echo)? {
+                        Some(answer) => answers.push(answer),
+                        None => bail!("Authentication was cancelled"),
+                    }
+                }
+                smol::block_on(auth.answer(answers))?;
+            }
+            SessionEvent::Error(err) => {
+                eprintln!("Error: {err}");
+            }
+            SessionEvent::HostVerificationFailed(failed) => {
+                eprintln!("REMOTE HOST IDENTIFICATION CHANGED");
+                eprintln!("SOMEONE MAY BE DOING SOMETHING NASTY!");
+                eprintln!(
+                    "The host is {}, and its fingerprint is\n{}",
+                    failed.remote_address, failed.key
+                );
+                if let Some(file) = failed.file {
+                    eprintln!(
+                        "If the administrator confirms that the key has changed, you can\n\
+                         fix this for yourself by removing the offending entry from\n\
+                         {} and then try connecting again.",
+                        file.display()
+                    );
+                }
+            }
+            SessionEvent::Authenticated => return Ok(session),
+        }
+    }
+
+    bail!("the ssh session was closed before authentication completed")
+}
+
+fn prompt(prompt: &str, echo: bool) -> anyhow::Result<Option<String>> {
+    let mut terminal = line_editor_terminal()?;
+    let mut editor = LineEditor::new(&mut terminal);
+    editor.set_prompt(prompt);
+    let mut host = PasswordPromptHost::default();
+    host.echo = echo;
+    Ok(editor.read_line(&mut host)?)
+}
+
+/// What a local path that is to be uploaded turned out to be
+enum LocalEntry {
+    File(std::fs::Metadata),
+    Dir(std::fs::Metadata),
+    /// A symbolic link, and its target
+    Link(Utf8PathBuf),
+    /// A directory that is already being copied, reached again
+    /// through a loop in the filesystem
+    Visited,
+}
+
+/// Identifies a local directory, so that loops can be detected
+type DirId = (u64, u64);
+
+#[cfg(unix)]
+fn dir_id(meta: &std::fs::Metadata) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(_meta: &std::fs::Metadata) -> Option<DirId> {
+    None
+}
+
+/// Examines `local`.  Symbolic links are only followed when `follow`
+/// is set, which is the case for the paths named on the command line;
+/// the links found in the directories that are copied are copied as links.
+/// Directories are recorded in `visited`.
+fn examine_local(
+    local: &Path,
+    follow: bool,
+    visited: &mut HashSet<DirId>,
+) -> anyhow::Result<LocalEntry> {
+    let meta = if follow {
+        std::fs::metadata(local)
+    } else {
+        std::fs::symlink_metadata(local)
+    }
+    .with_context(|| format!("reading {}", local.display()))?;
+
+    if meta.file_type().is_symlink() {
+        let target = std::fs::read_link(local)
+            .with_context(|| format!("reading the link {}", local.display()))?;
+        let target = Utf8PathBuf::from_path_buf(target).map_err(|target| {
+            anyhow!(
+                "the target of {}, {}, is not a valid UTF-8 path",
+                local.display(),
+                target.display()
+            )
+        })?;
+        return Ok(LocalEntry::Link(target));
+    }
+    if !meta.is_dir() {
+        return Ok(LocalEntry::File(meta));
+    }
+    match dir_id(&meta) {
+        Some(id) if !visited.insert(id) => Ok(LocalEntry::Visited),
+        _ => Ok(LocalEntry::Dir(meta)),
+    }
+}
+
+struct Copier {
+    sftp: Sftp,
+    recursive: bool,
+    preserve: bool,
+    resume: bool,
+    progress: bool,
+}
+
+impl Copier {
+    /// Resolves a relative remote path against the remote home directory
+    async fn remote_path(&self, path: &str) -> anyhow::Result<Utf8PathBuf> {
+        if path.starts_with('/') {
+            return Ok(Utf8PathBuf::from(path));
+        }
+        let home = self
+            .sftp
+            .canonicalize(".")
+            .await
+            .context("resolving the remote home directory")?;
+        if path.is_empty() {
+            Ok(home)
+        } else {
+            Ok(home.join(path))
+        }
+    }
+
+    fn progress_bar(&self, name: &str, size: u64, position: u64) -> ProgressBar {
+        if !self.progress {
+            return ProgressBar::hidden();
+        }
+        let pb = ProgressBar::new(size).with_position(position);
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template(
+                    "{msg} [{wide_bar:.cyan/blue}] {bytes} / {total_bytes} ({bytes_per_sec}, ETA {eta})",
+                )
+                .expect("valid progress bar template")
+                .progress_chars("=>-"),
+        );
+        pb.set_message(name.to_string());
+        pb
+    }
+
+    async fn upload(&self, sources: &[PathBuf], dest: &str) -> anyhow::Result<()> {
+        let dest = self.remote_path(dest).await?;
+        let dest_is_dir =
+            matches!(self.sftp.metadata(dest.clone()).await, Ok(meta) if meta.is_dir());
+        if sources.len() > 1 && !dest_is_dir {
+            bail!("{dest} is not a directory");
+        }
+
+        for source in sources {
+            let target = if dest_is_dir {
+                let name = source
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .ok_or_else(|| anyhow!("{} does not name a file", source.display()))?;
+                dest.join(name)
+            } else {
+                dest.clone()
+            };
+            self.upload_path(source, target).await?;
+        }
+        Ok(())
+    }
+
+    async fn upload_path(&self, local: &Path, remote: Utf8PathBuf) -> anyhow::Result<()> {
+        let mut pending = vec![(local.to_path_buf(), remote, true)];
+        let mut dirs = vec![];
+        let mut visited = HashSet::new();
+
+        while let Some((local, remote, follow)) = pending.pop() {
+            let meta = match examine_local(&local, follow, &mut visited)? {
+                LocalEntry::File(meta) => {
+                    self.upload_file(&local, &remote, &meta).await?;
+                    continue;
+                }
+                LocalEntry::Link(target) => {
+                    self.upload_link(&target, &remote).await?;
+                    continue;
+                }
+                LocalEntry::Visited => {
+                    eprintln!(
+                        "Skipping {}, which loops back to a directory that is already being copied",
+                        local.display()
+                    );
+                    continue;
+                }
+                LocalEntry::Dir(meta) => meta,
+            };
+
+            if !self.recursive {
+                bail!(
+                    "{} is a directory; use --recursive to copy it",
+                    local.display()
+                );
+            }
+            if self.sftp.metadata(remote.clone()).await.is_err() {
+                self.sftp
+                    .create_dir(remote.clone(), 0o777)
+                    .await
+                    .with_context(|| format!("creating {remote}"))?;
+            }
+            for entry in
+                std::fs::read_dir(&local).with_context(|| format!("reading {}", local.display()))?
+            {
+                let entry = entry?;
+                let name = entry.file_name();
+                let name = name.to_str().ok_or_else(|| {
+                    anyhow!("{} is not a valid UTF-8 path", entry.path().display())
+                })?;
+                pending.push((entry.path(), remote.join(name), false));
+            }
+            dirs.push((remote, meta));
+        }
+
+        // Apply the directory metadata last, as copying their
+        // contents would otherwise bump their modification times
+        if self.preserve {
+            for (remote, meta) in dirs.into_iter().rev() {
+                self.sftp
+                    .set_metadata(remote.clone(), remote_metadata(&meta))
+                    .await
+                    .with_context(|| format!("setting the metadata of {remote}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Recreates a local symbolic link, pointing at `target`, at `remote`
+    async fn upload_link(&self, target: &Utf8Path, remote: &Utf8Path) -> anyhow::Result<()> {
+        if let Ok(existing) = self.sftp.symlink_metadata(remote.to_path_buf()).await {
+            if existing.is_dir() {
+                bail!("{remote} is a directory");
+            }
+            self.sftp
+                .remove_file(remote.to_path_buf())
+                .await
+                .with_context(|| format!("replacing {remote}"))?;
+        }
+        self.sftp
+            .symlink(target.to_path_buf(), remote.to_path_buf())
+            .await
+            .with_context(|| format!("creating the link {remote}"))?;
+        Ok(())
+    }
+
+    async fn upload_file(
+        &self,
+        local: &Path,
+        remote: &Utf8Path,
+        meta: &std::fs::Metadata,
+    ) -> anyhow::Result<()> {
+        let size = meta.len();
+        let offset = if self.resume {
+            match self.sftp.metadata(remote.to_path_buf()).await {
+                Ok(existing) if existing.is_file() && existing.size.unwrap_or(0) <= size => {
+                    Some(existing.size.unwrap_or(0))
+                }
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        let mut file =
+            std::fs::File::open(local).with_context(|| format!("opening {}", local.display()))?;
+        let mut remote_file = match offset {
+            Some(offset) => {
+                file.seek(SeekFrom::Start(offset))?;
+                self.sftp
+                    .open_with_mode(
+                        remote.to_path_buf(),
+                        OpenOptions {
+                            read: false,
+                            write: Some(WriteMode::Append),
+                            mode: 0o666,
+                            ty: OpenFileType::File,
+                        },
+                    )
+                    .await
+            }
+            None => self.sftp.create(remote.to_path_buf()).await,
+        }
+        .with_context(|| format!("opening {remote}"))?;
+
+        let progress = self.progress_bar(
+            remote.file_name().unwrap_or(remote.as_str()),
+            size,
+            offset.unwrap_or(0),
+        );
+        let mut buf = vec![0u8; COPY_CHUNK_SIZE];
+        loop {
+            let n = file
+                .read(&mut buf)
+                .with_context(|| format!("reading {}", local.display()))?;
+            if n == 0 {
+                break;
+            }
+            remote_file
+                .write_all(&buf[..n])
+                .await
+                .with_context(|| format!("writing {remote}"))?;
+            progress.inc(n as u64);
+        }
+        remote_file
+            .close()
+            .await
+            .with_context(|| format!("closing {remote}"))?;
+        progress.finish();
+
+        if self.preserve {
+            self.sftp
+                .set_metadata(remote.to_path_buf(), remote_metadata(meta))
+                .await
+                .with_context(|| format!("setting the metadata of {remote}"))?;
+        }
+        Ok(())
+    }
+
+    async fn download(&self, sources: &[String], dest: &Path) -> anyhow::Result<()> {
+        let dest_is_dir = dest.is_dir();
+        if sources.len() > 1 && !dest_is_dir {
+            bail!("{} is not a directory", dest.display());
+        }
+
+        for source in sources {
+            let source = self.remote_path(source).await?;
+            let target = if dest_is_dir {
+                let name = source
+                    .file_name()
+                    .ok_or_else(|| anyhow!("{source} does not name a file"))?;
+                dest.join(name)
+            } else {
+                dest.to_path_buf()
+            };
+            self.download_path(source, target).await?;
+        }
+        Ok(())
+    }
+
+    async fn download_path(&self, remote: Utf8PathBuf, local: PathBuf) -> anyhow::Result<()> {
+        // As for uploads, only the paths named on the command line are
+        // followed if they are links, so a loop of links can't be entered
+        let mut pending = vec![(remote, local, true)];
+        let mut dirs = vec![];
+
+        while let Some((remote, local, follow)) = pending.pop() {
+            let meta = if follow {
+                self.sftp.metadata(remote.clone()).await
+            } else {
+                self.sftp.symlink_metadata(remote.clone()).await
+            }
+            .with_context(|| format!("reading {remote}"))?;
+            if meta.is_symlink() {
+                self.download_link(&remote, &local).await?;
+                continue;
+            }
+            if !meta.is_dir() {
+                self.download_file(&remote, &local, &meta).await?;
+                continue;
+            }
+
+            if !self.recursive {
+                bail!("{remote} is a directory; use --recursive to copy it");
+            }
+            if !local.is_dir() {
+                std::fs::create_dir(&local)
+                    .with_context(|| format!("creating {}", local.display()))?;
+            }
+            let entries = self
+                .sftp
+                .read_dir(remote.clone())
+                .await
+                .with_context(|| format!("reading {remote}"))?;
+            for (path, _) in entries {
+                if let Some(name) = path.file_name() {
+                    pending.push((path.clone(), local.join(name), false));
+                }
+            }
+            dirs.push((local, meta));
+        }
+
+        // Apply the directory metadata last, as copying their
+        // contents would otherwise bump their modification times
+        if self.preserve {
+            for (local, meta) in dirs.into_iter().rev() {
+                set_local_metadata(&local, &meta)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Recreates the remote symbolic link at `remote` at `local`
+    #[cfg(unix)]
+    async fn download_link(&self, remote: &Utf8Path, local: &Path) -> anyhow::Result<()> {
+        let target = self
+            .sftp
+            .read_link(remote.to_path_buf())
+            .await
+            .with_context(|| format!("reading the link {remote}"))?;
+        if let Ok(existing) = std::fs::symlink_metadata(local) {
+            if existing.is_dir() {
+                bail!("{} is a directory", local.display());
+            }
+            std::fs::remove_file(local)
+                .with_context(|| format!("replacing {}", local.display()))?;
+        }
+        std::os::unix::fs::symlink(target.as_std_path(), local)
+            .with_context(|| format!("creating the link {}", local.display()))?;
+        Ok(())
+    }
+
+    /// Creating symbolic links requires privileges on Windows,
+    /// so they are skipped
+    #[cfg(not(unix))]
+    async fn download_link(&self, remote: &Utf8Path, _local: &Path) -> anyhow::Result<()> {
+        eprintln!("Skipping the symbolic link {remote}");
+        Ok(())
+    }
+
+    async fn download_file(
+        &self,
+        remote: &Utf8Path,
+        local: &Path,
+        meta: &Metadata,
+    ) -> anyhow::Result<()> {
+        let size = meta.size.unwrap_or(0);
+        let offset = if self.resume {
+            // Don't append to the target of a link
+            match std::fs::symlink_metadata(local) {
+                Ok(existing) if existing.is_file() && existing.len() <= size => {
+                    Some(existing.len())
+                }
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        let mut file = match offset {
+            Some(_) => LocalOpenOptions::new().append(true).open(local),
+            None => std::fs::File::create(local),
+        }
+        .with_context(|| format!("opening {}", local.display()))?;
+        let mut remote_file = self
+            .sftp
+            .open(remote.to_path_buf())
+            .await
+            .with_context(|| format!("opening {remote}"))?;
+        if let Some(offset) = offset.filter(|&offset| offset > 0) {
+            remote_file
+                .seek(offset)
+                .await
+                .with_context(|| format!("seeking in {remote}"))?;
+        }
+
+        let progress = self.progress_bar(
+            remote.file_name().unwrap_or(remote.as_str()),
+            size,
+            offset.unwrap_or(0),
+        );
+        let mut buf = vec![0u8; COPY_CHUNK_SIZE];
+        loop {
+            let n = remote_file
+                .read(&mut buf)
+                .await
+                .with_context(|| format!("reading {remote}"))?;
+            if n == 0 {
+                break;
+            }
+            file.write_all(&buf[..n])
+                .with_context(|| format!("writing {}", local.display()))?;
+            progress.inc(n as u64);
+        }
+        progress.finish();
+        drop(file);
+
+        if self.preserve {
+            set_local_metadata(local, meta)?;
+        }
+        Ok(())
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_secs())
+}
+
+#[cfg(unix)]
+fn unix_mode(meta: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn unix_mode(meta: &std::fs::Metadata) -> u32 {
+    let mode = if meta.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    };
+    if meta.is_dir() {
+        mode | 0o111
+    } else {
+        mode
+    }
+}
+
+/// Returns the metadata to apply to a remote copy of a local file
+fn remote_metadata(meta: &std::fs::Metadata) -> Metadata {
+    Metadata {
+        ty: if meta.is_dir() {
+            FileType::Dir
+        } else {
+            FileType::File
+        },
+        permissions: Some(FilePermissions::from_unix_mode(unix_mode(meta))),
+        size: None,
+        uid: None,
+        gid: None,
+        accessed: meta.accessed().ok().and_then(unix_seconds),
+        modified: meta.modified().ok().and_then(unix_seconds),
+    }
+}
+
+/// Applies the metadata of a remote file to its local copy
+fn set_local_metadata(local: &Path, meta: &Metadata) -> anyhow::Result<()> {
+    let mut times = FileTimes::new();
+    if let Some(accessed) = meta.accessed {
+        times = times.set_accessed(UNIX_EPOCH + Duration::from_secs(accessed));
+    }
+    if let Some(modified) = meta.modified {
+        times = times.set_modified(UNIX_EPOCH + Duration::from_secs(modified));
+    }
+    // Windows can't open a directory to adjust its times
+    if !(cfg!(windows) && meta.is_dir()) {
+        let file = if meta.is_dir() {
+            std::fs::File::open(local)
+        } else {
+            LocalOpenOptions::new().write(true).open(local)
+        };
+        file.and_then(|file| file.set_times(times))
+            .with_context(|| format!("setting the times of {}", local.display()))?;
+    }
+
+    #[cfg(unix)]
+    if let Some(permissions) = meta.permissions {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(
+            local,
+            std::fs::Permissions::from_mode(permissions.to_unix_mode()),
+        )
+        .with_context(|| format!("setting the permissions of {}", local.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote(host: &str, path: &str) -> Location {
+        Location::Remote {
+            host: host.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_location() {
+        assert_eq!(Location::parse("host:file"), remote("host", "file"));
+        assert_eq!(
+            Location::parse("user@host:/tmp/file"),
+            remote("user@host", "/tmp/file")
+        );
+        assert_eq!(Location::parse("host:"), remote("host", ""));
+        assert_eq!(Location::parse("[::1]:file"), remote("::1", "file"));
+        assert_eq!(
+            Location::parse("./name:with:colons"),
+            Location::Local(PathBuf::from("./name:with:colons"))
+        );
+        assert_eq!(
+            Location::parse("file"),
+            Location::Local(PathBuf::from("file"))
+        );
+        assert_eq!(
+            Location::parse(":file"),
+            Location::Local(PathBuf::from(":file"))
+        );
+    }
+
+    #[test]
+    fn test_examine_local() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, "hello").unwrap();
+        let mut visited = HashSet::new();
+
+        assert!(matches!(
+            examine_local(&file, false, &mut visited).unwrap(),
+            LocalEntry::File(meta) if meta.len() == 5
+        ));
+        assert!(matches!(
+            examine_local(dir.path(), true, &mut visited).unwrap(),
+            LocalEntry::Dir(_)
+        ));
+
+        #[cfg(unix)]
+        {
+            // A link back to the directory is copied as a link,
+            // unless it was named on the command line
+            let link = dir.path().join("loop");
+            std::os::unix::fs::symlink(".", &link).unwrap();
+            assert!(matches!(
+                examine_local(&link, false, &mut visited).unwrap(),
+                LocalEntry::Link(target) if target == "."
+            ));
+            assert!(matches!(
+                examine_local(&link, true, &mut visited).unwrap(),
+                LocalEntry::Visited
+            ));
+        }
+    }
+}
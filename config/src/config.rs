@@ -251,6 +251,21 @@ pub struct Config {
     #[dynamic(default = "default_true")]
     pub detect_password_input: bool,
 
+    /// Draws a marker alongside each command block (the prompt, input
+    /// and output delimited by OSC 133), colored by the exit status
+    /// of the command.  Command blocks are not available for panes
+    /// in multiplexer domains.
+    #[dynamic(default = "default_true")]
+    pub show_command_block_markers: bool,
+
+    /// When true, the command lines that are run in any pane, as
+    /// reported by shell integration, are saved to a history file
+    /// in the data directory so that they can be searched with
+    /// `ShowCommandHistory`.  Commands that start with a space are
+    /// not saved.
+    #[dynamic(default)]
+    pub command_history: bool,
+
     /// Specifies a map of environment variables that should be set
     /// when spawning commands in the local domain.
     /// This is not used when working with remote domains.
@@ -828,6 +843,31 @@ pub struct Config {
     #[dynamic(default = "default_quit_when_all_windows_are_closed")]
     pub quit_when_all_windows_are_closed: bool,
 
+    /// When true, the windows, tabs, splits and working directories
+    /// that are open when arb exits are saved as the "last" session,
+    /// which can be brought back with `arb start --restore last`.
+    #[dynamic(default)]
+    pub save_session_on_exit: bool,
+
+    /// When a session is restored, the command that was running in the
+    /// foreground of each pane is typed at its shell prompt.  Unless this
+    /// is true, Enter is not pressed, so that the command can be reviewed
+    /// rather than re-running something with side effects.
+    #[dynamic(default)]
+    pub session_restore_runs_commands: bool,
+
+    /// When true, the scrollback of each local pane is saved along with
+    /// the session layout, and is reloaded into the pane when the session
+    /// is restored.  The alternate screen is never saved.
+    #[dynamic(default)]
+    pub save_session_scrollback: bool,
+
+    /// The maximum amount of scrollback, in bytes of text and escape
+    /// sequences before compression, that is saved for each pane.
+    /// The most recent output is kept when this limit is exceeded.
+    #[dynamic(default = "default_session_scrollback_max_bytes")]
+    pub session_scrollback_max_bytes: usize,
+
     #[dynamic(default = "default_true")]
     pub warn_about_missing_glyphs: bool,
 
@@ -887,6 +927,20 @@ pub struct Config {
     #[dynamic(default = "default_true")]
     pub allow_download_protocols: bool,
 
+    /// Whether applications may read the clipboard using OSC 52
+    #[dynamic(default)]
+    pub clipboard_read_policy: ClipboardReadPolicy,
+
+    /// Overrides clipboard_read_policy for panes in the named domains,
+    /// for example to allow reading from a trusted ssh domain
+    #[dynamic(default)]
+    pub clipboard_read_policy_by_domain: HashMap<String, ClipboardReadPolicy>,
+
+    /// OSC 52 queries are refused when the clipboard holds
+    /// more than this many bytes
+    #[dynamic(default = "default_clipboard_read_max_bytes")]
+    pub clipboard_read_max_bytes: usize,
+
     #[dynamic(default = "default_true")]
     pub allow_win32_input_mode: bool,
 
@@ -963,6 +1017,15 @@ impl Config {
         Self::load_with_overrides(&wezterm_dynamic::Value::default())
     }
 
+    /// Returns the clipboard_read_policy that applies to panes
+    /// in the domain named `domain_name`
+    pub fn clipboard_read_policy_for_domain(&self, domain_name: &str) -> ClipboardReadPolicy {
+        self.clipboard_read_policy_by_domain
+            .get(domain_name)
+            .copied()
+            .unwrap_or(self.clipboard_read_policy)
+    }
+
     /// It is relatively expensive to parse all the ssh config files,
     /// so we defer producing the default list until someone explicitly
     /// asks for it
@@ -1958,6 +2021,14 @@ fn default_status_update_interval() -> u64 {
     1_000
 }
 
+fn default_session_scrollback_max_bytes() -> usize {
+    2 * 1024 * 1024
+}
+
+fn default_clipboard_read_max_bytes() -> usize {
+    1024 * 1024
+}
+
 fn default_quit_when_all_windows_are_closed() -> bool {
     #[cfg(target_os = "macos")]
     {
@@ -2139,6 +2210,19 @@ impl PathPossibility {
     }
 }
 
+/// How to respond when an application asks to read the
+/// clipboard using OSC 52
+#[derive(Debug, FromDynamic, ToDynamic, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClipboardReadPolicy {
+    /// Reply with an empty clipboard
+    Deny,
+    /// Ask for confirmation each time
+    #[default]
+    Ask,
+    /// Reply with the contents of the clipboard
+    Allow,
+}
+
 /// Behavior when the program spawned by wezterm terminates
 #[derive(Debug, FromDynamic, ToDynamic, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ExitBehavior {
@@ -528,6 +528,20 @@ fn default_message() -> String {
     "🛑 Really continue?".to_string()
 }
 
+/// Identifies the APC sequence that `AddAsciicastMarker` sends to the
+/// pane.  `arb record` strips it from the input stream and writes a
+/// marker event in its place.
+pub const ASCIICAST_MARKER_APC: &str = "arb-asciicast-marker";
+
+/// Returns the sequence that requests a marker with the given label
+pub fn asciicast_marker_sequence(label: &str) -> String {
+    if label.is_empty() {
+        format!("\x1b_{ASCIICAST_MARKER_APC}\x1b\\")
+    } else {
+        format!("\x1b_{ASCIICAST_MARKER_APC};{label}\x1b\\")
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, FromDynamic, ToDynamic)]
 pub enum KeyAssignment {
     SpawnTab(SpawnTabDomain),
@@ -643,6 +657,17 @@ pub enum KeyAssignment {
     PromptInputLine(PromptInputLine),
     InputSelector(InputSelector),
     Confirmation(Confirmation),
+    AddAsciicastMarker(String),
+    TogglePaneRecording,
+    CopyCommandBlockOutput(ClipboardCopyDestination),
+    ToggleCommandBlockFold,
+    ScrollToFailedCommand(isize),
+    ShowCommandHistory,
+    ShowSftpBrowser,
+    ToggleSshForward {
+        domain: String,
+        forward: String,
+    },
 }
 impl_lua_conversion_dynamic!(KeyAssignment);
 
@@ -41,6 +41,7 @@ pub mod keyassignment;
 mod keys;
 pub mod lua;
 pub mod meta;
+mod reconnect;
 mod scheme_data;
 mod serial;
 mod ssh;
@@ -62,6 +63,7 @@ pub use exec_domain::*;
 pub use font::*;
 pub use frontend::*;
 pub use keys::*;
+pub use reconnect::*;
 pub use serial::*;
 pub use ssh::*;
 pub use terminal::*;
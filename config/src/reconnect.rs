@@ -0,0 +1,115 @@
+use std::time::Duration;
+use wezterm_dynamic::{FromDynamic, ToDynamic};
+
+/// Controls how a client domain behaves when its connection
+/// to the mux server is lost
+#[derive(Debug, Clone, FromDynamic, ToDynamic)]
+pub struct ReconnectPolicy {
+    /// Whether to reconnect and reattach to the same remote
+    /// panes automatically.  When not set, TLS domains reconnect
+    /// but SSH domains don't, as reconnecting an SSH domain may
+    /// need to ask for a password or passphrase again.
+    pub enabled: Option<bool>,
+
+    /// How long to wait before the first attempt to reconnect.
+    /// The wait doubles after each attempt that fails.
+    #[dynamic(default = "default_initial_interval")]
+    pub initial_interval: Duration,
+
+    /// The longest to wait between attempts to reconnect
+    #[dynamic(default = "default_max_interval")]
+    pub max_interval: Duration,
+
+    /// Give up and detach the domain after this many attempts.
+    /// The default is to keep trying.
+    pub max_attempts: Option<u32>,
+
+    /// How often to check that the server is still responding.
+    /// The connection is considered lost when nothing has been
+    /// received from the server for twice this long, which is
+    /// how a connection that died while the system was asleep
+    /// gets noticed.
+    #[dynamic(default = "default_heartbeat_interval")]
+    pub heartbeat_interval: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: None,
+            initial_interval: default_initial_interval(),
+            max_interval: default_max_interval(),
+            max_attempts: None,
+            heartbeat_interval: default_heartbeat_interval(),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Returns how long to wait before making reconnection
+    /// attempt number `attempt`, counting from zero
+    pub fn interval(&self, attempt: u32) -> Duration {
+        self.initial_interval
+            .checked_mul(2u32.saturating_pow(attempt))
+            .unwrap_or(self.max_interval)
+            .min(self.max_interval)
+    }
+
+    /// Returns how long to wait before making reconnection attempt
+    /// number `attempt`, counting from zero, or None if the policy
+    /// gives up before making that attempt
+    pub fn next_attempt(&self, attempt: u32) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| attempt >= max) {
+            None
+        } else {
+            Some(self.interval(attempt))
+        }
+    }
+}
+
+fn default_initial_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_max_interval() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_heartbeat_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interval_backs_off() {
+        let policy = ReconnectPolicy::default();
+        let intervals: Vec<u64> = (0..7).map(|n| policy.interval(n).as_secs()).collect();
+        assert_eq!(intervals, vec![1, 2, 4, 8, 16, 30, 30]);
+        assert_eq!(policy.interval(u32::MAX), policy.max_interval);
+    }
+
+    #[test]
+    fn retries_until_max_attempts() {
+        let policy = ReconnectPolicy {
+            max_attempts: Some(3),
+            ..ReconnectPolicy::default()
+        };
+        let attempts: Vec<Option<u64>> = (0..5)
+            .map(|n| policy.next_attempt(n).map(|interval| interval.as_secs()))
+            .collect();
+        assert_eq!(attempts, vec![Some(1), Some(2), Some(4), None, None]);
+
+        let policy = ReconnectPolicy {
+            max_attempts: Some(0),
+            ..ReconnectPolicy::default()
+        };
+        assert_eq!(policy.next_attempt(0), None);
+
+        // The default is to keep trying
+        let policy = ReconnectPolicy::default();
+        assert_eq!(policy.next_attempt(1000), Some(policy.max_interval));
+    }
+}
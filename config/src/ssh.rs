@@ -74,6 +74,11 @@ pub struct SshDomain {
     #[dynamic(default = "default_local_echo_threshold_ms")]
     pub local_echo_threshold_ms: Option<u64>,
 
+    /// What to do when the connection to the server is lost.
+    /// This applies only when `multiplexing` is "WezTerm".
+    #[dynamic(default)]
+    pub reconnect: ReconnectPolicy,
+
     /// Show time since last response when waiting for a response.
     /// It is recommended to use
     /// <https://wezterm.org/config/lua/pane/get_metadata.html#since_last_response_ms>
@@ -100,6 +105,22 @@ pub struct SshDomain {
     #[dynamic(default)]
     pub ssh_option: HashMap<String, String>,
 
+    /// Local port forwards, using the same syntax as `LocalForward`
+    /// in ssh_config, eg: `"8080 localhost:80"`.
+    /// These are in addition to any specified by ssh_config.
+    #[dynamic(default)]
+    pub local_forward: Vec<String>,
+
+    /// Remote port forwards, using the same syntax as `RemoteForward`
+    /// in ssh_config, eg: `"9000 localhost:3000"`
+    #[dynamic(default)]
+    pub remote_forward: Vec<String>,
+
+    /// Dynamic (SOCKS) port forwards, using the same syntax as
+    /// `DynamicForward` in ssh_config, eg: `"1080"`
+    #[dynamic(default)]
+    pub dynamic_forward: Vec<String>,
+
     pub default_prog: Option<Vec<String>>,
 
     #[dynamic(default)]
@@ -88,6 +88,10 @@ pub struct TlsDomainClient {
     /// The path to the wezterm binary on the remote host
     pub remote_wezterm_path: Option<String>,
 
+    /// What to do when the connection to the server is lost
+    #[dynamic(default)]
+    pub reconnect: ReconnectPolicy,
+
     /// Show time since last response when waiting for a response.
     /// It is recommended to use
     /// <https://wezterm.org/config/lua/pane/get_metadata.html#since_last_response_ms>
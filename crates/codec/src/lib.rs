@@ -18,7 +18,9 @@
 
 use anyhow::{bail, Context as _, Error};
 use config::keyassignment::{PaneDirection, ScrollbackEraseMode};
+use mux::asciicast::CastVersion;
 use mux::client::{ClientId, ClientInfo};
+use mux::layout::Layout;
 use mux::pane::PaneId;
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
 use mux::tab::{PaneNode, SerdeUrl, SplitRequest, TabId};
@@ -34,6 +36,7 @@ use std::io::Cursor;
 use std::ops::Range;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use termwiz::hyperlink::Hyperlink;
 use termwiz::image::{ImageData, TextureCoordinate};
 use termwiz::surface::{Line, SequenceNo};
@@ -446,7 +449,7 @@ macro_rules! pdu {
 /// The overall version of the codec.
 /// This must be bumped when backwards incompatible changes
 /// are made to the types and protocol.
-pub const CODEC_VERSION: usize = 45;
+pub const CODEC_VERSION: usize = 55;
 
 // Defines the Pdu enum.
 // Each struct has an explicit identifying number.
@@ -507,6 +510,24 @@ pdu! {
     GetPaneDirection: 60,
     GetPaneDirectionResponse: 61,
     AdjustPaneSize: 62,
+    RecordPane: 63,
+    RecordPaneResponse: 64,
+    QueryClipboard: 65,
+    WaitForPane: 66,
+    WaitForPaneResponse: 67,
+    SubscribePaneOutput: 68,
+    PaneOutput: 69,
+    ListWorkspaces: 70,
+    ListWorkspacesResponse: 71,
+    SetActiveWorkspace: 72,
+    MoveTabToWindow: 73,
+    MoveTabToWindowResponse: 74,
+    KillTab: 75,
+    KillWindow: 76,
+    ApplyLayout: 77,
+    ApplyLayoutResponse: 78,
+    DumpLayout: 79,
+    DumpLayoutResponse: 80,
 }
 
 impl Pdu {
@@ -599,6 +620,7 @@ impl Pdu {
             | Pdu::SetPalette(SetPalette { pane_id, .. })
             | Pdu::NotifyAlert(NotifyAlert { pane_id, .. })
             | Pdu::SetClipboard(SetClipboard { pane_id, .. })
+            | Pdu::QueryClipboard(QueryClipboard { pane_id, .. })
             | Pdu::PaneFocused(PaneFocused { pane_id })
             | Pdu::PaneRemoved(PaneRemoved { pane_id }) => Some(*pane_id),
             _ => None,
@@ -645,7 +667,11 @@ pub struct GetTlsCredsResponse {
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
-pub struct ListPanes {}
+pub struct ListPanes {
+    /// Whether to fill in the details of each pane that are costly
+    /// to compute, such as its foreground process
+    pub details: bool,
+}
 
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct ListPanesResponse {
@@ -774,6 +800,14 @@ pub struct SetClipboard {
     pub selection: ClipboardSelection,
 }
 
+/// Sent by the server when an application in the pane asks
+/// for the contents of the clipboard using OSC 52
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct QueryClipboard {
+    pub pane_id: PaneId,
+    pub selection: ClipboardSelection,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct SetWindowWorkspace {
     pub window_id: WindowId,
@@ -786,6 +820,82 @@ pub struct RenameWorkspace {
     pub new_workspace: String,
 }
 
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ListWorkspaces {}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct WorkspaceInfo {
+    pub name: String,
+    pub window_ids: Vec<WindowId>,
+    pub num_panes: usize,
+    /// true if this is the active workspace of the most
+    /// recently used of the other clients, such as the gui
+    pub is_active: bool,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ListWorkspacesResponse {
+    pub workspaces: Vec<WorkspaceInfo>,
+}
+
+/// Switches the active workspace of the other clients that
+/// are attached to the mux, such as the gui
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SetActiveWorkspace {
+    pub workspace: String,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct MoveTabToWindow {
+    pub tab_id: TabId,
+    /// If None, the tab is moved into a new window
+    pub window_id: Option<WindowId>,
+    /// The workspace for the new window; if None, the
+    /// workspace of the tab's current window is used
+    pub workspace: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct MoveTabToWindowResponse {
+    pub window_id: WindowId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct KillTab {
+    pub tab_id: TabId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct KillWindow {
+    pub window_id: WindowId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ApplyLayout {
+    pub layout: Layout,
+    /// The workspace to use if the layout doesn't name one
+    pub workspace: String,
+    pub size: TerminalSize,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ApplyLayoutResponse {
+    pub window_id: WindowId,
+}
+
+/// Captures the layout of a tab, or of all of the tabs in
+/// a workspace when `workspace` is set
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct DumpLayout {
+    pub tab_id: Option<TabId>,
+    pub workspace: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct DumpLayoutResponse {
+    pub layout: Layout,
+}
+
 /// This is used both as a notification from server->client
 /// and as a configuration request from client->server when
 /// the client's preferred configuration changes
@@ -881,6 +991,110 @@ pub struct AdjustPaneSize {
     pub amount: usize,
 }
 
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone)]
+pub enum RecordPaneAction {
+    Start,
+    Stop,
+    Toggle,
+    /// Add a marker with the given label to the in-progress recording
+    AddMarker(String),
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct RecordPane {
+    pub pane_id: PaneId,
+    pub action: RecordPaneAction,
+    /// Where to write the recording when starting.
+    /// If None, a file is created in the recordings directory
+    /// of the server.
+    pub path: Option<PathBuf>,
+    pub cast_version: CastVersion,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct RecordPaneResponse {
+    /// true if the pane is being recorded as a result of the request.
+    /// For `AddMarker`, true if the marker was added.
+    pub recording: bool,
+    /// The file holding the recording, if any
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub enum WaitCondition {
+    /// Output matching the regex `pattern` is present in the pane,
+    /// starting from `start_row`, or from the cursor row at the
+    /// time of the request if None.
+    Match {
+        pattern: String,
+        start_row: Option<StableRowIndex>,
+    },
+    /// No output has been produced for `idle`
+    Idle { idle: Duration },
+    /// The next command finishes, as reported by OSC 133;D
+    CommandFinished,
+    /// The pane is closed
+    PaneDead,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct WaitForPane {
+    pub pane_id: PaneId,
+    pub condition: WaitCondition,
+    /// Give up waiting after this long; None waits indefinitely
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub enum WaitForPaneResult {
+    /// The text of the lines that matched
+    Matched {
+        text: String,
+    },
+    Idle,
+    CommandFinished {
+        exit_status: Option<i32>,
+    },
+    /// The pane was closed before the condition was met
+    PaneDied,
+    TimedOut,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct WaitForPaneResponse {
+    pub result: WaitForPaneResult,
+}
+
+/// Asks the server to stream the output of a pane to this client,
+/// as a series of `PaneOutput` PDUs, until the pane's pty is closed
+/// or the client disconnects.
+/// The server responds with a `GetLinesResponse` holding the existing
+/// lines that were requested; the stream continues from exactly the
+/// point at which they were taken.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SubscribePaneOutput {
+    pub pane_id: PaneId,
+    /// Whether to include the escape sequences that were output.
+    /// If false, only the printable text and line breaks are sent.
+    pub escapes: bool,
+    /// How many lines of existing output to return, counting
+    /// back from the line holding the cursor
+    pub lines: usize,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct PaneOutput {
+    pub pane_id: PaneId,
+    pub text: String,
+    /// true once the pane's pty has been closed; no more output
+    /// will be sent for this subscription
+    pub finished: bool,
+    /// true if the subscription was ended because the subscriber
+    /// fell too far behind; some output was discarded.
+    /// `finished` is also true in that case.
+    pub overflowed: bool,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct GetPaneDirectionResponse {
     pub pane_id: Option<PaneId>,
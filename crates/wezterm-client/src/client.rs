@@ -12,14 +12,14 @@ use mux::connui::ConnectionUI;
 use mux::domain::DomainId;
 use mux::pane::PaneId;
 use mux::ssh::ssh_connect_with_ui;
-use mux::Mux;
+use mux::{Mux, MuxNotification};
 use openssl::ssl::{SslConnector, SslFiletype, SslMethod};
 use openssl::x509::X509;
 use portable_pty::Child;
 use smol::channel::{bounded, unbounded, Receiver, Sender};
 use smol::prelude::*;
 use smol::{block_on, Async};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{Read, Write};
 use std::marker::Unpin;
 use std::net::TcpStream;
@@ -30,9 +30,11 @@ use std::os::unix::process::CommandExt;
 #[cfg(windows)]
 use std::os::windows::io::{AsRawSocket, AsSocket, BorrowedSocket, RawSocket};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use thiserror::Error;
+use wezterm_term::Line;
 use wezterm_uds::UnixStream;
 
 #[derive(Error, Debug)]
@@ -48,7 +50,25 @@ enum ReaderMessage {
         pdu: Pdu,
         promise: Sender<anyhow::Result<Pdu>>,
     },
+    /// Routes `PaneOutput` PDUs for the pane to `sender`
+    SubscribePaneOutput {
+        pane_id: PaneId,
+        sender: Sender<PaneOutput>,
+    },
     Readable,
+    /// Time to check that the server is still responding
+    Heartbeat,
+}
+
+/// The state of the connection to the mux server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    Connected,
+    /// The connection was lost; `attempt` is the number of
+    /// the attempt to reconnect that is pending or in progress
+    Reconnecting {
+        attempt: u32,
+    },
 }
 
 #[derive(Clone)]
@@ -59,6 +79,7 @@ pub struct Client {
     client_domain_config: ClientDomainConfig,
     pub is_reconnectable: bool,
     pub is_local: bool,
+    status: Arc<Mutex<ConnectionStatus>>,
 }
 
 #[derive(Error, Debug, Clone, PartialEq, Eq)]
@@ -331,12 +352,47 @@ fn process_unilateral(
     Ok(())
 }
 
+/// The number of `PaneOutput` PDUs that may be waiting for a subscriber
+/// before it is considered to have fallen behind
+const MAX_PENDING_OUTPUT: usize = 1024;
+
+/// Routes `output` to the subscriber for its pane.  A subscriber that
+/// has fallen behind is sent a final `PaneOutput` flagged as having
+/// overflowed, in the slot that is kept for it, and removed.
+fn route_pane_output(
+    subscribers: &mut HashMap<PaneId, Sender<PaneOutput>>,
+    mut output: PaneOutput,
+) {
+    let pane_id = output.pane_id;
+    let sender = match subscribers.get(&pane_id) {
+        Some(sender) => sender,
+        None => {
+            log::trace!("no subscriber for output of pane {pane_id}");
+            return;
+        }
+    };
+    if sender.len() + 1 >= MAX_PENDING_OUTPUT {
+        log::warn!("subscriber fell behind the output of pane {pane_id}");
+        output.text.clear();
+        output.finished = true;
+        output.overflowed = true;
+    }
+    let finished = output.finished;
+    if sender.try_send(output).is_err() || finished {
+        subscribers.remove(&pane_id);
+    }
+}
+
 #[derive(Error, Debug, Clone, PartialEq, Eq)]
 enum NotReconnectableError {
     #[error("Client was destroyed")]
     ClientWasDestroyed,
 }
 
+#[derive(Error, Debug)]
+#[error("No response from the server for {0:?}")]
+struct ServerNotResponding(Duration);
+
 fn client_thread(
     reconnectable: &mut Reconnectable,
     local_domain_id: Option<DomainId>,
@@ -373,6 +429,19 @@ async fn client_thread_async(
     let mut promises = Promises {
         map: HashMap::new(),
     };
+    let mut output_subscribers: HashMap<PaneId, Sender<PaneOutput>> = HashMap::new();
+
+    // When we can reconnect, we periodically ping the server so that a
+    // connection that has silently died (eg: because the system was asleep)
+    // is noticed and replaced, rather than appearing to hang forever
+    let heartbeat_interval = reconnectable
+        .config
+        .reconnect_policy()
+        .map(|policy| policy.heartbeat_interval);
+    let mut next_heartbeat = heartbeat_interval.map(|interval| Instant::now() + interval);
+    let mut last_recv = Instant::now();
+    let mut ping_sent: Option<Instant> = None;
+    let mut pings = HashSet::new();
 
     let mut stream = reconnectable.take_stream().unwrap();
 
@@ -381,8 +450,17 @@ async fn client_thread_async(
         let wait_for_read = stream
             .wait_for_readable()
             .map(|_| Ok(ReaderMessage::Readable));
+        let heartbeat = async move {
+            match next_heartbeat {
+                Some(when) => {
+                    smol::Timer::at(when).await;
+                    Ok(ReaderMessage::Heartbeat)
+                }
+                None => smol::future::pending().await,
+            }
+        };
 
-        match smol::future::or(rx_msg, wait_for_read).await {
+        match smol::future::or(rx_msg, smol::future::or(wait_for_read, heartbeat)).await {
             Ok(ReaderMessage::SendPdu { pdu, promise }) => {
                 let serial = next_serial;
                 next_serial += 1;
@@ -393,6 +471,41 @@ async fn client_thread_async(
                     .context("encoding a PDU to send to the server")?;
                 stream.flush().await.context("flushing PDU to server")?;
             }
+            Ok(ReaderMessage::SubscribePaneOutput { pane_id, sender }) => {
+                output_subscribers.insert(pane_id, sender);
+            }
+            Ok(ReaderMessage::Heartbeat) => {
+                let interval =
+                    heartbeat_interval.expect("heartbeat is only scheduled with interval");
+                next_heartbeat.replace(Instant::now() + interval);
+                if last_recv.elapsed() < interval {
+                    continue;
+                }
+                match ping_sent {
+                    Some(sent) if sent.elapsed() >= interval => {
+                        let reason = ServerNotResponding(last_recv.elapsed());
+                        log::error!("{}", reason);
+                        promises.fail_all(&reason.to_string());
+                        return Err(reason.into());
+                    }
+                    Some(_) => {}
+                    None => {
+                        let serial = next_serial;
+                        next_serial += 1;
+                        pings.insert(serial);
+                        ping_sent.replace(Instant::now());
+
+                        Pdu::Ping(Ping {})
+                            .encode_async(&mut stream, serial)
+                            .await
+                            .context("encoding a heartbeat to send to the server")?;
+                        stream
+                            .flush()
+                            .await
+                            .context("flushing heartbeat to server")?;
+                    }
+                }
+            }
             Ok(ReaderMessage::Readable) => {
                 match Pdu::decode_async(&mut stream, Some(next_serial)).await {
                     Ok(decoded) => {
@@ -401,7 +514,14 @@ async fn client_thread_async(
                             decoded.serial,
                             decoded.pdu.pdu_name()
                         );
-                        if decoded.serial == 0 {
+                        last_recv = Instant::now();
+                        ping_sent.take();
+                        if pings.remove(&decoded.serial) {
+                            // The response to a heartbeat; receiving it is all
+                            // that we needed
+                        } else if let Pdu::PaneOutput(output) = decoded.pdu {
+                            route_pane_output(&mut output_subscribers, output);
+                        } else if decoded.serial == 0 {
                             process_unilateral(local_domain_id, decoded)
                                 .context("processing unilateral PDU from server")
                                 .map_err(|e| {
@@ -434,6 +554,30 @@ async fn client_thread_async(
     }
 }
 
+fn domain_has_panes(domain_id: DomainId) -> bool {
+    Mux::try_get()
+        .map(|mux| {
+            mux.iter_panes()
+                .iter()
+                .any(|pane| pane.domain_id() == domain_id)
+        })
+        .unwrap_or(false)
+}
+
+/// Updates the status, and repaints the windows so that the
+/// tab bar reflects it
+fn set_connection_status(status: &Mutex<ConnectionStatus>, new_status: ConnectionStatus) {
+    *status.lock().unwrap() = new_status;
+    promise::spawn::spawn_into_main_thread(async move {
+        if let Some(mux) = Mux::try_get() {
+            for window_id in mux.iter_windows() {
+                mux.notify(MuxNotification::WindowInvalidated(window_id));
+            }
+        }
+    })
+    .detach();
+}
+
 pub fn unix_connect_with_retry(
     target: &UnixTarget,
     just_spawned: bool,
@@ -630,19 +774,7 @@ impl Reconnectable {
     }
 
     fn reconnectable(&mut self) -> bool {
-        match &self.config {
-            // It doesn't make sense to reconnect to a unix socket; we only
-            // get disconnected it it dies, so respawning it would not preserve
-            // the set of tabs and we'd have confusing and inconsistent state
-            ClientDomainConfig::Unix(_) => false,
-            ClientDomainConfig::Tls(_) => true,
-            // It *does* make sense to reconnect with an ssh session, but we
-            // need to grow some smarts about whether the disconnect was because
-            // we sent CTRL-D to close the last session, or whether it was a network
-            // level disconnect, because we will otherwise throw up authentication
-            // dialogs that would be annoying
-            ClientDomainConfig::Ssh(_) => false,
-        }
+        self.config.reconnect_policy().is_some()
     }
 
     fn connect(
@@ -1045,12 +1177,15 @@ impl Client {
         let (sender, mut receiver) = unbounded();
         let client_id = ClientId::new();
 
+        let status = Arc::new(Mutex::new(ConnectionStatus::Connected));
+        let thread_status = Arc::clone(&status);
+
         thread::spawn(move || {
-            const BASE_INTERVAL: Duration = Duration::from_secs(1);
-            const MAX_INTERVAL: Duration = Duration::from_secs(10);
+            let status = thread_status;
+            let policy = reconnectable.config.reconnect_policy().unwrap_or_default();
+            let mut attempt = 0;
 
-            let mut backoff = BASE_INTERVAL;
-            loop {
+            'reconnect: loop {
                 if let Err(e) = client_thread(&mut reconnectable, local_domain_id, &mut receiver) {
                     if !reconnectable.reconnectable() || local_domain_id.is_none() {
                         log::debug!("client thread ended: {}", e);
@@ -1060,7 +1195,11 @@ impl Client {
                     let local_domain_id = local_domain_id.expect("checked above");
 
                     if let Some(ioerr) = e.root_cause().downcast_ref::<std::io::Error>() {
-                        if let std::io::ErrorKind::UnexpectedEof = ioerr.kind() {
+                        // A dropped ssh connection looks just like the server
+                        // closing the connection, so only a TLS EOF is final
+                        if ioerr.kind() == std::io::ErrorKind::UnexpectedEof
+                            && !matches!(reconnectable.config, ClientDomainConfig::Ssh(_))
+                        {
                             // Don't reconnect for a simple EOF
                             log::error!("server closed connection ({})", e);
                             break;
@@ -1072,10 +1211,39 @@ impl Client {
                         break;
                     }
 
+                    if !domain_has_panes(local_domain_id) {
+                        // Most likely the last pane was closed and the server
+                        // went away with it; either way, there is nothing that
+                        // we could reattach to
+                        log::info!("client disconnected {}; no panes to reattach", e);
+                        break;
+                    }
+
+                    // If we got as far as reattaching since the last failure,
+                    // then this is a fresh disconnect rather than a failure
+                    // to make a usable connection
+                    if *status.lock().unwrap() == ConnectionStatus::Connected {
+                        attempt = 0;
+                    }
+
                     let mut ui = ConnectionUI::new();
                     ui.title("Arb: Reconnecting...");
 
                     loop {
+                        let backoff = match policy.next_attempt(attempt) {
+                            Some(backoff) => backoff,
+                            None => {
+                                log::error!("giving up reconnecting after {} attempts", attempt);
+                                ui.output_str(&format!(
+                                    "Giving up reconnecting after {} attempts\n",
+                                    attempt
+                                ));
+                                break 'reconnect;
+                            }
+                        };
+                        attempt += 1;
+                        set_connection_status(&status, ConnectionStatus::Reconnecting { attempt });
+
                         ui.sleep_with_reason(
                             &format!("client disconnected {}; will reconnect", e),
                             backoff,
@@ -1085,7 +1253,6 @@ impl Client {
                         let no_auto_start = true; // Don't auto-start on a reconnect
                         match reconnectable.connect(initial, &mut ui, no_auto_start) {
                             Ok(_) => {
-                                backoff = BASE_INTERVAL;
                                 log::error!("Reconnected!");
                                 promise::spawn::spawn_into_main_thread(async move {
                                     ClientDomain::reattach(local_domain_id, ui).await.ok();
@@ -1094,11 +1261,14 @@ impl Client {
                                 break;
                             }
                             Err(err) => {
-                                backoff = (backoff + backoff).min(MAX_INTERVAL);
-                                ui.output_str(&format!(
-                                    "problem reconnecting: {}; will reconnect in {:?}\n",
-                                    err, backoff
-                                ));
+                                if let Some(backoff) = policy.next_attempt(attempt) {
+                                    ui.output_str(&format!(
+                                        "problem reconnecting: {}; will reconnect in {:?}\n",
+                                        err, backoff
+                                    ));
+                                } else {
+                                    ui.output_str(&format!("problem reconnecting: {}\n", err));
+                                }
                             }
                         }
                     }
@@ -1138,9 +1308,18 @@ impl Client {
             is_local,
             client_id,
             client_domain_config,
+            status,
         }
     }
 
+    pub fn connection_status(&self) -> ConnectionStatus {
+        *self.status.lock().unwrap()
+    }
+
+    pub(crate) fn mark_connected(&self) {
+        set_connection_status(&self.status, ConnectionStatus::Connected);
+    }
+
     pub fn into_client_domain_config(self) -> ClientDomainConfig {
         self.client_domain_config
     }
@@ -1313,6 +1492,49 @@ impl Client {
         rx.recv().await.context("send_pdu recv")?
     }
 
+    /// Subscribes to the output of a pane, returning up to `lines` of
+    /// its existing output, counting back from the line holding the
+    /// cursor.  The output that follows those lines is delivered to the
+    /// returned receiver, finishing with a `PaneOutput` whose `finished`
+    /// field is true once the pane's pty has closed, or once the
+    /// subscriber has fallen too far behind, in which case `overflowed`
+    /// is also true.
+    pub async fn subscribe_pane_output(
+        &self,
+        pane_id: PaneId,
+        escapes: bool,
+        lines: usize,
+    ) -> anyhow::Result<(Vec<Line>, Receiver<PaneOutput>)> {
+        let (sender, rx) = bounded(MAX_PENDING_OUTPUT);
+        self.sender
+            .send(ReaderMessage::SubscribePaneOutput { pane_id, sender })
+            .await
+            .map_err(|_| ChannelSendError)
+            .context("subscribe_pane_output send")?;
+
+        match self
+            .send_pdu(Pdu::SubscribePaneOutput(SubscribePaneOutput {
+                pane_id,
+                escapes,
+                lines,
+            }))
+            .await?
+        {
+            Pdu::GetLinesResponse(response) => {
+                let lines = response
+                    .lines
+                    .extract_data()
+                    .0
+                    .into_iter()
+                    .map(|(_idx, line)| line)
+                    .collect();
+                Ok((lines, rx))
+            }
+            Pdu::ErrorResponse(err) => bail!("{}", err.reason),
+            pdu => bail!("unexpected response {:?}", pdu),
+        }
+    }
+
     pub async fn resolve_pane_id(&self, pane_id: Option<PaneId>) -> anyhow::Result<PaneId> {
         let pane_id: PaneId = match pane_id {
             Some(p) => p,
@@ -1342,8 +1564,14 @@ impl Client {
         Ok(pane_id)
     }
 
+    /// Lists the panes, without the details that are costly to compute;
+    /// use `list_panes_with` to request those
+    pub async fn list_panes(&self) -> anyhow::Result<ListPanesResponse> {
+        self.list_panes_with(ListPanes { details: false }).await
+    }
+
     rpc!(ping, Ping = (), Pong);
-    rpc!(list_panes, ListPanes = (), ListPanesResponse);
+    rpc!(list_panes_with, ListPanes, ListPanesResponse);
     rpc!(spawn_v2, SpawnV2, SpawnResponse);
     rpc!(split_pane, SplitPane, SpawnResponse);
     rpc!(
@@ -1393,4 +1621,78 @@ impl Client {
         GetPaneDirectionResponse
     );
     rpc!(adjust_pane_size, AdjustPaneSize, UnitResponse);
+    rpc!(record_pane, RecordPane, RecordPaneResponse);
+    rpc!(wait_for_pane, WaitForPane, WaitForPaneResponse);
+    rpc!(list_workspaces, ListWorkspaces = (), ListWorkspacesResponse);
+    rpc!(set_active_workspace, SetActiveWorkspace, UnitResponse);
+    rpc!(move_tab_to_window, MoveTabToWindow, MoveTabToWindowResponse);
+    rpc!(kill_tab, KillTab, UnitResponse);
+    rpc!(kill_window, KillWindow, UnitResponse);
+    rpc!(apply_layout, ApplyLayout, ApplyLayoutResponse);
+    rpc!(dump_layout, DumpLayout, DumpLayoutResponse);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn output(text: &str, finished: bool) -> PaneOutput {
+        PaneOutput {
+            pane_id: 1,
+            text: text.to_string(),
+            finished,
+            overflowed: false,
+        }
+    }
+
+    #[test]
+    fn should_route_output_until_finished() {
+        let (sender, rx) = bounded(MAX_PENDING_OUTPUT);
+        let mut subscribers = HashMap::new();
+        subscribers.insert(1, sender);
+
+        route_pane_output(&mut subscribers, output("hello", false));
+        assert!(subscribers.contains_key(&1));
+        route_pane_output(&mut subscribers, output("", true));
+        assert!(subscribers.is_empty());
+
+        assert_eq!(rx.try_recv().unwrap(), output("hello", false));
+        assert_eq!(rx.try_recv().unwrap(), output("", true));
+        assert!(rx.is_closed());
+    }
+
+    #[test]
+    fn should_flag_overflow() {
+        let (sender, rx) = bounded(MAX_PENDING_OUTPUT);
+        let mut subscribers = HashMap::new();
+        subscribers.insert(1, sender);
+
+        for _ in 0..MAX_PENDING_OUTPUT + 10 {
+            route_pane_output(&mut subscribers, output("x", false));
+        }
+        assert!(subscribers.is_empty());
+
+        let received: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(received.len(), MAX_PENDING_OUTPUT);
+        assert_eq!(
+            received.last(),
+            Some(&PaneOutput {
+                pane_id: 1,
+                text: String::new(),
+                finished: true,
+                overflowed: true,
+            })
+        );
+    }
+
+    #[test]
+    fn should_drop_subscriber_that_went_away() {
+        let (sender, rx) = bounded(MAX_PENDING_OUTPUT);
+        let mut subscribers = HashMap::new();
+        subscribers.insert(1, sender);
+        drop(rx);
+
+        route_pane_output(&mut subscribers, output("hello", false));
+        assert!(subscribers.is_empty());
+    }
 }
@@ -4,7 +4,7 @@ use anyhow::{anyhow, bail};
 use async_trait::async_trait;
 use codec::{ListPanesResponse, SpawnV2, SplitPane};
 use config::keyassignment::SpawnTabDomain;
-use config::{SshDomain, TlsDomainClient, UnixDomain};
+use config::{ReconnectPolicy, SshDomain, TlsDomainClient, UnixDomain};
 use mux::connui::{ConnectionUI, ConnectionUIParams};
 use mux::domain::{alloc_domain_id, Domain, DomainId, DomainState, SplitSource};
 use mux::pane::{Pane, PaneId};
@@ -207,6 +207,29 @@ impl ClientDomainConfig {
         }
     }
 
+    /// Returns the policy for reconnecting after the connection is lost,
+    /// or None if the domain doesn't reconnect
+    pub fn reconnect_policy(&self) -> Option<ReconnectPolicy> {
+        let (policy, default) = match self {
+            // It doesn't make sense to reconnect to a unix socket; we only
+            // get disconnected it it dies, so respawning it would not preserve
+            // the set of tabs and we'd have confusing and inconsistent state
+            ClientDomainConfig::Unix(_) => return None,
+            ClientDomainConfig::Tls(tls) => (&tls.reconnect, true),
+            // It *does* make sense to reconnect with an ssh session, but we
+            // can't tell whether the disconnect was because we sent CTRL-D to
+            // close the last session, or whether it was a network level
+            // disconnect, and reconnecting can throw up authentication dialogs
+            // that would be annoying; so it must be enabled explicitly
+            ClientDomainConfig::Ssh(ssh) => (&ssh.reconnect, false),
+        };
+        if policy.enabled.unwrap_or(default) {
+            Some(policy.clone())
+        } else {
+            None
+        }
+    }
+
     pub fn label(&self) -> String {
         match self {
             ClientDomainConfig::Unix(unix) => format!("unix mux {}", unix.socket_path().display()),
@@ -467,7 +490,8 @@ impl ClientDomain {
         let inner = Self::get_client_inner_for_domain(domain_id)?;
 
         let panes = inner.client.list_panes().await?;
-        Self::process_pane_list(inner, panes, None)?;
+        Self::process_pane_list(Arc::clone(&inner), panes, None)?;
+        inner.client.mark_connected();
 
         ui.close();
         Ok(())
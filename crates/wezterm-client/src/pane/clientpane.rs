@@ -1,3 +1,4 @@
+use crate::client::ConnectionStatus;
 use crate::domain::ClientInner;
 use crate::pane::mousestate::MouseState;
 use crate::pane::renderable::{hydrate_lines, RenderableInner, RenderableState};
@@ -6,6 +7,7 @@ use async_trait::async_trait;
 use codec::*;
 use config::configuration;
 use config::keyassignment::ScrollbackEraseMode;
+use mux::asciicast::CastVersion;
 use mux::domain::DomainId;
 use mux::pane::{
     alloc_pane_id, CachePolicy, CloseReason, ForEachPaneLogicalLine, LogicalLine, Pane, PaneId,
@@ -20,6 +22,7 @@ use ratelim::RateLimiter;
 use std::cell::RefCell;
 use std::collections::{BTreeMap, HashMap};
 use std::ops::Range;
+use std::path::PathBuf;
 use std::sync::Arc;
 use termwiz::input::KeyEvent;
 use termwiz::surface::SequenceNo;
@@ -168,6 +171,26 @@ impl ClientPane {
                     log::error!("ClientPane: Ignoring SetClipboard request {:?}", clipboard);
                 }
             },
+            Pdu::QueryClipboard(QueryClipboard { selection, .. }) => {
+                // The clipboard of this pane decides whether the remote
+                // application may read it, and sends the reply through
+                // our writer back to the remote pane
+                let clipboard = self.clipboard.lock().clone();
+                match clipboard {
+                    Some(clip) => clip.query_contents(selection)?,
+                    None => {
+                        // The pane isn't in a window that could answer,
+                        // so reply with nothing rather than not at all
+                        self.client
+                            .client
+                            .write_to_pane(WriteToPane {
+                                pane_id: self.remote_pane_id,
+                                data: selection.osc52_response("").into_bytes(),
+                            })
+                            .await?;
+                    }
+                }
+            }
             Pdu::SetPalette(SetPalette { palette, .. }) => {
                 *self.application_palette.lock() = palette != *self.configured_palette.lock();
 
@@ -271,6 +294,20 @@ impl Pane for ClientPane {
             Value::String("since_last_response_ms".to_string()),
             Value::U64(inner.last_recv_time.elapsed().as_millis() as u64),
         );
+        let reconnect_attempt = match inner.client.client.connection_status() {
+            ConnectionStatus::Connected => None,
+            ConnectionStatus::Reconnecting { attempt } => Some(attempt),
+        };
+        map.insert(
+            Value::String("is_reconnecting".to_string()),
+            Value::Bool(reconnect_attempt.is_some()),
+        );
+        if let Some(attempt) = reconnect_attempt {
+            map.insert(
+                Value::String("reconnect_attempt".to_string()),
+                Value::U64(attempt.into()),
+            );
+        }
 
         Value::Object(map.into())
     }
@@ -447,6 +484,37 @@ impl Pane for ClientPane {
         }
     }
 
+    async fn toggle_recording(&self, cast_version: CastVersion) -> anyhow::Result<(PathBuf, bool)> {
+        let response = self
+            .client
+            .client
+            .record_pane(RecordPane {
+                pane_id: self.remote_pane_id,
+                action: RecordPaneAction::Toggle,
+                path: None,
+                cast_version,
+            })
+            .await?;
+        match response.path {
+            Some(path) => Ok((path, response.recording)),
+            None => bail!("pane {} was not recorded", self.remote_pane_id),
+        }
+    }
+
+    async fn add_recording_marker(&self, label: &str) -> anyhow::Result<bool> {
+        let response = self
+            .client
+            .client
+            .record_pane(RecordPane {
+                pane_id: self.remote_pane_id,
+                action: RecordPaneAction::AddMarker(label.to_string()),
+                path: None,
+                cast_version: CastVersion::default(),
+            })
+            .await?;
+        Ok(response.recording)
+    }
+
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
         let input_serial;
         {
@@ -0,0 +1,608 @@
+//! Speculative local echo for client panes.
+//!
+//! Input that has been sent to the server but whose effect we haven't
+//! yet seen is replayed over the most recent copy of the cursor line
+//! that the server sent us, and the result is shown in place of that
+//! line.  The server tags its responses with the serial of the input
+//! that it has processed; once it has acknowledged some input we check
+//! that the line it sends agrees with what we predicted for that input.
+//! If it doesn't, the predictions are thrown away and we stop showing
+//! new ones until one of them turns out to be right.
+use codec::InputSerial;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+use termwiz::cell::{Cell, CellAttributes, Underline};
+use termwiz::surface::SEQ_ZERO;
+use wezterm_term::{KeyCode, KeyModifiers, Line, StableRowIndex};
+
+/// The server acknowledges input as soon as it has written it to the
+/// pty, which is usually a little before the application has echoed
+/// it.  This is how long we give the echo to show up before deciding
+/// that a prediction was wrong.
+const ECHO_GRACE: Duration = Duration::from_millis(250);
+
+/// Predictions for input that the server hasn't acknowledged after this
+/// long are abandoned; the connection is probably stuck.
+const MAX_UNACKNOWLEDGED: Duration = Duration::from_secs(5);
+
+/// Words and phrases that suggest that the cursor line is asking for a
+/// secret.  They are matched case-insensitively against whole words.
+const SECRET_PROMPTS: &[&str] = &[
+    "password",
+    "passwd",
+    "passphrase",
+    "passcode",
+    "pin",
+    "otp",
+    "verification code",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Insert(char),
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    /// Input whose effect we can't guess, such as Enter or a control
+    /// key.  Nothing that follows it can be predicted either.
+    Opaque,
+}
+
+#[derive(Debug)]
+struct PendingEdit {
+    edit: Edit,
+    /// The serial of the key event that produced this edit.
+    /// Pastes are not tagged with a serial by the server, so those
+    /// are considered to be acknowledged after a round trip.
+    serial: Option<InputSerial>,
+    sent: Instant,
+    acknowledged: Option<Instant>,
+}
+
+#[derive(Debug, Clone)]
+struct CursorLine {
+    row: StableRowIndex,
+    x: usize,
+    line: Line,
+}
+
+/// The predicted state of the cursor line
+#[derive(Debug, Clone)]
+pub struct Prediction {
+    pub row: StableRowIndex,
+    pub x: usize,
+    pub line: Line,
+}
+
+struct Replay {
+    state: CursorLine,
+    /// The columns affected by the edits
+    touched: Range<usize>,
+    /// How many of the edits could be applied
+    applied: usize,
+    complete: bool,
+}
+
+impl Replay {
+    fn matches(&self, server: &CursorLine) -> bool {
+        self.state.row == server.row
+            && self.state.x == server.x
+            && self.state.line.columns_as_str(self.touched.clone())
+                == server.line.columns_as_str(self.touched.clone())
+    }
+}
+
+enum Verdict {
+    Confirmed,
+    Mismatch,
+    /// The edits included something we made no prediction for
+    Unknown,
+    /// The application may not have echoed the input yet
+    Waiting,
+}
+
+#[derive(Debug)]
+pub struct Predictor {
+    edits: VecDeque<PendingEdit>,
+    /// The most recent copy of the cursor line from the server
+    server: Option<CursorLine>,
+    /// The copy of the cursor line that `edits` are replayed over.
+    /// This lags behind `server` while we wait for acknowledged
+    /// input to be echoed.
+    anchor: Option<CursorLine>,
+    /// Cleared when a prediction turns out to be wrong.  We keep
+    /// making predictions while it is clear, but don't show them.
+    confident: bool,
+    rtt: Duration,
+    cols: usize,
+    /// Incremented whenever the prediction may have changed
+    seqno: usize,
+}
+
+impl Predictor {
+    pub fn new(cols: usize) -> Self {
+        Self {
+            edits: VecDeque::new(),
+            server: None,
+            anchor: None,
+            confident: true,
+            rtt: Duration::ZERO,
+            cols,
+            seqno: 0,
+        }
+    }
+
+    /// Returns a number that changes whenever the prediction may have
+    /// changed, so that it only needs to be repainted when it does
+    pub fn seqno(&self) -> usize {
+        self.seqno
+    }
+
+    /// Record a key press that is about to be sent to the server
+    pub fn push_key(&mut self, key: KeyCode, mods: KeyModifiers, serial: InputSerial) {
+        if key.is_modifier() {
+            return;
+        }
+        let edit = if mods != KeyModifiers::NONE && mods != KeyModifiers::SHIFT {
+            Edit::Opaque
+        } else {
+            match key {
+                KeyCode::Char(c) if !c.is_control() => {
+                    if self.looks_like_password_prompt() {
+                        Edit::Opaque
+                    } else {
+                        Edit::Insert(c)
+                    }
+                }
+                KeyCode::Backspace => Edit::Backspace,
+                KeyCode::Delete => Edit::Delete,
+                KeyCode::LeftArrow => Edit::Left,
+                KeyCode::RightArrow => Edit::Right,
+                _ => Edit::Opaque,
+            }
+        };
+        self.push(edit, Some(serial));
+    }
+
+    /// Record text that is about to be pasted
+    pub fn push_paste(&mut self, text: &str) {
+        if text.chars().any(char::is_control) || self.looks_like_password_prompt() {
+            self.push(Edit::Opaque, None);
+            return;
+        }
+        for c in text.chars() {
+            self.push(Edit::Insert(c), None);
+        }
+    }
+
+    fn push(&mut self, edit: Edit, serial: Option<InputSerial>) {
+        self.seqno += 1;
+        self.edits.push_back(PendingEdit {
+            edit,
+            serial,
+            sent: Instant::now(),
+            acknowledged: None,
+        });
+    }
+
+    /// Don't reveal what is typed in response to something that
+    /// might be a password prompt
+    fn looks_like_password_prompt(&self) -> bool {
+        self.server
+            .as_ref()
+            .map(|server| is_secret_prompt(&server.line.as_str()))
+            .unwrap_or(false)
+    }
+
+    /// Called when the server sends us a fresh copy of the cursor line.
+    /// `ack` is the serial of the most recent input that the server had
+    /// processed when it sent the line, if this update was caused by input.
+    pub fn server_update(
+        &mut self,
+        row: StableRowIndex,
+        x: usize,
+        line: Line,
+        ack: Option<InputSerial>,
+        cols: usize,
+    ) {
+        let now = Instant::now();
+        self.seqno += 1;
+        if let Some(ack) = ack {
+            self.rtt = Duration::from_millis(ack.elapsed_millis());
+        }
+        for pending in self.edits.iter_mut() {
+            if pending.acknowledged.is_some() {
+                continue;
+            }
+            let acknowledged = match (pending.serial, ack) {
+                (Some(serial), Some(ack)) => serial <= ack,
+                (Some(_), None) => false,
+                (None, _) => now.duration_since(pending.sent) >= self.rtt,
+            };
+            if acknowledged {
+                pending.acknowledged = Some(now);
+            }
+        }
+
+        self.cols = cols;
+        self.server = Some(CursorLine { row, x, line });
+        self.reconcile(now);
+    }
+
+    /// Forget everything; used when we don't know where the cursor is
+    pub fn clear(&mut self) {
+        self.seqno += 1;
+        self.edits.clear();
+        self.server = None;
+        self.anchor = None;
+        self.confident = false;
+    }
+
+    /// Abandons predictions that have been waiting too long for the
+    /// server, returning the affected row if any were abandoned
+    pub fn expire(&mut self, now: Instant) -> Option<StableRowIndex> {
+        let row = self.anchor.as_ref().map(|anchor| anchor.row)?;
+        let before = self.edits.len();
+
+        let stuck = self
+            .edits
+            .front()
+            .map(|pending| {
+                pending.acknowledged.is_none()
+                    && now.duration_since(pending.sent) > MAX_UNACKNOWLEDGED
+            })
+            .unwrap_or(false);
+        if stuck {
+            log::trace!("abandoning {} unacknowledged predictions", before);
+            self.edits.clear();
+            self.confident = false;
+            self.anchor = self.server.clone();
+        } else {
+            self.reconcile(now);
+        }
+
+        if self.edits.len() != before {
+            self.seqno += 1;
+            Some(row)
+        } else {
+            None
+        }
+    }
+
+    /// Compares acknowledged edits against the server's copy of the
+    /// line, and retires them if there is no reason to wait any longer
+    fn reconcile(&mut self, now: Instant) {
+        let server = match &self.server {
+            Some(server) => server,
+            None => return,
+        };
+        let num_acknowledged = self
+            .edits
+            .iter()
+            .take_while(|pending| pending.acknowledged.is_some())
+            .count();
+        if num_acknowledged == 0 {
+            // There's nothing to verify, so whatever is still in flight
+            // is best applied to the latest line from the server
+            self.anchor = Some(server.clone());
+            return;
+        }
+
+        let replay = self.anchor.as_ref().map(|anchor| {
+            replay(
+                anchor,
+                self.edits.iter().take(num_acknowledged).map(|p| p.edit),
+                self.cols,
+            )
+        });
+        let verdict = match replay {
+            Some(replay) if replay.complete => {
+                if replay.matches(server) {
+                    Verdict::Confirmed
+                } else {
+                    let first_ack = self.edits.front().and_then(|p| p.acknowledged);
+                    match first_ack {
+                        Some(when) if now.duration_since(when) < ECHO_GRACE => Verdict::Waiting,
+                        _ => Verdict::Mismatch,
+                    }
+                }
+            }
+            _ => Verdict::Unknown,
+        };
+
+        match verdict {
+            Verdict::Waiting => return,
+            Verdict::Confirmed => {
+                self.confident = true;
+            }
+            Verdict::Mismatch => {
+                log::trace!("prediction didn't match the server; rolling back");
+                self.confident = false;
+            }
+            Verdict::Unknown => {
+                // Something like Enter was processed.  We can't know what
+                // the line will look like next, so hold off on showing
+                // anything until we've been proven right again
+                self.confident = false;
+            }
+        }
+        self.edits.drain(..num_acknowledged);
+        self.anchor = Some(server.clone());
+    }
+
+    /// Returns the predicted cursor line, if there is anything worth
+    /// showing
+    pub fn prediction(&self) -> Option<Prediction> {
+        if !self.confident || self.edits.is_empty() {
+            return None;
+        }
+        let anchor = self.anchor.as_ref()?;
+        let replay = replay(anchor, self.edits.iter().map(|p| p.edit), self.cols);
+        if replay.applied == 0 {
+            return None;
+        }
+        Some(Prediction {
+            row: replay.state.row,
+            x: replay.state.x,
+            line: replay.state.line,
+        })
+    }
+}
+
+/// Applies `edits` to a copy of `anchor`, stopping at the first one
+/// that we can't predict
+fn replay(anchor: &CursorLine, edits: impl Iterator<Item = Edit>, cols: usize) -> Replay {
+    let mut state = anchor.clone();
+    let mut touched = state.x..state.x;
+    let mut applied = 0;
+    let mut complete = true;
+
+    for edit in edits {
+        let ok = match edit {
+            Edit::Insert(c) => {
+                let cell = Cell::new(
+                    c,
+                    CellAttributes::default()
+                        .set_underline(Underline::Double)
+                        .clone(),
+                );
+                let width = cell.width();
+                // We can't know how the application will wrap
+                if state.x + width >= cols {
+                    false
+                } else {
+                    state.line.insert_cell(state.x, cell, cols, SEQ_ZERO);
+                    state.line.resize(cols, SEQ_ZERO);
+                    state.x += width;
+                    true
+                }
+            }
+            Edit::Backspace => {
+                if state.x == 0 {
+                    false
+                } else {
+                    state.x -= 1;
+                    state.line.erase_cell(state.x, SEQ_ZERO);
+                    true
+                }
+            }
+            Edit::Delete => {
+                state.line.erase_cell(state.x, SEQ_ZERO);
+                true
+            }
+            Edit::Left => {
+                if state.x == 0 {
+                    false
+                } else {
+                    state.x -= 1;
+                    true
+                }
+            }
+            Edit::Right => {
+                // Line editors won't move past the end of the input
+                let end = state
+                    .line
+                    .visible_cells()
+                    .filter(|cell| cell.str() != " ")
+                    .last()
+                    .map(|cell| cell.cell_index() + cell.width())
+                    .unwrap_or(0);
+                if state.x < end {
+                    state.x += 1;
+                }
+                true
+            }
+            Edit::Opaque => false,
+        };
+        if !ok {
+            complete = false;
+            break;
+        }
+        applied += 1;
+        touched.start = touched.start.min(state.x);
+        touched.end = touched.end.max(state.x);
+    }
+
+    Replay {
+        state,
+        touched,
+        applied,
+        complete,
+    }
+}
+
+/// Returns true if `text` contains one of `SECRET_PROMPTS`
+fn is_secret_prompt(text: &str) -> bool {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let text = format!(" {} ", words.join(" "));
+    SECRET_PROMPTS
+        .iter()
+        .any(|prompt| text.contains(&format!(" {prompt} ")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const COLS: usize = 20;
+
+    fn line(text: &str) -> Line {
+        let mut line = Line::from_text(text, &CellAttributes::default(), SEQ_ZERO, None);
+        line.resize(COLS, SEQ_ZERO);
+        line
+    }
+
+    fn typed(predictor: &mut Predictor, text: &str) -> InputSerial {
+        let serial = InputSerial::now();
+        for c in text.chars() {
+            predictor.push_key(KeyCode::Char(c), KeyModifiers::NONE, serial);
+        }
+        serial
+    }
+
+    fn shown(predictor: &Predictor) -> Option<(String, usize)> {
+        predictor
+            .prediction()
+            .map(|p| (p.line.as_str().trim_end().to_string(), p.x))
+    }
+
+    #[test]
+    fn echo_is_confirmed() {
+        let mut predictor = Predictor::new(COLS);
+        predictor.server_update(0, 2, line("$ "), None, COLS);
+
+        let serial = typed(&mut predictor, "ls");
+        assert_eq!(shown(&predictor), Some(("$ ls".to_string(), 4)));
+
+        predictor.server_update(0, 4, line("$ ls"), Some(serial), COLS);
+        assert!(predictor.edits.is_empty());
+        assert!(predictor.confident);
+        assert_eq!(shown(&predictor), None);
+    }
+
+    #[test]
+    fn edits_shift_the_line() {
+        let mut predictor = Predictor::new(COLS);
+        predictor.server_update(0, 4, line("$ ac"), None, COLS);
+
+        let serial = InputSerial::now();
+        predictor.push_key(KeyCode::LeftArrow, KeyModifiers::NONE, serial);
+        predictor.push_key(KeyCode::Char('b'), KeyModifiers::NONE, serial);
+        assert_eq!(shown(&predictor), Some(("$ abc".to_string(), 4)));
+
+        predictor.push_key(KeyCode::Backspace, KeyModifiers::NONE, serial);
+        predictor.push_key(KeyCode::Backspace, KeyModifiers::NONE, serial);
+        assert_eq!(shown(&predictor), Some(("$ c".to_string(), 2)));
+    }
+
+    #[test]
+    fn mismatch_rolls_back() {
+        let mut predictor = Predictor::new(COLS);
+        predictor.server_update(0, 2, line("$ "), None, COLS);
+
+        let serial = typed(&mut predictor, "x");
+        // Force the grace period to have elapsed
+        predictor.server_update(0, 2, line("$ "), Some(serial), COLS);
+        predictor.edits[0].acknowledged =
+            Some(Instant::now() - ECHO_GRACE - Duration::from_millis(1));
+        predictor.reconcile(Instant::now());
+
+        assert!(predictor.edits.is_empty());
+        assert!(!predictor.confident);
+
+        // Still tracked, but not shown until one is confirmed
+        let serial = typed(&mut predictor, "y");
+        assert_eq!(shown(&predictor), None);
+        predictor.server_update(0, 3, line("$ y"), Some(serial), COLS);
+        assert!(predictor.confident);
+    }
+
+    #[test]
+    fn waits_for_echo() {
+        let mut predictor = Predictor::new(COLS);
+        predictor.server_update(0, 2, line("$ "), None, COLS);
+
+        let serial = typed(&mut predictor, "x");
+        predictor.server_update(0, 2, line("$ "), Some(serial), COLS);
+        assert_eq!(shown(&predictor), Some(("$ x".to_string(), 3)));
+
+        predictor.server_update(0, 3, line("$ x"), None, COLS);
+        assert!(predictor.edits.is_empty());
+        assert!(predictor.confident);
+    }
+
+    #[test]
+    fn nothing_is_predicted_after_enter() {
+        let mut predictor = Predictor::new(COLS);
+        predictor.server_update(0, 2, line("$ "), None, COLS);
+
+        let serial = InputSerial::now();
+        predictor.push_key(KeyCode::Char('a'), KeyModifiers::NONE, serial);
+        predictor.push_key(KeyCode::Enter, KeyModifiers::NONE, serial);
+        predictor.push_key(KeyCode::Char('b'), KeyModifiers::NONE, serial);
+        assert_eq!(shown(&predictor), Some(("$ a".to_string(), 3)));
+    }
+
+    #[test]
+    fn seqno_tracks_changes() {
+        let mut predictor = Predictor::new(COLS);
+        predictor.server_update(0, 2, line("$ "), None, COLS);
+        let seqno = predictor.seqno();
+
+        // Neither looking at the prediction, nor expiring it
+        // before it is due, changes it
+        assert_eq!(shown(&predictor), None);
+        assert_eq!(predictor.expire(Instant::now()), None);
+        assert_eq!(predictor.seqno(), seqno);
+
+        typed(&mut predictor, "x");
+        assert!(predictor.seqno() > seqno);
+        let seqno = predictor.seqno();
+        assert_eq!(shown(&predictor), Some(("$ x".to_string(), 3)));
+        assert_eq!(predictor.seqno(), seqno);
+
+        assert_eq!(
+            predictor.expire(Instant::now() + MAX_UNACKNOWLEDGED + Duration::from_millis(1)),
+            Some(0)
+        );
+        assert!(predictor.seqno() > seqno);
+        assert_eq!(shown(&predictor), None);
+    }
+
+    #[test]
+    fn password_prompts_are_not_echoed() {
+        let mut predictor = Predictor::new(COLS);
+        predictor.server_update(0, 10, line("Password: "), None, COLS);
+        typed(&mut predictor, "hunter2");
+        assert_eq!(shown(&predictor), None);
+    }
+
+    #[test]
+    fn secret_prompts_are_recognized() {
+        for prompt in [
+            "PASSWORD:",
+            "[sudo] password for me:",
+            "Enter passphrase for key",
+            "PIN:",
+            "Verification code:",
+        ] {
+            assert!(is_secret_prompt(prompt), "{}", prompt);
+
+            let mut predictor = Predictor::new(COLS);
+            let x = prompt.len().min(COLS - 1);
+            predictor.server_update(0, x, line(prompt), None, COLS);
+            typed(&mut predictor, "123456");
+            assert_eq!(shown(&predictor), None, "{prompt}");
+        }
+
+        for line in ["$ ", "$ ping spinning.example", "Code review:"] {
+            assert!(!is_secret_prompt(line), "{}", line);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::domain::ClientInner;
 use crate::pane::clientpane::ClientPane;
+use crate::pane::prediction::{Prediction, Predictor};
 use anyhow::anyhow;
 use codec::*;
 use config::{configuration, ConfigHandle};
@@ -17,7 +18,7 @@ use std::ops::Range;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use termwiz::cell::{Cell, CellAttributes, Underline};
+use termwiz::cell::CellAttributes;
 use termwiz::color::AnsiColor;
 use termwiz::image::{ImageCell, ImageData};
 use termwiz::surface::{SequenceNo, SEQ_ZERO};
@@ -76,8 +77,12 @@ pub struct RenderableInner {
     pub last_recv_time: Instant,
     last_late_dirty: Instant,
     last_input_rtt: u64,
+    /// The predictor seqno, and the row of the prediction, as of
+    /// the last time that get_changed_since reported them
+    reported_prediction: (usize, Option<StableRowIndex>),
 
     pub input_serial: InputSerial,
+    predictor: Predictor,
 }
 
 pub struct RenderableState {
@@ -115,7 +120,9 @@ impl RenderableInner {
             last_recv_time: now,
             last_late_dirty: now,
             last_input_rtt: 0,
+            reported_prediction: (0, None),
             input_serial: InputSerial::empty(),
+            predictor: Predictor::new(dimensions.cols),
             seqno: SEQ_ZERO,
         }
     }
@@ -142,159 +149,40 @@ impl RenderableInner {
             .unwrap_or(false)
     }
 
-    /// Compute a "prediction" and apply it to the line data that we
-    /// have available, marking it as dirty so that it gets rendered.
-    /// The prediction is basically just local echo.
-    /// Open questions:
-    /// how do we tell if the intent is to suppress local echo during eg:
-    ///  * password prompt?  One option is to look back and see if the line
-    ///                      looks like a password prompt.
-    ///  * normal mode in vim: letter presses are typically movement or
-    ///                        other editor commands
-    /// There are bound to be a number of other edge cases that we should
-    /// handle.
-    fn apply_prediction(&mut self, c: KeyCode, line: &mut Line) {
-        let text = line.as_str();
-        if text.contains("sword") {
-            // This line might be a password prompt.  Don't force
-            // on local echo here, as we don't want to reveal content
-            // from their password
-            return;
-        }
-
-        match c {
-            KeyCode::Enter => {
-                self.cursor_position.x = 0;
-                self.cursor_position.y += 1;
-            }
-            KeyCode::UpArrow => {
-                self.cursor_position.y = self.cursor_position.y.saturating_sub(1);
-            }
-            KeyCode::DownArrow => {
-                self.cursor_position.y += 1;
-            }
-            KeyCode::RightArrow => {
-                self.cursor_position.x += 1;
-            }
-            KeyCode::LeftArrow => {
-                self.cursor_position.x = self.cursor_position.x.saturating_sub(1);
-            }
-            KeyCode::Delete => {
-                line.erase_cell(self.cursor_position.x, SEQ_ZERO);
-            }
-            KeyCode::Backspace => {
-                if self.cursor_position.x > 0 {
-                    line.erase_cell(self.cursor_position.x - 1, SEQ_ZERO);
-                    self.cursor_position.x -= 1;
-                }
-            }
-            KeyCode::Char(c) => {
-                let cell = Cell::new(
-                    c,
-                    CellAttributes::default()
-                        .set_underline(Underline::Double)
-                        .clone(),
-                );
-
-                let width = cell.width();
-                line.set_cell(self.cursor_position.x, cell, SEQ_ZERO);
-                // Adjust the cursor to reflect the width of this new cell
-                self.cursor_position.x += width;
-            }
-            _ => {}
-        }
-    }
-
-    /// Based on a keypress, apply a "prediction" of what the terminal
-    /// content will look like once we receive the response from the
-    /// remote system.  The prediction helps to reduce perceived latency
+    /// Record a keypress so that we can show a "prediction" of what the
+    /// terminal content will look like once we receive the response from
+    /// the remote system.  The prediction helps to reduce perceived latency
     /// when a user is typing at any reasonable velocity.
     pub fn predict_from_key_event(&mut self, key: KeyCode, mods: KeyModifiers) {
-        if !self.should_predict() {
+        if self.client.local_echo_threshold_ms.is_none() {
             return;
         }
+        self.predictor.push_key(key, mods, self.input_serial);
+        self.notify_if_predicting();
+    }
 
-        let c = match key {
-            KeyCode::LeftArrow
-            | KeyCode::RightArrow
-            | KeyCode::UpArrow
-            | KeyCode::DownArrow
-            | KeyCode::Delete
-            | KeyCode::Backspace
-            | KeyCode::Enter
-            | KeyCode::Char(_) => key,
-            _ => return,
-        };
-        if mods != KeyModifiers::NONE && mods != KeyModifiers::SHIFT {
+    pub fn predict_from_paste(&mut self, text: &str) {
+        if self.client.local_echo_threshold_ms.is_none() {
             return;
         }
-
-        let row = self.cursor_position.y;
-        match self.lines.pop(&row) {
-            Some(LineEntry::Stale(mut line)) | Some(LineEntry::Line(mut line)) => {
-                self.apply_prediction(c, &mut line);
-                self.lines.put(row, LineEntry::Line(line));
-            }
-            Some(LineEntry::LineAndFetching(mut line, instant)) => {
-                self.apply_prediction(c, &mut line);
-                self.lines
-                    .put(row, LineEntry::LineAndFetching(line, instant));
-            }
-            Some(entry) => {
-                self.lines.put(row, entry);
-            }
-            None => {}
-        }
+        self.predictor.push_paste(text);
+        self.notify_if_predicting();
     }
 
-    fn apply_paste_prediction(&mut self, row: usize, text: &str, line: &mut Line) {
-        let attrs = CellAttributes::default()
-            .set_underline(Underline::Double)
-            .clone();
-
-        let text_line = Line::from_text(text, &attrs, SEQ_ZERO, None);
-
-        if row == 0 {
-            for cell in text_line.visible_cells() {
-                line.set_cell(self.cursor_position.x, cell.as_cell(), SEQ_ZERO);
-                self.cursor_position.x += cell.width();
-            }
-        } else {
-            // The pasted line replaces the data for the existing line
-            line.resize_and_clear(0, SEQ_ZERO, CellAttributes::default());
-            line.append_line(text_line, SEQ_ZERO);
-            self.cursor_position.x = line.len();
+    fn notify_if_predicting(&self) {
+        if self.prediction().is_some() {
+            Mux::get().notify(mux::MuxNotification::PaneOutput(self.local_pane_id));
         }
     }
 
-    pub fn predict_from_paste(&mut self, text: &str) {
-        if !self.should_predict() {
-            return;
-        }
-
-        let text = textwrap::fill(text, self.dimensions.cols);
-        let lines: Vec<&str> = text.split("\n").collect();
-
-        for (idx, paste_line) in lines.iter().enumerate() {
-            let row = self.cursor_position.y + idx as StableRowIndex;
-
-            match self.lines.pop(&row) {
-                Some(LineEntry::Stale(mut line)) | Some(LineEntry::Line(mut line)) => {
-                    self.apply_paste_prediction(idx, paste_line, &mut line);
-                    self.lines.put(row, LineEntry::Line(line));
-                }
-                Some(LineEntry::LineAndFetching(mut line, instant)) => {
-                    self.apply_paste_prediction(idx, paste_line, &mut line);
-                    self.lines
-                        .put(row, LineEntry::LineAndFetching(line, instant));
-                }
-                Some(entry) => {
-                    self.lines.put(row, entry);
-                }
-                None => {}
-            }
+    /// Returns the predicted state of the cursor line, if we are
+    /// currently showing predictions
+    fn prediction(&self) -> Option<Prediction> {
+        if self.should_predict() {
+            self.predictor.prediction()
+        } else {
+            None
         }
-        self.cursor_position.y += lines.len().saturating_sub(1) as StableRowIndex;
     }
 
     pub fn update_last_send(&mut self) {
@@ -360,6 +248,22 @@ impl RenderableInner {
         );
         self.seqno = delta.seqno;
 
+        // The server always sends the cursor line, which is what we
+        // need to check our predictions against
+        match bonus_lines
+            .iter()
+            .find(|(stable_row, _)| *stable_row == delta.cursor_position.y)
+        {
+            Some((_, line)) => self.predictor.server_update(
+                delta.cursor_position.y,
+                delta.cursor_position.x,
+                line.clone(),
+                delta.input_serial,
+                delta.dimensions.cols,
+            ),
+            None => self.predictor.clear(),
+        }
+
         let config = configuration();
         for (stable_row, line) in bonus_lines {
             log::trace!("bonus line {} seqno={}", stable_row, line.current_seqno());
@@ -721,7 +625,15 @@ pub(crate) async fn hydrate_lines(
 
 impl RenderableState {
     pub fn get_cursor_position(&self) -> StableCursorPosition {
-        self.inner.borrow().cursor_position
+        let inner = self.inner.borrow();
+        match inner.prediction() {
+            Some(prediction) => StableCursorPosition {
+                x: prediction.x,
+                y: prediction.row,
+                ..inner.cursor_position
+            },
+            None => inner.cursor_position,
+        }
     }
 
     pub fn get_lines(&self, lines: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>) {
@@ -729,6 +641,7 @@ impl RenderableState {
         let mut result = vec![];
         let mut to_fetch = RangeSet::new();
         let now = Instant::now();
+        let prediction = inner.prediction();
 
         for idx in lines.clone() {
             let entry = match inner.lines.pop(&idx) {
@@ -761,6 +674,12 @@ impl RenderableState {
                 }
             };
 
+            if let Some(prediction) = &prediction {
+                if prediction.row == idx {
+                    *result.last_mut().unwrap() = prediction.line.clone();
+                }
+            }
+
             if inner.client.overlay_lag_indicator && idx == inner.dimensions.physical_top
                 && inner.is_tardy() {
                     let status = format!(
@@ -835,6 +754,27 @@ impl RenderableState {
             }
         }
 
+        // Predictions are drawn over the cached line, so changes to them
+        // aren't reflected in its seqno
+        if let Some(row) = inner.predictor.expire(Instant::now()) {
+            result.add(row);
+        }
+        // ... and they are only reported when they change, so that we
+        // don't end up busy looping just to repaint them
+        let prediction = (
+            inner.predictor.seqno(),
+            inner.prediction().map(|prediction| prediction.row),
+        );
+        if prediction != inner.reported_prediction {
+            if let (_, Some(row)) = inner.reported_prediction {
+                result.add(row);
+            }
+            if let (_, Some(row)) = prediction {
+                result.add(row);
+            }
+            inner.reported_prediction = prediction;
+        }
+
         // If we're behind receiving an update, invalidate the top row so
         // that the indicator will update in a more timely fashion
         if inner.is_tardy() {
@@ -574,7 +574,7 @@ impl KittyImageTransmit {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct KittyImagePlacement {
     /// source rectangle bounds.
     /// Default is whole image.
@@ -605,6 +605,11 @@ pub struct KittyImagePlacement {
     pub placement_id: Option<u32>,
     /// z=...
     pub z_index: Option<i32>,
+    /// Create a virtual placement that is displayed wherever
+    /// Unicode placeholder cells referencing it are printed,
+    /// rather than at the cursor position.
+    /// U=0, U=1
+    pub virtual_placement: bool,
 }
 
 impl KittyImagePlacement {
@@ -625,6 +630,11 @@ impl KittyImagePlacement {
                 _ => return None,
             },
             z_index: geti(keys, "z"),
+            virtual_placement: match get(keys, "U") {
+                None | Some("0") => false,
+                Some("1") => true,
+                _ => return None,
+            },
         })
     }
 
@@ -644,6 +654,10 @@ impl KittyImagePlacement {
         }
 
         set(keys, "z", &self.z_index);
+
+        if self.virtual_placement {
+            keys.insert("U", "1".to_string());
+        }
     }
 }
 
@@ -680,8 +694,15 @@ pub enum KittyImageDelete {
     AtCursorPosition { delete: bool },
 
     /// d='f' or d='F'
-    /// Delete animation frames
-    AnimationFrames { delete: bool },
+    /// Delete an animation frame from the image with the
+    /// specified image_id or image_number.  frame_number
+    /// selects the frame, defaulting to the first.
+    AnimationFrames {
+        image_id: Option<u32>,
+        image_number: Option<u32>,
+        frame_number: Option<u32>,
+        delete: bool,
+    },
 
     /// d='p' or d='P'
     /// Delete all placements that intersect the specified
@@ -709,6 +730,11 @@ pub enum KittyImageDelete {
     /// d='z' or d='Z'
     /// Delete all placements that have the specified z-index.
     DeleteZ { z: i32, delete: bool },
+
+    /// d='r' or d='R'
+    /// Delete all images whose image_id is in the range
+    /// start..=end
+    DeleteIdRange { start: u32, end: u32, delete: bool },
 }
 
 impl KittyImageDelete {
@@ -732,7 +758,12 @@ impl KittyImageDelete {
                 delete,
             }),
             'c' | 'C' => Some(Self::AtCursorPosition { delete }),
-            'f' | 'F' => Some(Self::AnimationFrames { delete }),
+            'f' | 'F' => Some(Self::AnimationFrames {
+                image_id: geti(keys, "i"),
+                image_number: geti(keys, "I"),
+                frame_number: geti(keys, "r"),
+                delete,
+            }),
             'p' | 'P' => Some(Self::DeleteAt {
                 x: geti(keys, "x")?,
                 y: geti(keys, "y")?,
@@ -756,6 +787,11 @@ impl KittyImageDelete {
                 z: geti(keys, "z")?,
                 delete,
             }),
+            'r' | 'R' => Some(Self::DeleteIdRange {
+                start: geti(keys, "x")?,
+                end: geti(keys, "y")?,
+                delete,
+            }),
             _ => None,
         }
     }
@@ -794,8 +830,22 @@ impl KittyImageDelete {
             Self::AtCursorPosition { delete } => {
                 keys.insert("d", d('c', delete));
             }
-            Self::AnimationFrames { delete } => {
+            Self::AnimationFrames {
+                image_id,
+                image_number,
+                frame_number,
+                delete,
+            } => {
                 keys.insert("d", d('f', delete));
+                if let Some(i) = image_id {
+                    keys.insert("i", i.to_string());
+                }
+                if let Some(n) = image_number {
+                    keys.insert("I", n.to_string());
+                }
+                if let Some(r) = frame_number {
+                    keys.insert("r", r.to_string());
+                }
             }
             Self::DeleteAt { x, y, delete } => {
                 keys.insert("d", d('p', delete));
@@ -803,7 +853,7 @@ impl KittyImageDelete {
                 keys.insert("y", y.to_string());
             }
             Self::DeleteAtZ { x, y, z, delete } => {
-                keys.insert("d", d('p', delete));
+                keys.insert("d", d('q', delete));
                 keys.insert("x", x.to_string());
                 keys.insert("y", y.to_string());
                 keys.insert("z", z.to_string());
@@ -820,6 +870,11 @@ impl KittyImageDelete {
                 keys.insert("d", d('z', delete));
                 keys.insert("z", z.to_string());
             }
+            Self::DeleteIdRange { start, end, delete } => {
+                keys.insert("d", d('r', delete));
+                keys.insert("x", start.to_string());
+                keys.insert("y", end.to_string());
+            }
         }
     }
 }
@@ -1262,4 +1317,68 @@ mod test {
             }
         );
     }
+    #[test]
+    fn kitty_delete() {
+        let cases = [
+            (
+                "Ga=d,d=F,i=3,r=2,q=2",
+                KittyImageDelete::AnimationFrames {
+                    image_id: Some(3),
+                    image_number: None,
+                    frame_number: Some(2),
+                    delete: true,
+                },
+            ),
+            (
+                "Ga=d,d=q,x=4,y=5,z=-1,q=2",
+                KittyImageDelete::DeleteAtZ {
+                    x: 4,
+                    y: 5,
+                    z: -1,
+                    delete: false,
+                },
+            ),
+            (
+                "Ga=d,d=R,x=10,y=20,q=2",
+                KittyImageDelete::DeleteIdRange {
+                    start: 10,
+                    end: 20,
+                    delete: true,
+                },
+            ),
+        ];
+
+        for (apc, what) in cases {
+            let img = KittyImage::Delete {
+                what,
+                verbosity: KittyImageVerbosity::Quiet,
+            };
+            assert_eq!(KittyImage::parse_apc(apc.as_bytes()).unwrap(), img.clone());
+
+            // Round trip, skipping the leading ESC _
+            let encoded = img.to_string();
+            assert_eq!(KittyImage::parse_apc(&encoded.as_bytes()[2..]).unwrap(), img);
+        }
+    }
+    #[test]
+    fn kitty_virtual_placement() {
+        let img = KittyImage::parse_apc("Ga=p,U=1,i=42,c=4,r=2,q=2".as_bytes()).unwrap();
+        assert_eq!(
+            img,
+            KittyImage::Display {
+                image_id: Some(42),
+                image_number: None,
+                placement: KittyImagePlacement {
+                    columns: Some(4),
+                    rows: Some(2),
+                    virtual_placement: true,
+                    ..Default::default()
+                },
+                verbosity: KittyImageVerbosity::Quiet,
+            }
+        );
+
+        let encoded = img.to_string();
+        assert_eq!(KittyImage::parse_apc(&encoded.as_bytes()[2..]).unwrap(), img);
+    }
 }
@@ -132,6 +132,8 @@ pub enum CSI {
 
     Keyboard(Keyboard),
 
+    RectangularArea(Box<RectangularArea>),
+
     /// ECMA-48 SCP
     SelectCharacterPath(CharacterPath, i64),
 
@@ -225,6 +227,7 @@ impl Display for CSI {
             CSI::Mouse(mouse) => mouse.fmt(f)?,
             CSI::Device(dev) => dev.fmt(f)?,
             CSI::Window(window) => window.fmt(f)?,
+            CSI::RectangularArea(area) => area.fmt(f)?,
             CSI::Keyboard(Keyboard::SetKittyState { flags, mode }) => {
                 write!(f, "={};{}u", flags.bits(), *mode as u16)?
             }
@@ -660,6 +663,140 @@ impl Display for Window {
     }
 }
 
+/// The area affected by a rectangular area operation.
+/// The coordinates are relative to the scrolling margins when
+/// origin mode is enabled.  A `bottom` or `right` of zero
+/// or that is omitted is represented as `u32::MAX`, and
+/// extends to the bottom or right of the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rectangle {
+    pub top: OneBased,
+    pub left: OneBased,
+    pub bottom: OneBased,
+    pub right: OneBased,
+}
+
+impl Rectangle {
+    fn parse(params: &Cracked, first: usize) -> Result<Self, ()> {
+        let big = |idx| {
+            OneBased::from_esc_param_with_big_default(
+                params.get(idx).unwrap_or(&CsiParam::Integer(0)),
+            )
+        };
+        Ok(Self {
+            top: OneBased::from_optional_esc_param(params.get(first))?,
+            left: OneBased::from_optional_esc_param(params.get(first + 1))?,
+            bottom: big(first + 2)?,
+            right: big(first + 3)?,
+        })
+    }
+}
+
+impl Display for Rectangle {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(
+            f,
+            "{};{};{};{}",
+            self.top, self.left, self.bottom, self.right
+        )
+    }
+}
+
+/// The VT400 rectangular area operations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RectangularArea {
+    /// DECCRA - Copy Rectangular Area.
+    /// Pages are not supported, so the page numbers are
+    /// recorded but otherwise ignored.
+    Copy {
+        source: Rectangle,
+        source_page: i64,
+        dest_top: OneBased,
+        dest_left: OneBased,
+        dest_page: i64,
+    },
+    /// DECFRA - Fill Rectangular Area
+    Fill { character: char, area: Rectangle },
+    /// DECERA - Erase Rectangular Area
+    Erase(Rectangle),
+    /// DECSERA - Selective Erase Rectangular Area; leaves
+    /// characters protected by DECSCA alone
+    SelectiveErase(Rectangle),
+    /// DECCARA - Change Attributes in Rectangular Area.
+    /// Only the subset of SGR that DECCARA permits is used:
+    /// reset, bold, underline, blink, inverse and invisible.
+    ChangeAttributes {
+        area: Rectangle,
+        attributes: Vec<Sgr>,
+    },
+}
+
+impl RectangularArea {
+    fn attribute_from_code(code: i64) -> Option<Sgr> {
+        Some(match code {
+            0 => Sgr::Reset,
+            1 => Sgr::Intensity(Intensity::Bold),
+            4 => Sgr::Underline(Underline::Single),
+            5 => Sgr::Blink(Blink::Slow),
+            7 => Sgr::Inverse(true),
+            8 => Sgr::Invisible(true),
+            22 => Sgr::Intensity(Intensity::Normal),
+            24 => Sgr::Underline(Underline::None),
+            25 => Sgr::Blink(Blink::None),
+            27 => Sgr::Inverse(false),
+            28 => Sgr::Invisible(false),
+            _ => return None,
+        })
+    }
+
+    fn attribute_to_code(sgr: &Sgr) -> Result<i64, FmtError> {
+        Ok(match sgr {
+            Sgr::Reset => 0,
+            Sgr::Intensity(Intensity::Bold) => 1,
+            Sgr::Underline(Underline::Single) => 4,
+            Sgr::Blink(Blink::Slow) => 5,
+            Sgr::Inverse(true) => 7,
+            Sgr::Invisible(true) => 8,
+            Sgr::Intensity(Intensity::Normal) => 22,
+            Sgr::Underline(Underline::None) => 24,
+            Sgr::Blink(Blink::None) => 25,
+            Sgr::Inverse(false) => 27,
+            Sgr::Invisible(false) => 28,
+            _ => return Err(FmtError),
+        })
+    }
+}
+
+impl Display for RectangularArea {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            RectangularArea::Copy {
+                source,
+                source_page,
+                dest_top,
+                dest_left,
+                dest_page,
+            } => write!(
+                f,
+                "{};{};{};{};{}$v",
+                source, source_page, dest_top, dest_left, dest_page
+            ),
+            RectangularArea::Fill { character, area } => {
+                write!(f, "{};{}$x", *character as u32, area)
+            }
+            RectangularArea::Erase(area) => write!(f, "{}$z", area),
+            RectangularArea::SelectiveErase(area) => write!(f, "{}${{", area),
+            RectangularArea::ChangeAttributes { area, attributes } => {
+                write!(f, "{}", area)?;
+                for sgr in attributes {
+                    write!(f, ";{}", Self::attribute_to_code(sgr)?)?;
+                }
+                write!(f, "$r")
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MouseReport {
     SGR1006 {
@@ -1226,6 +1363,19 @@ pub enum Edit {
 
     /// REP - Repeat the preceding character n times
     Repeat(u32),
+
+    /// DECSED - Selective Erase in Display; like ED, but leaves
+    /// characters protected by DECSCA alone
+    SelectiveEraseInDisplay(EraseInDisplay),
+
+    /// DECSEL - Selective Erase in Line; like EL, but leaves
+    /// characters protected by DECSCA alone
+    SelectiveEraseInLine(EraseInLine),
+
+    /// DECSCA - Select Character Protection Attribute.
+    /// When true, characters printed from now on are protected
+    /// from the selective erase functions.
+    SelectCharacterProtection(bool),
 }
 
 trait EncodeCSIParam {
@@ -1276,6 +1426,17 @@ impl Display for Edit {
             Edit::ScrollUp(n) => n.write_csi(f, "S")?,
             Edit::EraseInDisplay(n) => n.write_csi(f, "J")?,
             Edit::Repeat(n) => n.write_csi(f, "b")?,
+            Edit::SelectiveEraseInDisplay(n) => {
+                write!(f, "?")?;
+                n.write_csi(f, "J")?
+            }
+            Edit::SelectiveEraseInLine(n) => {
+                write!(f, "?")?;
+                n.write_csi(f, "K")?
+            }
+            Edit::SelectCharacterProtection(protected) => {
+                write!(f, "{}\"q", if *protected { 1 } else { 0 })?
+            }
         }
         Ok(())
     }
@@ -1829,6 +1990,14 @@ impl<'a> CSIParser<'a> {
             ('k', [.., CsiParam::P(b' ')]) => self.select_character_path(params),
             ('q', [.., CsiParam::P(b' ')]) => self.cursor_style(params),
             ('y', [.., CsiParam::P(b'*')]) => self.checksum_area(params),
+            ('v', [.., CsiParam::P(b'$')])
+            | ('x', [.., CsiParam::P(b'$')])
+            | ('z', [.., CsiParam::P(b'$')])
+            | ('{', [.., CsiParam::P(b'$')])
+            | ('r', [.., CsiParam::P(b'$')]) => self.rectangular_area(params),
+            ('q', [.., CsiParam::P(b'"')]) => self.select_character_protection(params),
+            ('J', [CsiParam::P(b'?'), ..]) => parse!(Edit, SelectiveEraseInDisplay, &params[1..]),
+            ('K', [CsiParam::P(b'?'), ..]) => parse!(Edit, SelectiveEraseInLine, &params[1..]),
 
             ('c', [CsiParam::P(b'='), ..]) => self
                 .req_tertiary_device_attributes(params)
@@ -2079,6 +2248,49 @@ impl<'a> CSIParser<'a> {
         })))
     }
 
+    fn rectangular_area(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
+        let params = Cracked::parse(&params[..params.len() - 1])?;
+
+        let area = match self.control {
+            'v' => RectangularArea::Copy {
+                source: Rectangle::parse(&params, 0)?,
+                source_page: params.opt_int(4).unwrap_or(1),
+                dest_top: OneBased::from_optional_esc_param(params.get(5))?,
+                dest_left: OneBased::from_optional_esc_param(params.get(6))?,
+                dest_page: params.opt_int(7).unwrap_or(1),
+            },
+            'x' => RectangularArea::Fill {
+                character: char::from_u32(params.int(0)?.try_into().map_err(|_| ())?)
+                    .ok_or(())?,
+                area: Rectangle::parse(&params, 1)?,
+            },
+            'z' => RectangularArea::Erase(Rectangle::parse(&params, 0)?),
+            '{' => RectangularArea::SelectiveErase(Rectangle::parse(&params, 0)?),
+            'r' => RectangularArea::ChangeAttributes {
+                area: Rectangle::parse(&params, 0)?,
+                // No attributes is equivalent to 0; unknown values are ignored
+                attributes: (4..params.len().max(5))
+                    .filter_map(|idx| {
+                        RectangularArea::attribute_from_code(params.opt_int(idx).unwrap_or(0))
+                    })
+                    .collect(),
+            },
+            _ => return Err(()),
+        };
+        Ok(CSI::RectangularArea(Box::new(area)))
+    }
+
+    fn select_character_protection(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
+        let protected = match params {
+            [CsiParam::P(b'"')]
+            | [CsiParam::Integer(0), CsiParam::P(b'"')]
+            | [CsiParam::Integer(2), CsiParam::P(b'"')] => false,
+            [CsiParam::Integer(1), CsiParam::P(b'"')] => true,
+            _ => return Err(()),
+        };
+        Ok(CSI::Edit(Edit::SelectCharacterProtection(protected)))
+    }
+
     fn dsr(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
         match params {
             [CsiParam::Integer(5)] => {
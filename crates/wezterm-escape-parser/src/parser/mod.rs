@@ -7,7 +7,7 @@ use crate::{
 };
 #[cfg(feature = "tmux_cc")]
 use core::borrow::BorrowMut;
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
 use log::error;
 use num_traits::FromPrimitive;
 use vtparse::{CsiParam, VTActor, VTParser};
@@ -123,6 +123,28 @@ impl Parser {
         self.state_machine.parse(bytes, &mut perform);
     }
 
+    /// Like `parse`, but also passes the callback the number of bytes
+    /// of `bytes` that had been consumed when the action was recognized.
+    /// Bytes beyond that offset belong to actions that have yet to be
+    /// emitted, either later in `bytes` or in a subsequent call.
+    pub fn parse_with_offsets<F: FnMut(Action, usize)>(&mut self, bytes: &[u8], mut callback: F) {
+        #[cfg(feature = "tmux_cc")]
+        if self.state.borrow().tmux_state.is_some() {
+            let len = bytes.len();
+            return self.parse(bytes, |action| callback(action, len));
+        }
+
+        let consumed = Cell::new(0);
+        let mut perform = Performer {
+            callback: &mut |action| callback(action, consumed.get()),
+            state: &mut self.state.borrow_mut(),
+        };
+        for (idx, b) in bytes.iter().enumerate() {
+            consumed.set(idx + 1);
+            self.state_machine.parse_byte(*b, &mut perform);
+        }
+    }
+
     /// A specialized version of the parser that halts after recognizing the
     /// first action from the stream of bytes.  The return value is the action
     /// that was recognized and the length of the byte stream that was fed in
@@ -351,8 +373,9 @@ mod test {
     use super::*;
     use crate::color::ColorSpec;
     use crate::csi::{
-        CharacterPath, DecPrivateMode, DecPrivateModeCode, Device, Intensity, Mode, Sgr, Underline,
-        Window, XtSmGraphics, XtSmGraphicsItem, XtermKeyModifierResource,
+        CharacterPath, DecPrivateMode, DecPrivateModeCode, Device, Edit, EraseInDisplay,
+        EraseInLine, Intensity, Mode, Rectangle, RectangularArea, Sgr, Underline, Window,
+        XtSmGraphics, XtSmGraphicsItem, XtermKeyModifierResource,
     };
     use crate::{EscCode, OneBased};
     use k9::assert_equal as assert_eq;
@@ -425,6 +448,30 @@ mod test {
         );
     }
 
+    #[test]
+    fn parse_with_offsets() {
+        let mut p = Parser::new();
+        let mut offsets = vec![];
+        p.parse_with_offsets(b"a\xc3\xa9\x1b[1;4mb\x1b[", |action, offset| {
+            offsets.push((action, offset))
+        });
+        assert_eq!(
+            offsets,
+            vec![
+                (Action::Print('a'), 1),
+                (Action::Print('é'), 3),
+                (Action::CSI(CSI::Sgr(Sgr::Intensity(Intensity::Bold))), 9),
+                (Action::CSI(CSI::Sgr(Sgr::Underline(Underline::Single))), 9),
+                (Action::Print('b'), 10),
+            ]
+        );
+
+        // The incomplete sequence is completed by the next call
+        offsets.clear();
+        p.parse_with_offsets(b"0m", |action, offset| offsets.push((action, offset)));
+        assert_eq!(offsets, vec![(Action::CSI(CSI::Sgr(Sgr::Reset)), 2)]);
+    }
+
     // <https://github.com/markbt/streampager/issues/57>
     #[test]
     fn osc_st_parse_first_as_vec() {
@@ -823,6 +870,94 @@ mod test {
         );
     }
 
+    #[test]
+    fn rectangular_area() {
+        let area = Rectangle {
+            top: OneBased::new(2),
+            left: OneBased::new(3),
+            bottom: OneBased::new(4),
+            right: OneBased::new(5),
+        };
+        let rect = |area: RectangularArea| vec![Action::CSI(CSI::RectangularArea(Box::new(area)))];
+
+        assert_eq!(
+            round_trip_parse("\x1b[2;3;4;5;1;6;7;1$v"),
+            rect(RectangularArea::Copy {
+                source: area,
+                source_page: 1,
+                dest_top: OneBased::new(6),
+                dest_left: OneBased::new(7),
+                dest_page: 1,
+            })
+        );
+        assert_eq!(
+            round_trip_parse("\x1b[88;2;3;4;5$x"),
+            rect(RectangularArea::Fill {
+                character: 'X',
+                area,
+            })
+        );
+        assert_eq!(
+            round_trip_parse("\x1b[2;3;4;5$z"),
+            rect(RectangularArea::Erase(area))
+        );
+        assert_eq!(
+            round_trip_parse("\x1b[2;3;4;5${"),
+            rect(RectangularArea::SelectiveErase(area))
+        );
+        assert_eq!(
+            round_trip_parse("\x1b[2;3;4;5;1;27$r"),
+            rect(RectangularArea::ChangeAttributes {
+                area,
+                attributes: vec![Sgr::Intensity(Intensity::Bold), Sgr::Inverse(false)],
+            })
+        );
+
+        // Omitted coordinates cover the whole screen, and no
+        // attributes means reset
+        let max = OneBased::new(u32::MAX);
+        assert_eq!(
+            parse_as("\x1b[$r", "\x1b[1;1;4294967295;4294967295;0$r"),
+            rect(RectangularArea::ChangeAttributes {
+                area: Rectangle {
+                    top: OneBased::new(1),
+                    left: OneBased::new(1),
+                    bottom: max,
+                    right: max,
+                },
+                attributes: vec![Sgr::Reset],
+            })
+        );
+    }
+
+    #[test]
+    fn selective_erase() {
+        assert_eq!(
+            parse_as("\x1b[1\"q", "\x1b[1\"q"),
+            vec![Action::CSI(CSI::Edit(Edit::SelectCharacterProtection(
+                true
+            )))]
+        );
+        assert_eq!(
+            parse_as("\x1b[2\"q", "\x1b[0\"q"),
+            vec![Action::CSI(CSI::Edit(Edit::SelectCharacterProtection(
+                false
+            )))]
+        );
+        assert_eq!(
+            round_trip_parse("\x1b[?2J"),
+            vec![Action::CSI(CSI::Edit(Edit::SelectiveEraseInDisplay(
+                EraseInDisplay::EraseDisplay
+            )))]
+        );
+        assert_eq!(
+            round_trip_parse("\x1b[?K"),
+            vec![Action::CSI(CSI::Edit(Edit::SelectiveEraseInLine(
+                EraseInLine::EraseToEndOfLine
+            )))]
+        );
+    }
+
     #[test]
     fn dec_private_modes() {
         assert_eq!(
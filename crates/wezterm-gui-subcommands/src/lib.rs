@@ -101,6 +101,13 @@ pub struct StartCommand {
     #[arg(long, requires = "domain")]
     pub attach: bool,
 
+    /// Restore the windows, tabs, splits and working directories
+    /// from a saved session rather than spawning PROG.
+    /// The layout that was open when arb last exited is saved
+    /// as the session named "last".
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["prog", "attach"])]
+    pub restore: Option<String>,
+
     /// Instead of executing your shell, run PROG.
     /// For example: `wezterm start -- bash -l` will spawn bash
     /// as if it were a login shell. [aliases: -e]
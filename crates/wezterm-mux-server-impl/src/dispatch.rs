@@ -55,6 +55,10 @@ where
                 .try_send(Item::WritePdu(pdu))
                 .map_err(|e| anyhow::anyhow!("{:?}", e))
         }
+    })
+    .with_backlog({
+        let item_tx = item_tx.clone();
+        move || item_tx.len()
     });
     let mut handler = SessionHandler::new(pdu_sender);
 
@@ -141,6 +145,12 @@ where
                 .await?;
                 stream.flush().await.context("flushing PDU to client")?;
             }
+            Ok(Item::Notif(MuxNotification::QueryClipboard { pane_id, selection })) => {
+                Pdu::QueryClipboard(codec::QueryClipboard { pane_id, selection })
+                    .encode_async(&mut stream, 0)
+                    .await?;
+                stream.flush().await.context("flushing PDU to client")?;
+            }
             Ok(Item::Notif(MuxNotification::TabAddedToWindow { tab_id, window_id })) => {
                 Pdu::TabAddedToWindow(codec::TabAddedToWindow { tab_id, window_id })
                     .encode_async(&mut stream, 0)
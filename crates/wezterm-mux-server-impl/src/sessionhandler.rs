@@ -4,14 +4,16 @@ use codec::*;
 use config::TermConfig;
 use mux::client::ClientId;
 use mux::domain::SplitSource;
+use mux::layout::{Layout, TabLayout};
 use mux::pane::{CachePolicy, Pane, PaneId};
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
 use mux::tab::TabId;
+use mux::tail::TailOutput;
 use mux::{Mux, MuxNotification};
 use promise::spawn::spawn_into_main_thread;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use termwiz::surface::SequenceNo;
 use url::Url;
 use wezterm_term::terminal::Alert;
@@ -20,6 +22,7 @@ use wezterm_term::StableRowIndex;
 #[derive(Clone)]
 pub struct PduSender {
     func: Arc<dyn Fn(DecodedPdu) -> anyhow::Result<()> + Send + Sync>,
+    backlog: Arc<dyn Fn() -> usize + Send + Sync>,
 }
 
 impl PduSender {
@@ -27,11 +30,29 @@ impl PduSender {
         (self.func)(pdu)
     }
 
+    /// Returns the number of PDUs that are waiting to be written
+    pub fn backlog(&self) -> usize {
+        (self.backlog)()
+    }
+
     pub fn new<T>(f: T) -> Self
     where
         T: Fn(DecodedPdu) -> anyhow::Result<()> + Send + Sync + 'static,
     {
-        Self { func: Arc::new(f) }
+        Self {
+            func: Arc::new(f),
+            backlog: Arc::new(|| 0),
+        }
+    }
+
+    /// Sets the function that reports the number of PDUs
+    /// that are waiting to be written
+    pub fn with_backlog<T>(mut self, f: T) -> Self
+    where
+        T: Fn() -> usize + Send + Sync + 'static,
+    {
+        self.backlog = Arc::new(f);
+        self
     }
 }
 
@@ -385,7 +406,7 @@ impl SessionHandler {
                 })
                 .detach();
             }
-            Pdu::ListPanes(ListPanes {}) => {
+            Pdu::ListPanes(ListPanes { details }) => {
                 spawn_into_main_thread(async move {
                     catch(
                         move || {
@@ -397,7 +418,11 @@ impl SessionHandler {
                                 let window = mux.get_window(window_id).unwrap();
                                 window_titles.insert(window_id, window.get_title().to_string());
                                 for tab in window.iter() {
-                                    tabs.push(tab.codec_pane_tree());
+                                    tabs.push(if details {
+                                        tab.codec_pane_tree_with_details()
+                                    } else {
+                                        tab.codec_pane_tree()
+                                    });
                                     tab_titles.push(tab.get_title());
                                 }
                             }
@@ -469,6 +494,169 @@ impl SessionHandler {
                 })
                 .detach();
             }
+            Pdu::ListWorkspaces(ListWorkspaces {}) => {
+                let client_id = self.client_id.clone();
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get();
+                            let active = other_clients(&mux, client_id.as_ref())
+                                .into_iter()
+                                .next()
+                                .map(|info| mux.active_workspace_for_client(&info.client_id));
+                            let workspaces = mux
+                                .iter_workspaces()
+                                .into_iter()
+                                .map(|name| {
+                                    let window_ids = mux.iter_windows_in_workspace(&name);
+                                    let num_panes = window_ids
+                                        .iter()
+                                        .filter_map(|&window_id| mux.get_window(window_id))
+                                        .map(|window| {
+                                            window
+                                                .iter()
+                                                .map(|tab| tab.count_panes().unwrap_or(0))
+                                                .sum::<usize>()
+                                        })
+                                        .sum();
+                                    WorkspaceInfo {
+                                        is_active: active.as_deref() == Some(name.as_str()),
+                                        name,
+                                        window_ids,
+                                        num_panes,
+                                    }
+                                })
+                                .collect();
+                            Ok(Pdu::ListWorkspacesResponse(ListWorkspacesResponse {
+                                workspaces,
+                            }))
+                        },
+                        send_response,
+                    );
+                })
+                .detach();
+            }
+
+            Pdu::SetActiveWorkspace(SetActiveWorkspace { workspace }) => {
+                let client_id = self.client_id.clone();
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get();
+                            let clients = other_clients(&mux, client_id.as_ref());
+                            if clients.is_empty() {
+                                anyhow::bail!("there are no other clients whose workspace to set");
+                            }
+                            for info in clients {
+                                mux.set_active_workspace_for_client(&info.client_id, &workspace);
+                            }
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    );
+                })
+                .detach();
+            }
+
+            Pdu::MoveTabToWindow(MoveTabToWindow {
+                tab_id,
+                window_id,
+                workspace,
+            }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get();
+                            let window_id = mux.move_tab_to_window(tab_id, window_id, workspace)?;
+                            Ok(Pdu::MoveTabToWindowResponse(MoveTabToWindowResponse {
+                                window_id,
+                            }))
+                        },
+                        send_response,
+                    );
+                })
+                .detach();
+            }
+
+            Pdu::KillTab(KillTab { tab_id }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get();
+                            mux.get_tab(tab_id)
+                                .ok_or_else(|| anyhow!("no such tab {}", tab_id))?;
+                            mux.remove_tab(tab_id);
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    );
+                })
+                .detach();
+            }
+
+            Pdu::KillWindow(KillWindow { window_id }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get();
+                            if mux.get_window(window_id).is_none() {
+                                anyhow::bail!("no such window {}", window_id);
+                            }
+                            mux.kill_window(window_id);
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    );
+                })
+                .detach();
+            }
+
+            Pdu::ApplyLayout(request) => {
+                let client_id = self.client_id.clone();
+                spawn_into_main_thread(async move {
+                    schedule_apply_layout(request, send_response, client_id);
+                })
+                .detach();
+            }
+
+            Pdu::DumpLayout(DumpLayout { tab_id, workspace }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get();
+                            let layout = match (workspace, tab_id) {
+                                (Some(workspace), _) => {
+                                    let layout = Layout::capture_workspace(&mux, &workspace);
+                                    if layout.tabs.is_empty() {
+                                        anyhow::bail!("no such workspace {:?}", workspace);
+                                    }
+                                    layout
+                                }
+                                (None, Some(tab_id)) => {
+                                    let tab = mux
+                                        .get_tab(tab_id)
+                                        .ok_or_else(|| anyhow!("no such tab {}", tab_id))?;
+                                    let workspace = mux
+                                        .window_containing_tab(tab_id)
+                                        .and_then(|window_id| mux.get_window(window_id))
+                                        .map(|window| window.get_workspace().to_string());
+                                    Layout {
+                                        workspace,
+                                        tabs: TabLayout::capture(&mux, &tab).into_iter().collect(),
+                                    }
+                                }
+                                (None, None) => {
+                                    anyhow::bail!("either a tab or a workspace is required")
+                                }
+                            };
+                            Ok(Pdu::DumpLayoutResponse(DumpLayoutResponse { layout }))
+                        },
+                        send_response,
+                    );
+                })
+                .detach();
+            }
+
             Pdu::KillPane(KillPane { pane_id }) => {
                 let sender = self.to_write_tx.clone();
                 let per_pane = self.per_pane(pane_id);
@@ -733,6 +921,53 @@ impl SessionHandler {
                 .detach();
             }
 
+            Pdu::WaitForPane(request) => {
+                spawn_into_main_thread(async move {
+                    schedule_wait_for_pane(request, send_response);
+                })
+                .detach();
+            }
+
+            Pdu::SubscribePaneOutput(SubscribePaneOutput {
+                pane_id,
+                escapes,
+                lines,
+            }) => {
+                let sender = self.to_write_tx.clone();
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get();
+                            let pane = mux
+                                .get_pane(pane_id)
+                                .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+                            let (lines, rx) = mux::tail::subscribe_output(&pane, || {
+                                let cursor_row = pane.get_cursor_position().y;
+                                let start_row = (cursor_row + 1 - lines as StableRowIndex)
+                                    .max(pane.get_dimensions().scrollback_top);
+                                let (first_row, lines) = pane.get_lines(start_row..cursor_row + 1);
+                                lines
+                                    .into_iter()
+                                    .enumerate()
+                                    .map(|(idx, mut line)| {
+                                        line.compress_for_scrollback();
+                                        (first_row + idx as StableRowIndex, line)
+                                    })
+                                    .collect::<Vec<_>>()
+                            })?;
+                            promise::spawn::spawn(stream_pane_output(pane_id, escapes, rx, sender))
+                                .detach();
+                            Ok(Pdu::GetLinesResponse(GetLinesResponse {
+                                pane_id,
+                                lines: lines.into(),
+                            }))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+
             Pdu::GetPaneRenderableDimensions(GetPaneRenderableDimensions { pane_id }) => {
                 spawn_into_main_thread(async move {
                     catch(
@@ -987,16 +1222,81 @@ impl SessionHandler {
                 .detach();
             }
 
+            Pdu::RecordPane(RecordPane {
+                pane_id,
+                action,
+                path,
+                cast_version,
+            }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get();
+                            let pane = mux
+                                .get_pane(pane_id)
+                                .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+
+                            let (recording, path) = match action {
+                                RecordPaneAction::Start => (
+                                    true,
+                                    Some(mux::recording::start_recording(
+                                        &pane,
+                                        path,
+                                        cast_version,
+                                    )?),
+                                ),
+                                RecordPaneAction::Stop => {
+                                    (false, mux::recording::stop_recording(pane_id)?)
+                                }
+                                RecordPaneAction::AddMarker(label) => (
+                                    mux::recording::add_marker(pane_id, &label),
+                                    mux::recording::recording_path(pane_id),
+                                ),
+                                RecordPaneAction::Toggle => {
+                                    if mux::recording::recording_path(pane_id).is_some() {
+                                        (false, mux::recording::stop_recording(pane_id)?)
+                                    } else {
+                                        (
+                                            true,
+                                            Some(mux::recording::start_recording(
+                                                &pane,
+                                                path,
+                                                cast_version,
+                                            )?),
+                                        )
+                                    }
+                                }
+                            };
+
+                            Ok(Pdu::RecordPaneResponse(RecordPaneResponse {
+                                recording,
+                                path,
+                            }))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+
             Pdu::Invalid { .. } => send_response(Err(anyhow!("invalid PDU {:?}", decoded.pdu))),
             Pdu::Pong { .. }
             | Pdu::ListPanesResponse { .. }
             | Pdu::SetClipboard { .. }
+            | Pdu::QueryClipboard { .. }
             | Pdu::NotifyAlert { .. }
             | Pdu::SpawnResponse { .. }
             | Pdu::GetPaneRenderChangesResponse { .. }
             | Pdu::UnitResponse { .. }
             | Pdu::LivenessResponse { .. }
             | Pdu::GetPaneDirectionResponse { .. }
+            | Pdu::RecordPaneResponse { .. }
+            | Pdu::WaitForPaneResponse { .. }
+            | Pdu::PaneOutput { .. }
+            | Pdu::ListWorkspacesResponse { .. }
+            | Pdu::MoveTabToWindowResponse { .. }
+            | Pdu::ApplyLayoutResponse { .. }
+            | Pdu::DumpLayoutResponse { .. }
             | Pdu::SearchScrollbackResponse { .. }
             | Pdu::GetLinesResponse { .. }
             | Pdu::GetCodecVersionResponse { .. }
@@ -1017,6 +1317,18 @@ impl SessionHandler {
     }
 }
 
+/// Returns the clients other than `client_id`, most recently used first.
+/// When driven by the cli, these are the gui and any other attached clients.
+fn other_clients(mux: &Mux, client_id: Option<&Arc<ClientId>>) -> Vec<mux::client::ClientInfo> {
+    let mut clients: Vec<_> = mux
+        .iter_clients()
+        .into_iter()
+        .filter(|info| Some(&info.client_id) != client_id)
+        .collect();
+    clients.sort_by(|a, b| b.last_input.cmp(&a.last_input));
+    clients
+}
+
 // Dancing around a little bit here; we can't directly spawn_into_main_thread the domain_spawn
 // function below because the compiler thinks that all of its locals then need to be Send.
 // We need to shimmy through this helper to break that aspect of the compiler flow
@@ -1032,6 +1344,31 @@ fn schedule_domain_spawn_v2<SND>(
         .detach();
 }
 
+fn schedule_apply_layout<SND>(
+    request: ApplyLayout,
+    send_response: SND,
+    client_id: Option<Arc<ClientId>>,
+) where
+    SND: Fn(anyhow::Result<Pdu>) + 'static,
+{
+    promise::spawn::spawn(async move { send_response(apply_layout(request, client_id).await) })
+        .detach();
+}
+
+async fn apply_layout(
+    request: ApplyLayout,
+    client_id: Option<Arc<ClientId>>,
+) -> anyhow::Result<Pdu> {
+    let mux = Mux::get();
+    let _identity = mux.with_identity(client_id);
+
+    let window_id = request
+        .layout
+        .apply(&request.workspace, request.size)
+        .await?;
+    Ok(Pdu::ApplyLayoutResponse(ApplyLayoutResponse { window_id }))
+}
+
 fn schedule_split_pane<SND>(split: SplitPane, send_response: SND, client_id: Option<Arc<ClientId>>)
 where
     SND: Fn(anyhow::Result<Pdu>) + 'static,
@@ -1125,3 +1462,523 @@ async fn move_pane(
         window_id,
     }))
 }
+
+fn schedule_wait_for_pane<SND>(request: WaitForPane, send_response: SND)
+where
+    SND: Fn(anyhow::Result<Pdu>) + 'static,
+{
+    promise::spawn::spawn(async move { send_response(wait_for_pane(request).await) }).detach();
+}
+
+/// Returns the text of the first match for `pattern` between `start_row`
+/// and the bottom of the screen, if any.
+async fn find_match(
+    pane: &Arc<dyn Pane>,
+    pattern: &str,
+    start_row: StableRowIndex,
+) -> anyhow::Result<Option<String>> {
+    let dims = pane.get_dimensions();
+    let end_row = dims.physical_top + dims.viewport_rows as StableRowIndex;
+    let results = pane
+        .search(
+            mux::pane::Pattern::Regex(pattern.to_string()),
+            start_row..end_row,
+            Some(1),
+        )
+        .await?;
+
+    Ok(results.into_iter().next().map(|result| {
+        let (_first_row, lines) = pane.get_lines(result.start_y..result.end_y + 1);
+        lines
+            .iter()
+            .map(|line| line.as_str().trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }))
+}
+
+/// What a notification about the pane means for a `WaitCondition`
+#[derive(Debug, PartialEq)]
+enum WaitEvent {
+    /// The wait is over, with this result
+    Done(WaitForPaneResult),
+    /// The pane produced output
+    Output,
+    /// The notification has no bearing on the condition
+    Ignored,
+}
+
+fn classify_notification(condition: &WaitCondition, notif: &MuxNotification) -> WaitEvent {
+    match notif {
+        MuxNotification::PaneRemoved(_) => WaitEvent::Done(WaitForPaneResult::PaneDied),
+        MuxNotification::PaneOutput(_) => WaitEvent::Output,
+        MuxNotification::Alert {
+            alert: Alert::CommandFinished(block),
+            ..
+        } if *condition == WaitCondition::CommandFinished => {
+            WaitEvent::Done(WaitForPaneResult::CommandFinished {
+                exit_status: block.exit_status,
+            })
+        }
+        _ => WaitEvent::Ignored,
+    }
+}
+
+/// Resolves once `condition` is satisfied by the notifications that
+/// are delivered via `rx`.  For `Match`, `find_match` is called to
+/// search the pane each time that it produces output.
+async fn wait_for_notifications<F, Fut>(
+    condition: &WaitCondition,
+    rx: smol::channel::Receiver<MuxNotification>,
+    mut find_match: F,
+) -> anyhow::Result<WaitForPaneResult>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<Option<String>>>,
+{
+    loop {
+        let notif = match condition {
+            WaitCondition::Idle { idle } => {
+                let next = smol::future::or(async { Some(rx.recv().await) }, async {
+                    smol::Timer::after(*idle).await;
+                    None
+                })
+                .await;
+                match next {
+                    Some(notif) => notif?,
+                    None => return Ok(WaitForPaneResult::Idle),
+                }
+            }
+            _ => rx.recv().await?,
+        };
+
+        match classify_notification(condition, &notif) {
+            WaitEvent::Done(result) => return Ok(result),
+            WaitEvent::Output => {
+                if let WaitCondition::Match { .. } = condition {
+                    if let Some(text) = find_match().await? {
+                        return Ok(WaitForPaneResult::Matched { text });
+                    }
+                }
+            }
+            WaitEvent::Ignored => {}
+        }
+    }
+}
+
+/// Resolves once `condition` is satisfied for `pane`, driven by the
+/// mux notifications for that pane that are delivered via `rx`
+async fn wait_for_condition(
+    pane: Arc<dyn Pane>,
+    condition: WaitCondition,
+    rx: smol::channel::Receiver<MuxNotification>,
+) -> anyhow::Result<WaitForPaneResult> {
+    if pane.is_dead() {
+        return Ok(WaitForPaneResult::PaneDied);
+    }
+
+    let (pattern, start_row) = match &condition {
+        WaitCondition::Match { pattern, start_row } => {
+            let start_row = start_row.unwrap_or_else(|| pane.get_cursor_position().y);
+            // The output may already be present
+            if let Some(text) = find_match(&pane, pattern, start_row).await? {
+                return Ok(WaitForPaneResult::Matched { text });
+            }
+            (pattern.as_str(), start_row)
+        }
+        _ => ("", 0),
+    };
+
+    wait_for_notifications(&condition, rx, || find_match(&pane, pattern, start_row)).await
+}
+
+/// Resolves `wait`, or gives up with `TimedOut` once `timeout` has elapsed
+async fn wait_with_timeout(
+    wait: impl std::future::Future<Output = anyhow::Result<WaitForPaneResult>>,
+    timeout: Option<Duration>,
+) -> anyhow::Result<WaitForPaneResult> {
+    match timeout {
+        Some(timeout) => {
+            smol::future::or(wait, async move {
+                smol::Timer::after(timeout).await;
+                Ok(WaitForPaneResult::TimedOut)
+            })
+            .await
+        }
+        None => wait.await,
+    }
+}
+
+async fn wait_for_pane(request: WaitForPane) -> anyhow::Result<Pdu> {
+    let mux = Mux::get();
+    let pane_id = request.pane_id;
+    let pane = mux
+        .get_pane(pane_id)
+        .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+
+    let (tx, rx) = smol::channel::unbounded();
+    mux.subscribe(move |n| match n {
+        MuxNotification::PaneOutput(id) | MuxNotification::PaneRemoved(id) if id == pane_id => {
+            tx.try_send(n).is_ok()
+        }
+        MuxNotification::Alert {
+            pane_id: id,
+            alert: Alert::CommandFinished(_),
+        } if id == pane_id => tx.try_send(n).is_ok(),
+        // Unsubscribe once the waiter has gone away
+        _ => !tx.is_closed(),
+    });
+
+    let result = wait_with_timeout(
+        wait_for_condition(pane, request.condition, rx),
+        request.timeout,
+    )
+    .await?;
+
+    Ok(Pdu::WaitForPaneResponse(WaitForPaneResponse { result }))
+}
+
+/// The number of PDUs that may be waiting to be written to a client
+/// before a stream of pane output is considered to have fallen behind
+const MAX_OUTPUT_BACKLOG: usize = 1024;
+
+/// Forwards the output of a pane to the client as `PaneOutput` PDUs
+/// until the pane's pty is closed or the client goes away.
+/// If the client can't keep up, the stream is ended early and
+/// flagged as having overflowed.
+async fn stream_pane_output(
+    pane_id: PaneId,
+    escapes: bool,
+    rx: smol::channel::Receiver<TailOutput>,
+    sender: PduSender,
+) {
+    use termwiz::escape::parser::Parser;
+    use termwiz::escape::{Action, ControlCode};
+
+    let mut parser = Parser::new();
+    // Holds a utf8 sequence that was split across reads
+    let mut pending = vec![];
+    let mut overflowed = false;
+    while let Ok(output) = rx.recv().await {
+        let data = match output {
+            TailOutput::Data(data) => data,
+            TailOutput::Overflowed => {
+                overflowed = true;
+                break;
+            }
+        };
+        let text = if escapes {
+            pending.extend_from_slice(&data);
+            let valid = match std::str::from_utf8(&pending) {
+                Err(err) if err.error_len().is_none() => err.valid_up_to(),
+                _ => pending.len(),
+            };
+            let text = String::from_utf8_lossy(&pending[..valid]).into_owned();
+            pending.drain(..valid);
+            text
+        } else {
+            let mut text = String::new();
+            parser.parse(&data, |action| match action {
+                Action::Print(c) => text.push(c),
+                Action::PrintString(s) => text.push_str(&s),
+                Action::Control(ControlCode::LineFeed) => text.push('\n'),
+                Action::Control(ControlCode::HorizontalTab) => text.push('\t'),
+                _ => {}
+            });
+            text
+        };
+        if text.is_empty() {
+            continue;
+        }
+        if sender.backlog() >= MAX_OUTPUT_BACKLOG {
+            overflowed = true;
+            break;
+        }
+        let pdu = Pdu::PaneOutput(PaneOutput {
+            pane_id,
+            text,
+            finished: false,
+            overflowed: false,
+        });
+        if sender.send(DecodedPdu { pdu, serial: 0 }).is_err() {
+            // The client disconnected; dropping rx unsubscribes
+            return;
+        }
+    }
+
+    if overflowed {
+        log::warn!("client fell behind the output of pane {pane_id}; ending the stream");
+    }
+    let pdu = Pdu::PaneOutput(PaneOutput {
+        pane_id,
+        text: String::new(),
+        finished: true,
+        overflowed,
+    });
+    sender.send(DecodedPdu { pdu, serial: 0 }).ok();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wezterm_term::CommandBlock;
+
+    fn command_finished(exit_status: Option<i32>) -> MuxNotification {
+        MuxNotification::Alert {
+            pane_id: 1,
+            alert: Alert::CommandFinished(CommandBlock {
+                start_row: 0,
+                command: Some("make".to_string()),
+                output_row: Some(1),
+                end_row: Some(2),
+                exit_status,
+                duration: None,
+            }),
+        }
+    }
+
+    async fn no_match() -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Streams `outputs` as `stream_pane_output` would, with the given
+    /// backlog of PDUs, and returns the `PaneOutput` PDUs that were sent
+    fn stream(escapes: bool, outputs: Vec<TailOutput>, backlog: usize) -> Vec<PaneOutput> {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let sender = PduSender::new({
+            let sent = Arc::clone(&sent);
+            move |decoded| {
+                sent.lock().unwrap().push(decoded.pdu);
+                Ok(())
+            }
+        })
+        .with_backlog(move || backlog);
+
+        let (tx, rx) = smol::channel::unbounded();
+        for output in outputs {
+            tx.try_send(output).unwrap();
+        }
+        drop(tx);
+        smol::block_on(stream_pane_output(1, escapes, rx, sender));
+
+        let sent = std::mem::take(&mut *sent.lock().unwrap());
+        sent.into_iter()
+            .map(|pdu| match pdu {
+                Pdu::PaneOutput(output) => output,
+                pdu => panic!("unexpected {:?}", pdu),
+            })
+            .collect()
+    }
+
+    fn output(text: &str, finished: bool, overflowed: bool) -> PaneOutput {
+        PaneOutput {
+            pane_id: 1,
+            text: text.to_string(),
+            finished,
+            overflowed,
+        }
+    }
+
+    #[test]
+    fn should_classify_notifications() {
+        let match_cond = WaitCondition::Match {
+            pattern: "done".to_string(),
+            start_row: None,
+        };
+        for condition in [
+            match_cond.clone(),
+            WaitCondition::CommandFinished,
+            WaitCondition::PaneDead,
+        ] {
+            assert_eq!(
+                classify_notification(&condition, &MuxNotification::PaneRemoved(1)),
+                WaitEvent::Done(WaitForPaneResult::PaneDied)
+            );
+            assert_eq!(
+                classify_notification(&condition, &MuxNotification::PaneOutput(1)),
+                WaitEvent::Output
+            );
+        }
+
+        assert_eq!(
+            classify_notification(&WaitCondition::CommandFinished, &command_finished(Some(2))),
+            WaitEvent::Done(WaitForPaneResult::CommandFinished {
+                exit_status: Some(2)
+            })
+        );
+        assert_eq!(
+            classify_notification(&match_cond, &command_finished(Some(2))),
+            WaitEvent::Ignored
+        );
+        assert_eq!(
+            classify_notification(
+                &WaitCondition::CommandFinished,
+                &MuxNotification::Alert {
+                    pane_id: 1,
+                    alert: Alert::Bell
+                }
+            ),
+            WaitEvent::Ignored
+        );
+    }
+
+    #[test]
+    fn should_wait_for_command_to_finish() {
+        let (tx, rx) = smol::channel::unbounded();
+        tx.try_send(MuxNotification::PaneOutput(1)).unwrap();
+        tx.try_send(command_finished(None)).unwrap();
+
+        let result = smol::block_on(wait_for_notifications(
+            &WaitCondition::CommandFinished,
+            rx,
+            no_match,
+        ))
+        .unwrap();
+        assert_eq!(
+            result,
+            WaitForPaneResult::CommandFinished { exit_status: None }
+        );
+    }
+
+    #[test]
+    fn should_search_again_after_output() {
+        let (tx, rx) = smol::channel::unbounded();
+        for _ in 0..3 {
+            tx.try_send(MuxNotification::PaneOutput(1)).unwrap();
+        }
+
+        let mut searches = 0;
+        let result = smol::block_on(wait_for_notifications(
+            &WaitCondition::Match {
+                pattern: "done".to_string(),
+                start_row: None,
+            },
+            rx,
+            || {
+                searches += 1;
+                let found = searches == 2;
+                async move { Ok(found.then(|| "done".to_string())) }
+            },
+        ))
+        .unwrap();
+        assert_eq!(
+            result,
+            WaitForPaneResult::Matched {
+                text: "done".to_string()
+            }
+        );
+        assert_eq!(searches, 2);
+    }
+
+    #[test]
+    fn should_wait_for_pane_to_die() {
+        let (tx, rx) = smol::channel::unbounded();
+        tx.try_send(MuxNotification::PaneOutput(1)).unwrap();
+        tx.try_send(command_finished(Some(0))).unwrap();
+        tx.try_send(MuxNotification::PaneRemoved(1)).unwrap();
+
+        let result = smol::block_on(wait_for_notifications(
+            &WaitCondition::PaneDead,
+            rx,
+            no_match,
+        ))
+        .unwrap();
+        assert_eq!(result, WaitForPaneResult::PaneDied);
+    }
+
+    #[test]
+    fn should_restart_idle_timer_on_output() {
+        let (tx, rx) = smol::channel::unbounded();
+        let idle = Duration::from_millis(100);
+        let start = Instant::now();
+        let writer = std::thread::spawn(move || {
+            for _ in 0..3 {
+                std::thread::sleep(Duration::from_millis(40));
+                tx.try_send(MuxNotification::PaneOutput(1)).unwrap();
+            }
+            tx
+        });
+
+        let result = smol::block_on(wait_for_notifications(
+            &WaitCondition::Idle { idle },
+            rx,
+            no_match,
+        ))
+        .unwrap();
+        assert_eq!(result, WaitForPaneResult::Idle);
+        assert!(start.elapsed() >= Duration::from_millis(120) + idle);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn should_time_out() {
+        let (_tx, rx) = smol::channel::unbounded();
+        let result = smol::block_on(wait_with_timeout(
+            wait_for_notifications(&WaitCondition::PaneDead, rx, no_match),
+            Some(Duration::from_millis(10)),
+        ))
+        .unwrap();
+        assert_eq!(result, WaitForPaneResult::TimedOut);
+    }
+
+    #[test]
+    fn should_fail_when_notifications_stop() {
+        let (tx, rx) = smol::channel::unbounded();
+        drop(tx);
+        assert!(smol::block_on(wait_for_notifications(
+            &WaitCondition::CommandFinished,
+            rx,
+            no_match
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn should_stream_text() {
+        let outputs = vec![TailOutput::Data(b"\x1b[31mred\x1b[0m\r\n\tnext".to_vec())];
+        assert_eq!(
+            stream(false, outputs, 0),
+            vec![output("red\n\tnext", false, false), output("", true, false)]
+        );
+    }
+
+    #[test]
+    fn should_stream_escapes_without_splitting_utf8() {
+        let bytes = "\x1b[1mcafé".as_bytes();
+        let (first, second) = bytes.split_at(bytes.len() - 1);
+        let outputs = vec![
+            TailOutput::Data(first.to_vec()),
+            TailOutput::Data(second.to_vec()),
+        ];
+        assert_eq!(
+            stream(true, outputs, 0),
+            vec![
+                output("\x1b[1mcaf", false, false),
+                output("é", false, false),
+                output("", true, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn should_end_stream_on_overflow() {
+        let outputs = vec![
+            TailOutput::Data(b"one".to_vec()),
+            TailOutput::Overflowed,
+            TailOutput::Data(b"two".to_vec()),
+        ];
+        assert_eq!(
+            stream(false, outputs, 0),
+            vec![output("one", false, false), output("", true, true)]
+        );
+    }
+
+    #[test]
+    fn should_end_stream_when_client_falls_behind() {
+        let outputs = vec![TailOutput::Data(b"one".to_vec())];
+        assert_eq!(
+            stream(false, outputs, MAX_OUTPUT_BACKLOG),
+            vec![output("", true, true)]
+        );
+    }
+}
@@ -59,6 +59,23 @@ impl FileWrap {
         }
     }
 
+    pub fn seek(&mut self, offset: u64) -> SftpChannelResult<()> {
+        use std::io::{Seek, SeekFrom};
+        match self {
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(file) => {
+                file.seek(SeekFrom::Start(offset))?;
+                Ok(())
+            }
+
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(file) => {
+                file.seek(SeekFrom::Start(offset))?;
+                Ok(())
+            }
+        }
+    }
+
     pub fn fsync(&mut self) -> SftpChannelResult<()> {
         match self {
             #[cfg(feature = "ssh2")]
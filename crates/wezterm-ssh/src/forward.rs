@@ -0,0 +1,687 @@
+//! Port forwarding over an ssh session, equivalent to the
+//! `LocalForward`, `RemoteForward` and `DynamicForward` options
+//! of OpenSSH.
+//!
+//! Local and dynamic forwards listen on a local socket in a thread of
+//! their own, and ask the session to open a `direct-tcpip` channel for
+//! each connection.  Remote forwards ask the server to listen, and the
+//! session connects the channels that it accepts to the destination.
+//! Either way, the session relays the data between the channel and the
+//! local socket, just as it does for forwarded agent connections.
+use crate::config::ConfigMap;
+use crate::session::{DeadSession, SessionRequest, SessionSender};
+use anyhow::{anyhow, bail, Context};
+use filedescriptor::{poll, pollfd, AsRawSocketDescriptor, FileDescriptor, POLLIN};
+use smol::channel::bounded;
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ForwardKind {
+    /// Connections to a local port are forwarded to a host and port
+    /// reachable from the remote host
+    Local,
+    /// Connections to a port on the remote host are forwarded to a
+    /// host and port reachable from the local host
+    Remote,
+    /// Connections to a local port are forwarded to the destination
+    /// requested by a SOCKS4 or SOCKS5 client
+    Dynamic,
+}
+
+impl ForwardKind {
+    const ALL: [Self; 3] = [Self::Local, Self::Remote, Self::Dynamic];
+
+    fn flag(self) -> &'static str {
+        match self {
+            Self::Local => "-L",
+            Self::Remote => "-R",
+            Self::Dynamic => "-D",
+        }
+    }
+
+    /// The ssh_config option that specifies forwards of this kind
+    pub fn config_key(self) -> &'static str {
+        match self {
+            Self::Local => "localforward",
+            Self::Remote => "remoteforward",
+            Self::Dynamic => "dynamicforward",
+        }
+    }
+}
+
+/// Describes a port forward.
+/// It is displayed, and can be parsed, in the form of the equivalent
+/// ssh command line option, eg: `-L 8888:localhost:8888`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ForwardSpec {
+    pub kind: ForwardKind,
+    /// The address to listen on.  The default is the loopback
+    /// interface; an empty string or `*` listens on all interfaces.
+    pub bind_address: Option<String>,
+    /// The port to listen on; 0 picks any available port
+    pub port: u16,
+    /// The host and port to connect to.  Dynamic forwards
+    /// don't have one, as the client specifies it.
+    pub destination: Option<(String, u16)>,
+}
+
+impl ForwardSpec {
+    /// Parses the value of a forwarding option in ssh_config, such as
+    /// `[bind_address:]port host:hostport` for `LocalForward`, or the
+    /// colon separated form used on the ssh command line.
+    /// IPv6 addresses may be enclosed in square brackets.
+    pub fn parse(kind: ForwardKind, value: &str) -> anyhow::Result<Self> {
+        let mut fields = vec![];
+        for word in value.split_whitespace() {
+            fields.append(&mut split_colons(word)?);
+        }
+
+        fn port(field: &str, value: &str) -> anyhow::Result<u16> {
+            field
+                .parse()
+                .with_context(|| format!("invalid port {field:?} in {value:?}"))
+        }
+
+        let (bind_address, port, destination) = match (kind, fields.as_slice()) {
+            (ForwardKind::Dynamic, [p]) => (None, port(p, value)?, None),
+            (ForwardKind::Dynamic, [bind, p]) => (Some(bind.clone()), port(p, value)?, None),
+            (ForwardKind::Local | ForwardKind::Remote, [p, host, host_port]) => (
+                None,
+                port(p, value)?,
+                Some((host.clone(), port(host_port, value)?)),
+            ),
+            (ForwardKind::Local | ForwardKind::Remote, [bind, p, host, host_port]) => (
+                Some(bind.clone()),
+                port(p, value)?,
+                Some((host.clone(), port(host_port, value)?)),
+            ),
+            (ForwardKind::Dynamic, _) => bail!("expected [bind_address:]port, but got {value:?}"),
+            _ => bail!("expected [bind_address:]port host:hostport, but got {value:?}"),
+        };
+
+        Ok(Self {
+            kind,
+            bind_address,
+            port,
+            destination,
+        })
+    }
+
+    /// The local address to listen on for Local and Dynamic forwards
+    fn listen_host(&self) -> &str {
+        match self.bind_address.as_deref() {
+            None => "localhost",
+            Some("") | Some("*") => "0.0.0.0",
+            Some(addr) => addr,
+        }
+    }
+
+    /// The address for the server to listen on for Remote forwards
+    fn remote_listen_host(&self) -> &str {
+        match self.bind_address.as_deref() {
+            None => "localhost",
+            Some("*") => "",
+            Some(addr) => addr,
+        }
+    }
+}
+
+/// Splits `word` at colons, other than those inside square brackets
+fn split_colons(word: &str) -> anyhow::Result<Vec<String>> {
+    let mut fields = vec![];
+    let mut current = String::new();
+    let mut in_brackets = false;
+    for c in word.chars() {
+        match c {
+            '[' if !in_brackets && current.is_empty() => in_brackets = true,
+            ']' if in_brackets => in_brackets = false,
+            ':' if !in_brackets => fields.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    if in_brackets {
+        bail!("missing ] in {word:?}");
+    }
+    fields.push(current);
+    Ok(fields)
+}
+
+impl fmt::Display for ForwardSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn host(f: &mut fmt::Formatter, host: &str) -> fmt::Result {
+            if host.contains(':') {
+                write!(f, "[{host}]")
+            } else {
+                write!(f, "{host}")
+            }
+        }
+
+        write!(f, "{} ", self.kind.flag())?;
+        if let Some(bind) = &self.bind_address {
+            host(f, bind)?;
+            write!(f, ":")?;
+        }
+        write!(f, "{}", self.port)?;
+        if let Some((dest, port)) = &self.destination {
+            write!(f, ":")?;
+            host(f, dest)?;
+            write!(f, ":{port}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ForwardSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+        for kind in ForwardKind::ALL {
+            if let Some(value) = s.strip_prefix(kind.flag()) {
+                return Self::parse(kind, value);
+            }
+        }
+        bail!("expected a forward in the form -L, -R or -D followed by its spec, but got {s:?}");
+    }
+}
+
+/// Returns the forwards specified by the `LocalForward`, `RemoteForward`
+/// and `DynamicForward` options in `config`.
+/// An option may hold several comma separated forwards, as repeating
+/// it in ssh_config adds to the list of forwards.
+pub fn forwards_from_config(config: &ConfigMap) -> anyhow::Result<Vec<ForwardSpec>> {
+    let mut forwards = vec![];
+    for kind in ForwardKind::ALL {
+        if let Some(value) = config.get(kind.config_key()) {
+            for spec in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                forwards.push(ForwardSpec::parse(kind, spec)?);
+            }
+        }
+    }
+    Ok(forwards)
+}
+
+/// An active port forward.  Dropping it stops the forward;
+/// connections that were already forwarded remain open.
+pub struct Forward {
+    spec: ForwardSpec,
+    port: u16,
+    stop: StopForward,
+    session: SessionSender,
+}
+
+enum StopForward {
+    Listener(Arc<AtomicBool>),
+    Remote(SessionSender),
+}
+
+impl Forward {
+    pub fn spec(&self) -> &ForwardSpec {
+        &self.spec
+    }
+
+    /// The port that is being listened on.  This is only different
+    /// from the port of the spec when that asked for any port (0).
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns false once the session that carries the forwarded
+    /// connections has ended, which also stops a local listener
+    pub fn is_active(&self) -> bool {
+        !self.session.tx.is_closed()
+    }
+}
+
+impl fmt::Debug for Forward {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Forward")
+            .field("spec", &self.spec)
+            .field("port", &self.port)
+            .finish()
+    }
+}
+
+impl Drop for Forward {
+    fn drop(&mut self) {
+        match &self.stop {
+            StopForward::Listener(shutdown) => shutdown.store(true, Ordering::SeqCst),
+            StopForward::Remote(tx) => {
+                tx.try_send(SessionRequest::CancelRemoteForward(self.port))
+                    .ok();
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct DirectTcpIp {
+    pub host: String,
+    pub port: u16,
+    pub originator: SocketAddr,
+    pub socket: FileDescriptor,
+    /// Sent to the socket before any data from the channel
+    pub greeting: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub(crate) struct RemoteForward {
+    pub bind_address: String,
+    pub port: u16,
+    pub host: String,
+    pub host_port: u16,
+}
+
+pub(crate) async fn start_forward(
+    tx: &SessionSender,
+    spec: ForwardSpec,
+) -> anyhow::Result<Forward> {
+    match &spec.destination {
+        Some((host, host_port)) if spec.kind == ForwardKind::Remote => {
+            let (reply, rx) = bounded(1);
+            tx.send(SessionRequest::RemoteForward(
+                RemoteForward {
+                    bind_address: spec.remote_listen_host().to_string(),
+                    port: spec.port,
+                    host: host.clone(),
+                    host_port: *host_port,
+                },
+                reply,
+            ))
+            .await
+            .map_err(|_| DeadSession)?;
+            let port = rx.recv().await??;
+            Ok(Forward {
+                spec,
+                port,
+                stop: StopForward::Remote(tx.clone()),
+                session: tx.clone(),
+            })
+        }
+        None if spec.kind == ForwardKind::Remote => {
+            bail!("{spec}: dynamic remote forwarding is not supported")
+        }
+        _ => {
+            let listener = TcpListener::bind((spec.listen_host(), spec.port))
+                .with_context(|| format!("{spec}: listening on {}", spec.listen_host()))?;
+            let port = listener.local_addr()?.port();
+            listener.set_nonblocking(true)?;
+
+            let shutdown = Arc::new(AtomicBool::new(false));
+            std::thread::spawn({
+                let spec = spec.clone();
+                let tx = tx.clone();
+                let shutdown = Arc::clone(&shutdown);
+                move || accept_loop(listener, spec, tx, shutdown)
+            });
+
+            Ok(Forward {
+                spec,
+                port,
+                stop: StopForward::Listener(shutdown),
+                session: tx.clone(),
+            })
+        }
+    }
+}
+
+fn accept_loop(
+    listener: TcpListener,
+    spec: ForwardSpec,
+    tx: SessionSender,
+    shutdown: Arc<AtomicBool>,
+) {
+    // Stop listening once the session has ended, as connections
+    // can no longer be forwarded
+    while !shutdown.load(Ordering::SeqCst) && !tx.tx.is_closed() {
+        let mut poll_array = [pollfd {
+            fd: listener.as_socket_descriptor(),
+            events: POLLIN,
+            revents: 0,
+        }];
+        if let Err(err) = poll(&mut poll_array, Some(Duration::from_millis(250))) {
+            log::error!("{spec}: poll: {err:#}");
+            break;
+        }
+        if poll_array[0].revents == 0 {
+            continue;
+        }
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                let spec = spec.clone();
+                let tx = tx.clone();
+                std::thread::spawn(move || {
+                    if let Err(err) = forward_connection(stream, peer, &spec, &tx) {
+                        log::error!("{spec}: forwarding connection from {peer}: {err:#}");
+                    }
+                });
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(err) => {
+                log::error!("{spec}: accept: {err:#}");
+                break;
+            }
+        }
+    }
+    log::trace!("{spec}: stopped listening");
+}
+
+fn forward_connection(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    spec: &ForwardSpec,
+    tx: &SessionSender,
+) -> anyhow::Result<()> {
+    stream.set_nonblocking(false)?;
+    match &spec.destination {
+        Some((host, port)) => open_channel(tx, stream, host, *port, peer, vec![]),
+        None => {
+            let request = read_socks_request(&mut stream)?;
+            let socket = stream.try_clone()?;
+            match open_channel(
+                tx,
+                socket,
+                &request.host,
+                request.port,
+                peer,
+                request.reply(true),
+            ) {
+                Ok(()) => Ok(()),
+                Err(err) => {
+                    stream.write_all(&request.reply(false)).ok();
+                    Err(err)
+                }
+            }
+        }
+    }
+}
+
+/// Asks the session to open a channel to host:port and to relay
+/// its data to and from the stream
+fn open_channel(
+    tx: &SessionSender,
+    stream: TcpStream,
+    host: &str,
+    port: u16,
+    originator: SocketAddr,
+    greeting: Vec<u8>,
+) -> anyhow::Result<()> {
+    let (reply, rx) = bounded(1);
+    smol::block_on(tx.send(SessionRequest::DirectTcpIp(
+        DirectTcpIp {
+            host: host.to_string(),
+            port,
+            originator,
+            socket: socket_to_fd(stream),
+            greeting,
+        },
+        reply,
+    )))
+    .map_err(|_| DeadSession)?;
+    smol::block_on(rx.recv())?
+}
+
+pub(crate) fn socket_to_fd(stream: TcpStream) -> FileDescriptor {
+    #[cfg(unix)]
+    {
+        FileDescriptor::new(stream)
+    }
+    #[cfg(windows)]
+    unsafe {
+        use std::os::windows::io::{FromRawSocket, IntoRawSocket};
+        FileDescriptor::from_raw_socket(stream.into_raw_socket())
+    }
+}
+
+/// The parts of a SOCKS CONNECT request that are needed to act on it
+struct SocksRequest {
+    version: u8,
+    host: String,
+    port: u16,
+}
+
+impl SocksRequest {
+    /// The response that tells the client whether the
+    /// connection was established
+    fn reply(&self, success: bool) -> Vec<u8> {
+        if self.version == 4 {
+            vec![0, if success { 0x5a } else { 0x5b }, 0, 0, 0, 0, 0, 0]
+        } else {
+            socks5_reply(if success { 0 } else { 1 })
+        }
+    }
+}
+
+fn socks5_reply(status: u8) -> Vec<u8> {
+    vec![5, status, 0, 1, 0, 0, 0, 0, 0, 0]
+}
+
+fn read_byte<S: Read>(stream: &mut S) -> anyhow::Result<u8> {
+    let mut byte = [0u8; 1];
+    stream.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_nul_terminated<S: Read>(stream: &mut S) -> anyhow::Result<String> {
+    let mut result = vec![];
+    loop {
+        match read_byte(stream)? {
+            0 => break,
+            _ if result.len() >= 255 => bail!("SOCKS4 request field is too long"),
+            b => result.push(b),
+        }
+    }
+    Ok(String::from_utf8(result)?)
+}
+
+/// Carries out the SOCKS4, SOCKS4a or SOCKS5 handshake, up to the point
+/// where the client has said where it wants to connect to.
+/// Only the CONNECT command is supported, and SOCKS5 clients
+/// must accept connecting without authentication.
+fn read_socks_request<S: Read + Write>(stream: &mut S) -> anyhow::Result<SocksRequest> {
+    match read_byte(stream)? {
+        4 => {
+            let mut header = [0u8; 7];
+            stream.read_exact(&mut header)?;
+            let port = u16::from_be_bytes([header[1], header[2]]);
+            let ip = Ipv4Addr::new(header[3], header[4], header[5], header[6]);
+            let _user_id = read_nul_terminated(stream)?;
+            // SOCKS4a signals that a host name follows with 0.0.0.x
+            let octets = ip.octets();
+            let host = if octets[..3] == [0, 0, 0] && octets[3] != 0 {
+                read_nul_terminated(stream)?
+            } else {
+                ip.to_string()
+            };
+            let request = SocksRequest {
+                version: 4,
+                host,
+                port,
+            };
+            if header[0] != 1 {
+                stream.write_all(&request.reply(false))?;
+                bail!("unsupported SOCKS4 command {}", header[0]);
+            }
+            Ok(request)
+        }
+        5 => {
+            let num_methods = read_byte(stream)?;
+            let mut methods = vec![0u8; num_methods as usize];
+            stream.read_exact(&mut methods)?;
+            if !methods.contains(&0) {
+                stream.write_all(&[5, 0xff])?;
+                bail!("SOCKS5 client requires authentication, which is not supported");
+            }
+            stream.write_all(&[5, 0])?;
+
+            let mut header = [0u8; 4];
+            stream.read_exact(&mut header)?;
+            if header[1] != 1 {
+                stream.write_all(&socks5_reply(7))?;
+                bail!("unsupported SOCKS5 command {}", header[1]);
+            }
+            let host = match header[3] {
+                1 => {
+                    let mut addr = [0u8; 4];
+                    stream.read_exact(&mut addr)?;
+                    Ipv4Addr::from(addr).to_string()
+                }
+                3 => {
+                    let len = read_byte(stream)?;
+                    let mut name = vec![0u8; len as usize];
+                    stream.read_exact(&mut name)?;
+                    String::from_utf8(name)?
+                }
+                4 => {
+                    let mut addr = [0u8; 16];
+                    stream.read_exact(&mut addr)?;
+                    Ipv6Addr::from(addr).to_string()
+                }
+                atyp => {
+                    stream.write_all(&socks5_reply(8))?;
+                    bail!("unsupported SOCKS5 address type {atyp}");
+                }
+            };
+            let mut port = [0u8; 2];
+            stream.read_exact(&mut port)?;
+            Ok(SocksRequest {
+                version: 5,
+                host,
+                port: u16::from_be_bytes(port),
+            })
+        }
+        version => Err(anyhow!("unsupported SOCKS version {version}")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_config_forms() {
+        assert_eq!(
+            ForwardSpec::parse(ForwardKind::Local, "8888 localhost:8888").unwrap(),
+            ForwardSpec {
+                kind: ForwardKind::Local,
+                bind_address: None,
+                port: 8888,
+                destination: Some(("localhost".to_string(), 8888)),
+            }
+        );
+        assert_eq!(
+            ForwardSpec::parse(ForwardKind::Remote, "[::1]:5432 [fe80::1]:5433").unwrap(),
+            ForwardSpec {
+                kind: ForwardKind::Remote,
+                bind_address: Some("::1".to_string()),
+                port: 5432,
+                destination: Some(("fe80::1".to_string(), 5433)),
+            }
+        );
+        assert_eq!(
+            ForwardSpec::parse(ForwardKind::Dynamic, "*:1080").unwrap(),
+            ForwardSpec {
+                kind: ForwardKind::Dynamic,
+                bind_address: Some("*".to_string()),
+                port: 1080,
+                destination: None,
+            }
+        );
+        assert!(ForwardSpec::parse(ForwardKind::Local, "8888").is_err());
+        assert!(ForwardSpec::parse(ForwardKind::Dynamic, "nope").is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in [
+            "-L 8888:localhost:8888",
+            "-L 0.0.0.0:8888:db.internal:5432",
+            "-R [::1]:9000:[fe80::1]:80",
+            "-D 1080",
+        ] {
+            let spec: ForwardSpec = s.parse().unwrap();
+            assert_eq!(spec.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn forwards_from_config_splits_entries() {
+        let mut config = ConfigMap::new();
+        config.insert(
+            "localforward".to_string(),
+            "8888 localhost:8888, 9999 localhost:9999".to_string(),
+        );
+        config.insert("dynamicforward".to_string(), "1080".to_string());
+        let forwards: Vec<String> = forwards_from_config(&config)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            forwards,
+            vec![
+                "-L 8888:localhost:8888",
+                "-L 9999:localhost:9999",
+                "-D 1080"
+            ]
+        );
+    }
+
+    #[test]
+    fn socks5_request() {
+        struct Duplex {
+            input: std::io::Cursor<Vec<u8>>,
+            output: Vec<u8>,
+        }
+        impl Read for Duplex {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.input.read(buf)
+            }
+        }
+        impl Write for Duplex {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.output.write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut input = vec![5, 1, 0, 5, 1, 0, 3, 9];
+        input.extend_from_slice(b"localhost");
+        input.extend_from_slice(&8080u16.to_be_bytes());
+        let mut stream = Duplex {
+            input: std::io::Cursor::new(input),
+            output: vec![],
+        };
+        let request = read_socks_request(&mut stream).unwrap();
+        assert_eq!(request.host, "localhost");
+        assert_eq!(request.port, 8080);
+        assert_eq!(stream.output, vec![5, 0]);
+        assert_eq!(request.reply(true), socks5_reply(0));
+    }
+
+    #[test]
+    fn local_forward_ends_with_session() {
+        let (tx, rx) = bounded(8);
+        let (pipe, _pipe_read) = filedescriptor::socketpair().unwrap();
+        let session = SessionSender {
+            tx,
+            pipe: Arc::new(std::sync::Mutex::new(pipe)),
+        };
+        let spec = ForwardSpec::parse(ForwardKind::Local, "0 localhost:22").unwrap();
+        let forward = smol::block_on(start_forward(&session, spec)).unwrap();
+        assert!(forward.is_active());
+
+        // The session loop owns the receiver, so this is what
+        // happens when the session ends
+        drop(rx);
+        assert!(!forward.is_active());
+    }
+}
@@ -1,12 +1,13 @@
 use crate::auth::*;
 use crate::config::ConfigMap;
+use crate::forward::{DirectTcpIp, Forward, ForwardSpec, RemoteForward};
 use crate::host::*;
 use crate::pty::*;
 use crate::sessioninner::*;
 use crate::sftp::{Sftp, SftpRequest};
 use filedescriptor::{socketpair, FileDescriptor};
 use portable_pty::PtySize;
-use smol::channel::{bounded, Receiver, Sender};
+use smol::channel::{bounded, unbounded, Receiver, Sender};
 use std::collections::HashMap;
 use std::io::Write;
 use std::sync::{Arc, Mutex};
@@ -58,6 +59,9 @@ pub(crate) enum SessionRequest {
     Exec(Exec, Sender<anyhow::Result<ExecResult>>),
     Sftp(SftpRequest),
     SignalChannel(SignalChannel),
+    DirectTcpIp(DirectTcpIp, Sender<anyhow::Result<()>>),
+    RemoteForward(RemoteForward, Sender<anyhow::Result<u16>>),
+    CancelRemoteForward(u16),
     SessionDropped,
 }
 
@@ -97,6 +101,7 @@ impl Session {
             tx: tx_req,
             pipe: Arc::new(Mutex::new(sender_write)),
         };
+        let (tx_forward_connected, rx_forward_connected) = unbounded();
 
         let keep_alive = config.get("serveraliveinterval").and_then(|value| {
             let seconds: u64 = value.parse().ok()?;
@@ -116,6 +121,11 @@ impl Session {
             channels: HashMap::new(),
             files: HashMap::new(),
             dirs: HashMap::new(),
+            remote_forwards: HashMap::new(),
+            pending_forwards: HashMap::new(),
+            tx_forward_connected,
+            rx_forward_connected,
+            wake_pipe: Arc::clone(&session_sender.pipe),
             next_channel_id: 1,
             next_file_id: 1,
             sender_read,
@@ -175,6 +185,13 @@ impl Session {
         Ok(exec)
     }
 
+    /// Starts forwarding connections as described by `spec`.
+    /// The forward remains active until the returned `Forward`
+    /// is dropped.
+    pub async fn forward(&self, spec: ForwardSpec) -> anyhow::Result<Forward> {
+        crate::forward::start_forward(&self.tx, spec).await
+    }
+
     /// Creates a new reference to the sftp channel for filesystem operations
     ///
     /// ### Note
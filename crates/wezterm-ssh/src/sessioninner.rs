@@ -2,6 +2,7 @@ use crate::channelwrap::ChannelWrap;
 use crate::config::ConfigMap;
 use crate::dirwrap::DirWrap;
 use crate::filewrap::FileWrap;
+use crate::forward::{socket_to_fd, DirectTcpIp, RemoteForward};
 use crate::pty::*;
 use crate::session::{Exec, ExecResult, SessionEvent, SessionRequest, SignalChannel};
 use crate::sessionwrap::SessionWrap;
@@ -19,7 +20,8 @@ use smol::channel::{bounded, Receiver, Sender, TryRecvError};
 use socket2::{Domain, Socket, Type};
 use std::collections::{HashMap, VecDeque};
 use std::io::{Read, Write};
-use std::net::ToSocketAddrs;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 #[derive(Debug)]
@@ -45,6 +47,16 @@ pub(crate) struct SessionInner {
     pub channels: HashMap<ChannelId, ChannelInfo>,
     pub files: HashMap<FileId, FileWrap>,
     pub dirs: HashMap<DirId, DirWrap>,
+    /// Maps the port that the server listens on for a remote
+    /// forward to the host and port to connect to
+    pub remote_forwards: HashMap<u16, (String, u16)>,
+    /// Remote forward channels that are waiting for the connection
+    /// to their destination, which is made on a thread of its own
+    pub pending_forwards: HashMap<ChannelId, ChannelWrap>,
+    pub tx_forward_connected: Sender<(ChannelId, anyhow::Result<TcpStream>)>,
+    pub rx_forward_connected: Receiver<(ChannelId, anyhow::Result<TcpStream>)>,
+    /// Wakes up the session loop, as SessionSender does
+    pub wake_pipe: Arc<Mutex<FileDescriptor>>,
     pub next_channel_id: ChannelId,
     pub next_file_id: FileId,
     pub sender_read: FileDescriptor,
@@ -452,6 +464,8 @@ impl SessionInner {
             self.drain_request_pipe();
             self.dispatch_pending_requests(sess)?;
             self.connect_pending_agent_forward_channels(sess);
+            self.connect_pending_remote_forward_channels(sess);
+            self.bridge_connected_remote_forwards();
 
             if self.channels.is_empty() && self.session_was_dropped {
                 log::trace!(
@@ -670,6 +684,17 @@ impl SessionInner {
                         }
                         Ok(true)
                     }
+                    SessionRequest::DirectTcpIp(msg, reply) => {
+                        dispatch(reply, || self.direct_tcpip(sess, msg), "direct_tcpip")
+                    }
+                    SessionRequest::RemoteForward(msg, reply) => {
+                        dispatch(reply, || self.remote_forward(sess, msg), "remote_forward")
+                    }
+                    SessionRequest::CancelRemoteForward(port) => {
+                        self.remote_forwards.remove(&port);
+                        sess.cancel_forward(port);
+                        Ok(true)
+                    }
                     SessionRequest::Sftp(SftpRequest::OpenWithMode(msg, reply)) => {
                         dispatch(reply, || self.open_with_mode(sess, &msg), "OpenWithMode")
                     }
@@ -798,6 +823,19 @@ impl SessionInner {
                             "fsync",
                         )
                     }
+                    SessionRequest::Sftp(SftpRequest::File(FileRequest::Seek(msg, reply))) => {
+                        dispatch(
+                            reply,
+                            || {
+                                let file = self
+                                    .files
+                                    .get_mut(&msg.file_id)
+                                    .ok_or_else(|| anyhow!("invalid file_id"))?;
+                                file.seek(msg.offset)
+                            },
+                            "seek",
+                        )
+                    }
 
                     SessionRequest::Sftp(SftpRequest::ReadDir(path, reply)) => {
                         dispatch(reply, || self.init_sftp(sess)?.read_dir(&path), "read_dir")
@@ -863,7 +901,7 @@ impl SessionInner {
             let identity_agent = sess
                 .identity_agent()
                 .ok_or_else(|| anyhow!("no identity agent in config"))?;
-            let mut fd = {
+            let fd = {
                 use wezterm_uds::UnixStream;
                 #[cfg(unix)]
                 {
@@ -877,34 +915,7 @@ impl SessionInner {
                     )
                 }
             };
-            fd.set_non_blocking(true)?;
-
-            let read_from_agent = fd;
-            let write_to_agent = read_from_agent.try_clone()?;
-            let channel_id = sess.next_channel_id;
-            sess.next_channel_id += 1;
-            let info = ChannelInfo {
-                channel_id,
-                channel,
-                exit: None,
-                exited: false,
-                descriptors: [
-                    DescriptorState {
-                        fd: Some(read_from_agent),
-                        buf: VecDeque::with_capacity(8192),
-                    },
-                    DescriptorState {
-                        fd: Some(write_to_agent),
-                        buf: VecDeque::with_capacity(8192),
-                    },
-                    DescriptorState {
-                        fd: None,
-                        buf: VecDeque::with_capacity(8192),
-                    },
-                ],
-            };
-            sess.channels.insert(channel_id, info);
-            Ok(())
+            sess.bridge_channel(channel, fd, vec![])
         }
         while let Some(channel) = sess.accept_agent_forward() {
             if let Err(err) = process_one(self, channel) {
@@ -913,6 +924,119 @@ impl SessionInner {
         }
     }
 
+    fn connect_pending_remote_forward_channels(&mut self, sess: &mut SessionWrap) {
+        while let Some((port, channel)) = sess.accept_forward() {
+            let (host, host_port) = match self.remote_forwards.get(&port) {
+                Some(dest) => dest.clone(),
+                None => {
+                    // Most likely the forward was cancelled; dropping
+                    // the channel closes the connection
+                    log::trace!("closing connection to port {port}, which isn't forwarded");
+                    continue;
+                }
+            };
+
+            // Connecting can take a while, and must not hold up the
+            // other channels, so it is done on a thread of its own
+            let id = self.next_channel_id;
+            self.next_channel_id += 1;
+            self.pending_forwards.insert(id, channel);
+            let tx = self.tx_forward_connected.clone();
+            let wake_pipe = Arc::clone(&self.wake_pipe);
+            std::thread::spawn(move || {
+                let result = TcpStream::connect((host.as_str(), host_port)).with_context(|| {
+                    format!("connecting remote forward from port {port} to {host}:{host_port}")
+                });
+                if tx.try_send((id, result)).is_ok() {
+                    let _ = wake_pipe.lock().unwrap().write(b"x");
+                }
+            });
+        }
+    }
+
+    /// Relays the remote forward connections whose destination
+    /// has been connected to
+    fn bridge_connected_remote_forwards(&mut self) {
+        while let Ok((id, result)) = self.rx_forward_connected.try_recv() {
+            let channel = match self.pending_forwards.remove(&id) {
+                Some(channel) => channel,
+                None => continue,
+            };
+            // Dropping the channel on error closes the connection
+            let result = result
+                .and_then(|stream| self.bridge_channel(channel, socket_to_fd(stream), vec![]));
+            if let Err(err) = result {
+                log::error!("error connecting remote forward: {:#}", err);
+            }
+        }
+    }
+
+    /// Relays the data of `channel` to and from the socket `fd`, as is
+    /// done for forwarded agent and port connections.
+    /// `greeting` is written to the socket ahead of the channel data.
+    fn bridge_channel(
+        &mut self,
+        channel: ChannelWrap,
+        mut fd: FileDescriptor,
+        greeting: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        fd.set_non_blocking(true)?;
+
+        let read_from_socket = fd;
+        let write_to_socket = read_from_socket.try_clone()?;
+        let mut output = VecDeque::with_capacity(8192);
+        output.extend(greeting);
+
+        let channel_id = self.next_channel_id;
+        self.next_channel_id += 1;
+        let info = ChannelInfo {
+            channel_id,
+            channel,
+            exit: None,
+            exited: false,
+            descriptors: [
+                DescriptorState {
+                    fd: Some(read_from_socket),
+                    buf: VecDeque::with_capacity(8192),
+                },
+                DescriptorState {
+                    fd: Some(write_to_socket),
+                    buf: output,
+                },
+                DescriptorState {
+                    fd: None,
+                    buf: VecDeque::with_capacity(8192),
+                },
+            ],
+        };
+        self.channels.insert(channel_id, info);
+        Ok(())
+    }
+
+    pub fn direct_tcpip(&mut self, sess: &mut SessionWrap, msg: DirectTcpIp) -> anyhow::Result<()> {
+        let channel = sess
+            .open_direct_tcpip(&msg.host, msg.port, msg.originator)
+            .with_context(|| format!("opening a channel to {}:{}", msg.host, msg.port))?;
+        self.bridge_channel(channel, msg.socket, msg.greeting)
+    }
+
+    pub fn remote_forward(
+        &mut self,
+        sess: &mut SessionWrap,
+        msg: RemoteForward,
+    ) -> anyhow::Result<u16> {
+        let port = sess
+            .listen_forward(&msg.bind_address, msg.port)
+            .with_context(|| {
+                format!(
+                    "asking the server to listen on {}:{}",
+                    msg.bind_address, msg.port
+                )
+            })?;
+        self.remote_forwards.insert(port, (msg.host, msg.host_port));
+        Ok(port)
+    }
+
     pub fn signal_channel(&mut self, info: &SignalChannel) -> anyhow::Result<()> {
         let chan_info = self
             .channels
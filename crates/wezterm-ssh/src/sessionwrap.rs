@@ -1,11 +1,14 @@
 use crate::channelwrap::ChannelWrap;
 use crate::sftpwrap::SftpWrap;
 use filedescriptor::{AsRawSocketDescriptor, SocketDescriptor, POLLIN, POLLOUT};
+use std::net::SocketAddr;
 
 #[cfg(feature = "ssh2")]
 pub(crate) struct Ssh2Session {
     pub sess: ssh2::Session,
     pub sftp: Option<SftpWrap>,
+    /// Remote forward listeners, keyed by the port on the server
+    pub listeners: std::collections::HashMap<u16, ssh2::Listener>,
 }
 
 #[cfg(feature = "libssh-rs")]
@@ -25,7 +28,11 @@ pub(crate) enum SessionWrap {
 impl SessionWrap {
     #[cfg(feature = "ssh2")]
     pub fn with_ssh2(sess: ssh2::Session) -> Self {
-        Self::Ssh2(Ssh2Session { sess, sftp: None })
+        Self::Ssh2(Ssh2Session {
+            sess,
+            sftp: None,
+            listeners: Default::default(),
+        })
     }
 
     #[cfg(feature = "libssh-rs")]
@@ -104,4 +111,89 @@ impl SessionWrap {
             Self::LibSsh(sess) => sess.sess.accept_agent_forward().map(ChannelWrap::LibSsh),
         }
     }
+
+    pub fn open_direct_tcpip(
+        &self,
+        host: &str,
+        port: u16,
+        originator: SocketAddr,
+    ) -> anyhow::Result<ChannelWrap> {
+        let originator_host = originator.ip().to_string();
+        match self {
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(sess) => {
+                let channel = sess.sess.channel_direct_tcpip(
+                    host,
+                    port,
+                    Some((&originator_host, originator.port())),
+                )?;
+                Ok(ChannelWrap::Ssh2(channel))
+            }
+
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(sess) => {
+                let channel = sess.sess.new_channel()?;
+                channel.open_forward(host, port, &originator_host, originator.port())?;
+                Ok(ChannelWrap::LibSsh(channel))
+            }
+        }
+    }
+
+    /// Asks the server to listen for connections to forward to us,
+    /// returning the port that it is listening on
+    pub fn listen_forward(&mut self, bind_address: &str, port: u16) -> anyhow::Result<u16> {
+        match self {
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(sess) => {
+                let (listener, port) =
+                    sess.sess
+                        .channel_forward_listen(port, Some(bind_address), None)?;
+                sess.listeners.insert(port, listener);
+                Ok(port)
+            }
+
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(sess) => Ok(sess.sess.listen_forward(Some(bind_address), port)?),
+        }
+    }
+
+    pub fn cancel_forward(
+        &mut self,
+        #[cfg_attr(not(feature = "ssh2"), allow(unused_variables))] port: u16,
+    ) {
+        match self {
+            // Dropping the listener cancels the forward
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(sess) => {
+                sess.listeners.remove(&port);
+            }
+
+            // libssh-rs doesn't expose a way to cancel the forward,
+            // so the server keeps listening, but the connections that
+            // it forwards are closed as they are no longer wanted
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(_sess) => {}
+        }
+    }
+
+    /// Returns a forwarded connection that is ready to be accepted,
+    /// along with the port on the server that it was made to
+    pub fn accept_forward(&mut self) -> Option<(u16, ChannelWrap)> {
+        match self {
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(sess) => sess.listeners.iter_mut().find_map(|(port, listener)| {
+                listener
+                    .accept()
+                    .ok()
+                    .map(|channel| (*port, ChannelWrap::Ssh2(channel)))
+            }),
+
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(sess) => sess
+                .sess
+                .accept_forward(std::time::Duration::ZERO)
+                .ok()
+                .map(|(port, channel)| (port, ChannelWrap::LibSsh(channel))),
+        }
+    }
 }
@@ -34,6 +34,7 @@ pub(crate) enum FileRequest {
     SetMetadata(SetMetadataFile, Sender<SftpChannelResult<()>>),
     Metadata(FileId, Sender<SftpChannelResult<Metadata>>),
     Fsync(FileId, Sender<SftpChannelResult<()>>),
+    Seek(SeekFile, Sender<SftpChannelResult<()>>),
 }
 
 #[derive(Debug)]
@@ -54,6 +55,12 @@ pub(crate) struct SetMetadataFile {
     pub metadata: Metadata,
 }
 
+#[derive(Debug)]
+pub(crate) struct SeekFile {
+    pub file_id: FileId,
+    pub offset: u64,
+}
+
 impl fmt::Debug for File {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("File")
@@ -143,6 +150,27 @@ impl File {
         rx.recv().await??;
         Ok(())
     }
+
+    /// Moves the position of this handle to `offset` bytes from the
+    /// start of the file, so that subsequent reads and writes begin there.
+    ///
+    /// See [`ssh2::File::seek`] for more information.
+    pub async fn seek(&self, offset: u64) -> SftpChannelResult<()> {
+        let (reply, rx) = bounded(1);
+        self.tx
+            .as_ref()
+            .unwrap()
+            .send(SessionRequest::Sftp(SftpRequest::File(FileRequest::Seek(
+                SeekFile {
+                    file_id: self.file_id,
+                    offset,
+                },
+                reply,
+            ))))
+            .await?;
+        rx.recv().await??;
+        Ok(())
+    }
 }
 
 impl smol::io::AsyncRead for File {
@@ -1,15 +1,17 @@
 use config::keyassignment::SpawnTabDomain;
-use config::lua::mlua::{self, Lua, UserData, UserDataMethods, Value as LuaValue};
+use config::lua::mlua::{self, Lua, LuaSerdeExt, UserData, UserDataMethods, Value as LuaValue};
 use config::lua::{get_or_create_module, get_or_create_sub_module};
 use luahelper::impl_lua_conversion_dynamic;
 use mlua::UserDataRef;
 use mux::domain::{DomainId, SplitSource};
+use mux::layout::Layout;
 use mux::pane::{Pane, PaneId};
 use mux::tab::{SplitDirection, SplitRequest, SplitSize, Tab, TabId};
 use mux::window::{Window, WindowId};
 use mux::Mux;
 use portable_pty::CommandBuilder;
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
 use wezterm_dynamic::{FromDynamic, ToDynamic};
 use wezterm_term::TerminalSize;
@@ -169,6 +171,71 @@ pub fn register(lua: &Lua) -> anyhow::Result<()> {
         })?,
     )?;
 
+    mux_mod.set(
+        "save_session",
+        lua.create_function(|_, name: Option<String>| {
+            let name = name.unwrap_or_else(|| mux::session::LAST_SESSION.to_string());
+            let path = mux::session::save_session(&name)
+                .map_err(|e| mlua::Error::external(format!("{:#}", e)))?;
+            Ok(path.display().to_string())
+        })?,
+    )?;
+
+    mux_mod.set(
+        "restore_session",
+        lua.create_async_function(|_, name: String| async move {
+            let window_ids = mux::session::restore_session(&name)
+                .await
+                .map_err(|e| mlua::Error::external(format!("{:#}", e)))?;
+            Ok(window_ids
+                .into_iter()
+                .map(MuxWindow)
+                .collect::<Vec<MuxWindow>>())
+        })?,
+    )?;
+
+    mux_mod.set(
+        "list_sessions",
+        lua.create_function(|_, _: ()| Ok(mux::session::list_sessions()))?,
+    )?;
+
+    mux_mod.set(
+        "apply_layout",
+        lua.create_async_function(|lua, layout: LuaValue| async move {
+            // A layout is either the path to a layout file, or a table
+            // with the same structure as the file
+            let layout = match layout {
+                LuaValue::String(path) => Layout::load(Path::new(path.to_str()?))
+                    .map_err(|e| mlua::Error::external(format!("{:#}", e)))?,
+                value => {
+                    let mut layout: Layout = lua.from_value(value)?;
+                    layout.resolve_paths(&config::HOME_DIR);
+                    layout
+                }
+            };
+            let config = config::configuration();
+            let workspace = get_mux()?.active_workspace();
+            let window_id = layout
+                .apply(&workspace, config.initial_size(0, None))
+                .await
+                .map_err(|e| mlua::Error::external(format!("{:#}", e)))?;
+            Ok(MuxWindow(window_id))
+        })?,
+    )?;
+
+    mux_mod.set(
+        "dump_layout",
+        lua.create_function(|lua, workspace: Option<String>| {
+            let mux = get_mux()?;
+            let workspace = workspace.unwrap_or_else(|| mux.active_workspace());
+            let layout = Layout::capture_workspace(&mux, &workspace);
+            lua.to_value_with(
+                &layout,
+                mlua::SerializeOptions::new().serialize_none_to_null(false),
+            )
+        })?,
+    )?;
+
     Ok(())
 }
 
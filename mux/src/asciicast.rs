@@ -0,0 +1,643 @@
+//! Reading and writing asciicast files.
+//!
+//! Version 2 is described at
+//! <https://github.com/asciinema/asciinema/blob/develop/doc/asciicast-v2.md>
+//! and version 3 at <https://docs.asciinema.org/manual/asciicast/v3/>.
+//! The main differences are that v3 groups the terminal information
+//! in the header under `term`, and that v3 event times are relative to
+//! the previous event rather than to the start of the recording.
+use anyhow::Context;
+use chrono::serde::ts_seconds_option;
+use chrono::{DateTime, Utc};
+use config::keyassignment::ASCIICAST_MARKER_APC;
+use config::ConfigHandle;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, Lines, Write};
+use wezterm_term::color::ColorPalette;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CastVersion {
+    #[default]
+    V2,
+    V3,
+}
+
+impl std::str::FromStr for CastVersion {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "2" => Ok(Self::V2),
+            "3" => Ok(Self::V3),
+            _ => anyhow::bail!("unknown asciicast version {s}; expected 2 or 3"),
+        }
+    }
+}
+
+impl std::fmt::Display for CastVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::V2 => write!(f, "2"),
+            Self::V3 => write!(f, "3"),
+        }
+    }
+}
+
+/// The asciicast v2 header
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Header {
+    /// Must be 2 or higher
+    pub version: u32,
+    /// Initial terminal width (number of columns)
+    pub width: u32,
+    /// Initial terminal height (number of columns)
+    pub height: u32,
+    /// Unix timestamp of starting time of session
+    #[serde(
+        default,
+        with = "ts_seconds_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Duration of the whole recording in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f32>,
+    /// Used to reduce terminal inactivity (delays between frames)
+    /// to a maximum of this amount.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_time_limit: Option<f32>,
+    /// Command that was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Title of the asciicast
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Map of captured environment variables
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// Color theme of the recorded terminal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<Theme>,
+}
+
+impl Header {
+    /// Builds the header for a new recording of a terminal with the
+    /// given dimensions, capturing the environment and color scheme
+    pub fn new(config: &ConfigHandle, width: u32, height: u32, command: Option<String>) -> Self {
+        let mut env = HashMap::new();
+        env.insert("TERM".to_string(), config.term.to_string());
+        env.insert("ARB_VERSION".to_string(), config::arb_version().to_string());
+        env.insert(
+            "ARB_TARGET_TRIPLE".to_string(),
+            config::arb_target_triple().to_string(),
+        );
+        if let Ok(shell) = std::env::var("SHELL") {
+            env.insert("SHELL".to_string(), shell);
+        }
+        if let Ok(lang) = std::env::var("LANG") {
+            env.insert("LANG".to_string(), lang);
+        }
+
+        let palette: ColorPalette = config.resolved_palette.clone().into();
+
+        Header {
+            version: 2,
+            height,
+            width,
+            timestamp: Some(Utc::now()),
+            env,
+            command,
+            theme: Some(Theme::from_palette(&palette)),
+            ..Default::default()
+        }
+    }
+
+    /// Converts to the equivalent v3 header.  The TERM environment
+    /// variable and the theme move into the `term` section.
+    pub fn to_v3(&self) -> HeaderV3 {
+        let mut env = self.env.clone();
+        let term_type = env.remove("TERM");
+        HeaderV3 {
+            version: 3,
+            term: TermInfo {
+                cols: self.width,
+                rows: self.height,
+                term_type,
+                version: None,
+                theme: self.theme.clone(),
+            },
+            timestamp: self.timestamp,
+            idle_time_limit: self.idle_time_limit,
+            command: self.command.clone(),
+            title: self.title.clone(),
+            env,
+            tags: vec![],
+        }
+    }
+}
+
+/// The asciicast v3 header
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct HeaderV3 {
+    /// Must be 3
+    pub version: u32,
+    pub term: TermInfo,
+    /// Unix timestamp of starting time of session
+    #[serde(
+        default,
+        with = "ts_seconds_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Used to reduce terminal inactivity (delays between frames)
+    /// to a maximum of this amount.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_time_limit: Option<f32>,
+    /// Command that was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Title of the asciicast
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Map of captured environment variables
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// Describes the recorded terminal in a v3 header
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct TermInfo {
+    /// Initial terminal width (number of columns)
+    pub cols: u32,
+    /// Initial terminal height (number of rows)
+    pub rows: u32,
+    /// The value of TERM
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub term_type: Option<String>,
+    /// Version of the terminal emulator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Color theme of the recorded terminal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<Theme>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Theme {
+    /// Normal text color
+    pub fg: String,
+    /// Normal background color
+    pub bg: String,
+    /// List of 8 or 16 colors separated by a colon character
+    pub palette: String,
+}
+
+impl Theme {
+    pub fn from_palette(palette: &ColorPalette) -> Self {
+        let ansi_colors: Vec<String> = palette.colors.0[0..16]
+            .iter()
+            .map(|c| c.to_rgb_string())
+            .collect();
+
+        Self {
+            fg: palette.foreground.to_rgb_string(),
+            bg: palette.background.to_rgb_string(),
+            palette: ansi_colors.join(":"),
+        }
+    }
+}
+
+/// The header of either version
+#[derive(Debug, Clone)]
+pub enum CastHeader {
+    V2(Header),
+    V3(HeaderV3),
+}
+
+impl CastHeader {
+    pub fn parse(line: &str) -> anyhow::Result<Self> {
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+        let Version { version } = serde_json::from_str(line).context("parsing Header")?;
+        match version {
+            2 => Ok(Self::V2(
+                serde_json::from_str(line).context("parsing v2 Header")?,
+            )),
+            3 => Ok(Self::V3(
+                serde_json::from_str(line).context("parsing v3 Header")?,
+            )),
+            v => anyhow::bail!("asciicast version {v} is not supported"),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        match self {
+            Self::V2(header) => header.width,
+            Self::V3(header) => header.term.cols,
+        }
+    }
+
+    pub fn height(&self) -> u32 {
+        match self {
+            Self::V2(header) => header.height,
+            Self::V3(header) => header.term.rows,
+        }
+    }
+
+    pub fn idle_time_limit(&self) -> Option<f32> {
+        match self {
+            Self::V2(header) => header.idle_time_limit,
+            Self::V3(header) => header.idle_time_limit,
+        }
+    }
+}
+
+/// An event line, as it appears in the file.
+/// In v2 the time is relative to the start of the recording,
+/// in v3 it is relative to the previous event.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Event(pub f32, pub String, pub String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventKind {
+    /// Data written to the terminal
+    Output,
+    /// Data typed by the user
+    Input,
+    /// A marker, optionally with a label
+    Marker,
+    /// The terminal was resized; the data is `COLSxROWS`
+    Resize,
+    /// The recorded program exited; the data is its exit status.
+    /// v3 only.
+    Exit,
+    Other(String),
+}
+
+impl EventKind {
+    pub fn code(&self) -> &str {
+        match self {
+            Self::Output => "o",
+            Self::Input => "i",
+            Self::Marker => "m",
+            Self::Resize => "r",
+            Self::Exit => "x",
+            Self::Other(code) => code,
+        }
+    }
+
+    pub fn from_code(code: &str) -> Self {
+        match code {
+            "o" => Self::Output,
+            "i" => Self::Input,
+            "m" => Self::Marker,
+            "r" => Self::Resize,
+            "x" => Self::Exit,
+            code => Self::Other(code.to_string()),
+        }
+    }
+}
+
+/// An event with its time resolved to seconds since
+/// the start of the recording
+#[derive(Debug, Clone, PartialEq)]
+pub struct CastEvent {
+    pub time: f32,
+    pub kind: EventKind,
+    pub data: String,
+}
+
+/// Writes the header and events of either version, taking care of
+/// converting absolute event times to intervals for v3
+pub struct CastWriter<W: Write> {
+    out: W,
+    version: CastVersion,
+    last_time: f32,
+    /// Trailing bytes of output that are not yet a complete utf8 sequence
+    pending: Vec<u8>,
+}
+
+impl<W: Write> CastWriter<W> {
+    pub fn new(mut out: W, header: &Header, version: CastVersion) -> anyhow::Result<Self> {
+        let header = match version {
+            CastVersion::V2 => serde_json::to_string(&Header {
+                version: 2,
+                ..header.clone()
+            })?,
+            CastVersion::V3 => serde_json::to_string(&header.to_v3())?,
+        };
+        writeln!(out, "{}", header)?;
+        Ok(Self {
+            out,
+            version,
+            last_time: 0.,
+            pending: vec![],
+        })
+    }
+
+    /// Writes an event that happened `time` seconds after
+    /// the start of the recording
+    pub fn event(&mut self, time: f32, kind: EventKind, data: &str) -> anyhow::Result<()> {
+        let time = match self.version {
+            CastVersion::V2 => time,
+            CastVersion::V3 => {
+                let interval = (time - self.last_time).max(0.);
+                self.last_time = self.last_time.max(time);
+                interval
+            }
+        };
+        let event = Event(time, kind.code().to_string(), data.to_string());
+        writeln!(self.out, "{}", serde_json::to_string(&event)?)?;
+        Ok(())
+    }
+
+    pub fn output(&mut self, time: f32, data: &str) -> anyhow::Result<()> {
+        self.event(time, EventKind::Output, data)
+    }
+
+    /// Writes raw output from a pty.
+    /// The end of the data may be an incomplete utf8 sequence
+    /// that straddles the buffer boundary.  JSON requires strings
+    /// to be utf-8 so we need to send the currently-valid portions
+    /// through to the .cast file and buffer up the remainder
+    pub fn output_bytes(&mut self, time: f32, data: &[u8]) -> anyhow::Result<()> {
+        self.pending.extend_from_slice(data);
+        loop {
+            match std::str::from_utf8(&self.pending) {
+                Ok(valid) => {
+                    if !valid.is_empty() {
+                        let valid = valid.to_string();
+                        self.output(time, &valid)?;
+                    }
+                    self.pending.clear();
+                    return Ok(());
+                }
+                Err(error) => {
+                    let valid_len = error.valid_up_to();
+                    if valid_len > 0 {
+                        let valid =
+                            String::from_utf8_lossy(&self.pending[0..valid_len]).to_string();
+                        self.output(time, &valid)?;
+                        self.pending.drain(0..valid_len);
+                    }
+
+                    match error.error_len() {
+                        // Invalid sequence: skip it and try the remainder
+                        Some(invalid_sequence_length) => {
+                            self.pending.drain(0..invalid_sequence_length);
+                        }
+                        // Incomplete sequence: wait for more data
+                        None => return Ok(()),
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn marker(&mut self, time: f32, label: &str) -> anyhow::Result<()> {
+        self.event(time, EventKind::Marker, label)
+    }
+
+    pub fn resize(&mut self, time: f32, cols: u32, rows: u32) -> anyhow::Result<()> {
+        self.event(time, EventKind::Resize, &format!("{cols}x{rows}"))
+    }
+
+    /// Records the exit status of the program.
+    /// v2 has no representation for this, so it is omitted there.
+    pub fn exit(&mut self, time: f32, status: u32) -> anyhow::Result<()> {
+        match self.version {
+            CastVersion::V2 => Ok(()),
+            CastVersion::V3 => self.event(time, EventKind::Exit, &status.to_string()),
+        }
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads the events of a v2 or v3 file, resolving their times
+/// relative to the start of the recording
+pub struct CastReader<R: BufRead> {
+    header: CastHeader,
+    lines: Lines<R>,
+    time: f32,
+}
+
+impl<R: BufRead> CastReader<R> {
+    pub fn new(mut reader: R) -> anyhow::Result<Self> {
+        let mut header_line = String::new();
+        reader
+            .read_line(&mut header_line)
+            .context("reading Header line")?;
+        let header = CastHeader::parse(&header_line)?;
+        Ok(Self {
+            header,
+            lines: reader.lines(),
+            time: 0.,
+        })
+    }
+
+    pub fn header(&self) -> &CastHeader {
+        &self.header
+    }
+}
+
+impl<R: BufRead> Iterator for CastReader<R> {
+    type Item = anyhow::Result<CastEvent>;
+
+    fn next(&mut self) -> Option<anyhow::Result<CastEvent>> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err.into())),
+            };
+            let line = line.trim();
+            // v3 allows comment lines
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Event(time, code, data) = match serde_json::from_str(line) {
+                Ok(event) => event,
+                Err(err) => return Some(Err(anyhow::anyhow!("parsing event {line}: {err:#}"))),
+            };
+            self.time = match self.header {
+                CastHeader::V2(_) => time,
+                CastHeader::V3(_) => self.time + time,
+            };
+            return Some(Ok(CastEvent {
+                time: self.time,
+                kind: EventKind::from_code(&code),
+                data,
+            }));
+        }
+    }
+}
+
+/// Removes the marker requests sent by the `AddAsciicastMarker` key
+/// assignment from `input`, returning the remaining input and the
+/// labels of the markers that were found.
+pub fn extract_markers(input: &[u8]) -> (Vec<u8>, Vec<String>) {
+    let start = format!("\x1b_{ASCIICAST_MARKER_APC}");
+    let start = start.as_bytes();
+    let end = b"\x1b\\";
+
+    let mut remaining = Vec::with_capacity(input.len());
+    let mut labels = vec![];
+    let mut pos = 0;
+    while pos < input.len() {
+        let rest = &input[pos..];
+        if rest.starts_with(start) {
+            if let Some(len) = rest[start.len()..]
+                .windows(end.len())
+                .position(|w| w == end)
+            {
+                let body = &rest[start.len()..start.len() + len];
+                let label = body.strip_prefix(b";").unwrap_or(body);
+                labels.push(String::from_utf8_lossy(label).to_string());
+                pos += start.len() + len + end.len();
+                continue;
+            }
+        }
+        remaining.push(input[pos]);
+        pos += 1;
+    }
+    (remaining, labels)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use config::keyassignment::asciicast_marker_sequence;
+
+    fn header() -> Header {
+        let mut env = HashMap::new();
+        env.insert("TERM".to_string(), "xterm-256color".to_string());
+        env.insert("SHELL".to_string(), "/bin/zsh".to_string());
+        Header {
+            version: 2,
+            width: 80,
+            height: 24,
+            env,
+            ..Default::default()
+        }
+    }
+
+    fn write_events(version: CastVersion) -> String {
+        let mut out = vec![];
+        {
+            let mut writer = CastWriter::new(&mut out, &header(), version).unwrap();
+            writer.output(0.5, "hello").unwrap();
+            writer.marker(1.25, "build").unwrap();
+            writer.resize(2.0, 100, 30).unwrap();
+            writer.exit(3.0, 1).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    fn read_events(cast: &str) -> (CastHeader, Vec<CastEvent>) {
+        let reader = CastReader::new(cast.as_bytes()).unwrap();
+        let header = reader.header().clone();
+        (header, reader.map(|e| e.unwrap()).collect())
+    }
+
+    #[test]
+    fn should_write_v3_with_relative_times() {
+        let cast = write_events(CastVersion::V3);
+        let lines: Vec<&str> = cast.lines().collect();
+        let header: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(header["version"], 3);
+        assert_eq!(header["term"]["cols"], 80);
+        assert_eq!(header["term"]["type"], "xterm-256color");
+        assert!(header["env"].get("TERM").is_none());
+        assert_eq!(header["env"]["SHELL"], "/bin/zsh");
+        assert_eq!(lines[1], r#"[0.5,"o","hello"]"#);
+        assert_eq!(lines[2], r#"[0.75,"m","build"]"#);
+        assert_eq!(lines[3], r#"[0.75,"r","100x30"]"#);
+        assert_eq!(lines[4], r#"[1.0,"x","1"]"#);
+    }
+
+    #[test]
+    fn should_read_both_versions_with_absolute_times() {
+        for version in [CastVersion::V2, CastVersion::V3] {
+            let (header, events) = read_events(&write_events(version));
+            match (&header, version) {
+                (CastHeader::V2(_), CastVersion::V2) | (CastHeader::V3(_), CastVersion::V3) => {}
+                _ => panic!("read {header:?} but wrote {version:?}"),
+            }
+            assert_eq!(header.width(), 80);
+            assert_eq!(header.height(), 24);
+            let times: Vec<f32> = events.iter().map(|e| e.time).collect();
+            let kinds: Vec<EventKind> = events.iter().map(|e| e.kind.clone()).collect();
+            match version {
+                CastVersion::V2 => {
+                    // v2 has no exit event
+                    assert_eq!(times, vec![0.5, 1.25, 2.0]);
+                    assert_eq!(
+                        kinds,
+                        vec![EventKind::Output, EventKind::Marker, EventKind::Resize]
+                    );
+                }
+                CastVersion::V3 => {
+                    assert_eq!(times, vec![0.5, 1.25, 2.0, 3.0]);
+                    assert_eq!(events[3].kind, EventKind::Exit);
+                    assert_eq!(events[3].data, "1");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn should_skip_v3_comments() {
+        let cast = "{\"version\": 3, \"term\": {\"cols\": 10, \"rows\": 5}}\n\
+                    # a comment\n\
+                    [0.1, \"o\", \"a\"]\n\
+                    \n\
+                    [0.2, \"o\", \"b\"]\n";
+        let (_, events) = read_events(cast);
+        assert_eq!(events.len(), 2);
+        assert!((events[1].time - 0.3).abs() < 0.0001);
+    }
+
+    #[test]
+    fn should_reject_unknown_version() {
+        assert!(CastHeader::parse(r#"{"version": 1}"#).is_err());
+        assert!(CastHeader::parse("not json").is_err());
+    }
+
+    #[test]
+    fn should_buffer_incomplete_utf8_output() {
+        let mut out = vec![];
+        {
+            let mut writer = CastWriter::new(&mut out, &header(), CastVersion::V2).unwrap();
+            let data = "héllo".as_bytes();
+            writer.output_bytes(0.1, &data[0..2]).unwrap();
+            writer.output_bytes(0.2, &data[2..]).unwrap();
+            writer.output_bytes(0.3, b"\xffok").unwrap();
+        }
+        let (_, events) = read_events(std::str::from_utf8(&out).unwrap());
+        let data: Vec<&str> = events.iter().map(|e| e.data.as_str()).collect();
+        assert_eq!(data, vec!["h", "éllo", "ok"]);
+    }
+
+    #[test]
+    fn should_extract_markers_from_input() {
+        let mut input = b"ls".to_vec();
+        input.extend_from_slice(asciicast_marker_sequence("step 1").as_bytes());
+        input.extend_from_slice(b"\r");
+        input.extend_from_slice(asciicast_marker_sequence("").as_bytes());
+
+        let (remaining, labels) = extract_markers(&input);
+        assert_eq!(remaining, b"ls\r");
+        assert_eq!(labels, vec!["step 1".to_string(), String::new()]);
+
+        let (remaining, labels) = extract_markers(b"\x1b_other\x1b\\x");
+        assert_eq!(remaining, b"\x1b_other\x1b\\x");
+        assert!(labels.is_empty());
+    }
+}
@@ -0,0 +1,456 @@
+//! Declarative layouts.
+//!
+//! A layout describes a workspace as a set of named tabs, each holding
+//! a tree of split panes along with the working directory, environment,
+//! domain and command of every pane, and which pane has the focus.
+//! Applying a layout creates all of that in one step, rather than with
+//! a chain of `spawn` and `split-pane` commands.
+//!
+//! Layouts are written as TOML, and `capture` produces the same format
+//! from the live tabs, so that a layout can be dumped, edited and
+//! applied again:
+//!
+//! ```toml
+//! workspace = "api"
+//!
+//! [[tab]]
+//! title = "server"
+//!
+//! [tab.root.split]
+//! direction = "Horizontal"
+//! size = { Percent = 30 }
+//!
+//! [tab.root.split.first.pane]
+//! cwd = "~/src/api"
+//! command = ["cargo", "watch", "-x", "run"]
+//! env = { RUST_LOG = "debug" }
+//!
+//! [tab.root.split.second.pane]
+//! cwd = "~/src/api"
+//! focus = true
+//! ```
+use crate::domain::SplitSource;
+use crate::pane::{Pane, PaneId};
+use crate::session::{foreground_command, url_to_path};
+use crate::split_tree::{self, split_size, SplitNode, SplitTree};
+use crate::tab::{
+    PaneEntry, PaneNode, SplitDirection, SplitDirectionAndSize, SplitRequest, SplitSize, Tab,
+};
+use crate::window::WindowId;
+use crate::Mux;
+use anyhow::Context;
+use config::keyassignment::SpawnTabDomain;
+use portable_pty::CommandBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use wezterm_term::TerminalSize;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Layout {
+    /// The workspace in which the tabs are created.
+    /// When omitted, the caller decides.
+    #[serde(default)]
+    pub workspace: Option<String>,
+    #[serde(default, rename = "tab")]
+    pub tabs: Vec<TabLayout>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TabLayout {
+    #[serde(default)]
+    pub title: Option<String>,
+    pub root: LayoutNode,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutNode {
+    Split(SplitLayout),
+    Pane(PaneLayout),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SplitLayout {
+    /// Horizontal places `first` to the left of `second`,
+    /// Vertical places `first` above `second`
+    pub direction: SplitDirection,
+    /// The size of `second`
+    #[serde(default)]
+    pub size: SplitSize,
+    pub first: Box<LayoutNode>,
+    pub second: Box<LayoutNode>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PaneLayout {
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Additional environment variables for the command
+    #[serde(default)]
+    pub env: Option<BTreeMap<String, String>>,
+    /// The name of the domain in which to spawn the pane.
+    /// When omitted, the first pane of a tab is spawned in the
+    /// default domain and the others in the domain of the pane
+    /// that they were split from.
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// The argv to run in place of the default program
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+    /// Whether the pane is the active pane of its tab.
+    /// The first focused pane also selects the active tab.
+    #[serde(default)]
+    pub focus: bool,
+}
+
+impl SplitTree for LayoutNode {
+    type Pane = PaneLayout;
+
+    fn node(&self) -> SplitNode<'_, Self> {
+        match self {
+            Self::Split(split) => SplitNode::Split {
+                direction: split.direction,
+                size: split.size,
+                first: &split.first,
+                second: &split.second,
+            },
+            Self::Pane(pane) => SplitNode::Pane(pane),
+        }
+    }
+}
+
+impl LayoutNode {
+    fn panes_mut(&mut self) -> Vec<&mut PaneLayout> {
+        match self {
+            Self::Split(split) => {
+                let mut panes = split.first.panes_mut();
+                panes.append(&mut split.second.panes_mut());
+                panes
+            }
+            Self::Pane(pane) => vec![pane],
+        }
+    }
+
+    fn from_pane_node<F>(node: PaneNode, command: &F) -> Option<Self>
+    where
+        F: Fn(PaneId) -> Option<Vec<String>>,
+    {
+        split_tree::from_pane_node(
+            node,
+            &|node: SplitDirectionAndSize, first, second| {
+                Self::Split(SplitLayout {
+                    direction: node.direction,
+                    size: split_size(node.direction, &node.first, &node.second),
+                    first: Box::new(first),
+                    second: Box::new(second),
+                })
+            },
+            &|entry: PaneEntry| {
+                Self::Pane(PaneLayout {
+                    cwd: entry.working_dir.and_then(|url| url_to_path(&url.url)),
+                    env: None,
+                    domain: entry.domain_name,
+                    command: command(entry.pane_id),
+                    focus: entry.is_active_pane,
+                })
+            },
+        )
+    }
+}
+
+impl PaneLayout {
+    fn spawn_domain(&self) -> SpawnTabDomain {
+        match &self.domain {
+            Some(name) => SpawnTabDomain::DomainName(name.clone()),
+            None => SpawnTabDomain::CurrentPaneDomain,
+        }
+    }
+
+    fn spawn_command(&self) -> Option<CommandBuilder> {
+        if self.command.is_none() && self.env.is_none() {
+            return None;
+        }
+        let mut builder = match &self.command {
+            Some(argv) => CommandBuilder::from_argv(argv.iter().map(Into::into).collect()),
+            None => CommandBuilder::new_default_prog(),
+        };
+        for (k, v) in self.env.iter().flatten() {
+            builder.env(k, v);
+        }
+        if let Some(cwd) = &self.cwd {
+            builder.cwd(cwd);
+        }
+        Some(builder)
+    }
+
+    /// Spawns this pane by splitting the pane `pane_id`
+    async fn spawn_split(
+        &self,
+        mux: &Mux,
+        pane_id: PaneId,
+        request: SplitRequest,
+    ) -> anyhow::Result<Arc<dyn Pane>> {
+        let (pane, _size) = mux
+            .split_pane(
+                pane_id,
+                request,
+                SplitSource::Spawn {
+                    command: self.spawn_command(),
+                    command_dir: self.cwd.clone(),
+                },
+                self.spawn_domain(),
+            )
+            .await?;
+        Ok(pane)
+    }
+}
+
+impl TabLayout {
+    /// Captures the split tree of `tab`
+    pub fn capture(mux: &Mux, tab: &Arc<Tab>) -> Option<Self> {
+        let command = |pane_id: PaneId| mux.get_pane(pane_id).as_ref().and_then(foreground_command);
+        let root = LayoutNode::from_pane_node(tab.codec_pane_tree_with_details(), &command)?;
+        let title = tab.get_title();
+        Some(Self {
+            title: if title.is_empty() { None } else { Some(title) },
+            root,
+        })
+    }
+}
+
+impl Layout {
+    /// Captures the tabs of all of the windows in `workspace`
+    pub fn capture_workspace(mux: &Mux, workspace: &str) -> Self {
+        let tabs: Vec<Arc<Tab>> = mux
+            .iter_windows_in_workspace(workspace)
+            .into_iter()
+            .filter_map(|window_id| {
+                let window = mux.get_window(window_id)?;
+                Some(window.iter().cloned().collect::<Vec<_>>())
+            })
+            .flatten()
+            .collect();
+        Self {
+            workspace: Some(workspace.to_string()),
+            tabs: tabs
+                .iter()
+                .filter_map(|tab| TabLayout::capture(mux, tab))
+                .collect(),
+        }
+    }
+
+    pub fn from_toml(toml: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// Loads a layout from a TOML file.  Working directories that
+    /// begin with `~` are relative to the home directory, and other
+    /// relative working directories are relative to the file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let toml = std::fs::read_to_string(path)
+            .with_context(|| format!("reading layout {}", path.display()))?;
+        let mut layout =
+            Self::from_toml(&toml).with_context(|| format!("parsing layout {}", path.display()))?;
+        let base = path
+            .canonicalize()
+            .ok()
+            .and_then(|path| path.parent().map(Path::to_path_buf))
+            .unwrap_or_default();
+        layout.resolve_paths(&base);
+        Ok(layout)
+    }
+
+    /// Expands working directories that begin with `~` and makes
+    /// other relative working directories relative to `base`
+    pub fn resolve_paths(&mut self, base: &Path) {
+        for pane in self.tabs.iter_mut().flat_map(|tab| tab.root.panes_mut()) {
+            if let Some(cwd) = &pane.cwd {
+                let resolved = if cwd == "~" {
+                    config::HOME_DIR.clone()
+                } else if let Some(rest) = cwd.strip_prefix("~/") {
+                    config::HOME_DIR.join(rest)
+                } else {
+                    base.join(cwd)
+                };
+                pane.cwd = Some(resolved.to_string_lossy().to_string());
+            }
+        }
+    }
+
+    /// Spawns a window holding the tabs described by the layout.
+    /// The window is placed in the workspace named by the layout,
+    /// or `default_workspace` if the layout doesn't name one.
+    pub async fn apply(
+        &self,
+        default_workspace: &str,
+        size: TerminalSize,
+    ) -> anyhow::Result<WindowId> {
+        if self.tabs.is_empty() {
+            anyhow::bail!("the layout has no tabs");
+        }
+        let mux = Mux::get();
+        let workspace = self
+            .workspace
+            .clone()
+            .unwrap_or_else(|| default_workspace.to_string());
+
+        let mut window_id = None;
+        let mut active_tab = None;
+
+        for (tab_idx, tab_layout) in self.tabs.iter().enumerate() {
+            let first = tab_layout.root.first_pane();
+            let (tab, pane, new_window_id) = mux
+                .spawn_tab_or_window(
+                    window_id,
+                    first.spawn_domain(),
+                    first.spawn_command(),
+                    first.cwd.clone(),
+                    size,
+                    None,
+                    workspace.clone(),
+                    None,
+                )
+                .await?;
+            window_id = Some(new_window_id);
+
+            if let Some(title) = &tab_layout.title {
+                tab.set_title(title);
+            }
+            let spawned = split_tree::replay_splits(
+                &tab_layout.root,
+                pane,
+                |pane_id, request, pane_layout| pane_layout.spawn_split(&mux, pane_id, request),
+            )
+            .await?;
+            if let Some((_, pane)) = spawned.iter().find(|(pane_layout, _)| pane_layout.focus) {
+                tab.set_active_pane(pane);
+                active_tab.get_or_insert(tab_idx);
+            }
+        }
+
+        let window_id = window_id.expect("at least one tab was spawned");
+        if let Some(active_tab) = active_tab {
+            if let Some(mut window) = mux.get_window_mut(window_id) {
+                if active_tab < window.len() {
+                    window.set_active_without_saving(active_tab);
+                }
+            }
+        }
+        Ok(window_id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::session::test::{leaf, size};
+
+    #[test]
+    fn should_capture_pane_tree() {
+        let tree = PaneNode::Split {
+            left: Box::new(leaf(1, "file://host/home/me/src", false)),
+            right: Box::new(leaf(2, "file://host/home/me/notes", true)),
+            node: SplitDirectionAndSize {
+                direction: SplitDirection::Horizontal,
+                first: size(24, 60),
+                second: size(24, 19),
+            },
+        };
+        let command = |pane_id: PaneId| {
+            if pane_id == 2 {
+                Some(vec!["vim".to_string()])
+            } else {
+                None
+            }
+        };
+        let node = LayoutNode::from_pane_node(tree, &command).unwrap();
+        match &node {
+            LayoutNode::Split(split) => {
+                assert_eq!(split.direction, SplitDirection::Horizontal);
+                assert_eq!(split.size, SplitSize::Percent(24));
+            }
+            LayoutNode::Pane(_) => panic!("expected a split"),
+        }
+        let panes = node.panes();
+        assert_eq!(panes[0].cwd.as_deref(), Some("/home/me/src"));
+        assert_eq!(panes[0].domain.as_deref(), Some("local"));
+        assert!(!panes[0].focus);
+        assert_eq!(panes[1].command, Some(vec!["vim".to_string()]));
+        assert!(panes[1].focus);
+    }
+
+    #[test]
+    fn should_round_trip_toml() {
+        let layout = Layout::from_toml(
+            r#"
+workspace = "api"
+
+[[tab]]
+title = "server"
+
+[tab.root.split]
+direction = "Vertical"
+size = { Cells = 10 }
+
+[tab.root.split.first.pane]
+cwd = "/src/api"
+command = ["cargo", "run"]
+env = { RUST_LOG = "debug" }
+
+[tab.root.split.second.pane]
+focus = true
+
+[[tab]]
+[tab.root.pane]
+cwd = "logs"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(layout.workspace.as_deref(), Some("api"));
+        assert_eq!(layout.tabs.len(), 2);
+        assert_eq!(layout.tabs[1].title, None);
+        let first = layout.tabs[0].root.first_pane();
+        assert_eq!(
+            first.command,
+            Some(vec!["cargo".to_string(), "run".to_string()])
+        );
+        assert_eq!(
+            first.env.as_ref().and_then(|env| env.get("RUST_LOG")),
+            Some(&"debug".to_string())
+        );
+        match &layout.tabs[0].root {
+            LayoutNode::Split(split) => assert_eq!(split.size, SplitSize::Cells(10)),
+            LayoutNode::Pane(_) => panic!("expected a split"),
+        }
+        assert!(layout.tabs[0].root.panes()[1].focus);
+
+        let reparsed = Layout::from_toml(&layout.to_toml().unwrap()).unwrap();
+        assert_eq!(reparsed, layout);
+    }
+
+    #[test]
+    fn should_resolve_relative_cwd() {
+        let mut layout = Layout {
+            workspace: None,
+            tabs: vec![TabLayout {
+                title: None,
+                root: LayoutNode::Pane(PaneLayout {
+                    cwd: Some("logs".to_string()),
+                    ..Default::default()
+                }),
+            }],
+        };
+        layout.resolve_paths(Path::new("/src/api"));
+        assert_eq!(
+            layout.tabs[0].root.first_pane().cwd.as_deref(),
+            Some(Path::new("/src/api/logs").to_string_lossy().as_ref())
+        );
+    }
+}
@@ -52,15 +52,21 @@ use wezterm_term::{Clipboard, ClipboardSelection, DownloadHandler, TerminalSize}
 use winapi::um::winsock2::{SOL_SOCKET, SO_RCVBUF, SO_SNDBUF};
 
 pub mod activity;
+pub mod asciicast;
 pub mod client;
 pub mod connui;
 pub mod domain;
+pub mod layout;
 pub mod localpane;
 pub mod pane;
+pub mod recording;
 pub mod renderable;
+pub mod session;
+pub mod split_tree;
 pub mod ssh;
 pub mod ssh_agent;
 pub mod tab;
+pub mod tail;
 pub mod termwiztermtab;
 pub mod tmux;
 pub mod tmux_commands;
@@ -91,6 +97,11 @@ pub enum MuxNotification {
         selection: ClipboardSelection,
         clipboard: Option<String>,
     },
+    /// An application asked to read the clipboard using OSC 52
+    QueryClipboard {
+        pane_id: PaneId,
+        selection: ClipboardSelection,
+    },
     SaveToDownloads {
         name: Option<String>,
         data: Arc<Vec<u8>>,
@@ -126,6 +137,7 @@ pub struct Mux {
     domains_by_name: RwLock<HashMap<String, Arc<dyn Domain>>>,
     subscribers: RwLock<HashMap<usize, Box<dyn Fn(MuxNotification) -> bool + Send + Sync>>>,
     banner: RwLock<Option<String>>,
+    next_pane_preamble: Mutex<Option<Vec<u8>>>,
     clients: RwLock<HashMap<ClientId, ClientInfo>>,
     identity: RwLock<Option<Arc<ClientId>>>,
     num_panes_by_workspace: RwLock<HashMap<String, usize>>,
@@ -137,11 +149,16 @@ const BUFSIZE: usize = 1024 * 1024;
 
 /// This function applies parsed actions to the pane and notifies any
 /// mux subscribers about the output event
-fn send_actions_to_mux(pane: &Weak<dyn Pane>, dead: &Arc<AtomicBool>, actions: Vec<Action>) {
+fn send_actions_to_mux(
+    pane: &Weak<dyn Pane>,
+    dead: &Arc<AtomicBool>,
+    actions: Vec<Action>,
+    data: &[u8],
+) {
     let start = Instant::now();
     match pane.upgrade() {
         Some(pane) => {
-            pane.perform_actions(actions);
+            recording::apply_output(&pane, data, || pane.perform_actions(actions));
             histogram!("send_actions_to_mux.perform_actions.latency").record(start.elapsed());
             Mux::notify_from_any_thread(MuxNotification::PaneOutput(pane.pane_id()));
         }
@@ -155,14 +172,116 @@ fn send_actions_to_mux(pane: &Weak<dyn Pane>, dead: &Arc<AtomicBool>, actions: V
     histogram!("send_actions_to_mux.rate").record(1.);
 }
 
+/// Accumulates the actions parsed from the output of a pane, along with
+/// the bytes that produced them, so that each batch of actions that is
+/// applied to the pane can be recorded together with exactly its output.
+#[derive(Default)]
+struct OutputBatcher {
+    parser: termwiz::escape::parser::Parser,
+    actions: Vec<Action>,
+    /// Output that has yet to be applied to the pane
+    data: Vec<u8>,
+    /// How many bytes of `data` produced `actions`; the remainder
+    /// is the start of a sequence that has yet to be completed
+    covered: usize,
+    /// Set while synchronized output is in effect
+    hold: bool,
+}
+
+impl OutputBatcher {
+    /// Parses `bytes`, passing to `apply` any batches that have to be applied
+    /// immediately because synchronized output began or ended.
+    /// Returns true if `apply` was called.
+    fn parse<F: FnMut(Vec<Action>, &[u8])>(&mut self, bytes: &[u8], mut apply: F) -> bool {
+        let Self {
+            parser,
+            actions,
+            data,
+            covered,
+            hold,
+        } = self;
+        let mut applied = false;
+        data.extend_from_slice(bytes);
+
+        parser.parse_with_offsets(bytes, |action, offset| {
+            let mut flush = false;
+            match &action {
+                Action::CSI(CSI::Mode(Mode::SetDecPrivateMode(DecPrivateMode::Code(
+                    DecPrivateModeCode::SynchronizedOutput,
+                )))) => {
+                    *hold = true;
+
+                    // Flush prior actions
+                    applied |= Self::apply_batch(actions, data, covered, &mut apply);
+                }
+                Action::CSI(CSI::Mode(Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                    DecPrivateModeCode::SynchronizedOutput,
+                )))) => {
+                    *hold = false;
+                    flush = true;
+                }
+                Action::CSI(CSI::Device(dev)) if matches!(**dev, Device::SoftReset) => {
+                    *hold = false;
+                    flush = true;
+                }
+                _ => {}
+            };
+            action.append_to(actions);
+            // `data` may have been drained by an earlier flush, but the
+            // bytes that follow this action are still at its end
+            *covered = data.len() - (bytes.len() - offset);
+
+            if flush {
+                applied |= Self::apply_batch(actions, data, covered, &mut apply);
+            }
+        });
+
+        applied
+    }
+
+    /// Returns true if there are actions waiting to be applied
+    fn has_actions(&self) -> bool {
+        !self.actions.is_empty()
+    }
+
+    /// Returns true while synchronized output is in effect
+    fn is_held(&self) -> bool {
+        self.hold
+    }
+
+    /// Passes the accumulated actions and their output to `apply`
+    fn flush<F: FnMut(Vec<Action>, &[u8])>(&mut self, mut apply: F) {
+        Self::apply_batch(
+            &mut self.actions,
+            &mut self.data,
+            &mut self.covered,
+            &mut apply,
+        );
+    }
+
+    fn apply_batch<F: FnMut(Vec<Action>, &[u8])>(
+        actions: &mut Vec<Action>,
+        data: &mut Vec<u8>,
+        covered: &mut usize,
+        apply: &mut F,
+    ) -> bool {
+        if actions.is_empty() {
+            return false;
+        }
+        apply(std::mem::take(actions), &data[..*covered]);
+        data.drain(..*covered);
+        *covered = 0;
+        true
+    }
+}
 fn parse_buffered_data(pane: Weak<dyn Pane>, dead: &Arc<AtomicBool>, mut rx: FileDescriptor) {
     let mut buf = vec![0; configuration().mux_output_parser_buffer_size];
-    let mut parser = termwiz::escape::parser::Parser::new();
-    let mut actions = vec![];
-    let mut hold = false;
+    let mut batcher = OutputBatcher::default();
     let mut action_size = 0;
     let mut delay = Duration::from_millis(configuration().mux_output_parser_coalesce_delay_ms);
     let mut deadline = None;
+    let mut send =
+        |actions: Vec<Action>, data: &[u8]| send_actions_to_mux(&pane, dead, actions, data);
 
     loop {
         match rx.read(&mut buf) {
@@ -175,41 +294,11 @@ fn parse_buffered_data(pane: Weak<dyn Pane>, dead: &Arc<AtomicBool>, mut rx: Fil
                 break;
             }
             Ok(size) => {
-                parser.parse(&buf[0..size], |action| {
-                    let mut flush = false;
-                    match &action {
-                        Action::CSI(CSI::Mode(Mode::SetDecPrivateMode(DecPrivateMode::Code(
-                            DecPrivateModeCode::SynchronizedOutput,
-                        )))) => {
-                            hold = true;
-
-                            // Flush prior actions
-                            if !actions.is_empty() {
-                                send_actions_to_mux(&pane, dead, std::mem::take(&mut actions));
-                                action_size = 0;
-                            }
-                        }
-                        Action::CSI(CSI::Mode(Mode::ResetDecPrivateMode(
-                            DecPrivateMode::Code(DecPrivateModeCode::SynchronizedOutput),
-                        ))) => {
-                            hold = false;
-                            flush = true;
-                        }
-                        Action::CSI(CSI::Device(dev)) if matches!(**dev, Device::SoftReset) => {
-                            hold = false;
-                            flush = true;
-                        }
-                        _ => {}
-                    };
-                    action.append_to(&mut actions);
-
-                    if flush && !actions.is_empty() {
-                        send_actions_to_mux(&pane, dead, std::mem::take(&mut actions));
-                        action_size = 0;
-                    }
-                });
+                if batcher.parse(&buf[0..size], &mut send) {
+                    action_size = 0;
+                }
                 action_size += size;
-                if !actions.is_empty() && !hold {
+                if batcher.has_actions() && !batcher.is_held() {
                     // If we haven't accumulated too much data,
                     // pause for a short while to increase the chances
                     // that we coalesce a full "frame" from an unoptimized
@@ -239,7 +328,7 @@ fn parse_buffered_data(pane: Weak<dyn Pane>, dead: &Arc<AtomicBool>, mut rx: Fil
                         }
                     }
 
-                    send_actions_to_mux(&pane, dead, std::mem::take(&mut actions));
+                    batcher.flush(&mut send);
                     deadline = None;
                     action_size = 0;
                 }
@@ -255,9 +344,7 @@ fn parse_buffered_data(pane: Weak<dyn Pane>, dead: &Arc<AtomicBool>, mut rx: Fil
     // to be displayed before we return from here; this is important
     // for very short lived commands so that we don't forget to
     // display what they displayed.
-    if !actions.is_empty() {
-        send_actions_to_mux(&pane, dead, std::mem::take(&mut actions));
-    }
+    batcher.flush(&mut send);
 }
 
 fn set_socket_buffer(fd: &mut FileDescriptor, option: i32, size: usize) -> anyhow::Result<()> {
@@ -297,6 +384,7 @@ fn allocate_socketpair() -> anyhow::Result<(FileDescriptor, FileDescriptor)> {
 fn read_from_pane_pty(
     pane: Weak<dyn Pane>,
     banner: Option<String>,
+    preamble: Option<Vec<u8>>,
     mut reader: Box<dyn std::io::Read>,
 ) {
     let mut buf = vec![0; BUFSIZE];
@@ -327,9 +415,16 @@ fn read_from_pane_pty(
 
     std::thread::spawn({
         let dead = Arc::clone(&dead);
-        move || parse_buffered_data(pane, &dead, rx)
+        move || {
+            parse_buffered_data(pane, &dead, rx);
+            recording::pane_output_finished(pane_id);
+            tail::pane_output_finished(pane_id);
+        }
     });
 
+    if let Some(preamble) = preamble {
+        tx.write_all(&preamble).ok();
+    }
     if let Some(banner) = banner {
         tx.write_all(banner.as_bytes()).ok();
     }
@@ -461,6 +556,7 @@ impl Mux {
             domains: RwLock::new(domains),
             subscribers: RwLock::new(HashMap::new()),
             banner: RwLock::new(None),
+            next_pane_preamble: Mutex::new(None),
             clients: RwLock::new(HashMap::new()),
             identity: RwLock::new(None),
             num_panes_by_workspace: RwLock::new(HashMap::new()),
@@ -805,8 +901,9 @@ impl Mux {
         let pane_id = pane.pane_id();
         if let Some(reader) = pane.reader()? {
             let banner = self.banner.read().clone();
+            let preamble = self.next_pane_preamble.lock().take();
             let pane = Arc::downgrade(pane);
-            thread::spawn(move || read_from_pane_pty(pane, banner, reader));
+            thread::spawn(move || read_from_pane_pty(pane, banner, preamble, reader));
         }
         self.recompute_pane_count();
         self.notify(MuxNotification::PaneAdded(pane_id));
@@ -1127,6 +1224,19 @@ impl Mux {
         *self.banner.write() = banner;
     }
 
+    /// Sets output for the next pane with a pty that is added to the
+    /// mux to show ahead of anything read from its pty.  This is how
+    /// saved scrollback is restored without racing the first prompt
+    /// of the newly spawned shell.
+    pub fn set_next_pane_preamble(&self, preamble: Option<Vec<u8>>) {
+        *self.next_pane_preamble.lock() = preamble;
+    }
+
+    /// Returns the preamble if no pane has consumed it
+    pub fn take_next_pane_preamble(&self) -> Option<Vec<u8>> {
+        self.next_pane_preamble.lock().take()
+    }
+
     pub fn resolve_spawn_tab_domain(
         &self,
         // TODO: disambiguate with TabId
@@ -1319,6 +1429,58 @@ impl Mux {
         Ok((tab, window_id))
     }
 
+    /// Moves a tab out of its window and into `window_id`, or into a new
+    /// window in `workspace_for_new_window` (defaulting to the workspace of
+    /// its current window) if no window is specified.
+    /// Returns the window that now contains the tab.
+    pub fn move_tab_to_window(
+        &self,
+        tab_id: TabId,
+        window_id: Option<WindowId>,
+        workspace_for_new_window: Option<String>,
+    ) -> anyhow::Result<WindowId> {
+        let tab = self
+            .get_tab(tab_id)
+            .ok_or_else(|| anyhow!("tab {tab_id} not found"))?;
+        let src_window_id = self
+            .window_containing_tab(tab_id)
+            .ok_or_else(|| anyhow!("tab {tab_id} isn't in a window"))?;
+        if window_id == Some(src_window_id) {
+            return Ok(src_window_id);
+        }
+
+        let window_builder;
+        let window_id = match window_id {
+            Some(window_id) => {
+                let window = self
+                    .get_window(window_id)
+                    .ok_or_else(|| anyhow!("window_id {} not found on this server", window_id))?;
+                if let Some(active) = window.get_active() {
+                    tab.resize(active.get_size());
+                }
+                window_id
+            }
+            None => {
+                let workspace = workspace_for_new_window.or_else(|| {
+                    self.get_window(src_window_id)
+                        .map(|w| w.get_workspace().to_string())
+                });
+                window_builder = self.new_empty_window(workspace, None);
+                *window_builder
+            }
+        };
+
+        if let Some(mut src_window) = self.get_window_mut(src_window_id) {
+            if let Some(idx) = src_window.idx_by_id(tab_id) {
+                src_window.remove_by_idx(idx);
+            }
+        }
+        self.add_tab_to_window(&tab, window_id)?;
+        self.prune_dead_windows();
+
+        Ok(window_id)
+    }
+
     pub async fn spawn_tab_or_window(
         &self,
         window_id: Option<WindowId>,
@@ -1468,6 +1630,16 @@ impl Clipboard for MuxClipboard {
         });
         Ok(())
     }
+
+    fn query_contents(&self, selection: ClipboardSelection) -> anyhow::Result<()> {
+        let mux = Mux::try_get()
+            .ok_or_else(|| anyhow::anyhow!("MuxClipboard::query_contents: no Mux?"))?;
+        mux.notify(MuxNotification::QueryClipboard {
+            pane_id: self.pane_id,
+            selection,
+        });
+        Ok(())
+    }
 }
 
 struct MuxDownloader {}
@@ -1482,3 +1654,39 @@ impl wezterm_term::DownloadHandler for MuxDownloader {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn output_batches_cover_their_actions() {
+        let mut batcher = OutputBatcher::default();
+        let mut batches = vec![];
+        let mut apply = |actions: Vec<Action>, data: &[u8]| {
+            batches.push((actions.len(), String::from_utf8_lossy(data).to_string()))
+        };
+
+        // Ending synchronized output applies the frame right away, but
+        // leaves the text that follows it in the same read for later
+        assert!(batcher.parse(b"a\x1b[?2026hb\x1b[?2026lc\x1b[", &mut apply));
+        assert!(batcher.has_actions());
+        assert!(!batcher.is_held());
+        batcher.flush(&mut apply);
+
+        // The incomplete sequence is recorded along with its action
+        assert!(!batcher.parse(b"31m", &mut apply));
+        batcher.flush(&mut apply);
+        batcher.flush(&mut apply);
+
+        assert_eq!(
+            batches,
+            vec![
+                (1, "a".to_string()),
+                (3, "\x1b[?2026hb\x1b[?2026l".to_string()),
+                (1, "c".to_string()),
+                (1, "\x1b[31m".to_string()),
+            ]
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::asciicast::CastVersion;
 use crate::domain::DomainId;
 use crate::pane::{
     CachePolicy, CloseReason, ForEachPaneLogicalLine, LogicalLine, Pane, PaneId, Pattern,
@@ -11,7 +12,7 @@ use async_trait::async_trait;
 use config::keyassignment::ScrollbackEraseMode;
 use config::{configuration, ExitBehavior, ExitBehaviorMessaging};
 use fancy_regex::Regex;
-use parking_lot::{MappedMutexGuard, Mutex, MutexGuard};
+use parking_lot::{MappedMutexGuard, Mutex, MutexGuard, RwLock};
 use portable_pty::{Child, ChildKiller, ExitStatus, MasterPty, PtySize};
 use procinfo::LocalProcessInfo;
 use rangeset::RangeSet;
@@ -21,6 +22,7 @@ use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryInto;
 use std::io::{Result as IoResult, Write};
 use std::ops::Range;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use termwiz::escape::csi::{Sgr, CSI};
@@ -31,8 +33,9 @@ use url::Url;
 use wezterm_dynamic::Value;
 use wezterm_term::color::ColorPalette;
 use wezterm_term::{
-    Alert, AlertHandler, Clipboard, DownloadHandler, KeyCode, KeyModifiers, MouseEvent, Progress,
-    SemanticZone, StableRowIndex, Terminal, TerminalConfiguration, TerminalSize,
+    Alert, AlertHandler, Clipboard, CommandBlock, DownloadHandler, KeyCode, KeyModifiers,
+    MouseEvent, Progress, SemanticZone, StableRowIndex, Terminal, TerminalConfiguration,
+    TerminalSize,
 };
 
 const PROC_INFO_CACHE_TTL: Duration = Duration::from_millis(300);
@@ -133,6 +136,10 @@ pub struct LocalPane {
     #[cfg(unix)]
     leader: Arc<Mutex<Option<CachedLeaderInfo>>>,
     command_description: String,
+    /// Output is recorded, tailed and applied to the pane while holding
+    /// this for read; recordings and tail subscriptions take their
+    /// snapshot of the pane while holding it for write
+    applying_output: RwLock<()>,
 }
 
 #[async_trait(?Send)]
@@ -422,6 +429,7 @@ impl Pane for LocalPane {
             pixel_height: size.pixel_height.try_into()?,
         })?;
         self.terminal.lock().resize(size);
+        crate::recording::record_resize(self.pane_id, size.cols, size.rows);
         Ok(())
     }
 
@@ -656,6 +664,21 @@ impl Pane for LocalPane {
         term.get_semantic_zones()
     }
 
+    fn get_command_blocks(&self) -> Vec<CommandBlock> {
+        self.terminal.lock().get_command_blocks()
+    }
+
+    async fn toggle_recording(&self, version: CastVersion) -> anyhow::Result<(PathBuf, bool)> {
+        let pane = Mux::get()
+            .get_pane(self.pane_id)
+            .ok_or_else(|| anyhow::anyhow!("no such pane {}", self.pane_id))?;
+        crate::recording::toggle_recording(&pane, version)
+    }
+
+    async fn add_recording_marker(&self, label: &str) -> anyhow::Result<bool> {
+        Ok(crate::recording::add_marker(self.pane_id, label))
+    }
+
     async fn search(
         &self,
         pattern: Pattern,
@@ -1032,9 +1055,23 @@ impl LocalPane {
             #[cfg(unix)]
             leader: Arc::new(Mutex::new(None)),
             command_description,
+            applying_output: RwLock::new(()),
         }
     }
 
+    pub(crate) fn applying_output(&self) -> &RwLock<()> {
+        &self.applying_output
+    }
+
+    /// Returns the lines of the primary screen, including its scrollback.
+    /// The alternate screen is never included, as its content only makes
+    /// sense to the application that is currently using it.
+    pub fn primary_screen_lines(&self) -> Vec<Line> {
+        let terminal = self.terminal.lock();
+        let screen = terminal.primary_screen();
+        screen.lines_in_phys_range(0..screen.scrollback_rows())
+    }
+
     #[cfg(unix)]
     fn get_leader(&self, policy: CachePolicy) -> CachedLeaderInfo {
         let mut leader = self.leader.lock();
@@ -1,3 +1,4 @@
+use crate::asciicast::CastVersion;
 use crate::domain::DomainId;
 use crate::renderable::*;
 use crate::ExitBehavior;
@@ -9,6 +10,7 @@ use rangeset::RangeSet;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::ops::Range;
+use std::path::PathBuf;
 use std::sync::Arc;
 use termwiz::hyperlink::Rule;
 use termwiz::input::KeyboardEncoding;
@@ -17,8 +19,8 @@ use url::Url;
 use wezterm_dynamic::Value;
 use wezterm_term::color::ColorPalette;
 use wezterm_term::{
-    Clipboard, DownloadHandler, KeyCode, KeyModifiers, MouseEvent, Progress, SemanticZone,
-    StableRowIndex, TerminalConfiguration, TerminalSize,
+    Clipboard, CommandBlock, DownloadHandler, KeyCode, KeyModifiers, MouseEvent, Progress,
+    SemanticZone, StableRowIndex, TerminalConfiguration, TerminalSize,
 };
 
 static PANE_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
@@ -306,11 +308,29 @@ pub trait Pane: Downcast + Send + Sync {
         Ok(vec![])
     }
 
+    /// Starts recording the output of the pane to an asciicast file if
+    /// it isn't being recorded, otherwise stops the recording.
+    /// Returns the path of the recording and whether it is now in progress.
+    async fn toggle_recording(&self, _version: CastVersion) -> anyhow::Result<(PathBuf, bool)> {
+        anyhow::bail!("pane {} cannot be recorded", self.pane_id())
+    }
+
+    /// Adds a marker to the in-progress recording of the pane.
+    /// Returns false if the pane isn't being recorded.
+    async fn add_recording_marker(&self, _label: &str) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+
     /// Retrieve the set of semantic zones
     fn get_semantic_zones(&self) -> anyhow::Result<Vec<SemanticZone>> {
         Ok(vec![])
     }
 
+    /// Retrieve the command blocks that are still present in the scrollback
+    fn get_command_blocks(&self) -> Vec<CommandBlock> {
+        vec![]
+    }
+
     /// Returns true if the terminal has grabbed the mouse and wants to
     /// give the embedded application a chance to process events.
     /// In practice this controls whether the gui will perform local
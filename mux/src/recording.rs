@@ -0,0 +1,244 @@
+//! Records the output of live panes into asciicast files.
+//!
+//! Unlike `arb record`, which spawns its own pty, this taps the output
+//! that the mux reads from the pty of an existing local pane, so that a
+//! recording can be started after the fact.  The recording begins with
+//! a snapshot of the visible screen so that it replays correctly.
+//! Output is recorded as it is applied to the terminal rather than as
+//! it is read from the pty, so that output that was read but not yet
+//! parsed is neither lost nor duplicated by the snapshot.
+use crate::asciicast::{CastVersion, CastWriter, Header, Theme};
+use crate::localpane::LocalPane;
+use crate::pane::{Pane, PaneId};
+use anyhow::Context;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+struct PaneRecording {
+    writer: CastWriter<BufWriter<File>>,
+    path: PathBuf,
+    start: Instant,
+}
+
+impl PaneRecording {
+    fn elapsed(&self) -> f32 {
+        self.start.elapsed().as_secs_f32()
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref RECORDINGS: Mutex<HashMap<PaneId, PaneRecording>> = Mutex::new(HashMap::new());
+}
+
+/// The number of active recordings; allows the pty reader to
+/// skip taking the lock in the common case where nothing is recording
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+pub fn recordings_dir() -> PathBuf {
+    config::DATA_DIR.join("recordings")
+}
+
+fn default_recording_path(pane_id: PaneId) -> PathBuf {
+    let now = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    recordings_dir().join(format!("pane-{pane_id}-{now}.cast"))
+}
+
+/// Returns the file that the pane is being recorded into, if any
+pub fn recording_path(pane_id: PaneId) -> Option<PathBuf> {
+    RECORDINGS.lock().get(&pane_id).map(|rec| rec.path.clone())
+}
+
+/// Starts recording the output of `pane` into `path`, or into a new
+/// file in `recordings_dir()` if no path is given.
+/// Returns the path of the recording.
+pub fn start_recording(
+    pane: &Arc<dyn Pane>,
+    path: Option<PathBuf>,
+    version: CastVersion,
+) -> anyhow::Result<PathBuf> {
+    let pane_id = pane.pane_id();
+    let local = pane.downcast_ref::<LocalPane>().ok_or_else(|| {
+        anyhow::anyhow!(
+            "pane {pane_id} is not a local pane; \
+             only panes whose pty is owned by this process can be recorded"
+        )
+    })?;
+    if let Some(existing) = recording_path(pane_id) {
+        anyhow::bail!(
+            "pane {pane_id} is already being recorded to {}",
+            existing.display()
+        );
+    }
+
+    let path = path.unwrap_or_else(|| default_recording_path(pane_id));
+    if let Some(parent) = path.parent() {
+        config::create_user_owned_dirs(parent)
+            .with_context(|| format!("creating {}", parent.display()))?;
+    }
+    let file = File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+    let config = config::configuration();
+
+    // Only the output of this pane waits while the snapshot is taken
+    let _applying = local.applying_output().write();
+    let dims = pane.get_dimensions();
+    let mut header = Header::new(&config, dims.cols as u32, dims.viewport_rows as u32, None);
+    header.title = Some(pane.get_title());
+    header.theme = Some(Theme::from_palette(&pane.palette()));
+
+    let mut writer = CastWriter::new(BufWriter::new(file), &header, version)?;
+    writer.output(0., &screen_snapshot(pane)?)?;
+
+    let mut recordings = RECORDINGS.lock();
+    if let Some(existing) = recordings.get(&pane_id) {
+        anyhow::bail!(
+            "pane {pane_id} is already being recorded to {}",
+            existing.path.display()
+        );
+    }
+    recordings.insert(
+        pane_id,
+        PaneRecording {
+            writer,
+            path: path.clone(),
+            start: Instant::now(),
+        },
+    );
+    ACTIVE.fetch_add(1, Ordering::SeqCst);
+    log::info!("recording pane {pane_id} to {}", path.display());
+    Ok(path)
+}
+
+/// Stops recording the pane, returning the path of the finished
+/// recording, or None if the pane wasn't being recorded
+pub fn stop_recording(pane_id: PaneId) -> anyhow::Result<Option<PathBuf>> {
+    let recording = RECORDINGS.lock().remove(&pane_id);
+    match recording {
+        Some(mut recording) => {
+            ACTIVE.fetch_sub(1, Ordering::SeqCst);
+            recording
+                .writer
+                .flush()
+                .with_context(|| format!("writing {}", recording.path.display()))?;
+            log::info!(
+                "finished recording pane {pane_id} to {}",
+                recording.path.display()
+            );
+            Ok(Some(recording.path))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Starts recording if the pane isn't being recorded, otherwise
+/// stops recording.  Returns the path of the recording and whether
+/// it is now in progress.
+pub fn toggle_recording(
+    pane: &Arc<dyn Pane>,
+    version: CastVersion,
+) -> anyhow::Result<(PathBuf, bool)> {
+    match stop_recording(pane.pane_id())? {
+        Some(path) => Ok((path, false)),
+        None => Ok((start_recording(pane, None, version)?, true)),
+    }
+}
+
+/// Applies `func` to the recording of `pane_id`, if any.
+/// A recording that fails to write is abandoned.
+fn with_recording<F: FnOnce(&mut PaneRecording) -> anyhow::Result<()>>(pane_id: PaneId, func: F) {
+    if ACTIVE.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let mut recordings = RECORDINGS.lock();
+    if let Some(recording) = recordings.get_mut(&pane_id) {
+        if let Err(err) = func(recording) {
+            log::error!(
+                "recording pane {pane_id} to {} failed: {err:#}",
+                recording.path.display()
+            );
+            recordings.remove(&pane_id);
+            ACTIVE.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Called by the output parser with the output that produced the
+/// actions that `apply` applies to the pane.  The output is also
+/// delivered to any subscribers to the pane's output.
+pub(crate) fn apply_output<F: FnOnce()>(pane: &Arc<dyn Pane>, data: &[u8], apply: F) {
+    // Only local panes can be recorded or tailed
+    let local = match pane.downcast_ref::<LocalPane>() {
+        Some(local) => local,
+        None => return apply(),
+    };
+    let pane_id = pane.pane_id();
+    let _applying = local.applying_output().read();
+    with_recording(pane_id, |rec| {
+        let elapsed = rec.elapsed();
+        rec.writer.output_bytes(elapsed, data)
+    });
+    crate::tail::tail_output(pane_id, data);
+    apply();
+}
+
+/// Adds a marker with the given label at the current position of the
+/// recording of `pane_id`.  Returns false if the pane isn't being recorded.
+pub fn add_marker(pane_id: PaneId, label: &str) -> bool {
+    let mut found = false;
+    with_recording(pane_id, |rec| {
+        found = true;
+        let elapsed = rec.elapsed();
+        rec.writer.marker(elapsed, label)
+    });
+    found
+}
+
+/// Called when the pty of the pane is resized
+pub(crate) fn record_resize(pane_id: PaneId, cols: usize, rows: usize) {
+    with_recording(pane_id, |rec| {
+        let elapsed = rec.elapsed();
+        rec.writer.resize(elapsed, cols as u32, rows as u32)
+    });
+}
+
+/// Called once all of the output of the pane has been applied
+pub(crate) fn pane_output_finished(pane_id: PaneId) {
+    if ACTIVE.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    if let Err(err) = stop_recording(pane_id) {
+        log::error!("{err:#}");
+    }
+}
+
+/// Renders the visible screen of the pane as escape sequences that
+/// reproduce it on a terminal of the same size, leaving the cursor in
+/// the same position
+fn screen_snapshot(pane: &Arc<dyn Pane>) -> anyhow::Result<String> {
+    let dims = pane.get_dimensions();
+    let cursor = pane.get_cursor_position();
+    let top = dims.physical_top;
+    let (_first, lines) = pane.get_lines(top..top + dims.viewport_rows as isize);
+
+    let mut snapshot = "\x1b[0m\x1b[H\x1b[2J".to_string();
+    for (idx, line) in lines.into_iter().enumerate() {
+        if line.is_whitespace() {
+            continue;
+        }
+        snapshot.push_str(&format!("\x1b[{};1H", idx + 1));
+        // Each line is terminated with CRLF, which would scroll the
+        // screen when rendering the bottom row; position explicitly instead
+        snapshot.push_str(&termwiz_funcs::lines_to_escapes(vec![line])?.replace("\r\n", ""));
+    }
+    snapshot.push_str(&format!(
+        "\x1b[{};{}H",
+        (cursor.y - top).max(0) + 1,
+        cursor.x + 1
+    ));
+    Ok(snapshot)
+}
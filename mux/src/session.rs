@@ -0,0 +1,840 @@
+//! Saving and restoring the layout of the mux.
+//!
+//! A session is a snapshot of the windows, tabs and split panes
+//! known to the mux, along with the working directory and foreground
+//! command of each pane.  Sessions are stored as JSON files in the
+//! `sessions` directory beneath `config::DATA_DIR`.
+//!
+//! When `save_session_scrollback` is enabled, the scrollback of each
+//! local pane is encoded as escape sequences, compressed with zstd and
+//! stored in a directory alongside the session file.
+use crate::domain::SplitSource;
+use crate::localpane::LocalPane;
+use crate::pane::{CachePolicy, Pane, PaneId};
+use crate::split_tree::{self, split_size, SplitNode, SplitTree};
+use crate::tab::{PaneEntry, PaneNode, SplitDirection, SplitDirectionAndSize, Tab};
+use crate::window::WindowId;
+use crate::Mux;
+use anyhow::{anyhow, Context};
+use config::keyassignment::SpawnTabDomain;
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use termwiz::surface::Line;
+use url::Url;
+use wezterm_term::TerminalSize;
+
+/// Bump this if the snapshot format changes incompatibly
+pub const SESSION_FORMAT_VERSION: u32 = 1;
+
+/// The name of the session that is written when arb exits
+pub const LAST_SESSION: &str = "last";
+
+/// Saved scrollback is encoded this many lines at a time; the size
+/// limit is applied in whole chunks
+const SCROLLBACK_CHUNK_LINES: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub version: u32,
+    pub windows: Vec<WindowSnapshot>,
+    /// Where the scrollback files of a loaded session live
+    #[serde(skip)]
+    scrollback_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowSnapshot {
+    pub workspace: String,
+    pub title: String,
+    pub active_tab: usize,
+    pub tabs: Vec<TabSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TabSnapshot {
+    pub title: String,
+    pub size: TerminalSize,
+    pub root: SessionNode,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SessionNode {
+    Split {
+        direction: SplitDirection,
+        /// The size of the left/top portion of the split
+        first: TerminalSize,
+        /// The size of the right/bottom portion of the split
+        second: TerminalSize,
+        left: Box<SessionNode>,
+        right: Box<SessionNode>,
+    },
+    Pane(PaneSnapshot),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaneSnapshot {
+    /// Name of the domain that the pane belonged to
+    pub domain: String,
+    /// The working directory, as reported via OSC 7 or
+    /// by inspecting the process
+    pub cwd: Option<String>,
+    /// The argv of the foreground process, if it was something
+    /// other than the shell
+    pub command: Option<Vec<String>>,
+    pub title: String,
+    pub is_active: bool,
+    pub is_zoomed: bool,
+    /// The name of the file that holds the saved scrollback
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scrollback: Option<String>,
+    /// The encoded scrollback captured from the live pane; it is
+    /// written to the `scrollback` file when the session is saved
+    #[serde(skip)]
+    scrollback_data: Option<Vec<u8>>,
+}
+
+/// The information about a live pane that is not part of `PaneEntry`
+struct PaneDetails {
+    domain: String,
+    command: Option<Vec<String>>,
+    scrollback: Option<Vec<u8>>,
+}
+
+impl SplitTree for SessionNode {
+    type Pane = PaneSnapshot;
+
+    fn node(&self) -> SplitNode<'_, Self> {
+        match self {
+            Self::Split {
+                direction,
+                first,
+                second,
+                left,
+                right,
+            } => SplitNode::Split {
+                direction: *direction,
+                size: split_size(*direction, first, second),
+                first: left,
+                second: right,
+            },
+            Self::Pane(pane) => SplitNode::Pane(pane),
+        }
+    }
+}
+
+impl SessionNode {
+    fn from_pane_node<F>(node: PaneNode, details: &F) -> Option<Self>
+    where
+        F: Fn(PaneId) -> PaneDetails,
+    {
+        split_tree::from_pane_node(
+            node,
+            &|node: SplitDirectionAndSize, left, right| Self::Split {
+                direction: node.direction,
+                first: node.first,
+                second: node.second,
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+            &|entry: PaneEntry| {
+                let PaneDetails {
+                    domain,
+                    command,
+                    scrollback,
+                } = details(entry.pane_id);
+                Self::Pane(PaneSnapshot {
+                    domain,
+                    cwd: entry.working_dir.and_then(|url| url_to_path(&url.url)),
+                    command,
+                    title: entry.title,
+                    is_active: entry.is_active_pane,
+                    is_zoomed: entry.is_zoomed_pane,
+                    scrollback: scrollback
+                        .as_ref()
+                        .map(|_| format!("{}.zst", entry.pane_id)),
+                    scrollback_data: scrollback,
+                })
+            },
+        )
+    }
+}
+
+pub fn url_to_path(url: &Url) -> Option<String> {
+    if url.scheme() != "file" {
+        return None;
+    }
+    let path = percent_decode_str(url.path()).decode_utf8().ok()?;
+    // A windows path in a file URL looks like `/C:\Users`
+    let bytes = path.as_bytes();
+    if bytes.len() > 2 && bytes[0] == b'/' && bytes[2] == b':' {
+        Some(path[1..].to_string())
+    } else {
+        Some(path.into_owned())
+    }
+}
+
+/// Returns the argv of the foreground process in the pane, unless it
+/// is one of the processes (such as the shell) that are considered
+/// to be safe to close without prompting.
+pub(crate) fn foreground_command(pane: &Arc<dyn Pane>) -> Option<Vec<String>> {
+    let info = pane.get_foreground_process_info(CachePolicy::AllowStale)?;
+    let name = info.executable.file_name()?.to_string_lossy().to_string();
+    let config = config::configuration();
+    if config
+        .skip_close_confirmation_for_processes_named
+        .iter()
+        .any(|skip| *skip == name)
+    {
+        return None;
+    }
+    if info.argv.is_empty() {
+        None
+    } else {
+        Some(info.argv)
+    }
+}
+
+/// Encodes the most recent `lines` as escape sequences, keeping as
+/// many as fit within `max_bytes`.  Blank lines at the bottom of the
+/// screen are not worth keeping and are dropped.
+fn encode_scrollback(mut lines: Vec<Line>, max_bytes: usize) -> anyhow::Result<Vec<u8>> {
+    while lines
+        .last()
+        .map(|line| line.is_whitespace())
+        .unwrap_or(false)
+    {
+        lines.pop();
+    }
+
+    let mut chunks = vec![];
+    let mut total = 0;
+    for chunk in lines.rchunks(SCROLLBACK_CHUNK_LINES) {
+        let encoded = termwiz_funcs::lines_to_escapes(chunk.to_vec())?;
+        if total + encoded.len() > max_bytes {
+            break;
+        }
+        total += encoded.len();
+        chunks.push(encoded);
+    }
+    chunks.reverse();
+    Ok(chunks.concat().into_bytes())
+}
+
+fn pane_scrollback(pane: &Arc<dyn Pane>, max_bytes: usize) -> Option<Vec<u8>> {
+    let local_pane = pane.downcast_ref::<LocalPane>()?;
+    match encode_scrollback(local_pane.primary_screen_lines(), max_bytes) {
+        Ok(data) if data.is_empty() => None,
+        Ok(data) => Some(data),
+        Err(err) => {
+            log::warn!("saving scrollback of pane {}: {:#}", pane.pane_id(), err);
+            None
+        }
+    }
+}
+
+/// Reads the scrollback saved for `snapshot`, followed by a line
+/// that separates it from the new output of the pane
+fn load_scrollback(dir: Option<&Path>, snapshot: &PaneSnapshot) -> Option<Vec<u8>> {
+    let path = dir?.join(snapshot.scrollback.as_ref()?);
+    let load = || -> anyhow::Result<Vec<u8>> {
+        let file =
+            std::fs::File::open(&path).with_context(|| format!("opening {}", path.display()))?;
+        let mut data =
+            zstd::decode_all(file).with_context(|| format!("decoding {}", path.display()))?;
+        data.extend_from_slice(
+            "\x1b[0m\x1b[2m\u{2500}\u{2500}\u{2500} restored scrollback \u{2500}\u{2500}\u{2500}\x1b[0m\r\n"
+                .as_bytes(),
+        );
+        Ok(data)
+    };
+    match load() {
+        Ok(data) => Some(data),
+        Err(err) => {
+            // Losing the scrollback is not a reason to fail
+            // restoring the rest of the session
+            log::warn!("restoring scrollback: {:#}", err);
+            None
+        }
+    }
+}
+
+/// Awaits `spawn`, which spawns the pane for `snapshot`, arranging for
+/// the pane to show its saved scrollback ahead of any output from the
+/// pty, so that the scrollback can't land after the first prompt
+async fn spawn_with_scrollback<T>(
+    mux: &Mux,
+    scrollback_dir: Option<&Path>,
+    snapshot: &PaneSnapshot,
+    spawn: impl std::future::Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    mux.set_next_pane_preamble(load_scrollback(scrollback_dir, snapshot));
+    let result = spawn.await;
+    // Don't let it leak into some other pane if no pane with
+    // a pty was spawned
+    mux.take_next_pane_preamble();
+    result
+}
+
+/// How to quote the arguments of a command typed at a shell prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShellSyntax {
+    Posix,
+    Fish,
+    /// cmd and PowerShell
+    Windows,
+}
+
+fn shell_syntax(pane: &Arc<dyn Pane>) -> ShellSyntax {
+    if cfg!(windows) {
+        return ShellSyntax::Windows;
+    }
+    let shell = pane.get_foreground_process_name(CachePolicy::AllowStale);
+    match shell
+        .as_deref()
+        .map(Path::new)
+        .and_then(Path::file_name)
+        .and_then(|name| name.to_str())
+    {
+        Some("fish") => ShellSyntax::Fish,
+        _ => ShellSyntax::Posix,
+    }
+}
+
+/// Joins `command` into a line for the shell, quoting only
+/// the arguments that need it
+fn command_line(syntax: ShellSyntax, command: &[String]) -> String {
+    let quote = |arg: &String| -> String {
+        let plain = !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_alphanumeric() || "-_./:=+".contains(c));
+        if plain {
+            return arg.clone();
+        }
+        match syntax {
+            ShellSyntax::Posix => shell_words::quote(arg).into_owned(),
+            // fish treats backslashes within single quotes
+            // as escapes, so they must be doubled too
+            ShellSyntax::Fish => {
+                format!("'{}'", arg.replace('\\', "\\\\").replace('\'', "\\'"))
+            }
+            // Both cmd and PowerShell treat a doubled quote within
+            // a quoted string as a literal quote
+            ShellSyntax::Windows => format!("\"{}\"", arg.replace('"', "\"\"")),
+        }
+    };
+    command.iter().map(quote).collect::<Vec<_>>().join(" ")
+}
+
+pub fn sessions_dir() -> PathBuf {
+    config::DATA_DIR.join("sessions")
+}
+
+/// Resolves the name of a session to the file that holds it
+pub fn session_path(name: &str) -> anyhow::Result<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\', ':']) {
+        anyhow::bail!("{name:?} is not a valid session name");
+    }
+    Ok(sessions_dir().join(format!("{name}.json")))
+}
+
+fn scrollback_dir(name: &str) -> anyhow::Result<PathBuf> {
+    Ok(session_path(name)?.with_extension("scrollback"))
+}
+
+/// Returns the names of the saved sessions
+pub fn list_sessions() -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(sessions_dir())
+        .map(|dir| {
+            dir.filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "json" {
+                    return None;
+                }
+                Some(path.file_stem()?.to_str()?.to_string())
+            })
+            .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+impl SessionSnapshot {
+    /// Captures the current layout of the mux
+    pub fn capture(mux: &Mux) -> Self {
+        let windows = mux
+            .iter_windows()
+            .into_iter()
+            .filter_map(|window_id| Self::capture_window(mux, window_id))
+            .collect();
+        Self {
+            version: SESSION_FORMAT_VERSION,
+            windows,
+            scrollback_dir: None,
+        }
+    }
+
+    fn capture_window(mux: &Mux, window_id: WindowId) -> Option<WindowSnapshot> {
+        // Don't hold the window lock while walking the tabs;
+        // codec_pane_tree needs to look up the window again.
+        let (workspace, title, active_tab, tabs) = {
+            let window = mux.get_window(window_id)?;
+            (
+                window.get_workspace().to_string(),
+                window.get_title().to_string(),
+                window.get_active_idx(),
+                window.iter().cloned().collect::<Vec<Arc<Tab>>>(),
+            )
+        };
+
+        let config = config::configuration();
+        let details = |pane_id: PaneId| {
+            let pane = mux.get_pane(pane_id);
+            PaneDetails {
+                domain: pane
+                    .as_ref()
+                    .and_then(|pane| mux.get_domain(pane.domain_id()))
+                    .map(|domain| domain.domain_name().to_string())
+                    .unwrap_or_default(),
+                command: pane.as_ref().and_then(foreground_command),
+                scrollback: if config.save_session_scrollback {
+                    pane.as_ref()
+                        .and_then(|pane| pane_scrollback(pane, config.session_scrollback_max_bytes))
+                } else {
+                    None
+                },
+            }
+        };
+
+        let tabs: Vec<TabSnapshot> = tabs
+            .iter()
+            .filter_map(|tab| {
+                let root = SessionNode::from_pane_node(tab.codec_pane_tree(), &details)?;
+                Some(TabSnapshot {
+                    title: tab.get_title(),
+                    size: tab.get_size(),
+                    root,
+                })
+            })
+            .collect();
+
+        if tabs.is_empty() {
+            return None;
+        }
+
+        Some(WindowSnapshot {
+            workspace,
+            title,
+            active_tab: active_tab.min(tabs.len() - 1),
+            tabs,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    fn panes(&self) -> impl Iterator<Item = &PaneSnapshot> {
+        self.windows
+            .iter()
+            .flat_map(|window| window.tabs.iter())
+            .flat_map(|tab| tab.root.panes())
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let snapshot: Self = serde_json::from_str(json)?;
+        if snapshot.version != SESSION_FORMAT_VERSION {
+            anyhow::bail!(
+                "session format version {} is not supported (expected {})",
+                snapshot.version,
+                SESSION_FORMAT_VERSION
+            );
+        }
+        Ok(snapshot)
+    }
+
+    /// Saves the snapshot under `name`, replacing any existing
+    /// session of the same name
+    pub fn save(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let path = session_path(name)?;
+        let dir = sessions_dir();
+        std::fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+        // Write to a temporary file first so that a crash part way
+        // through doesn't destroy the previous session
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, self.to_json()?)
+            .with_context(|| format!("writing {}", temp.display()))?;
+        std::fs::rename(&temp, &path).with_context(|| format!("writing {}", path.display()))?;
+        self.save_scrollback(&scrollback_dir(name)?)?;
+        Ok(path)
+    }
+
+    fn save_scrollback(&self, dir: &Path) -> anyhow::Result<()> {
+        // Scrollback from a previous save of this session is stale
+        if dir.exists() {
+            std::fs::remove_dir_all(dir).with_context(|| format!("removing {}", dir.display()))?;
+        }
+        for pane in self.panes() {
+            if let (Some(file_name), Some(data)) = (&pane.scrollback, &pane.scrollback_data) {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("creating {}", dir.display()))?;
+                let path = dir.join(file_name);
+                let compressed = zstd::encode_all(data.as_slice(), zstd::DEFAULT_COMPRESSION_LEVEL)
+                    .with_context(|| format!("compressing {}", path.display()))?;
+                std::fs::write(&path, compressed)
+                    .with_context(|| format!("writing {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load(name: &str) -> anyhow::Result<Self> {
+        let path = session_path(name)?;
+        let json = std::fs::read_to_string(&path)
+            .with_context(|| format!("reading session {name:?} from {}", path.display()))?;
+        let mut snapshot = Self::from_json(&json)
+            .with_context(|| format!("parsing session {}", path.display()))?;
+        snapshot.scrollback_dir = Some(scrollback_dir(name)?);
+        Ok(snapshot)
+    }
+
+    /// Spawns the windows, tabs and panes described by the snapshot.
+    /// Returns the ids of the newly created windows.
+    pub async fn restore(&self) -> anyhow::Result<Vec<WindowId>> {
+        let mux = Mux::get();
+        let mut window_ids = vec![];
+
+        for window in &self.windows {
+            let mut window_id = None;
+
+            for tab_snapshot in &window.tabs {
+                let first = tab_snapshot.root.first_pane();
+                let (tab, pane, new_window_id) = spawn_with_scrollback(
+                    &mux,
+                    self.scrollback_dir.as_deref(),
+                    first,
+                    mux.spawn_tab_or_window(
+                        window_id,
+                        spawn_domain(&mux, first),
+                        None,
+                        first.cwd.clone(),
+                        tab_snapshot.size,
+                        None,
+                        window.workspace.clone(),
+                        None,
+                    ),
+                )
+                .await?;
+                window_id = Some(new_window_id);
+
+                if !tab_snapshot.title.is_empty() {
+                    tab.set_title(&tab_snapshot.title);
+                }
+                restore_splits(
+                    &mux,
+                    &tab,
+                    &tab_snapshot.root,
+                    pane,
+                    self.scrollback_dir.as_deref(),
+                )
+                .await?;
+            }
+
+            if let Some(window_id) = window_id {
+                if let Some(mut mux_window) = mux.get_window_mut(window_id) {
+                    if !window.title.is_empty() {
+                        mux_window.set_title(&window.title);
+                    }
+                    if window.active_tab < mux_window.len() {
+                        mux_window.set_active_without_saving(window.active_tab);
+                    }
+                }
+                window_ids.push(window_id);
+            }
+        }
+
+        Ok(window_ids)
+    }
+}
+
+fn spawn_domain(mux: &Mux, pane: &PaneSnapshot) -> SpawnTabDomain {
+    if mux.get_domain_by_name(&pane.domain).is_some() {
+        SpawnTabDomain::DomainName(pane.domain.clone())
+    } else {
+        SpawnTabDomain::DefaultDomain
+    }
+}
+
+/// Splits `pane`, which was spawned for the first pane of `root`,
+/// until the tab has the same layout as `root`, then restores the
+/// commands, focus and zoom of the panes.
+async fn restore_splits(
+    mux: &Arc<Mux>,
+    tab: &Arc<Tab>,
+    root: &SessionNode,
+    pane: Arc<dyn Pane>,
+    scrollback_dir: Option<&Path>,
+) -> anyhow::Result<()> {
+    let restored = split_tree::replay_splits(root, pane, |pane_id, request, snapshot| {
+        let mux = Arc::clone(mux);
+        async move {
+            let (new_pane, _size) = spawn_with_scrollback(
+                &mux,
+                scrollback_dir,
+                snapshot,
+                mux.split_pane(
+                    pane_id,
+                    request,
+                    SplitSource::Spawn {
+                        command: None,
+                        command_dir: snapshot.cwd.clone(),
+                    },
+                    spawn_domain(&mux, snapshot),
+                ),
+            )
+            .await?;
+            Ok(new_pane)
+        }
+    })
+    .await?;
+
+    for (snapshot, pane) in &restored {
+        if let Some(command) = &snapshot.command {
+            // Type the command into the shell rather than spawning
+            // it directly, so that the pane remains usable after
+            // the command exits
+            let mut line = command_line(shell_syntax(pane), command);
+            if config::configuration().session_restore_runs_commands {
+                line.push('\r');
+            }
+            pane.writer()
+                .write_all(line.as_bytes())
+                .map_err(|err| anyhow!("restoring command {command:?}: {err:#}"))?;
+        }
+    }
+
+    if let Some((_, pane)) = restored.iter().find(|(snapshot, _)| snapshot.is_active) {
+        tab.set_active_pane(pane);
+    }
+    if restored.iter().any(|(snapshot, _)| snapshot.is_zoomed) {
+        tab.set_zoomed(true);
+    }
+
+    Ok(())
+}
+
+/// Captures the current layout of the mux and saves it under `name`.
+/// Returns the path of the session file.
+pub fn save_session(name: &str) -> anyhow::Result<PathBuf> {
+    SessionSnapshot::capture(&Mux::get()).save(name)
+}
+
+/// Loads the session saved under `name` and spawns its windows
+pub async fn restore_session(name: &str) -> anyhow::Result<Vec<WindowId>> {
+    SessionSnapshot::load(name)?.restore().await
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::renderable::StableCursorPosition;
+    use termwiz::cell::CellAttributes;
+    use termwiz::surface::SEQ_ZERO;
+
+    pub(crate) fn size(rows: usize, cols: usize) -> TerminalSize {
+        TerminalSize {
+            rows,
+            cols,
+            pixel_width: cols * 8,
+            pixel_height: rows * 16,
+            dpi: 96,
+        }
+    }
+
+    pub(crate) fn leaf(pane_id: PaneId, cwd: &str, is_active: bool) -> PaneNode {
+        PaneNode::Leaf(PaneEntry {
+            window_id: 0,
+            tab_id: 0,
+            pane_id,
+            title: format!("pane {pane_id}"),
+            size: size(24, 40),
+            working_dir: Some(Url::parse(cwd).unwrap().into()),
+            is_active_pane: is_active,
+            is_zoomed_pane: false,
+            workspace: "default".to_string(),
+            cursor_pos: StableCursorPosition::default(),
+            physical_top: 0,
+            top_row: 0,
+            left_col: 0,
+            tty_name: None,
+            domain_name: Some("local".to_string()),
+            foreground_process_name: None,
+        })
+    }
+
+    fn details(pane_id: PaneId) -> PaneDetails {
+        PaneDetails {
+            domain: "local".to_string(),
+            command: if pane_id == 2 {
+                Some(vec!["vim".to_string(), "notes.md".to_string()])
+            } else {
+                None
+            },
+            scrollback: None,
+        }
+    }
+
+    fn two_pane_tree() -> PaneNode {
+        PaneNode::Split {
+            left: Box::new(leaf(1, "file://host/home/me/src", false)),
+            right: Box::new(leaf(2, "file://host/home/me/my%20notes", true)),
+            node: SplitDirectionAndSize {
+                direction: SplitDirection::Horizontal,
+                first: size(24, 40),
+                second: size(24, 39),
+            },
+        }
+    }
+
+    #[test]
+    fn should_quote_commands_for_the_shell() {
+        let command: Vec<String> = ["git", "commit", "-m", r"it's a \ fix", "--", "a.txt"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            command_line(ShellSyntax::Posix, &command),
+            r"git commit -m 'it'\''s a \ fix' -- a.txt"
+        );
+        assert_eq!(
+            command_line(ShellSyntax::Fish, &command),
+            r"git commit -m 'it\'s a \\ fix' -- a.txt"
+        );
+        assert_eq!(
+            command_line(
+                ShellSyntax::Windows,
+                &["echo".to_string(), r#"say "hi""#.to_string()]
+            ),
+            r#"echo "say ""hi""""#
+        );
+    }
+
+    #[test]
+    fn should_convert_pane_tree() {
+        let node = SessionNode::from_pane_node(two_pane_tree(), &details).unwrap();
+        let panes = node.panes();
+        assert_eq!(panes.len(), 2);
+        assert_eq!(panes[0].cwd.as_deref(), Some("/home/me/src"));
+        assert_eq!(panes[0].domain, "local");
+        assert_eq!(panes[0].command, None);
+        assert!(!panes[0].is_active);
+        assert_eq!(panes[1].cwd.as_deref(), Some("/home/me/my notes"));
+        assert_eq!(
+            panes[1].command,
+            Some(vec!["vim".to_string(), "notes.md".to_string()])
+        );
+        assert!(panes[1].is_active);
+        assert_eq!(node.first_pane().title, "pane 1");
+    }
+
+    #[test]
+    fn should_name_scrollback_after_pane() {
+        let with_scrollback = |pane_id: PaneId| PaneDetails {
+            scrollback: if pane_id == 1 {
+                Some(b"make: *** [all] Error 2\r\n".to_vec())
+            } else {
+                None
+            },
+            ..details(pane_id)
+        };
+        let node = SessionNode::from_pane_node(two_pane_tree(), &with_scrollback).unwrap();
+        let panes = node.panes();
+        assert_eq!(panes[0].scrollback.as_deref(), Some("1.zst"));
+        assert!(panes[0].scrollback_data.is_some());
+        assert_eq!(panes[1].scrollback, None);
+
+        // Only the file name is part of the saved session
+        let json = serde_json::to_string(panes[0]).unwrap();
+        assert!(json.contains("\"scrollback\":\"1.zst\""));
+        assert!(!json.contains("Error 2"));
+    }
+
+    #[test]
+    fn should_keep_most_recent_scrollback_within_limit() {
+        let attrs = CellAttributes::default();
+        let mut lines: Vec<Line> = (0..1000)
+            .map(|i| Line::from_text(&format!("line {i}"), &attrs, SEQ_ZERO, None))
+            .collect();
+        lines.push(Line::from_text("", &attrs, SEQ_ZERO, None));
+
+        let all = String::from_utf8(encode_scrollback(lines.clone(), usize::MAX).unwrap()).unwrap();
+        assert!(all.contains("line 0\r\n"));
+        assert!(all.contains("line 999\r\n"));
+
+        let limit = all.len() / 2;
+        let recent = encode_scrollback(lines, limit).unwrap();
+        assert!(recent.len() <= limit);
+        let recent = String::from_utf8(recent).unwrap();
+        assert!(!recent.contains("line 0\r\n"));
+        assert!(recent.contains("line 999\r\n"));
+    }
+
+    #[test]
+    fn should_collapse_empty_branches() {
+        let tree = PaneNode::Split {
+            left: Box::new(PaneNode::Empty),
+            right: Box::new(leaf(3, "file:///tmp", true)),
+            node: SplitDirectionAndSize {
+                direction: SplitDirection::Vertical,
+                first: size(12, 80),
+                second: size(11, 80),
+            },
+        };
+        let node = SessionNode::from_pane_node(tree, &details).unwrap();
+        assert!(matches!(node, SessionNode::Pane(_)));
+        assert!(SessionNode::from_pane_node(PaneNode::Empty, &details).is_none());
+    }
+
+    #[test]
+    fn should_round_trip_json() {
+        let snapshot = SessionSnapshot {
+            version: SESSION_FORMAT_VERSION,
+            windows: vec![WindowSnapshot {
+                workspace: "default".to_string(),
+                title: String::new(),
+                active_tab: 0,
+                tabs: vec![TabSnapshot {
+                    title: "work".to_string(),
+                    size: size(24, 80),
+                    root: SessionNode::from_pane_node(two_pane_tree(), &details).unwrap(),
+                }],
+            }],
+            scrollback_dir: None,
+        };
+        let json = snapshot.to_json().unwrap();
+        assert_eq!(SessionSnapshot::from_json(&json).unwrap(), snapshot);
+
+        let future = json.replace(
+            &format!("\"version\": {SESSION_FORMAT_VERSION}"),
+            "\"version\": 999",
+        );
+        assert!(SessionSnapshot::from_json(&future).is_err());
+    }
+
+    #[test]
+    fn should_validate_session_names() {
+        assert!(session_path("morning").unwrap().ends_with("morning.json"));
+        assert!(session_path("").is_err());
+        assert!(session_path("../escape").is_err());
+        assert!(session_path(".hidden").is_err());
+        assert!(session_path("a/b").is_err());
+    }
+}
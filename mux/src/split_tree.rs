@@ -0,0 +1,160 @@
+//! Trees of split panes.
+//!
+//! Saved sessions and layouts both describe a tab as a tree of split
+//! panes.  This module holds what they have in common: capturing the
+//! tree from a live tab, walking it, and recreating its splits in a
+//! newly spawned tab.
+use crate::pane::{Pane, PaneId};
+use crate::tab::{
+    PaneEntry, PaneNode, SplitDirection, SplitDirectionAndSize, SplitRequest, SplitSize,
+};
+use std::future::Future;
+use std::sync::Arc;
+use wezterm_term::TerminalSize;
+
+/// What a node of a `SplitTree` holds
+pub enum SplitNode<'a, T: SplitTree + ?Sized> {
+    Split {
+        direction: SplitDirection,
+        /// The size of `second`
+        size: SplitSize,
+        /// The left/top portion of the split
+        first: &'a T,
+        /// The right/bottom portion of the split
+        second: &'a T,
+    },
+    Pane(&'a T::Pane),
+}
+
+pub trait SplitTree {
+    /// Describes one of the panes of the tree
+    type Pane;
+
+    fn node(&self) -> SplitNode<'_, Self>;
+
+    /// Returns the pane that occupies the top-left corner of this node.
+    /// That pane is spawned first and then split to produce the others.
+    fn first_pane(&self) -> &Self::Pane {
+        match self.node() {
+            SplitNode::Split { first, .. } => first.first_pane(),
+            SplitNode::Pane(pane) => pane,
+        }
+    }
+
+    /// Returns the panes in this node, in the order that they
+    /// appear in the tab
+    fn panes(&self) -> Vec<&Self::Pane> {
+        match self.node() {
+            SplitNode::Split { first, second, .. } => {
+                let mut panes = first.panes();
+                panes.append(&mut second.panes());
+                panes
+            }
+            SplitNode::Pane(pane) => vec![pane],
+        }
+    }
+}
+
+/// Converts the tree of a live tab, as returned by `Tab::codec_pane_tree`,
+/// using `split` and `pane` to produce each node.  Branches that hold no
+/// panes are collapsed.
+pub(crate) fn from_pane_node<T, S, P>(node: PaneNode, split: &S, pane: &P) -> Option<T>
+where
+    S: Fn(SplitDirectionAndSize, T, T) -> T,
+    P: Fn(PaneEntry) -> T,
+{
+    match node {
+        PaneNode::Empty => None,
+        PaneNode::Split { left, right, node } => match (
+            from_pane_node(*left, split, pane),
+            from_pane_node(*right, split, pane),
+        ) {
+            (Some(first), Some(second)) => Some(split(node, first, second)),
+            (Some(only), None) | (None, Some(only)) => Some(only),
+            (None, None) => None,
+        },
+        PaneNode::Leaf(entry) => Some(pane(entry)),
+    }
+}
+
+/// Computes the size of the new, right/bottom, pane of a split as
+/// a percentage of the space occupied by the split
+pub(crate) fn split_size(
+    direction: SplitDirection,
+    first: &TerminalSize,
+    second: &TerminalSize,
+) -> SplitSize {
+    let (first, second) = match direction {
+        SplitDirection::Horizontal => (first.cols, second.cols),
+        SplitDirection::Vertical => (first.rows, second.rows),
+    };
+    let total = first + second;
+    if total == 0 {
+        return SplitSize::default();
+    }
+    SplitSize::Percent((second * 100 / total).clamp(1, 99) as u8)
+}
+
+/// Splits `pane`, which was spawned for the first pane of `root`,
+/// until the tab has the same layout as `root`.  `split` is called to
+/// split the pane with the given id, spawning the given pane of the tree.
+/// Returns each pane of the tree along with the pane spawned for it.
+pub(crate) async fn replay_splits<'a, T, F, Fut>(
+    root: &'a T,
+    pane: Arc<dyn Pane>,
+    mut split: F,
+) -> anyhow::Result<Vec<(&'a T::Pane, Arc<dyn Pane>)>>
+where
+    T: SplitTree,
+    F: FnMut(PaneId, SplitRequest, &'a T::Pane) -> Fut,
+    Fut: Future<Output = anyhow::Result<Arc<dyn Pane>>>,
+{
+    let mut spawned = vec![];
+    // Each split subdivides the space occupied by its pane, so the
+    // outermost splits must be made before the nested ones
+    let mut stack = vec![(root, pane)];
+    while let Some((node, pane)) = stack.pop() {
+        match node.node() {
+            SplitNode::Split {
+                direction,
+                size,
+                first,
+                second,
+            } => {
+                let request = SplitRequest {
+                    direction,
+                    target_is_second: true,
+                    top_level: false,
+                    size,
+                };
+                let new_pane = split(pane.pane_id(), request, second.first_pane()).await?;
+                stack.push((second, new_pane));
+                stack.push((first, pane));
+            }
+            SplitNode::Pane(described) => spawned.push((described, pane)),
+        }
+    }
+    Ok(spawned)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::session::test::size;
+
+    #[test]
+    fn should_compute_split_percentage() {
+        assert_eq!(
+            split_size(SplitDirection::Horizontal, &size(24, 60), &size(24, 20)),
+            SplitSize::Percent(25)
+        );
+        assert_eq!(
+            split_size(SplitDirection::Vertical, &size(10, 80), &size(30, 80)),
+            SplitSize::Percent(75)
+        );
+        assert_eq!(
+            split_size(SplitDirection::Vertical, &size(0, 80), &size(0, 80)),
+            SplitSize::default()
+        );
+    }
+}
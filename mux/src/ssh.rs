@@ -23,14 +23,17 @@ use termwiz::render::terminfo::TerminfoRenderer;
 use termwiz::surface::{Change, LineAttribute};
 use termwiz::terminal::{ScreenSize, Terminal, TerminalWaker};
 use wezterm_ssh::{
-    ConfigMap, HostVerificationFailed, Session, SessionEvent, SshChildProcess, SshPty,
+    forwards_from_config, ConfigMap, Forward, ForwardSpec, HostVerificationFailed, Session,
+    SessionEvent, Sftp, SshChildProcess, SshPty,
 };
 use wezterm_term::TerminalSize;
 
+/// A LineEditorHost for answering ssh authentication prompts.
+/// When `echo` is false, the input is obscured as it is typed.
 #[derive(Default)]
-struct PasswordPromptHost {
+pub struct PasswordPromptHost {
     history: BasicHistory,
-    echo: bool,
+    pub echo: bool,
 }
 impl LineEditorHost for PasswordPromptHost {
     fn history(&mut self) -> &mut dyn History {
@@ -179,11 +182,70 @@ fn format_host_verification_for_terminal(failed: HostVerificationFailed) -> Vec<
 /// interactive setup.  The bulk of that is driven by `connect_ssh_session`.
 pub struct RemoteSshDomain {
     session: Mutex<Option<Session>>,
+    forwards: Arc<Mutex<Vec<ForwardEntry>>>,
     dom: SshDomain,
     id: DomainId,
     name: String,
 }
 
+/// A port forward configured for an ssh domain
+struct ForwardEntry {
+    spec: ForwardSpec,
+    /// Whether the forward should be active while connected
+    enabled: bool,
+    forward: Option<Forward>,
+    error: Option<String>,
+}
+
+/// Starts forwarding `spec` over `session`.  This waits for the
+/// session, so the `forwards` lock must not be held while calling it.
+fn start_forward(session: &Session, spec: &ForwardSpec) -> anyhow::Result<Forward> {
+    smol::block_on(session.forward(spec.clone()))
+}
+
+impl ForwardEntry {
+    /// Records the outcome of `start_forward`
+    fn started(&mut self, result: anyhow::Result<Forward>) {
+        match result {
+            Ok(forward) => {
+                self.forward.replace(forward);
+                self.error.take();
+            }
+            Err(err) => {
+                log::error!("Failed to start forwarding {}: {:#}", self.spec, err);
+                self.error.replace(format!("{:#}", err));
+            }
+        }
+    }
+
+    /// Returns true if connections are being forwarded.
+    /// A forward whose session has ended is discarded.
+    fn check_active(&mut self) -> bool {
+        match &self.forward {
+            Some(forward) if !forward.is_active() => {
+                self.forward.take();
+                self.error.replace("the ssh session has ended".to_string());
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+}
+
+/// Describes the state of a port forward of an ssh domain
+#[derive(Debug, Clone)]
+pub struct SshForwardStatus {
+    pub spec: ForwardSpec,
+    /// true if connections are currently being forwarded
+    pub active: bool,
+    /// The port that is being listened on; this may differ
+    /// from the port in `spec` if that was 0
+    pub port: Option<u16>,
+    /// The reason that the forward could not be started
+    pub error: Option<String>,
+}
+
 pub fn ssh_domain_to_ssh_config(ssh_dom: &SshDomain) -> anyhow::Result<ConfigMap> {
     let mut ssh_config = wezterm_ssh::Config::new();
     ssh_config.add_default_config_files();
@@ -214,6 +276,23 @@ pub fn ssh_domain_to_ssh_config(ssh_dom: &SshDomain) -> anyhow::Result<ConfigMap
         ssh_config.insert(k.to_string(), v.to_string());
     }
 
+    for (key, forwards) in [
+        ("localforward", &ssh_dom.local_forward),
+        ("remoteforward", &ssh_dom.remote_forward),
+        ("dynamicforward", &ssh_dom.dynamic_forward),
+    ] {
+        if forwards.is_empty() {
+            continue;
+        }
+        let value = ssh_config.entry(key.to_string()).or_default();
+        for forward in forwards {
+            if !value.is_empty() {
+                value.push(',');
+            }
+            value.push_str(forward);
+        }
+    }
+
     if let Some(username) = &ssh_dom.username {
         ssh_config.insert("user".to_string(), username.to_string());
     }
@@ -236,14 +315,104 @@ impl RemoteSshDomain {
             id,
             name: dom.name.clone(),
             session: Mutex::new(None),
+            forwards: Arc::new(Mutex::new(vec![])),
             dom: dom.clone(),
         })
     }
 
+    /// Returns the port forwards configured for this domain.
+    /// The list is populated when the domain connects.
+    pub fn forwards(&self) -> Vec<SshForwardStatus> {
+        self.forwards
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .map(|entry| SshForwardStatus {
+                active: entry.check_active(),
+                spec: entry.spec.clone(),
+                port: entry.forward.as_ref().map(Forward::port),
+                error: entry.error.clone(),
+            })
+            .collect()
+    }
+
+    /// Stops the forward matching `spec` if it is active,
+    /// otherwise starts it.
+    pub fn toggle_forward(&self, spec: &ForwardSpec) -> anyhow::Result<()> {
+        let session = self.session.lock().unwrap().clone();
+        {
+            let mut forwards = self.forwards.lock().unwrap();
+            let entry = forwards
+                .iter_mut()
+                .find(|entry| entry.spec == *spec)
+                .ok_or_else(|| anyhow!("{} has no forward {}", self.name, spec))?;
+
+            if entry.check_active() {
+                entry.forward.take();
+                entry.enabled = false;
+                return Ok(());
+            }
+            entry.enabled = true;
+        }
+
+        let session = session.ok_or_else(|| anyhow!("{} is not connected", self.name))?;
+        let result = start_forward(&session, spec);
+
+        let mut forwards = self.forwards.lock().unwrap();
+        let entry = match forwards
+            .iter_mut()
+            .find(|entry| entry.spec == *spec && entry.enabled)
+        {
+            Some(entry) => entry,
+            // It was toggled off, or the domain reconnected, in the
+            // meantime; dropping the forward stops it
+            None => return Ok(()),
+        };
+        entry.started(result);
+        match &entry.error {
+            Some(err) => bail!("Failed to start forwarding {}: {}", spec, err),
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces the forwards with those specified by `ssh_config`,
+    /// carrying over whether they were toggled off from a previous
+    /// connection.
+    fn load_forwards(&self, ssh_config: &ConfigMap) {
+        let specs = match forwards_from_config(ssh_config) {
+            Ok(specs) => specs,
+            Err(err) => {
+                log::error!("{}: {:#}", self.name, err);
+                vec![]
+            }
+        };
+        let mut forwards = self.forwards.lock().unwrap();
+        let entries = specs
+            .into_iter()
+            .map(|spec| ForwardEntry {
+                enabled: forwards
+                    .iter()
+                    .find(|entry| entry.spec == spec)
+                    .map(|entry| entry.enabled)
+                    .unwrap_or(true),
+                spec,
+                forward: None,
+                error: None,
+            })
+            .collect();
+        *forwards = entries;
+    }
+
     pub fn ssh_config(&self) -> anyhow::Result<ConfigMap> {
         ssh_domain_to_ssh_config(&self.dom)
     }
 
+    /// Returns an sftp client that shares the ssh session used by
+    /// the panes of this domain, or None if it isn't connected
+    pub fn sftp(&self) -> Option<Sftp> {
+        self.session.lock().unwrap().as_ref().map(Session::sftp)
+    }
+
     fn build_command(
         &self,
         pane_id: PaneId,
@@ -331,8 +500,9 @@ impl RemoteSshDomain {
         env: HashMap<String, String>,
         size: TerminalSize,
     ) -> anyhow::Result<StartNewSessionResult> {
-        let (session, events) = Session::connect(self.ssh_config().context("obtain ssh config")?)
-            .context("connect to ssh server")?;
+        let ssh_config = self.ssh_config().context("obtain ssh config")?;
+        self.load_forwards(&ssh_config);
+        let (session, events) = Session::connect(ssh_config).context("connect to ssh server")?;
         self.session.lock().unwrap().replace(session.clone());
 
         // We get to establish the session!
@@ -388,10 +558,12 @@ impl RemoteSshDomain {
         // to perform the blocking (from its perspective) terminal
         // UI to carry out any authentication.
         let mut stdout_write = BufWriter::new(stdout_write);
+        let forwards = Arc::clone(&self.forwards);
         std::thread::spawn(move || {
             if let Err(err) = connect_ssh_session(
                 session,
                 events,
+                forwards,
                 stdin_read,
                 writer_tx,
                 &mut stdout_write,
@@ -422,6 +594,7 @@ struct StartNewSessionResult {
 fn connect_ssh_session(
     session: Session,
     events: smol::channel::Receiver<SessionEvent>,
+    forwards: Arc<Mutex<Vec<ForwardEntry>>>,
     mut stdin_read: FileDescriptor,
     stdin_tx: Sender<BoxedWriter>,
     stdout_write: &mut BufWriter<FileDescriptor>,
@@ -648,6 +821,29 @@ fn connect_ssh_session(
                 shim.render(&message)?;
             }
             SessionEvent::Authenticated => {
+                let specs: Vec<ForwardSpec> = forwards
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|entry| entry.enabled)
+                    .map(|entry| entry.spec.clone())
+                    .collect();
+                for spec in specs {
+                    let result = start_forward(&session, &spec);
+                    let error = forwards
+                        .lock()
+                        .unwrap()
+                        .iter_mut()
+                        .find(|entry| entry.spec == spec && entry.enabled)
+                        .and_then(|entry| {
+                            entry.started(result);
+                            entry.error.clone()
+                        });
+                    if let Some(err) = error {
+                        shim.output_line(&format!("Failed to start forwarding {}: {}", spec, err))?;
+                    }
+                }
+
                 // Our session has been authenticated: we can now
                 // set up the real pty for the pane
                 match smol::block_on(session.request_pty(
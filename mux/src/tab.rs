@@ -235,6 +235,7 @@ fn pane_tree(
     workspace: &str,
     left_col: usize,
     top_row: usize,
+    details: bool,
 ) -> PaneNode {
     match tree {
         Tree::Empty => PaneNode::Empty,
@@ -242,7 +243,7 @@ fn pane_tree(
             let data = data.unwrap();
             PaneNode::Split {
                 left: Box::new(pane_tree(
-                    left, tab_id, window_id, active, zoomed, workspace, left_col, top_row,
+                    left, tab_id, window_id, active, zoomed, workspace, left_col, top_row, details,
                 )),
                 right: Box::new(pane_tree(
                     right,
@@ -261,6 +262,7 @@ fn pane_tree(
                     } else {
                         top_row + data.top_of_second()
                     },
+                    details,
                 )),
                 node: data,
             }
@@ -291,6 +293,18 @@ fn pane_tree(
                 left_col,
                 top_row,
                 tty_name: pane.tty_name(),
+                domain_name: if details {
+                    Mux::try_get()
+                        .and_then(|mux| mux.get_domain(pane.domain_id()))
+                        .map(|domain| domain.domain_name().to_string())
+                } else {
+                    None
+                },
+                foreground_process_name: if details {
+                    pane.get_foreground_process_name(CachePolicy::AllowStale)
+                } else {
+                    None
+                },
             })
         }
     }
@@ -567,7 +581,13 @@ impl Tab {
     }
 
     pub fn codec_pane_tree(&self) -> PaneNode {
-        self.inner.lock().codec_pane_tree()
+        self.inner.lock().codec_pane_tree(false)
+    }
+
+    /// Like `codec_pane_tree`, but also fills in the details of each
+    /// pane that are costly to compute, such as its foreground process
+    pub fn codec_pane_tree_with_details(&self) -> PaneNode {
+        self.inner.lock().codec_pane_tree(true)
     }
 
     /// Returns a count of how many panes are in this tab
@@ -857,7 +877,7 @@ impl TabInner {
         assert!(self.pane.is_some());
     }
 
-    fn codec_pane_tree(&mut self) -> PaneNode {
+    fn codec_pane_tree(&mut self, details: bool) -> PaneNode {
         let mux = Mux::get();
         let tab_id = self.id;
         let window_id = match mux.window_containing_tab(tab_id) {
@@ -891,6 +911,7 @@ impl TabInner {
                 &workspace,
                 0,
                 0,
+                details,
             )
         } else {
             PaneNode::Empty
@@ -2276,6 +2297,10 @@ pub struct PaneEntry {
     pub top_row: usize,
     pub left_col: usize,
     pub tty_name: Option<String>,
+    /// Only filled in by `Tab::codec_pane_tree_with_details`
+    pub domain_name: Option<String>,
+    /// Only filled in by `Tab::codec_pane_tree_with_details`
+    pub foreground_process_name: Option<String>,
 }
 
 #[derive(Deserialize, Clone, Serialize, PartialEq, Debug)]
@@ -2317,6 +2342,7 @@ mod test {
     use parking_lot::{MappedMutexGuard, Mutex};
     use rangeset::RangeSet;
     use std::ops::Range;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use termwiz::surface::SequenceNo;
     use url::Url;
     use wezterm_term::color::ColorPalette;
@@ -2325,14 +2351,20 @@ mod test {
     struct FakePane {
         id: PaneId,
         size: Mutex<TerminalSize>,
+        process_name_lookups: AtomicUsize,
     }
 
     impl FakePane {
         fn new(id: PaneId, size: TerminalSize) -> Arc<dyn Pane> {
-            Arc::new(Self {
+            Arc::new(Self::new_concrete(id, size))
+        }
+
+        fn new_concrete(id: PaneId, size: TerminalSize) -> Self {
+            Self {
                 id,
                 size: Mutex::new(size),
-            })
+                process_name_lookups: AtomicUsize::new(0),
+            }
         }
     }
 
@@ -2342,7 +2374,7 @@ mod test {
         }
 
         fn get_cursor_position(&self) -> StableCursorPosition {
-            unimplemented!();
+            StableCursorPosition::default()
         }
 
         fn get_current_seqno(&self) -> SequenceNo {
@@ -2382,11 +2414,22 @@ mod test {
         }
 
         fn get_dimensions(&self) -> RenderableDimensions {
-            unimplemented!();
+            let size = *self.size.lock();
+            RenderableDimensions {
+                cols: size.cols,
+                viewport_rows: size.rows,
+                scrollback_rows: size.rows,
+                physical_top: 0,
+                scrollback_top: 0,
+                dpi: size.dpi,
+                pixel_width: size.pixel_width,
+                pixel_height: size.pixel_height,
+                reverse_video: false,
+            }
         }
 
         fn get_title(&self) -> String {
-            unimplemented!()
+            format!("pane {}", self.id)
         }
         fn send_paste(&self, _text: &str) -> anyhow::Result<()> {
             unimplemented!()
@@ -2429,6 +2472,57 @@ mod test {
         fn get_current_working_dir(&self, _policy: CachePolicy) -> Option<Url> {
             None
         }
+        fn get_foreground_process_name(&self, _policy: CachePolicy) -> Option<String> {
+            self.process_name_lookups.fetch_add(1, Ordering::SeqCst);
+            Some("/usr/bin/vim".to_string())
+        }
+    }
+
+    fn pane_entry(tab: &Tab, details: bool) -> PaneEntry {
+        let inner = tab.inner.lock();
+        let tree = pane_tree(
+            inner.pane.as_ref().unwrap(),
+            inner.id,
+            0,
+            None,
+            None,
+            "default",
+            0,
+            0,
+            details,
+        );
+        match tree {
+            PaneNode::Leaf(entry) => entry,
+            _ => panic!("expected a single pane"),
+        }
+    }
+
+    #[test]
+    fn pane_tree_details() {
+        let size = TerminalSize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+            dpi: 96,
+        };
+        let pane = Arc::new(FakePane::new_concrete(1, size));
+        let tab = Tab::new(&size);
+        tab.assign_pane(&(Arc::clone(&pane) as Arc<dyn Pane>));
+
+        let entry = pane_entry(&tab, false);
+        assert_eq!(entry.title, "pane 1");
+        assert_eq!(entry.size.cols, 80);
+        assert_eq!(entry.foreground_process_name, None);
+        assert_eq!(entry.domain_name, None);
+        assert_eq!(pane.process_name_lookups.load(Ordering::SeqCst), 0);
+
+        let entry = pane_entry(&tab, true);
+        assert_eq!(
+            entry.foreground_process_name.as_deref(),
+            Some("/usr/bin/vim")
+        );
+        assert_eq!(pane.process_name_lookups.load(Ordering::SeqCst), 1);
     }
 
     #[test]
@@ -0,0 +1,141 @@
+//! Streams the raw output of live panes to subscribers, such as
+//! `arb cli tail --follow`.
+//!
+//! Like recording, this taps the output of a local pane before it is
+//! parsed, so subscribers see exactly what the program in the pane wrote.
+//! The output is delivered as it is applied to the terminal, so that a
+//! snapshot of the pane taken when subscribing neither overlaps with nor
+//! misses any of the streamed output.
+use crate::localpane::LocalPane;
+use crate::pane::{Pane, PaneId};
+use parking_lot::Mutex;
+use smol::channel::{bounded, Receiver, Sender};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The number of chunks of output that may be waiting for a subscriber
+/// before it is considered to have fallen behind
+const MAX_PENDING: usize = 1024;
+
+/// What is delivered to a subscriber
+#[derive(Debug, PartialEq)]
+pub enum TailOutput {
+    /// A chunk of output from the pane
+    Data(Vec<u8>),
+    /// The subscriber fell too far behind, so output was discarded
+    /// and the subscription has ended
+    Overflowed,
+}
+
+lazy_static::lazy_static! {
+    static ref SUBSCRIBERS: Mutex<HashMap<PaneId, Vec<Sender<TailOutput>>>> = Mutex::new(HashMap::new());
+}
+
+/// The number of active subscriptions; allows the output parser to
+/// skip taking the lock in the common case where nobody is tailing
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Subscribes to the output of `pane`.  `snapshot` is called to capture
+/// the current state of the pane without any output being applied in
+/// the meantime, so that the stream continues from exactly that state.
+///
+/// Each chunk of output is delivered to the returned receiver, which is
+/// closed when the pane's pty reaches EOF.  Dropping the receiver ends
+/// the subscription.
+pub fn subscribe_output<T, F: FnOnce() -> T>(
+    pane: &Arc<dyn Pane>,
+    snapshot: F,
+) -> anyhow::Result<(T, Receiver<TailOutput>)> {
+    let pane_id = pane.pane_id();
+    let local = pane.downcast_ref::<LocalPane>().ok_or_else(|| {
+        anyhow::anyhow!(
+            "pane {pane_id} is not a local pane; \
+             only panes whose pty is owned by this process can be tailed"
+        )
+    })?;
+    if pane.is_dead() {
+        anyhow::bail!("pane {pane_id} is dead");
+    }
+
+    let _applying = local.applying_output().write();
+    let (tx, rx) = bounded(MAX_PENDING);
+    SUBSCRIBERS.lock().entry(pane_id).or_default().push(tx);
+    ACTIVE.fetch_add(1, Ordering::SeqCst);
+    Ok((snapshot(), rx))
+}
+
+/// Delivers `data` to each of `senders`, removing those that have gone
+/// away.  A subscriber that has fallen behind is sent `Overflowed`, in
+/// the slot that is kept for it, and removed.
+fn deliver(senders: &mut Vec<Sender<TailOutput>>, data: &[u8]) {
+    senders.retain(|tx| {
+        if tx.len() + 1 >= MAX_PENDING {
+            tx.try_send(TailOutput::Overflowed).ok();
+            return false;
+        }
+        tx.try_send(TailOutput::Data(data.to_vec())).is_ok()
+    });
+}
+
+/// Called with each chunk of output as it is applied to the pane
+pub(crate) fn tail_output(pane_id: PaneId, data: &[u8]) {
+    if ACTIVE.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let mut subscribers = SUBSCRIBERS.lock();
+    if let Some(senders) = subscribers.get_mut(&pane_id) {
+        let before = senders.len();
+        deliver(senders, data);
+        ACTIVE.fetch_sub(before - senders.len(), Ordering::SeqCst);
+        if senders.is_empty() {
+            subscribers.remove(&pane_id);
+        }
+    }
+}
+
+/// Called once all of the output of the pane has been applied;
+/// closes its subscriptions
+pub(crate) fn pane_output_finished(pane_id: PaneId) {
+    if ACTIVE.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    if let Some(senders) = SUBSCRIBERS.lock().remove(&pane_id) {
+        ACTIVE.fetch_sub(senders.len(), Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn should_deliver_to_subscribers() {
+        let (tx1, rx1) = bounded(MAX_PENDING);
+        let (tx2, rx2) = bounded(MAX_PENDING);
+        let mut senders = vec![tx1, tx2];
+        drop(rx2);
+
+        deliver(&mut senders, b"hello");
+        assert_eq!(senders.len(), 1);
+        assert_eq!(rx1.try_recv(), Ok(TailOutput::Data(b"hello".to_vec())));
+    }
+
+    #[test]
+    fn should_end_subscription_on_overflow() {
+        let (tx, rx) = bounded(MAX_PENDING);
+        let mut senders = vec![tx];
+        for _ in 0..MAX_PENDING + 10 {
+            deliver(&mut senders, b"x");
+        }
+        assert!(senders.is_empty());
+
+        let received: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(received.len(), MAX_PENDING);
+        assert_eq!(received.last(), Some(&TailOutput::Overflowed));
+        assert!(received[..MAX_PENDING - 1]
+            .iter()
+            .all(|output| *output == TailOutput::Data(b"x".to_vec())));
+        assert!(rx.is_closed());
+    }
+}
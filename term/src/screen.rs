@@ -5,6 +5,7 @@ use log::debug;
 use std::collections::VecDeque;
 use std::sync::Arc;
 use termwiz::input::KeyboardEncoding;
+use wezterm_escape_parser::csi::DecPrivateModeCode;
 use wezterm_surface::SequenceNo;
 
 /// Holds the model of a screen.  This can either be the primary screen
@@ -37,6 +38,9 @@ pub struct Screen {
 
     pub(crate) keyboard_stack: Vec<KeyboardEncoding>,
 
+    /// The DEC private modes saved by XTSAVE, most recent last
+    pub(crate) saved_dec_modes: Vec<(DecPrivateModeCode, bool)>,
+
     /// Physical, visible height of the screen (not including scrollback)
     pub physical_rows: usize,
     /// Physical, visible width of the screen
@@ -85,12 +89,14 @@ impl Screen {
             stable_row_index_offset: 0,
             dpi: size.dpi,
             keyboard_stack: vec![],
+            saved_dec_modes: vec![],
             saved_cursor: None,
         }
     }
 
     pub fn full_reset(&mut self) {
         self.keyboard_stack.clear();
+        self.saved_dec_modes.clear();
     }
 
     fn scrollback_size(&self) -> usize {
@@ -1,7 +1,9 @@
 use super::*;
 use crate::terminalstate::performer::Performer;
 use std::sync::Arc;
+use wezterm_escape_parser::osc::Selection;
 use wezterm_escape_parser::parser::Parser;
+use wezterm_escape_parser::OperatingSystemCommand;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
@@ -10,12 +12,33 @@ pub enum ClipboardSelection {
     PrimarySelection,
 }
 
+impl ClipboardSelection {
+    /// Returns the OSC 52 sequence that reports `data` as the contents
+    /// of this selection, in response to a query
+    pub fn osc52_response(self, data: &str) -> String {
+        let selection = match self {
+            Self::Clipboard => Selection::CLIPBOARD,
+            Self::PrimarySelection => Selection::PRIMARY,
+        };
+        OperatingSystemCommand::SetSelection(selection, data.to_string()).to_string()
+    }
+}
+
 pub trait Clipboard: Send + Sync {
     fn set_contents(
         &self,
         selection: ClipboardSelection,
         data: Option<String>,
     ) -> anyhow::Result<()>;
+
+    /// Called when the application asks for the contents of `selection`
+    /// using OSC 52.  The implementation decides whether the request is
+    /// permitted, and if so, writes the `osc52_response` to the pane
+    /// once the contents are available.
+    /// The default is to ignore the request.
+    fn query_contents(&self, _selection: ClipboardSelection) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 impl Clipboard for Box<dyn Clipboard> {
@@ -26,6 +49,10 @@ impl Clipboard for Box<dyn Clipboard> {
     ) -> anyhow::Result<()> {
         self.as_ref().set_contents(selection, data)
     }
+
+    fn query_contents(&self, selection: ClipboardSelection) -> anyhow::Result<()> {
+        self.as_ref().query_contents(selection)
+    }
 }
 
 pub trait DeviceControlHandler: Send + Sync {
@@ -71,6 +98,8 @@ pub enum Alert {
     OutputSinceFocusLost,
     /// A change to the progress bar state
     Progress(Progress),
+    /// A command reported its exit status via OSC 133;D
+    CommandFinished(CommandBlock),
 }
 
 pub trait AlertHandler: Send + Sync {
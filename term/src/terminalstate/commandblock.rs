@@ -0,0 +1,197 @@
+//! Tracks command blocks: a prompt, the command that was entered at it
+//! and that command's output, as delimited by the OSC 133 semantic
+//! prompt markers that shell integration emits.
+use crate::{Alert, SemanticType, StableRowIndex, TerminalState};
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// Blocks beyond this many are discarded, oldest first, even if their
+/// rows are still present in the scrollback
+const MAX_COMMAND_BLOCKS: usize = 1024;
+
+/// A prompt and the command that was run from it
+#[cfg_attr(feature = "use_serde", derive(Deserialize, Serialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandBlock {
+    /// The first row of the prompt
+    pub start_row: StableRowIndex,
+    /// The command line, taken from the input zone of the prompt
+    /// when the command is started.  Leading whitespace is kept, as
+    /// shells use it to keep commands out of their history.
+    pub command: Option<String>,
+    /// The first row of output, once the command has been started
+    /// (OSC 133;C)
+    pub output_row: Option<StableRowIndex>,
+    /// The last row of the block, once the command has finished
+    pub end_row: Option<StableRowIndex>,
+    /// The exit status reported by OSC 133;D
+    pub exit_status: Option<i32>,
+    /// How long the command ran for, from OSC 133;C to OSC 133;D
+    pub duration: Option<Duration>,
+}
+
+impl CommandBlock {
+    pub fn is_finished(&self) -> bool {
+        self.end_row.is_some()
+    }
+
+    /// Returns true if the command reported a non-zero exit status
+    pub fn failed(&self) -> bool {
+        matches!(self.exit_status, Some(status) if status != 0)
+    }
+
+    /// The rows that hold the output of the command, if it has finished
+    /// and produced any
+    pub fn output_rows(&self) -> Option<std::ops::Range<StableRowIndex>> {
+        match (self.output_row, self.end_row) {
+            (Some(start), Some(end)) if end >= start => Some(start..end + 1),
+            _ => None,
+        }
+    }
+
+    pub fn contains_row(&self, row: StableRowIndex) -> bool {
+        row >= self.start_row && self.end_row.map(|end| row <= end).unwrap_or(true)
+    }
+}
+
+impl TerminalState {
+    fn cursor_stable_row(&self) -> StableRowIndex {
+        self.screen.visible_row_to_stable_row(self.cursor.y)
+    }
+
+    /// Called when a prompt is started by OSC 133;A or OSC 133;N
+    pub(crate) fn start_command_block(&mut self) {
+        if self.screen.is_alt_screen_active() {
+            return;
+        }
+        let row = self.cursor_stable_row();
+
+        if let Some(block) = self.command_blocks.back_mut() {
+            if block.output_row.is_none() {
+                // No command was run from the previous prompt; it was
+                // abandoned or is being redrawn, so replace it
+                self.command_blocks.pop_back();
+            } else if block.end_row.is_none() {
+                // The command finished without reporting its status
+                block.end_row = Some((row - 1).max(block.start_row));
+                self.command_started = None;
+            }
+        }
+
+        self.command_blocks.push_back(CommandBlock {
+            start_row: row,
+            command: None,
+            output_row: None,
+            end_row: None,
+            exit_status: None,
+            duration: None,
+        });
+        while self.command_blocks.len() > MAX_COMMAND_BLOCKS {
+            self.command_blocks.pop_front();
+        }
+    }
+
+    /// Called when the command starts to run, by OSC 133;C
+    pub(crate) fn start_command_output(&mut self) {
+        if self.screen.is_alt_screen_active() {
+            return;
+        }
+        let row = self.cursor_stable_row();
+
+        let start_row = match self.command_blocks.back() {
+            Some(block) if block.output_row.is_none() => Some(block.start_row),
+            _ => None,
+        };
+        let command = start_row.and_then(|start| self.input_text(start..row + 1));
+
+        match self.command_blocks.back_mut() {
+            Some(block) if block.output_row.is_none() => {
+                block.output_row = Some(row);
+                block.command = command;
+            }
+            _ => {
+                // Output without a prompt; start a block here so
+                // that the command still gets its status
+                self.command_blocks.push_back(CommandBlock {
+                    start_row: row,
+                    command: None,
+                    output_row: Some(row),
+                    end_row: None,
+                    exit_status: None,
+                    duration: None,
+                });
+            }
+        }
+        self.command_started = Some(Instant::now());
+    }
+
+    /// Called when the command reports its exit status, by OSC 133;D
+    pub(crate) fn finish_command_block(&mut self, status: i32) {
+        if self.screen.is_alt_screen_active() {
+            return;
+        }
+        let mut row = self.cursor_stable_row();
+        let at_left_margin = self.cursor.x == 0;
+
+        let block = match self.command_blocks.back_mut() {
+            Some(block) if block.output_row.is_some() && block.end_row.is_none() => block,
+            // The status of an empty command line isn't interesting
+            _ => return,
+        };
+        if at_left_margin {
+            // The cursor is on the fresh line that follows the output;
+            // if that is also the first output row, there was no output
+            row -= 1;
+        }
+        block.end_row = Some(row.max(block.start_row));
+        block.exit_status = Some(status);
+        block.duration = self.command_started.take().map(|start| start.elapsed());
+
+        let block = block.clone();
+        if let Some(handler) = self.alert_handler.as_mut() {
+            handler.alert(Alert::CommandFinished(block));
+        }
+    }
+
+    /// Returns the text in the rows that was marked up as input
+    fn input_text(&self, rows: Range<StableRowIndex>) -> Option<String> {
+        let mut command = String::new();
+        let phys_range = self.screen.stable_range(&rows);
+        self.screen.with_phys_lines(phys_range, |lines| {
+            for line in lines {
+                let input: String = line
+                    .visible_cells()
+                    .filter(|cell| cell.attrs().semantic_type() == SemanticType::Input)
+                    .map(|cell| cell.str().to_string())
+                    .collect();
+                if line.last_cell_was_wrapped() {
+                    command.push_str(&input);
+                } else {
+                    command.push_str(input.trim_end());
+                    if !command.is_empty() {
+                        command.push('\n');
+                    }
+                }
+            }
+        });
+        let command = command.trim_end();
+        if command.trim_start().is_empty() {
+            None
+        } else {
+            Some(command.to_string())
+        }
+    }
+
+    /// Returns the command blocks whose rows are still present
+    /// in the scrollback, oldest first
+    pub fn get_command_blocks(&self) -> Vec<CommandBlock> {
+        let first_row = self.screen.phys_to_stable_row_index(0);
+        self.command_blocks
+            .iter()
+            .filter(|block| block.end_row.map(|end| end >= first_row).unwrap_or(true))
+            .cloned()
+            .collect()
+    }
+}
@@ -12,8 +12,25 @@ use wezterm_surface::TextureCoordinate;
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PlacementInfo {
     pub first_row: StableRowIndex,
+    pub first_col: usize,
     pub rows: usize,
     pub cols: usize,
+    pub z_index: i32,
+}
+
+impl PlacementInfo {
+    /// Returns true if the placement covers the specified cell
+    pub fn contains(&self, x: usize, row: StableRowIndex) -> bool {
+        self.covers_col(x) && self.covers_row(row)
+    }
+
+    pub fn covers_col(&self, x: usize) -> bool {
+        x >= self.first_col && x < self.first_col + self.cols
+    }
+
+    pub fn covers_row(&self, row: StableRowIndex) -> bool {
+        row >= self.first_row && row < self.first_row + self.rows as StableRowIndex
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -248,8 +265,10 @@ impl TerminalState {
 
         Ok(PlacementInfo {
             first_row,
+            first_col: cursor_x,
             rows: height_in_cells,
             cols: width_in_cells,
+            z_index: params.z_index,
         })
     }
 
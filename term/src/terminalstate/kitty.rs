@@ -1,6 +1,9 @@
 use crate::terminalstate::image::*;
+use crate::terminalstate::kitty_placeholder::{
+    color_to_id, PlaceholderDiacritics, PlaceholderGeometry,
+};
 use crate::terminalstate::{ImageAttachParams, PlacementInfo};
-use crate::{StableRowIndex, TerminalState};
+use crate::{CellAttributes, Screen, StableRowIndex, TerminalState, VisibleRowIndex};
 use ::image::{
     DynamicImage, GenericImage, GenericImageView, ImageBuffer, RgbImage, Rgba, RgbaImage,
 };
@@ -9,13 +12,14 @@ use std::collections::{HashMap, HashSet};
 use std::io::Write;
 use std::sync::Arc;
 use std::time::Duration;
-use wezterm_cell::image::ImageDataType;
+use wezterm_cell::image::{ImageCell, ImageDataType};
 use wezterm_escape_parser::apc::{
     KittyFrameCompositionMode, KittyImage, KittyImageCompression, KittyImageData, KittyImageDelete,
     KittyImageFormat, KittyImageFrame, KittyImageFrameCompose, KittyImagePlacement,
     KittyImageTransmit, KittyImageVerbosity,
 };
 use wezterm_surface::change::ImageData;
+use wezterm_surface::TextureCoordinate;
 
 #[derive(Debug, Default)]
 pub struct KittyImageState {
@@ -24,9 +28,34 @@ pub struct KittyImageState {
     number_to_id: HashMap<u32, u32>,
     id_to_data: HashMap<u32, Arc<ImageData>>,
     placements: HashMap<(u32, Option<u32>), PlacementInfo>,
+    virtual_placements: HashMap<(u32, Option<u32>), VirtualPlacement>,
+    last_placeholder: Option<LastPlaceholder>,
     used_memory: usize,
 }
 
+/// A placement made with U=1, which is displayed by printing
+/// Unicode placeholder cells that reference it
+#[derive(Debug)]
+struct VirtualPlacement {
+    placement: KittyImagePlacement,
+    /// The rows in which placeholder cells have been mapped
+    /// to this placement
+    rows: HashSet<StableRowIndex>,
+}
+
+/// The most recently printed placeholder cell, from which
+/// the row, column and id of the next one can be inferred
+#[derive(Debug, Clone, Copy)]
+struct LastPlaceholder {
+    x: usize,
+    y: VisibleRowIndex,
+    id_low: u32,
+    placement_id: Option<u32>,
+    row: u32,
+    col: u32,
+    id_msb: u32,
+}
+
 impl KittyImageState {
     fn remove_data_for_id(&mut self, image_id: u32) {
         if let Some(data) = self.id_to_data.remove(&image_id) {
@@ -43,10 +72,22 @@ impl KittyImageState {
         self.id_to_data.insert(image_id, data);
     }
 
+    fn is_referenced(&self, image_id: u32) -> bool {
+        self.placements
+            .keys()
+            .chain(self.virtual_placements.keys())
+            .any(|(id, _)| *id == image_id)
+    }
+
     fn prune_unreferenced(&mut self) {
         let budget = 320 * 1024 * 1024; // FIXME: make this configurable
         if self.used_memory > budget {
-            let referenced: HashSet<u32> = self.placements.keys().map(|(k, _)| *k).collect();
+            let referenced: HashSet<u32> = self
+                .placements
+                .keys()
+                .chain(self.virtual_placements.keys())
+                .map(|(k, _)| *k)
+                .collect();
             let target = self.used_memory - budget;
             let mut freed = 0;
             self.id_to_data.retain(|id, data| {
@@ -100,6 +141,9 @@ impl TerminalState {
             placement,
             verbosity
         );
+        if placement.virtual_placement {
+            return self.kitty_img_place_virtual(image_id, placement);
+        }
         if image_id != 0 {
             self.kitty_remove_placement(image_id, placement.placement_id);
         }
@@ -145,6 +189,164 @@ impl TerminalState {
         Ok(())
     }
 
+    fn kitty_img_place_virtual(
+        &mut self,
+        image_id: u32,
+        placement: KittyImagePlacement,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.kitty_img.id_to_data.contains_key(&image_id),
+            "no matching image id {} in id_to_data for virtual placement",
+            image_id
+        );
+
+        // Placeholders that are already on screen keep showing the
+        // image; replacing the placement only affects those printed
+        // from here on
+        let key = (image_id, placement.placement_id);
+        match self.kitty_img.virtual_placements.get_mut(&key) {
+            Some(existing) => existing.placement = placement,
+            None => {
+                self.kitty_img.virtual_placements.insert(
+                    key,
+                    VirtualPlacement {
+                        placement,
+                        rows: HashSet::new(),
+                    },
+                );
+            }
+        }
+        log::trace!("record virtual placement {:?}", key);
+        Ok(())
+    }
+
+    /// Called before printing a Unicode placeholder at `x`, `y` with
+    /// `attrs`.  Attaches the slice of the image from the virtual
+    /// placement that it references to `attrs`.
+    pub(crate) fn kitty_placeholder(
+        &mut self,
+        x: usize,
+        y: VisibleRowIndex,
+        grapheme: &str,
+        attrs: &mut CellAttributes,
+    ) {
+        let diacritics = match PlaceholderDiacritics::parse(grapheme) {
+            Some(diacritics) => diacritics,
+            None => return,
+        };
+        let id_low = match color_to_id(attrs.foreground()) {
+            Some(id) => id,
+            None => return,
+        };
+        let placement_id = color_to_id(attrs.underline_color()).filter(|&id| id != 0);
+
+        // Anything that isn't specified is carried over from the
+        // placeholder immediately to the left, if it refers to the
+        // same placement
+        let prev = self.kitty_img.last_placeholder.take().filter(|prev| {
+            prev.x + 1 == x
+                && prev.y == y
+                && prev.id_low == id_low
+                && prev.placement_id == placement_id
+        });
+        let row = match (diacritics.row, prev) {
+            (Some(row), _) => row,
+            (None, Some(prev)) => prev.row,
+            (None, None) => 0,
+        };
+        let col = match (diacritics.col, prev) {
+            (Some(col), _) => col,
+            (None, Some(prev)) if prev.row == row => prev.col + 1,
+            _ => 0,
+        };
+        let id_msb = match (diacritics.id_msb, prev) {
+            (Some(msb), _) => msb,
+            (None, Some(prev)) if prev.row == row && prev.col + 1 == col => prev.id_msb,
+            _ => 0,
+        };
+        self.kitty_img.last_placeholder = Some(LastPlaceholder {
+            x,
+            y,
+            id_low,
+            placement_id,
+            row,
+            col,
+            id_msb,
+        });
+
+        let image_id = (id_msb << 24) | (id_low & 0xff_ffff);
+        let key = match placement_id {
+            Some(_) => (image_id, placement_id),
+            // Without a placement id, use any virtual placement of the image
+            None => match self
+                .kitty_img
+                .virtual_placements
+                .keys()
+                .filter(|(id, _)| *id == image_id)
+                .min()
+            {
+                Some(key) => *key,
+                None => return,
+            },
+        };
+        let data = match self.kitty_img.id_to_data.get(&image_id) {
+            Some(data) => Arc::clone(data),
+            None => return,
+        };
+        let (image_width, image_height) = match data.data().dimensions() {
+            Ok(dims) => dims,
+            Err(_) => return,
+        };
+
+        let cell_width = self.pixel_width / self.screen().physical_cols;
+        let cell_height = self.pixel_height / self.screen().physical_rows;
+        let stable_row = self.screen().visible_row_to_stable_row(y);
+        let virt = match self.kitty_img.virtual_placements.get_mut(&key) {
+            Some(virt) => virt,
+            None => return,
+        };
+        let placement = &virt.placement;
+        let src_x = placement.x.unwrap_or(0).min(image_width);
+        let src_y = placement.y.unwrap_or(0).min(image_height);
+        let geometry = PlaceholderGeometry {
+            image_width,
+            image_height,
+            source: (
+                src_x,
+                src_y,
+                placement.w.unwrap_or(image_width).min(image_width - src_x),
+                placement
+                    .h
+                    .unwrap_or(image_height)
+                    .min(image_height - src_y),
+            ),
+            cell_width,
+            cell_height,
+            columns: placement.columns,
+            rows: placement.rows,
+        };
+        let slice = match geometry.slice(col, row) {
+            Some(slice) => slice,
+            None => return,
+        };
+
+        attrs.attach_image(Box::new(ImageCell::with_z_index(
+            TextureCoordinate::new_f32(slice.top_left.0, slice.top_left.1),
+            TextureCoordinate::new_f32(slice.bottom_right.0, slice.bottom_right.1),
+            data,
+            placement.z_index.unwrap_or(0),
+            slice.padding_left,
+            slice.padding_top,
+            slice.padding_right,
+            slice.padding_bottom,
+            Some(image_id),
+            key.1,
+        )));
+        // The image is drawn in place of the placeholder character
+        attrs.set_invisible(true);
+        virt.rows.insert(stable_row);
+    }
+
     fn kitty_img_inner(&mut self, img: KittyImage) -> anyhow::Result<()> {
         match self
             .coalesce_kitty_accumulation(img)
@@ -238,37 +440,11 @@ impl TerminalState {
             } => {
                 self.kitty_img_place(image_id, image_number, placement, verbosity)?;
             }
-            KittyImage::Delete {
-                what:
-                    KittyImageDelete::ByImageId {
-                        image_id,
-                        placement_id,
-                        delete,
-                    },
-                verbosity,
-            } => {
-                log::trace!(
-                    "remove a placement: image_id {} placement_id {:?} delete {} verb {:?}",
-                    image_id,
-                    placement_id,
-                    delete,
-                    verbosity
-                );
-
-                self.kitty_remove_placement(image_id, placement_id);
-
-                if delete {
-                    self.kitty_img.remove_data_for_id(image_id);
-                }
-            }
-            KittyImage::Delete {
-                what: KittyImageDelete::All { delete },
-                verbosity: _,
-            } => {
-                self.kitty_remove_all_placements(delete);
-            }
             KittyImage::Delete { what, verbosity } => {
-                log::warn!("unhandled KittyImage::Delete {:?} {:?}", what, verbosity);
+                log::trace!("delete {:?} verb {:?}", what, verbosity);
+                if let Err(err) = self.kitty_img_delete(what) {
+                    log::error!("Error {:#} while handling KittyImage::Delete", err);
+                }
             }
             KittyImage::TransmitFrame {
                 transmit,
@@ -289,16 +465,280 @@ impl TerminalState {
         Ok(())
     }
 
+    fn kitty_img_delete(&mut self, what: KittyImageDelete) -> anyhow::Result<()> {
+        // Cell coordinates in delete commands are 1-based and
+        // relative to the visible screen
+        let col = |x: u32| x.saturating_sub(1) as usize;
+        let row = |screen: &Screen, y: u32| {
+            screen.visible_row_to_stable_row(y.saturating_sub(1) as VisibleRowIndex)
+        };
+
+        match what {
+            KittyImageDelete::All { delete } => {
+                let screen = self.screen();
+                let top = screen.visible_row_to_stable_row(0);
+                let bottom = top + screen.physical_rows as StableRowIndex;
+                let removed = self.kitty_remove_placements_where(|_, _, info| {
+                    info.first_row < bottom && info.first_row + info.rows as StableRowIndex > top
+                });
+                if delete {
+                    self.kitty_free_unreferenced(removed);
+                }
+            }
+            KittyImageDelete::ByImageId {
+                image_id,
+                placement_id,
+                delete,
+            } => {
+                self.kitty_remove_placement(image_id, placement_id);
+                if delete {
+                    self.kitty_free_unreferenced([image_id]);
+                }
+            }
+            KittyImageDelete::ByImageNumber {
+                image_number,
+                placement_id,
+                delete,
+            } => {
+                // number_to_id tracks the newest image with a given number
+                if let Some(image_id) = self.kitty_img.number_to_id.get(&image_number).copied() {
+                    self.kitty_remove_placement(image_id, placement_id);
+                    if delete {
+                        self.kitty_free_unreferenced([image_id]);
+                    }
+                }
+            }
+            KittyImageDelete::AtCursorPosition { delete } => {
+                let x = self.cursor.x;
+                let y = self.screen().visible_row_to_stable_row(self.cursor.y);
+                let removed = self.kitty_remove_placements_where(|_, _, info| info.contains(x, y));
+                if delete {
+                    self.kitty_free_unreferenced(removed);
+                }
+            }
+            KittyImageDelete::AnimationFrames {
+                image_id,
+                image_number,
+                frame_number,
+                delete,
+            } => {
+                self.kitty_delete_frame(image_id, image_number, frame_number, delete)?;
+            }
+            KittyImageDelete::DeleteAt { x, y, delete } => {
+                let x = col(x);
+                let y = row(self.screen(), y);
+                let removed = self.kitty_remove_placements_where(|_, _, info| info.contains(x, y));
+                if delete {
+                    self.kitty_free_unreferenced(removed);
+                }
+            }
+            KittyImageDelete::DeleteAtZ { x, y, z, delete } => {
+                let x = col(x);
+                let y = row(self.screen(), y);
+                let removed = self.kitty_remove_placements_where(|_, _, info| {
+                    info.z_index == z && info.contains(x, y)
+                });
+                if delete {
+                    self.kitty_free_unreferenced(removed);
+                }
+            }
+            KittyImageDelete::DeleteColumn { x, delete } => {
+                let x = col(x);
+                let removed = self.kitty_remove_placements_where(|_, _, info| info.covers_col(x));
+                if delete {
+                    self.kitty_free_unreferenced(removed);
+                }
+            }
+            KittyImageDelete::DeleteRow { y, delete } => {
+                let y = row(self.screen(), y);
+                let removed = self.kitty_remove_placements_where(|_, _, info| info.covers_row(y));
+                if delete {
+                    self.kitty_free_unreferenced(removed);
+                }
+            }
+            KittyImageDelete::DeleteZ { z, delete } => {
+                let removed = self.kitty_remove_placements_where(|_, _, info| info.z_index == z);
+                if delete {
+                    self.kitty_free_unreferenced(removed);
+                }
+            }
+            KittyImageDelete::DeleteIdRange { start, end, delete } => {
+                let ids = start..=end;
+                let mut removed =
+                    self.kitty_remove_placements_where(|image_id, _, _| ids.contains(&image_id));
+                removed.extend(
+                    self.kitty_remove_virtual_placements_where(|image_id, _| {
+                        ids.contains(&image_id)
+                    }),
+                );
+                if delete {
+                    // Also free images that were never placed
+                    removed.extend(
+                        self.kitty_img
+                            .id_to_data
+                            .keys()
+                            .filter(|image_id| ids.contains(image_id)),
+                    );
+                    self.kitty_free_unreferenced(removed);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the placements for which `pred` returns true,
+    /// returning the ids of the images that they belonged to
+    fn kitty_remove_placements_where<F: Fn(u32, Option<u32>, &PlacementInfo) -> bool>(
+        &mut self,
+        pred: F,
+    ) -> HashSet<u32> {
+        let matched: Vec<(u32, Option<u32>)> = self
+            .kitty_img
+            .placements
+            .iter()
+            .filter(|((image_id, p), info)| pred(*image_id, *p, info))
+            .map(|(key, _)| *key)
+            .collect();
+
+        let mut image_ids = HashSet::new();
+        for (image_id, p) in matched {
+            if let Some(info) = self.kitty_img.placements.remove(&(image_id, p)) {
+                self.kitty_remove_placement_from_model(image_id, p, info);
+            }
+            image_ids.insert(image_id);
+        }
+        image_ids
+    }
+
+    /// Removes the virtual placements for which `pred` returns true,
+    /// detaching their images from the placeholder cells that
+    /// reference them, and returns the ids of their images
+    fn kitty_remove_virtual_placements_where<F: Fn(u32, Option<u32>) -> bool>(
+        &mut self,
+        pred: F,
+    ) -> HashSet<u32> {
+        let matched: Vec<(u32, Option<u32>)> = self
+            .kitty_img
+            .virtual_placements
+            .keys()
+            .filter(|(image_id, p)| pred(*image_id, *p))
+            .copied()
+            .collect();
+
+        let mut image_ids = HashSet::new();
+        for (image_id, p) in matched {
+            if let Some(virt) = self.kitty_img.virtual_placements.remove(&(image_id, p)) {
+                for row in virt.rows {
+                    self.kitty_detach_image(image_id, p, row..row + 1);
+                }
+            }
+            image_ids.insert(image_id);
+        }
+        image_ids
+    }
+
+    /// Frees the data for those of `image_ids` that no longer
+    /// have any placements
+    fn kitty_free_unreferenced(&mut self, image_ids: impl IntoIterator<Item = u32>) {
+        for image_id in image_ids {
+            if self.kitty_img.is_referenced(image_id) {
+                continue;
+            }
+            self.kitty_img.remove_data_for_id(image_id);
+            self.kitty_img.number_to_id.retain(|_, id| *id != image_id);
+        }
+    }
+
+    fn kitty_delete_frame(
+        &mut self,
+        image_id: Option<u32>,
+        image_number: Option<u32>,
+        frame_number: Option<u32>,
+        delete: bool,
+    ) -> anyhow::Result<()> {
+        let image_id = match (image_id, image_number) {
+            (Some(id), _) => id,
+            (None, Some(no)) => *self
+                .kitty_img
+                .number_to_id
+                .get(&no)
+                .ok_or_else(|| anyhow::anyhow!("no such image_number {}", no))?,
+            (None, None) => anyhow::bail!("no image_id or image_number specified!"),
+        };
+
+        let img = Arc::clone(
+            self.kitty_img
+                .id_to_data
+                .get(&image_id)
+                .ok_or_else(|| anyhow::anyhow!("invalid image id {}", image_id))?,
+        );
+        let len_before = img.len();
+
+        let mut data = img.data();
+        if let ImageDataType::AnimRgba8 {
+            width,
+            height,
+            frames,
+            durations,
+            hashes,
+        } = &mut *data
+        {
+            let frame_no = frame_number.unwrap_or(1).clamp(1, frames.len() as u32) as usize;
+            frames.remove(frame_no - 1);
+            durations.remove(frame_no - 1);
+            hashes.remove(frame_no - 1);
+
+            if frames.len() == 1 {
+                *data = ImageDataType::Rgba8 {
+                    width: *width,
+                    height: *height,
+                    data: frames.remove(0),
+                    hash: hashes[0],
+                };
+            }
+            drop(data);
+
+            self.kitty_img.used_memory = self
+                .kitty_img
+                .used_memory
+                .saturating_sub(len_before.saturating_sub(img.len()));
+            return Ok(());
+        }
+        drop(data);
+
+        // There is only a single frame; deleting it with d=F
+        // frees the image
+        if delete {
+            self.kitty_remove_placement(image_id, None);
+            self.kitty_free_unreferenced([image_id]);
+        }
+
+        Ok(())
+    }
+
     fn kitty_remove_placement_from_model(
         &mut self,
         image_id: u32,
         placement_id: Option<u32>,
         info: PlacementInfo,
+    ) {
+        self.kitty_detach_image(
+            image_id,
+            placement_id,
+            info.first_row..info.first_row + info.rows as StableRowIndex,
+        );
+    }
+
+    fn kitty_detach_image(
+        &mut self,
+        image_id: u32,
+        placement_id: Option<u32>,
+        rows: std::ops::Range<StableRowIndex>,
     ) {
         let seqno = self.seqno;
         let screen = self.screen_mut();
-        let range =
-            screen.stable_range(&(info.first_row..info.first_row + info.rows as StableRowIndex));
+        let range = screen.stable_range(&rows);
         for idx in range {
             let line = screen.line_mut(idx);
             for c in line.cells_mut() {
@@ -310,6 +750,9 @@ impl TerminalState {
     }
 
     fn kitty_remove_placement(&mut self, image_id: u32, placement_id: Option<u32>) {
+        self.kitty_remove_virtual_placements_where(|id, p| {
+            id == image_id && (placement_id.is_none() || p == placement_id)
+        });
         if placement_id.is_some() {
             if let Some(info) = self.kitty_img.placements.remove(&(image_id, placement_id)) {
                 log::trace!("removed placement {} {:?}", image_id, placement_id);
@@ -341,6 +784,8 @@ impl TerminalState {
         for ((image_id, p), info) in std::mem::take(&mut self.kitty_img.placements).into_iter() {
             self.kitty_remove_placement_from_model(image_id, p, info);
         }
+        self.kitty_remove_virtual_placements_where(|_, _| true);
+        self.kitty_img.last_placeholder.take();
         if delete {
             self.kitty_img.id_to_data.clear();
             self.kitty_img.used_memory = 0;
@@ -0,0 +1,196 @@
+//! Support for the Unicode placeholders used by virtual placements
+//! in the kitty graphics protocol.
+//!
+//! A placeholder is the character U+10EEEE, optionally followed by
+//! up to three combining characters from `DIACRITICS` whose position
+//! in that table encodes the row and column of the image slice to
+//! display in that cell, and the most significant byte of the image id.
+//! The remaining bits of the image id are encoded in the foreground
+//! color, and the placement id, if any, in the underline color.
+//! <https://sw.kovidgoyal.net/kitty/graphics-protocol/#unicode-placeholders>
+use crate::color::ColorAttribute;
+
+pub const PLACEHOLDER: char = '\u{10EEEE}';
+
+/// The combining characters used to encode numbers in placeholders;
+/// the number is the index into this table.
+/// This is kitty's rowcolumn-diacritics.txt
+const DIACRITICS: &[u32] = &[
+    0x0305, 0x030D, 0x030E, 0x0310, 0x0312, 0x033D, 0x033E, 0x033F, 0x0346, 0x034A, 0x034B, 0x034C,
+    0x0350, 0x0351, 0x0352, 0x0357, 0x035B, 0x0363, 0x0364, 0x0365, 0x0366, 0x0367, 0x0368, 0x0369,
+    0x036A, 0x036B, 0x036C, 0x036D, 0x036E, 0x036F, 0x0483, 0x0484, 0x0485, 0x0486, 0x0487, 0x0592,
+    0x0593, 0x0594, 0x0595, 0x0597, 0x0598, 0x0599, 0x059C, 0x059D, 0x059E, 0x059F, 0x05A0, 0x05A1,
+    0x05A8, 0x05A9, 0x05AB, 0x05AC, 0x05AF, 0x05C4, 0x0610, 0x0611, 0x0612, 0x0613, 0x0614, 0x0615,
+    0x0616, 0x0617, 0x0657, 0x0658, 0x0659, 0x065A, 0x065B, 0x065D, 0x065E, 0x06D6, 0x06D7, 0x06D8,
+    0x06D9, 0x06DA, 0x06DB, 0x06DC, 0x06DF, 0x06E0, 0x06E1, 0x06E2, 0x06E4, 0x06E7, 0x06E8, 0x06EB,
+    0x06EC, 0x0730, 0x0732, 0x0733, 0x0735, 0x0736, 0x073A, 0x073D, 0x073F, 0x0740, 0x0741, 0x0743,
+    0x0745, 0x0747, 0x0749, 0x074A, 0x07EB, 0x07EC, 0x07ED, 0x07EE, 0x07EF, 0x07F0, 0x07F1, 0x07F3,
+    0x0816, 0x0817, 0x0818, 0x0819, 0x081B, 0x081C, 0x081D, 0x081E, 0x081F, 0x0820, 0x0821, 0x0822,
+    0x0823, 0x0825, 0x0826, 0x0827, 0x0829, 0x082A, 0x082B, 0x082C, 0x082D, 0x0951, 0x0953, 0x0954,
+    0x0F82, 0x0F83, 0x0F86, 0x0F87, 0x135D, 0x135E, 0x135F, 0x17DD, 0x193A, 0x1A17, 0x1A75, 0x1A76,
+    0x1A77, 0x1A78, 0x1A79, 0x1A7A, 0x1A7B, 0x1A7C, 0x1B6B, 0x1B6D, 0x1B6E, 0x1B6F, 0x1B70, 0x1B71,
+    0x1B72, 0x1B73, 0x1CD0, 0x1CD1, 0x1CD2, 0x1CDA, 0x1CDB, 0x1CE0, 0x1DC0, 0x1DC1, 0x1DC3, 0x1DC4,
+    0x1DC5, 0x1DC6, 0x1DC7, 0x1DC8, 0x1DC9, 0x1DCB, 0x1DCC, 0x1DD1, 0x1DD2, 0x1DD3, 0x1DD4, 0x1DD5,
+    0x1DD6, 0x1DD7, 0x1DD8, 0x1DD9, 0x1DDA, 0x1DDB, 0x1DDC, 0x1DDD, 0x1DDE, 0x1DDF, 0x1DE0, 0x1DE1,
+    0x1DE2, 0x1DE3, 0x1DE4, 0x1DE5, 0x1DE6, 0x1DFE, 0x20D0, 0x20D1, 0x20D4, 0x20D5, 0x20D6, 0x20D7,
+    0x20DB, 0x20DC, 0x20E1, 0x20E7, 0x20E9, 0x20F0, 0x2CEF, 0x2CF0, 0x2CF1, 0x2DE0, 0x2DE1, 0x2DE2,
+    0x2DE3, 0x2DE4, 0x2DE5, 0x2DE6, 0x2DE7, 0x2DE8, 0x2DE9, 0x2DEA, 0x2DEB, 0x2DEC, 0x2DED, 0x2DEE,
+    0x2DEF, 0x2DF0, 0x2DF1, 0x2DF2, 0x2DF3, 0x2DF4, 0x2DF5, 0x2DF6, 0x2DF7, 0x2DF8, 0x2DF9, 0x2DFA,
+    0x2DFB, 0x2DFC, 0x2DFD, 0x2DFE, 0x2DFF, 0xA66F, 0xA67C, 0xA67D, 0xA6F0, 0xA6F1, 0xA8E0, 0xA8E1,
+    0xA8E2, 0xA8E3, 0xA8E4, 0xA8E5, 0xA8E6, 0xA8E7, 0xA8E8, 0xA8E9, 0xA8EA, 0xA8EB, 0xA8EC, 0xA8ED,
+    0xA8EE, 0xA8EF, 0xA8F0, 0xA8F1, 0xAAB0, 0xAAB2, 0xAAB3, 0xAAB7, 0xAAB8, 0xAABE, 0xAABF, 0xAAC1,
+    0xFE20, 0xFE21, 0xFE22, 0xFE23, 0xFE24, 0xFE25, 0xFE26, 0x10A0F, 0x10A38, 0x1D185, 0x1D186,
+    0x1D187, 0x1D188, 0x1D189, 0x1D1AA, 0x1D1AB, 0x1D1AC, 0x1D1AD, 0x1D242, 0x1D243, 0x1D244,
+];
+
+fn diacritic_value(c: char) -> Option<u32> {
+    DIACRITICS
+        .binary_search(&(c as u32))
+        .ok()
+        .map(|idx| idx as u32)
+}
+
+/// The numbers encoded by the diacritics that follow a placeholder.
+/// Any that are missing are inferred from the placeholder to the left.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlaceholderDiacritics {
+    pub row: Option<u32>,
+    pub col: Option<u32>,
+    pub id_msb: Option<u32>,
+}
+
+impl PlaceholderDiacritics {
+    /// Returns None if `grapheme` is not a placeholder
+    pub fn parse(grapheme: &str) -> Option<Self> {
+        let mut chars = grapheme.chars();
+        if chars.next() != Some(PLACEHOLDER) {
+            return None;
+        }
+        let mut values = chars.map(diacritic_value);
+        let mut next = || values.next().flatten();
+        Some(Self {
+            row: next(),
+            col: next(),
+            id_msb: next(),
+        })
+    }
+}
+
+/// Extracts the number encoded in the foreground or underline color
+/// of a placeholder: the 24 bits of a true color, or the palette index
+pub fn color_to_id(color: ColorAttribute) -> Option<u32> {
+    match color {
+        ColorAttribute::TrueColorWithPaletteFallback(color, _)
+        | ColorAttribute::TrueColorWithDefaultFallback(color) => {
+            let (r, g, b, _) = color.to_srgb_u8();
+            Some(((r as u32) << 16) | ((g as u32) << 8) | b as u32)
+        }
+        ColorAttribute::PaletteIndex(idx) => Some(idx as u32),
+        ColorAttribute::Default => None,
+    }
+}
+
+/// The portion of an image to draw in a placeholder cell
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaceholderSlice {
+    /// Texture coordinates, normalized to the whole image
+    pub top_left: (f32, f32),
+    pub bottom_right: (f32, f32),
+    /// Pixels of the cell that are not covered by the image
+    pub padding_left: u16,
+    pub padding_top: u16,
+    pub padding_right: u16,
+    pub padding_bottom: u16,
+}
+
+/// Describes how the source rectangle of an image is laid out
+/// over the cells of a virtual placement
+#[derive(Debug, Clone, Copy)]
+pub struct PlaceholderGeometry {
+    pub image_width: u32,
+    pub image_height: u32,
+    /// The source rectangle; x, y, width, height in pixels
+    pub source: (u32, u32, u32, u32),
+    pub cell_width: usize,
+    pub cell_height: usize,
+    pub columns: Option<u32>,
+    pub rows: Option<u32>,
+}
+
+impl PlaceholderGeometry {
+    /// Returns the size of the placement in cells.  If only one of
+    /// the dimensions was specified, the other is chosen to preserve
+    /// the aspect ratio of the source rectangle.
+    pub fn size_in_cells(&self) -> (u32, u32) {
+        let (_, _, src_w, src_h) = self.source;
+        let cell_w = self.cell_width.max(1) as f32;
+        let cell_h = self.cell_height.max(1) as f32;
+        let src_w = src_w.max(1) as f32;
+        let src_h = src_h.max(1) as f32;
+        match (self.columns, self.rows) {
+            (Some(cols), Some(rows)) => (cols, rows),
+            (Some(cols), None) => (
+                cols,
+                (cols as f32 * cell_w * src_h / src_w / cell_h).ceil() as u32,
+            ),
+            (None, Some(rows)) => (
+                (rows as f32 * cell_h * src_w / src_h / cell_w).ceil() as u32,
+                rows,
+            ),
+            (None, None) => (
+                (src_w / cell_w).ceil() as u32,
+                (src_h / cell_h).ceil() as u32,
+            ),
+        }
+    }
+
+    /// Computes the slice of the image for the cell at `col`, `row` of
+    /// the placement.  The image is scaled to fit the placement while
+    /// preserving its aspect ratio, and centered within it, so some
+    /// cells may not show any of it.
+    pub fn slice(&self, col: u32, row: u32) -> Option<PlaceholderSlice> {
+        let (cols, rows) = self.size_in_cells();
+        if col >= cols || row >= rows {
+            return None;
+        }
+        let (src_x, src_y, src_w, src_h) = self.source;
+        if src_w == 0 || src_h == 0 || self.image_width == 0 || self.image_height == 0 {
+            return None;
+        }
+
+        let cell_w = self.cell_width as f32;
+        let cell_h = self.cell_height as f32;
+        let box_w = cols as f32 * cell_w;
+        let box_h = rows as f32 * cell_h;
+        let scale = (box_w / src_w as f32).min(box_h / src_h as f32);
+        let img_w = src_w as f32 * scale;
+        let img_h = src_h as f32 * scale;
+        let off_x = (box_w - img_w) / 2.;
+        let off_y = (box_h - img_h) / 2.;
+
+        let x0 = col as f32 * cell_w;
+        let x1 = x0 + cell_w;
+        let y0 = row as f32 * cell_h;
+        let y1 = y0 + cell_h;
+
+        let ix0 = x0.max(off_x);
+        let ix1 = x1.min(off_x + img_w);
+        let iy0 = y0.max(off_y);
+        let iy1 = y1.min(off_y + img_h);
+        if ix0 >= ix1 || iy0 >= iy1 {
+            return None;
+        }
+
+        let u = |x: f32| (src_x as f32 + (x - off_x) / scale) / self.image_width as f32;
+        let v = |y: f32| (src_y as f32 + (y - off_y) / scale) / self.image_height as f32;
+
+        Some(PlaceholderSlice {
+            top_left: (u(ix0), v(iy0)),
+            bottom_right: (u(ix1), v(iy1)),
+            padding_left: (ix0 - x0).round() as u16,
+            padding_top: (iy0 - y0).round() as u16,
+            padding_right: (x1 - ix1).round() as u16,
+            padding_bottom: (y1 - iy1).round() as u16,
+        })
+    }
+}
@@ -6,11 +6,12 @@ use crate::color::{ColorPalette, RgbColor};
 use crate::config::{BidiMode, NewlineCanon};
 use log::debug;
 use num_traits::ToPrimitive;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::io::{BufWriter, Write};
 use std::num::NonZeroUsize;
 use std::sync::mpsc::{channel, Sender};
 use std::sync::Arc;
+use std::time::Instant;
 use terminfo::{Database, Value};
 use termwiz::input::KeyboardEncoding;
 use url::Url;
@@ -25,15 +26,19 @@ use wezterm_escape_parser::csi::{
 use wezterm_escape_parser::{OneBased, OperatingSystemCommand, CSI};
 use wezterm_surface::{CursorShape, CursorVisibility, SequenceNo};
 
+mod commandblock;
 mod image;
 mod iterm;
 mod keyboard;
 mod kitty;
+mod kitty_placeholder;
 mod mouse;
 pub(crate) mod performer;
+mod rectangle;
 mod sixel;
 use crate::terminalstate::image::*;
 use crate::terminalstate::kitty::*;
+pub use commandblock::CommandBlock;
 
 lazy_static::lazy_static! {
     static ref DB: Database = {
@@ -128,7 +133,6 @@ pub(crate) struct SavedCursor {
     dec_origin_mode: bool,
     g0_charset: CharSet,
     g1_charset: CharSet,
-    // TODO: selective_erase when supported
 }
 
 struct ScreenOrAlt {
@@ -241,6 +245,12 @@ impl ScreenOrAlt {
         self.screen.full_reset();
         self.alt_screen.full_reset();
     }
+
+    /// Forgets the cursor saved by DECSC on both screens
+    pub fn clear_saved_cursors(&mut self) {
+        self.screen.saved_cursor.take();
+        self.alt_screen.saved_cursor.take();
+    }
 }
 
 /// Manages the state for the terminal
@@ -261,6 +271,12 @@ pub struct TerminalState {
 
     clear_semantic_attribute_on_newline: bool,
 
+    /// Prompts and the commands run from them, as marked up
+    /// by OSC 133
+    command_blocks: VecDeque<CommandBlock>,
+    /// When the command of the last command block started running
+    command_started: Option<Instant>,
+
     /// If true, writing a character inserts a new cell
     insert: bool,
 
@@ -525,6 +541,8 @@ impl TerminalState {
             left_and_right_margin_mode: false,
             wrap_next: false,
             clear_semantic_attribute_on_newline: false,
+            command_blocks: VecDeque::new(),
+            command_started: None,
             // We default auto wrap to true even though the default for
             // a dec terminal is false, because it is more useful this way.
             dec_auto_wrap: true,
@@ -719,6 +737,13 @@ impl TerminalState {
         Ok(())
     }
 
+    fn query_clipboard_contents(&self, selection: ClipboardSelection) -> anyhow::Result<()> {
+        if let Some(clip) = self.clipboard.as_ref() {
+            clip.query_contents(selection)?;
+        }
+        Ok(())
+    }
+
     pub fn erase_scrollback_and_viewport(&mut self) {
         // Since we may be called outside of perform_actions,
         // we need to ensure that we increment the seqno in
@@ -1276,34 +1301,37 @@ impl TerminalState {
                 }
             }
             Device::SoftReset => {
-                // TODO: see https://vt100.net/docs/vt510-rm/DECSTR.html
-                self.pen = CellAttributes::default();
+                // DECSTR resets the state listed in
+                // https://vt100.net/docs/vt510-rm/DECSTR.html
+                // leaving the screen contents and cursor position alone.
+                // DECTCEM
+                self.cursor_visible = true;
+                // IRM
                 self.insert = false;
+                // DECOM
                 self.dec_origin_mode = false;
                 // Note that xterm deviates from the documented DECSTR
                 // setting for dec_auto_wrap, so we do too
                 self.dec_auto_wrap = true;
+                // DECNKM and DECCKM
+                self.application_keypad = false;
                 self.application_cursor_keys = false;
                 self.modify_other_keys = None;
-                self.application_keypad = false;
+                // DECSTBM, and the left and right margins
                 self.top_and_bottom_margins = 0..self.screen().physical_rows as i64;
                 self.left_and_right_margins = 0..self.screen().physical_cols;
                 self.left_and_right_margin_mode = false;
-                // 退出 alt screen 时清除 primary_peek，防止状态泄漏
-                self.primary_peek = false;
-                self.screen.activate_alt_screen(self.seqno);
-                self.screen.saved_cursor().take();
-                self.screen.activate_primary_screen(self.seqno);
-                self.screen.saved_cursor().take();
-                self.kitty_remove_all_placements(true);
-
-                self.reverse_wraparound_mode = false;
-                self.reverse_video_mode = false;
-                self.bidi_enabled.take();
-                self.bidi_hint.take();
-
+                // G0-G3, GL and GR
                 self.g0_charset = CharSet::Ascii;
                 self.g1_charset = CharSet::Ascii;
+                self.shift_out = false;
+                // SGR and DECSCA
+                self.pen = CellAttributes::default();
+                // DECSC; restoring the cursor now moves it home
+                self.screen.clear_saved_cursors();
+
+                // 清除 primary_peek，防止状态泄漏
+                self.primary_peek = false;
             }
             Device::RequestPrimaryDeviceAttributes => {
                 let mut ident = "\x1b[?65".to_string(); // Vt500
@@ -1450,6 +1478,77 @@ impl TerminalState {
         self.writer.flush().ok();
     }
 
+    /// Returns the current state of the DEC private modes that can
+    /// be saved by XTSAVE, or None for those that can't
+    fn dec_private_mode_state(&self, code: &DecPrivateModeCode) -> Option<bool> {
+        Some(match code {
+            DecPrivateModeCode::ApplicationCursorKeys => self.application_cursor_keys,
+            DecPrivateModeCode::DecAnsiMode => self.dec_ansi_mode,
+            DecPrivateModeCode::ReverseVideo => self.reverse_video_mode,
+            DecPrivateModeCode::OriginMode => self.dec_origin_mode,
+            DecPrivateModeCode::AutoWrap => self.dec_auto_wrap,
+            DecPrivateModeCode::ShowCursor => self.cursor_visible,
+            DecPrivateModeCode::ReverseWraparound => self.reverse_wraparound_mode,
+            DecPrivateModeCode::LeftRightMarginMode => self.left_and_right_margin_mode,
+            DecPrivateModeCode::SixelDisplayMode => self.sixel_display_mode,
+            DecPrivateModeCode::UsePrivateColorRegistersForEachGraphic => {
+                self.use_private_color_registers_for_each_graphic
+            }
+            DecPrivateModeCode::SixelScrollsRight => self.sixel_scrolls_right,
+            DecPrivateModeCode::MouseTracking => self.mouse_tracking,
+            DecPrivateModeCode::ButtonEventMouse => self.button_event_mouse,
+            DecPrivateModeCode::AnyEventMouse => self.any_event_mouse,
+            DecPrivateModeCode::FocusTracking => self.focus_tracking,
+            DecPrivateModeCode::SGRMouse => matches!(self.mouse_encoding, MouseEncoding::SGR),
+            DecPrivateModeCode::SGRPixelsMouse => {
+                matches!(self.mouse_encoding, MouseEncoding::SgrPixels)
+            }
+            DecPrivateModeCode::Utf8Mouse => matches!(self.mouse_encoding, MouseEncoding::Utf8),
+            DecPrivateModeCode::BracketedPaste => self.bracketed_paste,
+            DecPrivateModeCode::Win32InputMode => self.keyboard_encoding == KeyboardEncoding::Win32,
+            _ => return None,
+        })
+    }
+
+    /// XTSAVE - push the state of a DEC private mode onto the
+    /// saved mode stack of the current screen
+    fn save_dec_private_mode(&mut self, code: DecPrivateModeCode) {
+        match self.dec_private_mode_state(&code) {
+            Some(enabled) => {
+                let saved = &mut self.screen_mut().saved_dec_modes;
+                saved.push((code, enabled));
+                if saved.len() > 128 {
+                    saved.remove(0);
+                }
+            }
+            None => {
+                if self.config.log_unknown_escape_sequences() {
+                    log::warn!("save dec mode {:?} unsupported", code);
+                }
+            }
+        }
+    }
+
+    /// XTRESTORE - pop the most recently saved state of a DEC
+    /// private mode and apply it
+    fn restore_dec_private_mode(&mut self, code: DecPrivateModeCode) {
+        let saved = &mut self.screen_mut().saved_dec_modes;
+        let enabled = match saved.iter().rposition(|(c, _)| *c == code) {
+            Some(idx) => saved.remove(idx).1,
+            None => return,
+        };
+        // Only apply modes that changed, so that restoring one of the
+        // mutually exclusive mouse encodings doesn't undo another one
+        if self.dec_private_mode_state(&code) != Some(enabled) {
+            let mode = DecPrivateMode::Code(code);
+            self.perform_csi_mode(if enabled {
+                Mode::SetDecPrivateMode(mode)
+            } else {
+                Mode::ResetDecPrivateMode(mode)
+            });
+        }
+    }
+
     fn perform_csi_mode(&mut self, mode: Mode) {
         match mode {
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
@@ -1930,9 +2029,11 @@ impl TerminalState {
                     self.dec_restore_cursor();
                 }
             }
-            Mode::SaveDecPrivateMode(DecPrivateMode::Code(n))
-            | Mode::RestoreDecPrivateMode(DecPrivateMode::Code(n)) => {
-                log::warn!("save/restore dec mode {:?} unimplemented", n)
+            Mode::SaveDecPrivateMode(DecPrivateMode::Code(n)) => {
+                self.save_dec_private_mode(n);
+            }
+            Mode::RestoreDecPrivateMode(DecPrivateMode::Code(n)) => {
+                self.restore_dec_private_mode(n);
             }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
@@ -2280,6 +2381,11 @@ impl TerminalState {
             Edit::ScrollDown(n) => self.scroll_down(n as usize),
             Edit::ScrollUp(n) => self.scroll_up(n as usize),
             Edit::EraseInDisplay(erase) => self.erase_in_display(erase),
+            Edit::SelectiveEraseInDisplay(erase) => self.selective_erase_in_display(erase),
+            Edit::SelectiveEraseInLine(erase) => self.selective_erase_in_line(erase),
+            Edit::SelectCharacterProtection(protected) => {
+                self.pen.set_protected(protected);
+            }
             Edit::Repeat(n) => {
                 let mut y = self.cursor.y;
                 let mut x = self.cursor.x;
@@ -2674,9 +2780,12 @@ impl TerminalState {
             Sgr::Reset => {
                 let link = self.pen.hyperlink().map(Arc::clone);
                 let semantic_type = self.pen.semantic_type();
+                // DECSCA is independent of SGR
+                let protected = self.pen.protected();
                 self.pen = CellAttributes::default();
                 self.pen.set_hyperlink(link);
                 self.pen.set_semantic_type(semantic_type);
+                self.pen.set_protected(protected);
             }
             Sgr::Intensity(intensity) => {
                 self.pen.set_intensity(intensity);
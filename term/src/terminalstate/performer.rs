@@ -1,4 +1,5 @@
 use crate::terminal::{Alert, Progress};
+use crate::terminalstate::kitty_placeholder::PLACEHOLDER;
 use crate::terminalstate::{
     default_color_map, CharSet, MouseEncoding, TabStop, UnicodeVersionStackEntry,
 };
@@ -14,11 +15,15 @@ use termwiz::input::KeyboardEncoding;
 use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
 use url::Url;
 use wezterm_bidi::ParagraphDirectionHint;
+use wezterm_cell::color::ColorAttribute;
 use wezterm_cell::{
-    grapheme_column_width, is_white_space_grapheme, Cell, CellAttributes, SemanticType,
+    grapheme_column_width, is_white_space_grapheme, Blink, Cell, CellAttributes, Intensity,
+    SemanticType, Underline,
 };
+use wezterm_escape_parser::color::ColorSpec;
 use wezterm_escape_parser::csi::{
-    CharacterPath, EraseInDisplay, Keyboard, KittyKeyboardFlags, KittyKeyboardMode,
+    CharacterPath, CursorStyle, EraseInDisplay, Keyboard, KittyKeyboardFlags, KittyKeyboardMode,
+    Sgr,
 };
 use wezterm_escape_parser::osc::{
     ChangeColorPair, ColorOrQuery, FinalTermSemanticPrompt, ITermProprietary,
@@ -27,6 +32,7 @@ use wezterm_escape_parser::osc::{
 use wezterm_escape_parser::{
     Action, ControlCode, DeviceControlMode, Esc, EscCode, OperatingSystemCommand, CSI,
 };
+use wezterm_surface::CursorShape;
 
 /// A helper struct for implementing `vtparse::VTActor` while compartmentalizing
 /// the terminal state and the embedding/host terminal interface
@@ -198,7 +204,10 @@ impl<'a> Performer<'a> {
             let y = self.cursor.y;
             let width = self.left_and_right_margins.end;
 
-            let pen = self.pen.clone();
+            let mut pen = self.pen.clone();
+            if g.starts_with(PLACEHOLDER) && self.config.enable_kitty_graphics() {
+                self.kitty_placeholder(x, y, g, &mut pen);
+            }
 
             let wrappable = x + print_width >= width;
 
@@ -300,6 +309,34 @@ impl<'a> Performer<'a> {
                                 write!(self.writer, "{}1$r65;1\"p{}", DCS, ST).ok();
                                 self.writer.flush().ok();
                             }
+                            [b'm'] => {
+                                // SGR - graphic rendition
+                                let sgr = sgr_report(&self.pen);
+                                write!(self.writer, "{}1$r{}m{}", DCS, sgr, ST).ok();
+                                self.writer.flush().ok();
+                            }
+                            [b' ', b'q'] => {
+                                // DECSCUSR - cursor style
+                                let style = match self.cursor.shape {
+                                    CursorShape::Default => CursorStyle::Default,
+                                    CursorShape::BlinkingBlock => CursorStyle::BlinkingBlock,
+                                    CursorShape::SteadyBlock => CursorStyle::SteadyBlock,
+                                    CursorShape::BlinkingUnderline => {
+                                        CursorStyle::BlinkingUnderline
+                                    }
+                                    CursorShape::SteadyUnderline => CursorStyle::SteadyUnderline,
+                                    CursorShape::BlinkingBar => CursorStyle::BlinkingBar,
+                                    CursorShape::SteadyBar => CursorStyle::SteadyBar,
+                                };
+                                write!(self.writer, "{}1$r{} q{}", DCS, style as u8, ST).ok();
+                                self.writer.flush().ok();
+                            }
+                            [b'"', b'q'] => {
+                                // DECSCA - character protection attribute
+                                let protected = if self.pen.protected() { 1 } else { 0 };
+                                write!(self.writer, "{}1$r{}\"q{}", DCS, protected, ST).ok();
+                                self.writer.flush().ok();
+                            }
                             [b'r'] => {
                                 // DECSTBM - top and bottom margins
                                 let margins = self.top_and_bottom_margins.clone();
@@ -501,6 +538,7 @@ impl<'a> Performer<'a> {
             CSI::Device(dev) => self.state.perform_device(*dev),
             CSI::Mouse(mouse) => error!("mouse report sent by app? {:?}", mouse),
             CSI::Window(window) => self.state.perform_csi_window(*window),
+            CSI::RectangularArea(area) => self.state.perform_csi_rectangular_area(*area),
             CSI::SelectCharacterPath(CharacterPath::ImplementationDefault, _) => {
                 self.state.bidi_hint.take();
             }
@@ -705,6 +743,7 @@ impl<'a> Performer<'a> {
                 self.palette.take();
                 self.top_and_bottom_margins = 0..self.screen().physical_rows as VisibleRowIndex;
                 self.left_and_right_margins = 0..self.screen().physical_cols;
+                self.left_and_right_margin_mode = false;
                 self.unicode_version = self.config.unicode_version();
                 self.unicode_version_stack.clear();
                 self.suppress_initial_title_change = false;
@@ -717,6 +756,7 @@ impl<'a> Performer<'a> {
                 self.screen.activate_primary_screen(seqno);
                 self.erase_in_display(EraseInDisplay::EraseScrollback);
                 self.erase_in_display(EraseInDisplay::EraseDisplay);
+                self.kitty_remove_all_placements(true);
                 self.palette_did_change();
             }
 
@@ -779,7 +819,12 @@ impl<'a> Performer<'a> {
                 let selection = selection_to_selection(selection);
                 self.set_clipboard_contents(selection, None).ok();
             }
-            OperatingSystemCommand::QuerySelection(_) => {}
+            OperatingSystemCommand::QuerySelection(selection) => {
+                let selection = selection_to_selection(selection);
+                if let Err(err) = self.query_clipboard_contents(selection) {
+                    error!("failed to query clipboard in response to OSC 52: {:#}", err);
+                }
+            }
             OperatingSystemCommand::SetSelection(selection, selection_data) => {
                 let selection = selection_to_selection(selection);
                 match self.set_clipboard_contents(selection, Some(selection_data)) {
@@ -861,6 +906,7 @@ impl<'a> Performer<'a> {
                 FinalTermSemanticPrompt::FreshLineAndStartPrompt { .. },
             ) => {
                 self.fresh_line();
+                self.start_command_block();
                 self.pen.set_semantic_type(SemanticType::Prompt);
             }
             OperatingSystemCommand::FinalTermSemanticPrompt(
@@ -872,6 +918,7 @@ impl<'a> Performer<'a> {
                 FinalTermSemanticPrompt::MarkEndOfCommandWithFreshLine { .. },
             ) => {
                 self.fresh_line();
+                self.start_command_block();
                 self.pen.set_semantic_type(SemanticType::Prompt);
             }
             OperatingSystemCommand::FinalTermSemanticPrompt(
@@ -889,11 +936,14 @@ impl<'a> Performer<'a> {
                 FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput { .. },
             ) => {
                 self.pen.set_semantic_type(SemanticType::Output);
+                self.start_command_output();
             }
 
             OperatingSystemCommand::FinalTermSemanticPrompt(
-                FinalTermSemanticPrompt::CommandStatus { .. },
-            ) => {}
+                FinalTermSemanticPrompt::CommandStatus { status, .. },
+            ) => {
+                self.finish_command_block(status);
+            }
 
             OperatingSystemCommand::SystemNotification(message) => {
                 if let Some(handler) = self.alert_handler.as_mut() {
@@ -1089,6 +1139,62 @@ impl<'a> Performer<'a> {
     }
 }
 
+/// Returns the SGR parameters that reproduce `attrs`,
+/// for reporting the current pen via DECRQSS
+fn sgr_report(attrs: &CellAttributes) -> String {
+    fn color_spec(color: ColorAttribute) -> ColorSpec {
+        match color {
+            ColorAttribute::TrueColorWithPaletteFallback(color, _)
+            | ColorAttribute::TrueColorWithDefaultFallback(color) => ColorSpec::TrueColor(color),
+            ColorAttribute::PaletteIndex(idx) => ColorSpec::PaletteIndex(idx),
+            ColorAttribute::Default => ColorSpec::Default,
+        }
+    }
+
+    let mut sgr = vec![Sgr::Reset];
+    if attrs.intensity() != Intensity::Normal {
+        sgr.push(Sgr::Intensity(attrs.intensity()));
+    }
+    if attrs.italic() {
+        sgr.push(Sgr::Italic(true));
+    }
+    if attrs.underline() != Underline::None {
+        sgr.push(Sgr::Underline(attrs.underline()));
+    }
+    if attrs.blink() != Blink::None {
+        sgr.push(Sgr::Blink(attrs.blink()));
+    }
+    if attrs.reverse() {
+        sgr.push(Sgr::Inverse(true));
+    }
+    if attrs.invisible() {
+        sgr.push(Sgr::Invisible(true));
+    }
+    if attrs.strikethrough() {
+        sgr.push(Sgr::StrikeThrough(true));
+    }
+    if attrs.overline() {
+        sgr.push(Sgr::Overline(true));
+    }
+    if attrs.foreground() != ColorAttribute::Default {
+        sgr.push(Sgr::Foreground(color_spec(attrs.foreground())));
+    }
+    if attrs.background() != ColorAttribute::Default {
+        sgr.push(Sgr::Background(color_spec(attrs.background())));
+    }
+    if attrs.underline_color() != ColorAttribute::Default {
+        sgr.push(Sgr::UnderlineColor(color_spec(attrs.underline_color())));
+    }
+
+    // Sgr is displayed as a complete escape sequence, so strip
+    // off the trailing `m` from each of them
+    let params: Vec<String> = sgr
+        .iter()
+        .map(|sgr| sgr.to_string().trim_end_matches('m').to_string())
+        .collect();
+    params.join(";")
+}
+
 fn selection_to_selection(sel: Selection) -> ClipboardSelection {
     match sel {
         Selection::CLIPBOARD => ClipboardSelection::Clipboard,
@@ -0,0 +1,234 @@
+//! The VT400 rectangular area operations, and the selective
+//! erase functions that leave cells protected by DECSCA alone
+use crate::{Cell, TerminalState, VisibleRowIndex};
+use std::ops::Range;
+use wezterm_cell::{grapheme_column_width, Blink, Intensity, Underline};
+use wezterm_escape_parser::csi::{EraseInDisplay, EraseInLine, Rectangle, RectangularArea, Sgr};
+use wezterm_escape_parser::OneBased;
+
+impl TerminalState {
+    pub(crate) fn perform_csi_rectangular_area(&mut self, area: RectangularArea) {
+        match area {
+            RectangularArea::Copy {
+                source,
+                dest_top,
+                dest_left,
+                ..
+            } => self.copy_rectangle(source, dest_top, dest_left),
+            RectangularArea::Fill { character, area } => self.fill_rectangle(character, area),
+            RectangularArea::Erase(area) => {
+                if let Some((cols, rows)) = self.rectangle_bounds(&area) {
+                    self.erase_cells(cols, rows, false);
+                }
+            }
+            RectangularArea::SelectiveErase(area) => {
+                if let Some((cols, rows)) = self.rectangle_bounds(&area) {
+                    self.erase_cells(cols, rows, true);
+                }
+            }
+            RectangularArea::ChangeAttributes { area, attributes } => {
+                self.change_rectangle_attributes(area, &attributes)
+            }
+        }
+    }
+
+    /// DECSED
+    pub(crate) fn selective_erase_in_display(&mut self, erase: EraseInDisplay) {
+        let cy = self.cursor.y;
+        let cols = 0..self.screen().physical_cols;
+        let rows = self.screen().physical_rows as VisibleRowIndex;
+        let row_range = match erase {
+            EraseInDisplay::EraseToEndOfDisplay => {
+                self.selective_erase_in_line(EraseInLine::EraseToEndOfLine);
+                cy + 1..rows
+            }
+            EraseInDisplay::EraseToStartOfDisplay => {
+                self.selective_erase_in_line(EraseInLine::EraseToStartOfLine);
+                0..cy
+            }
+            EraseInDisplay::EraseDisplay => 0..rows,
+            EraseInDisplay::EraseScrollback => return,
+        };
+        self.erase_cells(cols, row_range, true);
+    }
+
+    /// DECSEL
+    pub(crate) fn selective_erase_in_line(&mut self, erase: EraseInLine) {
+        let cx = self.cursor.x;
+        let cy = self.cursor.y;
+        let cols = self.screen().physical_cols;
+        let range = match erase {
+            // See the comment on wrap_next in the handling of EL
+            EraseInLine::EraseToEndOfLine => cx + if self.wrap_next { 1 } else { 0 }..cols,
+            EraseInLine::EraseToStartOfLine => 0..(cx + 1).min(cols),
+            EraseInLine::EraseLine => 0..cols,
+        };
+        self.erase_cells(range, cy..cy + 1, true);
+    }
+
+    /// Returns the portion of the screen that rectangle coordinates
+    /// are relative to and clipped to: the scrolling margins in
+    /// origin mode, otherwise the whole screen
+    fn rectangle_extent(&self) -> (Range<usize>, Range<VisibleRowIndex>) {
+        if self.dec_origin_mode {
+            (
+                self.left_and_right_margins.clone(),
+                self.top_and_bottom_margins.clone(),
+            )
+        } else {
+            let screen = self.screen();
+            (
+                0..screen.physical_cols,
+                0..screen.physical_rows as VisibleRowIndex,
+            )
+        }
+    }
+
+    /// Returns the columns and rows covered by `area`,
+    /// or None if it is empty
+    fn rectangle_bounds(&self, area: &Rectangle) -> Option<(Range<usize>, Range<VisibleRowIndex>)> {
+        let (cols, rows) = self.rectangle_extent();
+        let left = cols
+            .start
+            .saturating_add(area.left.as_zero_based() as usize);
+        let right = cols
+            .start
+            .saturating_add(area.right.as_zero_based() as usize)
+            .saturating_add(1)
+            .min(cols.end);
+        let top = rows.start + area.top.as_zero_based() as VisibleRowIndex;
+        let bottom =
+            (rows.start + area.bottom.as_zero_based() as VisibleRowIndex + 1).min(rows.end);
+        if left >= right || top >= bottom {
+            None
+        } else {
+            Some((left..right, top..bottom))
+        }
+    }
+
+    /// DECCRA
+    fn copy_rectangle(&mut self, source: Rectangle, dest_top: OneBased, dest_left: OneBased) {
+        let (src_cols, src_rows) = match self.rectangle_bounds(&source) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let (cols, rows) = self.rectangle_extent();
+        let dest_x = cols
+            .start
+            .saturating_add(dest_left.as_zero_based() as usize);
+        let dest_y = rows.start + dest_top.as_zero_based() as VisibleRowIndex;
+        let seqno = self.seqno;
+        let screen = self.screen_mut();
+
+        // Copy the source out first, as it may overlap the destination
+        let mut copied = vec![];
+        for y in src_rows {
+            let mut row = vec![];
+            for x in src_cols.clone() {
+                row.push(screen.get_cell(x, y).cloned().unwrap_or_else(Cell::blank));
+            }
+            copied.push(row);
+        }
+
+        for (row, y) in copied.into_iter().zip(dest_y..rows.end) {
+            for (cell, x) in row.into_iter().zip(dest_x..cols.end) {
+                screen.set_cell(x, y, &cell, seqno);
+            }
+        }
+    }
+
+    /// DECFRA
+    fn fill_rectangle(&mut self, character: char, area: Rectangle) {
+        let mut buf = [0u8; 4];
+        let text: &str = character.encode_utf8(&mut buf);
+        // Control characters, and those that don't fit in
+        // a single cell, are ignored
+        if character.is_control() || grapheme_column_width(text, Some(&self.unicode_version)) != 1 {
+            return;
+        }
+        let (cols, rows) = match self.rectangle_bounds(&area) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let seqno = self.seqno;
+        let pen = self.pen.clone();
+        let screen = self.screen_mut();
+        for y in rows {
+            for x in cols.clone() {
+                screen.set_cell_grapheme(x, y, text, 1, pen.clone(), seqno);
+            }
+        }
+    }
+
+    /// Erases the cells in `cols` and `rows`.  When `selective`
+    /// is true, cells that are protected by DECSCA are left alone.
+    fn erase_cells(&mut self, cols: Range<usize>, rows: Range<VisibleRowIndex>, selective: bool) {
+        let seqno = self.seqno;
+        let blank = Cell::blank_with_attrs(self.pen.clone_sgr_only());
+        let screen = self.screen_mut();
+        for y in rows {
+            for x in cols.clone() {
+                if selective
+                    && screen
+                        .get_cell(x, y)
+                        .map(|cell| cell.attrs().protected())
+                        .unwrap_or(false)
+                {
+                    continue;
+                }
+                screen.set_cell(x, y, &blank, seqno);
+            }
+        }
+    }
+
+    /// DECCARA
+    fn change_rectangle_attributes(&mut self, area: Rectangle, attributes: &[Sgr]) {
+        let (cols, rows) = match self.rectangle_bounds(&area) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let seqno = self.seqno;
+        let screen = self.screen_mut();
+        for y in rows {
+            let line_idx = screen.phys_row(y);
+            let line = screen.line_mut(line_idx);
+            // The attributes apply to the blank cells past
+            // the end of the line too
+            if line.len() < cols.end {
+                line.resize(cols.end, seqno);
+            }
+            line.update_last_change_seqno(seqno);
+            for cell in &mut line.cells_mut_for_attr_changes_only()[cols.clone()] {
+                let attrs = cell.attrs_mut();
+                for sgr in attributes {
+                    match sgr {
+                        Sgr::Reset => {
+                            attrs
+                                .set_intensity(Intensity::Normal)
+                                .set_underline(Underline::None)
+                                .set_blink(Blink::None)
+                                .set_reverse(false)
+                                .set_invisible(false);
+                        }
+                        Sgr::Intensity(intensity) => {
+                            attrs.set_intensity(*intensity);
+                        }
+                        Sgr::Underline(underline) => {
+                            attrs.set_underline(*underline);
+                        }
+                        Sgr::Blink(blink) => {
+                            attrs.set_blink(*blink);
+                        }
+                        Sgr::Inverse(inverse) => {
+                            attrs.set_reverse(*inverse);
+                        }
+                        Sgr::Invisible(invisible) => {
+                            attrs.set_invisible(*invisible);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
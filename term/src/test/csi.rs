@@ -1,4 +1,5 @@
 use super::*;
+use k9::assert_equal as assert_eq;
 
 /// In this issue, the `CSI 2 P` sequence incorrectly removed two
 /// cells from the line, leaving them effectively blank, when those
@@ -401,3 +402,349 @@ fn test_ed_erase_scrollback() {
     term.print("b");
     assert_all_contents(&term, file!(), line!(), &["111", "222", "ab"]);
 }
+
+fn populate_rectangle_test(term: &mut TestTerm) {
+    term.print("abcdef\r\nghijkl\r\nmnopqr\r\nstuvwx");
+    assert_visible_contents(
+        term,
+        file!(),
+        line!(),
+        &["abcdef", "ghijkl", "mnopqr", "stuvwx"],
+    );
+}
+
+#[test]
+fn test_decfra() {
+    let mut term = TestTerm::new(4, 6, 0);
+    populate_rectangle_test(&mut term);
+    term.print("\x1b[88;2;2;3;4$x");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcdef", "gXXXkl", "mXXXqr", "stuvwx"],
+    );
+
+    // The bottom and right default to the edge of the screen
+    term.print("\x1b[42;4;5$x");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcdef", "gXXXkl", "mXXXqr", "stuv**"],
+    );
+
+    // Control characters are ignored
+    term.print("\x1b[10;1;1;4;6$x");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcdef", "gXXXkl", "mXXXqr", "stuv**"],
+    );
+}
+
+#[test]
+fn test_deccra() {
+    let mut term = TestTerm::new(4, 6, 0);
+    populate_rectangle_test(&mut term);
+    term.print("\x1b[1;1;2;2;1;3;5;1$v");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcdef", "ghijkl", "mnopab", "stuvgh"],
+    );
+
+    // Overlapping areas copy what was there before the copy;
+    // the destination is clipped to the screen
+    term.print("\x1b[1;1;2;3;1;2;2;1$v");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcdef", "gabckl", "mghiab", "stuvgh"],
+    );
+}
+
+#[test]
+fn test_decera() {
+    let mut term = TestTerm::new(4, 6, 0);
+    populate_rectangle_test(&mut term);
+    // DECERA doesn't care about protection
+    term.print("\x1b[1;1H\x1b[1\"qAB\x1b[0\"q");
+    term.print("\x1b[1;2;3;3$z");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["A  def", "g  jkl", "m  pqr", "stuvwx"],
+    );
+
+    // An empty rectangle does nothing
+    term.print("\x1b[3;3;2;2$z");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["A  def", "g  jkl", "m  pqr", "stuvwx"],
+    );
+}
+
+#[test]
+fn test_decsera() {
+    let mut term = TestTerm::new(4, 6, 0);
+    populate_rectangle_test(&mut term);
+    term.print("\x1b[2;2H\x1b[1\"qHIJ\x1b[0\"qK");
+    term.print("\x1b[${");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["      ", " HIJ  ", "      ", "      "],
+    );
+}
+
+#[test]
+fn test_decsed_decsel() {
+    let mut term = TestTerm::new(3, 6, 0);
+    term.print("ab\x1b[1\"qcd\x1b[0\"qef\r\n");
+    // SGR reset doesn't turn off protection
+    term.print("\x1b[1\"q\x1b[0mgh\x1b[2\"qijkl\r\n");
+    term.print("mn\x1b[1\"qop\x1b[0\"qqr");
+
+    term.print("\x1b[1;4H\x1b[?K");
+    assert_visible_contents(&term, file!(), line!(), &["abcd  ", "ghijkl", "mnopqr"]);
+    term.print("\x1b[?1K");
+    assert_visible_contents(&term, file!(), line!(), &["  cd  ", "ghijkl", "mnopqr"]);
+
+    term.print("\x1b[2;1H\x1b[?J");
+    assert_visible_contents(&term, file!(), line!(), &["  cd  ", "gh    ", "  op  "]);
+
+    // Regular erase ignores protection
+    term.print("\x1b[2J");
+    assert_visible_contents(&term, file!(), line!(), &["      ", "      ", "      "]);
+}
+
+#[test]
+fn test_deccara() {
+    let mut term = TestTerm::new(2, 4, 0);
+    term.print("abcd\r\nefgh");
+    term.print("\x1b[1;2;2;3;1;7$r");
+
+    let attrs = |term: &TestTerm| -> Vec<Vec<(Intensity, bool)>> {
+        term.screen()
+            .visible_lines()
+            .iter()
+            .map(|line| {
+                line.visible_cells()
+                    .map(|cell| (cell.attrs().intensity(), cell.attrs().reverse()))
+                    .collect()
+            })
+            .collect()
+    };
+    let plain = (Intensity::Normal, false);
+    let both = (Intensity::Bold, true);
+    assert_eq!(
+        attrs(&term),
+        vec![
+            vec![plain, both, both, plain],
+            vec![plain, both, both, plain]
+        ]
+    );
+
+    // Reset only affects the DECCARA attributes, and
+    // the text is left alone
+    term.print("\x1b[1;3;1;4;0;1$r");
+    assert_eq!(
+        attrs(&term),
+        vec![
+            vec![
+                plain,
+                both,
+                (Intensity::Bold, false),
+                (Intensity::Bold, false)
+            ],
+            vec![plain, both, both, plain]
+        ]
+    );
+    assert_visible_contents(&term, file!(), line!(), &["abcd", "efgh"]);
+}
+
+#[test]
+fn test_rectangle_origin_mode() {
+    let mut term = TestTerm::new(4, 6, 0);
+    populate_rectangle_test(&mut term);
+    term.set_scroll_region(1, 2);
+    term.set_mode("?69", true);
+    term.set_left_and_right_margins(1, 3);
+    term.set_mode("?6", true);
+
+    // Coordinates are relative to, and clipped by, the margins
+    term.print("\x1b[88;1;2;9;9$x");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcdef", "ghXXkl", "mnXXqr", "stuvwx"],
+    );
+}
+
+#[test]
+fn test_xtsave_xtrestore() {
+    let mut term = TestTerm::new(2, 4, 0);
+    let auto_wrap = |term: &mut TestTerm| {
+        term.print("\x1b[?7$p");
+        term.read_reply()
+    };
+
+    term.print("\x1b[?7s\x1b[?7l\x1b[?7s\x1b[?7h");
+    term.print("\x1b[?7r");
+    assert_eq!(auto_wrap(&mut term), "\x1b[?7;2$y");
+    term.print("\x1b[?7r");
+    assert_eq!(auto_wrap(&mut term), "\x1b[?7;1$y");
+
+    // Nothing is left to restore
+    term.print("\x1b[?7l\x1b[?7r");
+    assert_eq!(auto_wrap(&mut term), "\x1b[?7;2$y");
+}
+
+#[test]
+fn test_xtrestore_mouse_encoding() {
+    let mut term = TestTerm::new(2, 4, 0);
+    term.print("\x1b[?1006h\x1b[?1005;1006s\x1b[?1005h");
+
+    // Restoring the reset utf8 encoding must not
+    // undo the restored sgr encoding, whichever order
+    // they are restored in
+    term.print("\x1b[?1006;1005r");
+    term.print("\x1b[?1006$p");
+    assert_eq!(term.read_reply(), "\x1b[?1006;1$y");
+    term.print("\x1b[?1005$p");
+    assert_eq!(term.read_reply(), "\x1b[?1005;2$y");
+}
+
+#[test]
+fn test_xtsave_is_per_screen() {
+    let mut term = TestTerm::new(2, 4, 0);
+    term.print("\x1b[?2004h\x1b[?2004s\x1b[?1049h\x1b[?2004l\x1b[?2004r");
+    term.print("\x1b[?2004$p");
+    assert_eq!(term.read_reply(), "\x1b[?2004;2$y");
+
+    term.print("\x1b[?1049l\x1b[?2004r");
+    term.print("\x1b[?2004$p");
+    assert_eq!(term.read_reply(), "\x1b[?2004;1$y");
+}
+
+#[test]
+fn test_decrqss() {
+    let mut term = TestTerm::new(4, 10, 0);
+    let decrqss = |term: &mut TestTerm, setting: &str| {
+        term.print(format!("\x1bP$q{}\x1b\\", setting));
+        term.read_reply()
+    };
+
+    assert_eq!(decrqss(&mut term, "m"), "\x1bP1$r0m\x1b\\");
+    term.print("\x1b[1;3;4:3;7;31;48;2;1;2;3m");
+    assert_eq!(
+        decrqss(&mut term, "m"),
+        "\x1bP1$r0;1;3;4:3;7;31;48:2::1:2:3m\x1b\\"
+    );
+
+    assert_eq!(decrqss(&mut term, " q"), "\x1bP1$r0 q\x1b\\");
+    term.print("\x1b[6 q");
+    assert_eq!(decrqss(&mut term, " q"), "\x1bP1$r6 q\x1b\\");
+
+    assert_eq!(decrqss(&mut term, "\"q"), "\x1bP1$r0\"q\x1b\\");
+    term.print("\x1b[1\"q");
+    assert_eq!(decrqss(&mut term, "\"q"), "\x1bP1$r1\"q\x1b\\");
+
+    term.set_scroll_region(1, 2);
+    assert_eq!(decrqss(&mut term, "r"), "\x1bP1$r2;3r\x1b\\");
+    term.set_mode("?69", true);
+    term.set_left_and_right_margins(2, 5);
+    assert_eq!(decrqss(&mut term, "s"), "\x1bP1$r3;6s\x1b\\");
+    assert_eq!(decrqss(&mut term, "\"p"), "\x1bP1$r65;1\"p\x1b\\");
+
+    assert_eq!(decrqss(&mut term, "x"), "\x1bP0$r\x1b\\");
+}
+
+/// Returns the settings that DECSTR resets, as reported
+/// by DECRQM and DECRQSS
+fn soft_reset_state(term: &mut TestTerm) -> Vec<String> {
+    [
+        "\x1b[4$p",
+        "\x1b[?1$p",
+        "\x1b[?6$p",
+        "\x1b[?7$p",
+        "\x1b[?25$p",
+        "\x1b[?69$p",
+        "\x1bP$qm\x1b\\",
+        "\x1bP$q\"q\x1b\\",
+        "\x1bP$qr\x1b\\",
+        "\x1bP$qs\x1b\\",
+    ]
+    .iter()
+    .map(|query| {
+        term.print(query);
+        term.read_reply()
+    })
+    .collect()
+}
+
+/// Changes everything that DECSTR resets
+fn populate_soft_reset_test(term: &mut TestTerm) {
+    term.print("abc\r\ndef");
+    term.cup(1, 1);
+    term.print("\x1b7");
+    term.print("\x1b[4h\x1b[?1h\x1b[?7l\x1b[?25l");
+    term.set_scroll_region(1, 2);
+    term.set_mode("?69", true);
+    term.set_left_and_right_margins(1, 3);
+    term.print("\x1b[?6h");
+    term.print("\x1b[1;31m\x1b[1\"q");
+    term.print("\x1b(0\x1b)0\x0e");
+    term.cup(2, 0);
+}
+
+#[test]
+fn test_decstr() {
+    let mut term = TestTerm::new(4, 6, 0);
+    populate_soft_reset_test(&mut term);
+    let cursor = term.cursor_pos();
+
+    term.soft_reset();
+    // The screen and the cursor position are left alone
+    let after = term.cursor_pos();
+    assert_eq!((after.x, after.y), (cursor.x, cursor.y));
+    assert_visible_contents(&term, file!(), line!(), &["abc", "def", "", ""]);
+
+    // Everything that was changed is back to the same state
+    // as after RIS
+    let mut reset = TestTerm::new(4, 6, 0);
+    populate_soft_reset_test(&mut reset);
+    reset.print("\x1bc");
+    assert_visible_contents(&reset, file!(), line!(), &["      ", "      ", "", ""]);
+    assert_eq!(soft_reset_state(&mut term), soft_reset_state(&mut reset));
+
+    // The charsets are back to ascii, and the
+    // saved cursor is now the home position
+    term.cup(0, 3);
+    term.print("q");
+    term.print("\x1b8");
+    term.assert_cursor_pos(0, 0, None, None);
+    assert_visible_contents(&term, file!(), line!(), &["abc", "def", "", "q"]);
+}
+
+#[test]
+fn test_decstr_alt_screen() {
+    let mut term = TestTerm::new(2, 4, 0);
+    term.print("abc");
+    term.set_mode("?1049", true);
+    term.print("xyz");
+
+    // The alternate screen stays active
+    term.soft_reset();
+    assert!(term.is_alt_screen_active());
+    assert_visible_contents(&term, file!(), line!(), &["xyz", ""]);
+}
@@ -0,0 +1,380 @@
+//! Tests for the kitty graphics protocol delete commands
+//! and Unicode placeholders
+use super::*;
+use k9::assert_equal as assert_eq;
+use std::collections::BTreeSet;
+use wezterm_cell::image::ImageDataType;
+
+#[derive(Debug)]
+struct KittyTermConfig;
+impl TerminalConfiguration for KittyTermConfig {
+    fn color_palette(&self) -> ColorPalette {
+        ColorPalette::default()
+    }
+
+    fn enable_kitty_graphics(&self) -> bool {
+        true
+    }
+}
+
+fn kitty_term() -> TestTerm {
+    TestTerm::with_config(3, 8, Arc::new(KittyTermConfig))
+}
+
+/// 16x16 pixels of transparent RGBA, which occupies 2x1 cells
+/// of the 8x16 pixel cells used by TestTerm
+fn image_payload() -> String {
+    format!("{}AA==", "AAAA".repeat(341))
+}
+
+fn transmit(term: &mut TestTerm, image_id: u32) {
+    term.print(format!(
+        "\x1b_Ga=t,f=32,s=16,v=16,i={},q=2;{}\x1b\\",
+        image_id,
+        image_payload()
+    ));
+}
+
+fn transmit_numbered(term: &mut TestTerm, image_number: u32) {
+    term.print(format!(
+        "\x1b_Ga=t,f=32,s=16,v=16,I={},q=2;{}\x1b\\",
+        image_number,
+        image_payload()
+    ));
+}
+
+/// Places the image with its top left corner at the specified
+/// cell, without moving the cursor
+fn place(term: &mut TestTerm, image_id: u32, placement_id: u32, col: isize, row: isize, z: i32) {
+    term.cup(col, row);
+    term.print(format!(
+        "\x1b_Ga=p,i={},p={},z={},C=1,q=2\x1b\\",
+        image_id, placement_id, z
+    ));
+}
+
+fn delete(term: &mut TestTerm, keys: &str) {
+    term.print(format!("\x1b_Ga=d,{},q=2\x1b\\", keys));
+}
+
+/// Returns the (image_id, placement_id) pairs that are visible
+fn placements(term: &TestTerm) -> BTreeSet<(u32, u32)> {
+    let mut result = BTreeSet::new();
+    for line in term.screen().visible_lines() {
+        for cell in line.visible_cells() {
+            for image in cell.attrs().images().unwrap_or_default() {
+                result.insert((
+                    image.image_id().unwrap_or(0),
+                    image.placement_id().unwrap_or(0),
+                ));
+            }
+        }
+    }
+    result
+}
+
+/// Returns the number of frames in the first visible image
+fn frame_count(term: &TestTerm) -> Option<usize> {
+    for line in term.screen().visible_lines() {
+        for cell in line.visible_cells() {
+            if let Some(image) = cell
+                .attrs()
+                .images()
+                .and_then(|images| images.into_iter().next())
+            {
+                return Some(match &*image.image_data().data() {
+                    ImageDataType::AnimRgba8 { frames, .. } => frames.len(),
+                    _ => 1,
+                });
+            }
+        }
+    }
+    None
+}
+
+fn set(pairs: &[(u32, u32)]) -> BTreeSet<(u32, u32)> {
+    pairs.iter().copied().collect()
+}
+
+/// Three images side by side on the top row, a fourth on the
+/// bottom row and a second placement of image 1 in the middle
+fn populate(term: &mut TestTerm) {
+    for id in 1..=4 {
+        transmit(term, id);
+    }
+    place(term, 1, 1, 0, 0, 0);
+    place(term, 2, 1, 2, 0, 1);
+    place(term, 3, 1, 4, 0, 2);
+    place(term, 4, 1, 0, 2, 1);
+    place(term, 1, 2, 4, 1, 0);
+    assert_eq!(
+        placements(term),
+        set(&[(1, 1), (1, 2), (2, 1), (3, 1), (4, 1)])
+    );
+}
+
+#[test]
+fn delete_all() {
+    let mut term = kitty_term();
+    populate(&mut term);
+    delete(&mut term, "d=a");
+    assert_eq!(placements(&term), set(&[]));
+
+    // The data was kept, so the image can be placed again
+    place(&mut term, 2, 1, 0, 0, 0);
+    assert_eq!(placements(&term), set(&[(2, 1)]));
+
+    delete(&mut term, "d=A");
+    place(&mut term, 2, 1, 0, 0, 0);
+    assert_eq!(placements(&term), set(&[]));
+}
+
+#[test]
+fn delete_by_id() {
+    let mut term = kitty_term();
+    populate(&mut term);
+
+    delete(&mut term, "d=i,i=1,p=2");
+    assert_eq!(placements(&term), set(&[(1, 1), (2, 1), (3, 1), (4, 1)]));
+
+    // Image 1 still has a placement, so its data is kept
+    delete(&mut term, "d=I,i=3");
+    delete(&mut term, "d=i,i=1");
+    assert_eq!(placements(&term), set(&[(2, 1), (4, 1)]));
+
+    place(&mut term, 1, 1, 4, 1, 0);
+    place(&mut term, 3, 1, 4, 0, 0);
+    assert_eq!(placements(&term), set(&[(1, 1), (2, 1), (4, 1)]));
+}
+
+#[test]
+fn delete_by_number() {
+    let mut term = kitty_term();
+    transmit_numbered(&mut term, 7);
+    transmit_numbered(&mut term, 7);
+
+    term.cup(0, 0);
+    term.print("\x1b_Ga=p,I=7,C=1,q=2\x1b\\");
+    let placed = placements(&term);
+    assert_eq!(placed.len(), 1);
+
+    // Only the newest image with that number is affected
+    delete(&mut term, "d=N,I=7");
+    assert_eq!(placements(&term), set(&[]));
+
+    term.print("\x1b_Ga=p,I=7,C=1,q=2\x1b\\");
+    assert_eq!(placements(&term), set(&[]));
+}
+
+#[test]
+fn delete_at_cursor() {
+    let mut term = kitty_term();
+    populate(&mut term);
+    term.cup(3, 0);
+    delete(&mut term, "d=c");
+    assert_eq!(placements(&term), set(&[(1, 1), (1, 2), (3, 1), (4, 1)]));
+}
+
+#[test]
+fn delete_at_cell() {
+    let mut term = kitty_term();
+    populate(&mut term);
+
+    // Coordinates are 1-based
+    delete(&mut term, "d=p,x=6,y=2");
+    assert_eq!(placements(&term), set(&[(1, 1), (2, 1), (3, 1), (4, 1)]));
+
+    // Only the placement with the matching z-index is removed
+    delete(&mut term, "d=q,x=2,y=3,z=0");
+    assert_eq!(placements(&term), set(&[(1, 1), (2, 1), (3, 1), (4, 1)]));
+    delete(&mut term, "d=q,x=2,y=3,z=1");
+    assert_eq!(placements(&term), set(&[(1, 1), (2, 1), (3, 1)]));
+}
+
+#[test]
+fn delete_column_row_and_z() {
+    let mut term = kitty_term();
+    populate(&mut term);
+    delete(&mut term, "d=x,x=5");
+    assert_eq!(placements(&term), set(&[(1, 1), (2, 1), (4, 1)]));
+
+    delete(&mut term, "d=y,y=1");
+    assert_eq!(placements(&term), set(&[(4, 1)]));
+
+    let mut term = kitty_term();
+    populate(&mut term);
+    delete(&mut term, "d=z,z=1");
+    assert_eq!(placements(&term), set(&[(1, 1), (1, 2), (3, 1)]));
+}
+
+#[test]
+fn delete_id_range() {
+    let mut term = kitty_term();
+    populate(&mut term);
+    transmit(&mut term, 5);
+
+    delete(&mut term, "d=R,x=2,y=5");
+    assert_eq!(placements(&term), set(&[(1, 1), (1, 2)]));
+
+    // The never-placed image 5 was freed too
+    place(&mut term, 5, 1, 0, 2, 0);
+    assert_eq!(placements(&term), set(&[(1, 1), (1, 2)]));
+}
+
+#[test]
+fn delete_frames() {
+    let mut term = kitty_term();
+    transmit(&mut term, 1);
+    place(&mut term, 1, 1, 0, 0, 0);
+
+    // Add a second frame
+    term.print(format!(
+        "\x1b_Ga=f,f=32,s=16,v=16,i=1,q=2;{}\x1b\\",
+        image_payload()
+    ));
+    assert_eq!(frame_count(&term), Some(2));
+
+    delete(&mut term, "d=f,i=1,r=2");
+    assert_eq!(frame_count(&term), Some(1));
+
+    // Deleting the only frame leaves the image alone, unless
+    // its data is to be freed
+    delete(&mut term, "d=f,i=1");
+    assert_eq!(frame_count(&term), Some(1));
+    assert_eq!(placements(&term), set(&[(1, 1)]));
+
+    delete(&mut term, "d=F,i=1");
+    assert_eq!(frame_count(&term), None);
+    assert_eq!(placements(&term), set(&[]));
+}
+
+/// U+10EEEE, which is followed by diacritics encoding the row and column
+const PLACEHOLDER: char = '\u{10EEEE}';
+
+/// The diacritics for 0, 1 and 2
+const DIACRITICS: [char; 3] = ['\u{305}', '\u{30d}', '\u{30e}'];
+
+fn virtual_place(term: &mut TestTerm, image_id: u32, keys: &str) {
+    term.print(format!("\x1b_Ga=p,U=1,i={},{},q=2\x1b\\", image_id, keys));
+}
+
+type CellImage = (u32, (f32, f32), (f32, f32));
+
+/// Returns the image_id and the texture coordinates of the
+/// image attached to the cell at `x`, `y`
+fn cell_image(term: &TestTerm, x: usize, y: usize) -> Option<CellImage> {
+    let lines = term.screen().visible_lines();
+    let cell = lines[y].get_cell(x)?;
+    let image = cell.attrs().images()?.into_iter().next()?;
+    let tl = image.top_left();
+    let br = image.bottom_right();
+    Some((
+        image.image_id()?,
+        (tl.x.into_inner(), tl.y.into_inner()),
+        (br.x.into_inner(), br.y.into_inner()),
+    ))
+}
+
+#[test]
+fn placeholder_cells() {
+    let mut term = kitty_term();
+    transmit(&mut term, 5);
+    virtual_place(&mut term, 5, "c=2,r=2");
+    // Placing virtually doesn't display anything by itself
+    assert_eq!(placements(&term), set(&[]));
+
+    // Image id 5 in the foreground color; explicit row and column
+    term.print("\x1b[38;5;5m");
+    for (row, row_diacritic) in DIACRITICS[..2].iter().enumerate() {
+        term.cup(0, row as isize);
+        for col_diacritic in &DIACRITICS[..2] {
+            term.print(format!("{}{}{}", PLACEHOLDER, row_diacritic, col_diacritic));
+        }
+    }
+    assert_eq!(placements(&term), set(&[(5, 0)]));
+
+    // The 16x16 image is scaled to fit the 16x32 placement,
+    // and centered vertically, so it is split across the rows
+    assert_eq!(cell_image(&term, 0, 0), Some((5, (0., 0.), (0.5, 0.5))));
+    assert_eq!(cell_image(&term, 1, 0), Some((5, (0.5, 0.), (1., 0.5))));
+    assert_eq!(cell_image(&term, 0, 1), Some((5, (0., 0.5), (0.5, 1.))));
+    assert_eq!(cell_image(&term, 1, 1), Some((5, (0.5, 0.5), (1., 1.))));
+
+    // The placeholder character itself isn't drawn
+    let lines = term.screen().visible_lines();
+    assert!(lines[0].get_cell(0).unwrap().attrs().invisible());
+}
+
+#[test]
+fn placeholder_inference() {
+    let mut term = kitty_term();
+    transmit(&mut term, 0x010203);
+    virtual_place(&mut term, 0x010203, "c=2,r=1");
+
+    // The id is in the true color foreground, and the column
+    // of the second cell is inferred from the first
+    term.print(format!(
+        "\x1b[38;2;1;2;3m{}{}{}",
+        PLACEHOLDER, DIACRITICS[0], PLACEHOLDER
+    ));
+    assert_eq!(
+        cell_image(&term, 0, 0),
+        Some((0x010203, (0., 0.), (0.5, 1.)))
+    );
+    assert_eq!(
+        cell_image(&term, 1, 0),
+        Some((0x010203, (0.5, 0.), (1., 1.)))
+    );
+
+    // Cells beyond the placement are left empty
+    term.print(format!("{}", PLACEHOLDER));
+    assert_eq!(cell_image(&term, 2, 0), None);
+
+    // A placeholder for an unknown image is just a character
+    term.print(format!("\x1b[38;5;9m{}", PLACEHOLDER));
+    assert_eq!(cell_image(&term, 3, 0), None);
+}
+
+#[test]
+fn placeholder_placement_id() {
+    let mut term = kitty_term();
+    transmit(&mut term, 1);
+    virtual_place(&mut term, 1, "p=7,c=1,r=1");
+
+    // The placement id is in the underline color
+    term.print(format!(
+        "\x1b[38;5;1m\x1b[58;5;7m{}{}{}",
+        PLACEHOLDER, DIACRITICS[0], DIACRITICS[0]
+    ));
+    assert_eq!(placements(&term), set(&[(1, 7)]));
+
+    // No placement 8, so nothing is shown
+    term.print(format!(
+        "\x1b[58;5;8m{}{}{}",
+        PLACEHOLDER, DIACRITICS[0], DIACRITICS[0]
+    ));
+    assert_eq!(cell_image(&term, 1, 0), None);
+}
+
+#[test]
+fn placeholder_delete() {
+    let mut term = kitty_term();
+    transmit(&mut term, 3);
+    virtual_place(&mut term, 3, "c=1,r=1");
+    term.print(format!(
+        "\x1b[38;5;3m{}{}{}",
+        PLACEHOLDER, DIACRITICS[0], DIACRITICS[0]
+    ));
+    assert_eq!(placements(&term), set(&[(3, 0)]));
+
+    // Deleting the placement detaches the image from the cells
+    delete(&mut term, "d=I,i=3");
+    assert_eq!(placements(&term), set(&[]));
+
+    // and the data was freed
+    term.print(format!(
+        "\r{}{}{}",
+        PLACEHOLDER, DIACRITICS[0], DIACRITICS[0]
+    ));
+    assert_eq!(placements(&term), set(&[]));
+}
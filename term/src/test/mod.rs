@@ -6,10 +6,13 @@ mod c0;
 use bitflags::bitflags;
 mod c1;
 mod csi;
+mod kitty;
 // mod selection; FIXME: port to render layer
 use crate::color::ColorPalette;
 use k9::assert_equal as assert_eq;
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use wezterm_escape_parser::csi::{Edit, EraseInDisplay, EraseInLine};
 use wezterm_escape_parser::{OneBased, OperatingSystemCommand, CSI};
 use wezterm_surface::{CursorShape, CursorVisibility, SequenceNo, SEQ_ZERO};
@@ -17,12 +20,14 @@ use wezterm_surface::{CursorShape, CursorVisibility, SequenceNo, SEQ_ZERO};
 #[derive(Debug)]
 struct LocalClip {
     clip: Mutex<Option<String>>,
+    queries: Mutex<Vec<ClipboardSelection>>,
 }
 
 impl LocalClip {
     fn new() -> Self {
         Self {
             clip: Mutex::new(None),
+            queries: Mutex::new(vec![]),
         }
     }
 }
@@ -36,10 +41,34 @@ impl Clipboard for LocalClip {
         *self.clip.lock().unwrap() = clip;
         Ok(())
     }
+
+    fn query_contents(&self, selection: ClipboardSelection) -> anyhow::Result<()> {
+        self.queries.lock().unwrap().push(selection);
+        Ok(())
+    }
+}
+
+/// Passes the replies that the terminal writes back to the
+/// application on to the test
+struct ReplyWriter(Sender<Vec<u8>>);
+
+impl std::io::Write for ReplyWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .send(buf.to_vec())
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::BrokenPipe, err))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
 struct TestTerm {
     term: Terminal,
+    clip: Arc<LocalClip>,
+    replies: Receiver<Vec<u8>>,
 }
 
 #[derive(Debug)]
@@ -58,11 +87,20 @@ impl TerminalConfiguration for TestTermConfig {
 
 impl TestTerm {
     fn new(height: usize, width: usize, scrollback: usize) -> Self {
+        Self::with_config(height, width, Arc::new(TestTermConfig { scrollback }))
+    }
+
+    fn with_config(
+        height: usize,
+        width: usize,
+        config: Arc<dyn TerminalConfiguration + Send + Sync>,
+    ) -> Self {
         let _ = env_logger::Builder::new()
             .is_test(true)
             .filter_level(log::LevelFilter::Trace)
             .try_init();
 
+        let (reply_tx, replies) = channel();
         let mut term = Terminal::new(
             TerminalSize {
                 rows: height,
@@ -71,15 +109,20 @@ impl TestTerm {
                 pixel_height: height * 16,
                 dpi: 0,
             },
-            Arc::new(TestTermConfig { scrollback }),
+            config,
             "WezTerm",
             "O_o",
-            Box::new(Vec::new()),
+            Box::new(ReplyWriter(reply_tx)),
         );
-        let clip: Arc<dyn Clipboard> = Arc::new(LocalClip::new());
-        term.set_clipboard(&clip);
-
-        let mut term = Self { term };
+        let clip = Arc::new(LocalClip::new());
+        let dyn_clip: Arc<dyn Clipboard> = clip.clone();
+        term.set_clipboard(&dyn_clip);
+
+        let mut term = Self {
+            term,
+            clip,
+            replies,
+        };
 
         term.set_auto_wrap(true);
 
@@ -90,6 +133,15 @@ impl TestTerm {
         self.term.advance_bytes(bytes);
     }
 
+    /// Returns the next reply written back to the application
+    fn read_reply(&self) -> String {
+        let reply = self
+            .replies
+            .recv_timeout(Duration::from_secs(5))
+            .expect("terminal to reply");
+        String::from_utf8(reply).unwrap()
+    }
+
     fn set_mode(&mut self, mode: &str, enable: bool) {
         self.print(CSI);
         self.print(mode);
@@ -479,6 +531,85 @@ fn test_semantic() {
     );
 }
 
+#[test]
+fn test_command_blocks() {
+    use wezterm_escape_parser::osc::FinalTermSemanticPrompt;
+    let mut term = TestTerm::new(10, 10, 0);
+    let prompt = OperatingSystemCommand::FinalTermSemanticPrompt(
+        FinalTermSemanticPrompt::FreshLineAndStartPrompt {
+            aid: None,
+            cl: None,
+        },
+    );
+    let input = OperatingSystemCommand::FinalTermSemanticPrompt(
+        FinalTermSemanticPrompt::MarkEndOfPromptAndStartOfInputUntilNextMarker,
+    );
+    let output = OperatingSystemCommand::FinalTermSemanticPrompt(
+        FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput { aid: None },
+    );
+    let status = |status| {
+        OperatingSystemCommand::FinalTermSemanticPrompt(FinalTermSemanticPrompt::CommandStatus {
+            status,
+            aid: None,
+        })
+    };
+
+    term.print(format!(
+        "{prompt}> {input}ls\r\n{output}a\r\nb\r\n{}",
+        status(0)
+    ));
+    // A prompt that is redrawn replaces the block for the prior one
+    term.print(format!(
+        "{prompt}> \r{prompt}> {input}false\r\n{output}{}",
+        status(1)
+    ));
+    term.print(format!("{prompt}> {input}"));
+
+    let blocks = term.get_command_blocks();
+    assert_eq!(blocks.len(), 3);
+
+    assert_eq!(blocks[0].start_row, 0);
+    assert_eq!(blocks[0].command.as_deref(), Some("ls"));
+    assert_eq!(blocks[0].output_rows(), Some(1..3));
+    assert_eq!(blocks[0].exit_status, Some(0));
+    assert!(blocks[0].duration.is_some());
+    assert!(!blocks[0].failed());
+
+    assert_eq!(blocks[1].start_row, 3);
+    assert_eq!(blocks[1].command.as_deref(), Some("false"));
+    assert_eq!(blocks[1].end_row, Some(3));
+    assert_eq!(blocks[1].output_rows(), None);
+    assert!(blocks[1].failed());
+
+    assert_eq!(blocks[2].start_row, 4);
+    assert!(!blocks[2].is_finished());
+    assert!(blocks[2].contains_row(9));
+}
+
+#[test]
+fn test_command_block_keeps_leading_space() {
+    use wezterm_escape_parser::osc::FinalTermSemanticPrompt;
+    let mut term = TestTerm::new(5, 20, 0);
+    let prompt = OperatingSystemCommand::FinalTermSemanticPrompt(
+        FinalTermSemanticPrompt::FreshLineAndStartPrompt {
+            aid: None,
+            cl: None,
+        },
+    );
+    let input = OperatingSystemCommand::FinalTermSemanticPrompt(
+        FinalTermSemanticPrompt::MarkEndOfPromptAndStartOfInputUntilNextMarker,
+    );
+    let output = OperatingSystemCommand::FinalTermSemanticPrompt(
+        FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput { aid: None },
+    );
+
+    // A leading space keeps the command out of the shell history,
+    // and so it must be preserved for the benefit of our own history
+    term.print(format!("{prompt}> {input} secret  \r\n{output}"));
+    let blocks = term.get_command_blocks();
+    assert_eq!(blocks[0].command.as_deref(), Some(" secret"));
+}
+
 #[test]
 fn issue_1161() {
     let mut term = TestTerm::new(1, 5, 0);
@@ -1374,6 +1505,38 @@ fn test_hyperlinks() {
     );
 }
 
+// ========== OSC 52 Clipboard 测试 ==========
+
+#[test]
+fn test_osc52_set_and_query() {
+    let mut term = TestTerm::new(3, 10, 0);
+    term.print("\x1b]52;c;aGVsbG8=\x1b\\");
+    assert_eq!(term.clip.clip.lock().unwrap().as_deref(), Some("hello"));
+
+    // Queries are passed to the clipboard to decide whether to answer
+    term.print("\x1b]52;c;?\x1b\\");
+    term.print("\x1b]52;p;?\x07");
+    assert_eq!(
+        *term.clip.queries.lock().unwrap(),
+        vec![
+            ClipboardSelection::Clipboard,
+            ClipboardSelection::PrimarySelection
+        ]
+    );
+}
+
+#[test]
+fn test_osc52_response() {
+    assert_eq!(
+        ClipboardSelection::Clipboard.osc52_response("hello"),
+        "\x1b]52;c;aGVsbG8=\x1b\\"
+    );
+    assert_eq!(
+        ClipboardSelection::PrimarySelection.osc52_response(""),
+        "\x1b]52;p;\x1b\\"
+    );
+}
+
 // ========== Primary Screen Peek 测试 ==========
 
 #[test]
@@ -1428,7 +1591,7 @@ fn test_primary_peek_cleared_on_soft_reset() {
 
     // Soft Reset
     term.soft_reset();
-    // Soft Reset 会退出 alt screen，peek flag 应被清除
+    // Soft Reset 不会退出 alt screen，但 peek flag 应被清除
     assert!(!term.is_primary_peek(), "soft reset 后 peek 不应残留");
 
     // 重新进入 alt screen 不应有残留 peek